@@ -6,20 +6,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=proto");
     println!("cargo:rerun-if-changed=build.rs");
     
-    // Compile Protocol Buffers
-    let mut config = prost_build::Config::new();
-    config.bytes(["."]);
-    
     // Out dir is set by Cargo
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     
-    // Create proto directory if it doesn't exist
-    let proto_dir = PathBuf::from("proto");
-    if !proto_dir.exists() {
-        std::fs::create_dir_all(&proto_dir)?;
+    // Compile Protocol Buffers for the embedding file format
+    #[cfg(feature = "proto-store")]
+    {
+        let mut config = prost_build::Config::new();
+        config.bytes(["."]);
+        // Maps encode in key order, so the same collection always serializes to the same bytes
+        config.btree_map(["."]);
+        
+        // Create proto directory if it doesn't exist
+        let proto_dir = PathBuf::from("proto");
+        if !proto_dir.exists() {
+            std::fs::create_dir_all(&proto_dir)?;
         
-        // Create embeddings.proto file with basic schema
-        let proto_content = r#"syntax = "proto3";
+            // Create embeddings.proto file with basic schema
+            let proto_content = r#"syntax = "proto3";
 
 package embeddings;
 
@@ -38,12 +42,53 @@ message EmbeddingCollection {
   int32 dimension = 4;  // Dimension of each embedding vector
 }
 "#;
-        std::fs::write(proto_dir.join("embeddings.proto"), proto_content)?;
+            std::fs::write(proto_dir.join("embeddings.proto"), proto_content)?;
+        }
+        
+        // Compile the proto files
+        config.out_dir(&out_dir);
+        config.compile_protos(&[proto_dir.join("embeddings.proto")], &[proto_dir])?;
+    }
+    
+    // Locate the model bundle compiled into the binary
+    #[cfg(feature = "embedded-model")]
+    {
+        println!("cargo:rerun-if-env-changed=RUST_EMBED_EMBEDDED_MODEL");
+        let bundle = env::var("RUST_EMBED_EMBEDDED_MODEL").map_err(|_| {
+            "The embedded-model feature needs RUST_EMBED_EMBEDDED_MODEL set to a bundle written by `rust_embed model bundle`"
+        })?;
+        let bundle = std::fs::canonicalize(&bundle)
+            .map_err(|e| format!("RUST_EMBED_EMBEDDED_MODEL points at {}: {}", bundle, e))?;
+        println!("cargo:rerun-if-changed={}", bundle.display());
+        println!("cargo:rustc-env=RUST_EMBED_EMBEDDED_BUNDLE={}", bundle.display());
     }
     
-    // Compile the proto files
-    config.out_dir(&out_dir);
-    config.compile_protos(&[proto_dir.join("embeddings.proto")], &[proto_dir])?;
+    // Regenerate the C header for the FFI layer
+    #[cfg(feature = "ffi-header")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let crate_dir = env::var("CARGO_MANIFEST_DIR")?;
+        cbindgen::generate(&crate_dir)?
+            .write_to_file(PathBuf::from(&crate_dir).join("include").join("rust_embed.h"));
+    }
+    
+    // Record libtorch's location in the rpath, so binaries load it without DYLD_* or LD_LIBRARY_PATH,
+    // trying the `lib/` directory next to the executable first, where release packages put it
+    #[cfg(feature = "torch")]
+    {
+        println!("cargo:rerun-if-env-changed=LIBTORCH");
+        let origin = match env::var("CARGO_CFG_TARGET_OS")?.as_str() {
+            "macos" => Some("@executable_path/lib"),
+            "linux" => Some("$ORIGIN/lib"),
+            _ => None,
+        };
+        if let Some(origin) = origin {
+            println!("cargo:rustc-link-arg=-Wl,-rpath,{}", origin);
+            if let Ok(libtorch) = env::var("LIBTORCH") {
+                println!("cargo:rustc-link-arg=-Wl,-rpath,{}", PathBuf::from(libtorch).join("lib").display());
+            }
+        }
+    }
     
     // Detect Apple Silicon
     if cfg!(target_os = "macos") {
@@ -97,8 +142,7 @@ message EmbeddingCollection {
                 }
             }
         } else {
-            println!("cargo:warning=Building for Intel Mac (x86_64)");
-            println!("cargo:warning=This build is optimized for Apple Silicon (M-series) processors");
+            println!("cargo:warning=Building for Intel Mac (x86_64), models run on the CPU");
         }
     }
     
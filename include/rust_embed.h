@@ -0,0 +1,53 @@
+#ifndef RUST_EMBED_H
+#define RUST_EMBED_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. Rebuild with --features ffi-header instead. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// An embedding model usable from C, running on its own worker thread
+//
+// Models keep per-thread state, so the handle forwards calls to a dedicated thread
+// and may be shared between threads of the calling application.
+typedef struct RustEmbedder RustEmbedder;
+
+// Loads an embedding model, returning NULL on failure
+//
+// `model` is a registry name such as "minilm" or "bge-small", NULL selects "minilm".
+// `model_path` is the model directory, required by ONNX models, or NULL.
+//
+// # Safety
+// `model` and `model_path` must each be null or point to a NUL-terminated string.
+struct RustEmbedder *rust_embed_new(const char *model, const char *model_path);
+
+// Dimension of the embeddings produced by the model, 0 for a NULL handle
+//
+// # Safety
+// `embedder` must be null or a handle returned by `rust_embed_new` that has not been freed.
+size_t rust_embed_dimension(const struct RustEmbedder *embedder);
+
+// Embeds a text into `out`, which must hold at least `capacity` floats
+//
+// Returns the number of floats written, or -1 on failure.
+//
+// # Safety
+// `embedder` must be a live handle from `rust_embed_new`, `text` a NUL-terminated string
+// and `out` must be valid for writes of `capacity` floats.
+int64_t rust_embed_embed_text(const struct RustEmbedder *embedder,
+                              const char *text,
+                              float *out,
+                              size_t capacity);
+
+// Message describing the last failure on the calling thread, or NULL
+//
+// The string stays valid until the next failing call on the same thread.
+const char *rust_embed_last_error(void);
+
+// Releases a handle returned by `rust_embed_new`, NULL is ignored
+//
+// # Safety
+// `embedder` must be null or a handle from `rust_embed_new` that has not been freed yet.
+void rust_embed_free(struct RustEmbedder *embedder);
+
+#endif /* RUST_EMBED_H */
@@ -0,0 +1,198 @@
+use super::{centroid, nearest_centroid};
+use crate::embedding::Embedder;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// The label predicted for a text and its similarity to the label centroid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub label: String,
+    pub score: f32,
+}
+
+/// A few-shot text classifier assigning the label of the nearest class centroid
+#[derive(Debug, Clone)]
+pub struct CentroidClassifier {
+    labels: Vec<String>,
+    centroids: Vec<Array1<f32>>,
+    model_name: String,
+    model_version: String,
+}
+
+impl CentroidClassifier {
+    /// Trains a classifier by embedding labeled example texts given as `(text, label)` pairs
+    pub fn train<E: Embedder>(embedder: &E, examples: &[(String, String)]) -> Result<Self> {
+        let texts: Vec<String> = examples.iter().map(|(text, _)| text.clone()).collect();
+        let embeddings = embedder.embed_batch(&texts)?;
+
+        let labeled: Vec<(Array1<f32>, String)> = embeddings.into_iter()
+            .zip(examples.iter().map(|(_, label)| label.clone()))
+            .collect();
+
+        let mut classifier = Self::fit(&labeled)?;
+        classifier.model_name = embedder.model_name().to_string();
+        classifier.model_version = embedder.model_version().to_string();
+        Ok(classifier)
+    }
+
+    /// Builds a classifier from precomputed `(embedding, label)` pairs
+    pub fn fit(examples: &[(Array1<f32>, String)]) -> Result<Self> {
+        if examples.is_empty() {
+            return Err(err!("Cannot train a classifier without examples"));
+        }
+
+        // Group the examples by label, keeping labels in first-seen order
+        let mut labels: Vec<String> = Vec::new();
+        let mut groups: Vec<Vec<Array1<f32>>> = Vec::new();
+        for (embedding, label) in examples {
+            match labels.iter().position(|l| l == label) {
+                Some(i) => groups[i].push(embedding.clone()),
+                None => {
+                    labels.push(label.clone());
+                    groups.push(vec![embedding.clone()]);
+                }
+            }
+        }
+
+        let centroids = groups.iter()
+            .map(|group| centroid(group))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            labels,
+            centroids,
+            model_name: String::new(),
+            model_version: String::new(),
+        })
+    }
+
+    /// The labels known to the classifier
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Dimension of the embeddings the classifier expects
+    pub fn dimension(&self) -> usize {
+        self.centroids.first().map(|c| c.len()).unwrap_or(0)
+    }
+
+    /// Predicts the label for an embedding
+    pub fn predict(&self, embedding: &Array1<f32>) -> Result<Prediction> {
+        if embedding.len() != self.dimension() {
+            return Err(err!(
+                "Dimension mismatch: classifier expects {} but got {}",
+                self.dimension(), embedding.len()
+            ));
+        }
+
+        let (index, score) = nearest_centroid(embedding, &self.centroids)
+            .ok_or_else(|| err!("Classifier has no centroids"))?;
+
+        Ok(Prediction {
+            label: self.labels[index].clone(),
+            score,
+        })
+    }
+
+    /// Embeds a text and predicts its label
+    pub fn classify<E: Embedder>(&self, embedder: &E, text: &str) -> Result<Prediction> {
+        let embedding = embedder.embed_text(text)?;
+        self.predict(&embedding)
+    }
+
+    /// Similarity of an embedding to every label, most similar first
+    pub fn scores(&self, embedding: &Array1<f32>) -> Vec<Prediction> {
+        let mut scores: Vec<Prediction> = self.labels.iter()
+            .zip(&self.centroids)
+            .map(|(label, centroid)| Prediction {
+                label: label.clone(),
+                score: utils::cosine_similarity(embedding, centroid),
+            })
+            .collect();
+
+        scores.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        scores
+    }
+
+    /// Saves the classifier as an embedding collection with one centroid per label
+    #[cfg(feature = "proto-store")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        utils::save_embeddings(
+            &self.centroids,
+            Some(&self.labels),
+            &self.model_name,
+            &self.model_version,
+            self.dimension() as i32,
+            path,
+        )?;
+        Ok(())
+    }
+
+    /// Loads a classifier saved with `save`
+    #[cfg(feature = "proto-store")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let collection: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
+
+        let model_name = collection.model_name.clone();
+        let model_version = collection.model_version.clone();
+        let (centroids, labels) = utils::convert_proto_embeddings(collection)?;
+        let labels = labels.ok_or_else(|| err!("Classifier file does not contain labels"))?;
+
+        if centroids.is_empty() {
+            return Err(err!("Classifier file does not contain any centroids"));
+        }
+
+        Ok(Self {
+            labels,
+            centroids,
+            model_name,
+            model_version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn examples() -> Vec<(Array1<f32>, String)> {
+        vec![
+            (array![1.0, 0.1], "sports".to_string()),
+            (array![0.9, 0.0], "sports".to_string()),
+            (array![0.0, 1.0], "science".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_predicts_nearest_label() -> Result<()> {
+        let classifier = CentroidClassifier::fit(&examples())?;
+
+        assert_eq!(classifier.labels(), &["sports".to_string(), "science".to_string()]);
+        assert_eq!(classifier.predict(&array![0.1, 0.8])?.label, "science");
+        assert_eq!(classifier.scores(&array![1.0, 0.0])[0].label, "sports");
+        assert!(classifier.predict(&array![1.0, 0.0, 0.0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "proto-store")]
+    fn test_save_and_load_roundtrip() -> Result<()> {
+        let classifier = CentroidClassifier::fit(&examples())?;
+        let path = std::env::temp_dir().join(format!("rust_embed_classifier_{}.pb", std::process::id()));
+
+        classifier.save(&path)?;
+        let loaded = CentroidClassifier::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded.labels(), classifier.labels());
+        assert_eq!(loaded.predict(&array![0.1, 0.8])?.label, "science");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use crate::embedding::Embedder;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use std::collections::HashSet;
+
+/// Common English words that never start or end a candidate phrase
+pub(crate) const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "below", "between", "both", "but", "by", "can", "could",
+    "did", "do", "does", "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has",
+    "have", "having", "he", "her", "here", "hers", "him", "his", "how", "i", "if", "in", "into", "is", "it",
+    "its", "itself", "just", "me", "more", "most", "my", "no", "nor", "not", "now", "of", "off", "on",
+    "once", "only", "or", "other", "our", "ours", "out", "over", "own", "same", "she", "should", "so",
+    "some", "such", "than", "that", "the", "their", "theirs", "them", "then", "there", "these", "they",
+    "this", "those", "through", "to", "too", "under", "until", "up", "very", "was", "we", "were", "what",
+    "when", "where", "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your",
+];
+
+/// Options for keyword extraction
+#[derive(Debug, Clone)]
+pub struct KeywordOptions {
+    /// Minimum and maximum number of words per candidate phrase
+    pub ngram_range: (usize, usize),
+
+    /// Number of keywords to return
+    pub top_k: usize,
+
+    /// Diversity for maximal marginal relevance (0 = pure relevance, 1 = pure diversity), disabled if None
+    pub diversity: Option<f32>,
+
+    /// Drop candidates that start or end with a stop word
+    pub remove_stop_words: bool,
+}
+
+impl Default for KeywordOptions {
+    fn default() -> Self {
+        Self {
+            ngram_range: (1, 2),
+            top_k: 5,
+            diversity: None,
+            remove_stop_words: true,
+        }
+    }
+}
+
+/// A keyword and its similarity to the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyword {
+    pub phrase: String,
+    pub score: f32,
+}
+
+/// Extracts the `top_k` phrases of `min..=max` words most similar to the document
+pub fn extract_keywords<E: Embedder>(
+    embedder: &E,
+    document: &str,
+    candidate_ngrams: (usize, usize),
+    top_k: usize,
+) -> Result<Vec<Keyword>> {
+    let options = KeywordOptions {
+        ngram_range: candidate_ngrams,
+        top_k,
+        ..KeywordOptions::default()
+    };
+    extract_keywords_with(embedder, document, &options)
+}
+
+/// Extracts keywords with full control over the options
+pub fn extract_keywords_with<E: Embedder>(embedder: &E, document: &str, options: &KeywordOptions) -> Result<Vec<Keyword>> {
+    let (min, max) = options.ngram_range;
+    if min == 0 || min > max {
+        return Err(err!("Invalid n-gram range ({}, {})", min, max));
+    }
+
+    let candidates = candidate_phrases(document, options.ngram_range, options.remove_stop_words);
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let document_embedding = embedder.embed_text(document)?;
+    let candidate_embeddings = embedder.embed_batch(&candidates)?;
+
+    let selected = match options.diversity {
+        Some(diversity) => maximal_marginal_relevance(&document_embedding, &candidate_embeddings, options.top_k, diversity),
+        None => {
+            let mut ranked: Vec<usize> = (0..candidates.len()).collect();
+            ranked.sort_by(|&a, &b| {
+                let score_a = utils::cosine_similarity(&document_embedding, &candidate_embeddings[a]);
+                let score_b = utils::cosine_similarity(&document_embedding, &candidate_embeddings[b]);
+                score_b.partial_cmp(&score_a).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            ranked.truncate(options.top_k);
+            ranked
+        }
+    };
+
+    Ok(selected.into_iter()
+        .map(|i| Keyword {
+            phrase: candidates[i].clone(),
+            score: utils::cosine_similarity(&document_embedding, &candidate_embeddings[i]),
+        })
+        .collect())
+}
+
+/// Lists the distinct lowercase n-grams of a document, in order of first appearance
+pub fn candidate_phrases(document: &str, ngram_range: (usize, usize), remove_stop_words: bool) -> Vec<String> {
+    let words: Vec<String> = document
+        .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '\''))
+        .map(|word| word.trim_matches(|c| c == '-' || c == '\'').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let is_stop_word = |word: &str| remove_stop_words && STOP_WORDS.contains(&word);
+
+    let mut seen = HashSet::new();
+    let mut phrases = Vec::new();
+    for n in ngram_range.0.max(1)..=ngram_range.1 {
+        for window in words.windows(n) {
+            if is_stop_word(&window[0]) || is_stop_word(&window[n - 1]) {
+                continue;
+            }
+
+            let phrase = window.join(" ");
+            if seen.insert(phrase.clone()) {
+                phrases.push(phrase);
+            }
+        }
+    }
+
+    phrases
+}
+
+/// Selects `top_k` candidates balancing similarity to the query against similarity to already selected ones
+pub fn maximal_marginal_relevance(
+    query: &Array1<f32>,
+    candidates: &[Array1<f32>],
+    top_k: usize,
+    diversity: f32,
+) -> Vec<usize> {
+    let relevance: Vec<f32> = candidates.iter()
+        .map(|candidate| utils::cosine_similarity(query, candidate))
+        .collect();
+
+    let mut selected: Vec<usize> = Vec::new();
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+
+    while selected.len() < top_k && !remaining.is_empty() {
+        let best = remaining.iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let redundancy = selected.iter()
+                    .map(|&j| utils::cosine_similarity(&candidates[i], &candidates[j]))
+                    .fold(0.0f32, f32::max);
+                let score = (1.0 - diversity) * relevance[i] - diversity * redundancy;
+                (position, score)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(position, _)| position);
+
+        match best {
+            Some(position) => selected.push(remaining.remove(position)),
+            None => break,
+        }
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_candidates_skip_stop_word_boundaries() {
+        let phrases = candidate_phrases("The embedding of text is fast", (1, 2), true);
+
+        assert!(phrases.contains(&"embedding".to_string()));
+        assert!(phrases.contains(&"text".to_string()));
+        assert!(!phrases.contains(&"the".to_string()));
+        assert!(!phrases.contains(&"embedding of".to_string()));
+        assert!(!phrases.contains(&"the embedding".to_string()));
+    }
+
+    #[test]
+    fn test_mmr_prefers_diverse_candidates() {
+        let query = array![1.0, 1.0];
+        let candidates = vec![array![1.0, 0.9], array![1.0, 0.91], array![0.2, 1.0]];
+
+        let relevant = maximal_marginal_relevance(&query, &candidates, 2, 0.0);
+        assert_eq!(relevant, vec![1, 0]);
+
+        let diverse = maximal_marginal_relevance(&query, &candidates, 2, 0.7);
+        assert_eq!(diverse, vec![1, 2]);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::error::{err, Result};
+use ndarray::Array1;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Options for k-means clustering
+#[derive(Debug, Clone)]
+pub struct KMeansOptions {
+    /// Number of clusters
+    pub k: usize,
+
+    /// Maximum number of Lloyd iterations
+    pub max_iterations: usize,
+
+    /// Seed for k-means++ initialization, making results reproducible
+    pub seed: u64,
+}
+
+impl KMeansOptions {
+    /// Creates options for `k` clusters with default iteration limit and seed
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            max_iterations: 50,
+            seed: 42,
+        }
+    }
+}
+
+/// The result of a k-means run
+#[derive(Debug, Clone)]
+pub struct KMeans {
+    /// Cluster centers
+    pub centroids: Vec<Array1<f32>>,
+
+    /// Cluster index of each input vector
+    pub assignments: Vec<usize>,
+
+    /// Number of iterations run before convergence
+    pub iterations: usize,
+}
+
+impl KMeans {
+    /// Clusters vectors by squared Euclidean distance using k-means++ initialization
+    pub fn fit(vectors: &[Array1<f32>], options: &KMeansOptions) -> Result<Self> {
+        if vectors.is_empty() {
+            return Err(err!("Cannot cluster an empty set of vectors"));
+        }
+        if options.k == 0 {
+            return Err(err!("Number of clusters must be positive"));
+        }
+
+        let dimension = vectors[0].len();
+        if vectors.iter().any(|v| v.len() != dimension) {
+            return Err(err!("All vectors must have the same dimension to be clustered"));
+        }
+
+        let k = options.k.min(vectors.len());
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let mut centroids = initialize_centroids(vectors, k, &mut rng);
+        let mut assignments = vec![0; vectors.len()];
+        let mut iterations = 0;
+
+        for iteration in 0..options.max_iterations.max(1) {
+            iterations = iteration + 1;
+
+            // Assignment step, stop once no vector changes cluster
+            let mut changed = iteration == 0;
+            for (i, vector) in vectors.iter().enumerate() {
+                let (nearest, _) = nearest(vector, &centroids);
+                if nearest != assignments[i] {
+                    assignments[i] = nearest;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+
+            // Update step, empty clusters are re-seeded with the point farthest from its centroid
+            let mut sums = vec![Array1::<f32>::zeros(dimension); k];
+            let mut counts = vec![0usize; k];
+            for (vector, &cluster) in vectors.iter().zip(&assignments) {
+                sums[cluster] += vector;
+                counts[cluster] += 1;
+            }
+
+            for cluster in 0..k {
+                if counts[cluster] > 0 {
+                    centroids[cluster] = &sums[cluster] / counts[cluster] as f32;
+                } else if let Some(farthest) = farthest_point(vectors, &centroids, &assignments) {
+                    centroids[cluster] = vectors[farthest].clone();
+                    assignments[farthest] = cluster;
+                }
+            }
+        }
+
+        Ok(Self {
+            centroids,
+            assignments,
+            iterations,
+        })
+    }
+
+    /// Number of clusters
+    pub fn k(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Index and squared distance of the centroid nearest to a vector
+    pub fn predict(&self, vector: &Array1<f32>) -> (usize, f32) {
+        nearest(vector, &self.centroids)
+    }
+
+    /// Indices of the input vectors assigned to a cluster
+    pub fn members(&self, cluster: usize) -> Vec<usize> {
+        self.assignments.iter()
+            .enumerate()
+            .filter(|(_, &assigned)| assigned == cluster)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Squared Euclidean distance between two vectors
+pub fn squared_distance(a: &Array1<f32>, b: &Array1<f32>) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Index and squared distance of the nearest centroid
+fn nearest(vector: &Array1<f32>, centroids: &[Array1<f32>]) -> (usize, f32) {
+    centroids.iter()
+        .map(|centroid| squared_distance(vector, centroid))
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or((0, f32::INFINITY))
+}
+
+/// Picks initial centroids with k-means++ seeding
+fn initialize_centroids(vectors: &[Array1<f32>], k: usize, rng: &mut StdRng) -> Vec<Array1<f32>> {
+    let mut centroids = vec![vectors[rng.gen_range(0..vectors.len())].clone()];
+
+    while centroids.len() < k {
+        let distances: Vec<f32> = vectors.iter()
+            .map(|vector| nearest(vector, &centroids).1)
+            .collect();
+        let total: f32 = distances.iter().sum();
+
+        // All remaining points coincide with a centroid, fall back to uniform sampling
+        if total <= 0.0 {
+            centroids.push(vectors[rng.gen_range(0..vectors.len())].clone());
+            continue;
+        }
+
+        let mut target = rng.gen::<f32>() * total;
+        let mut chosen = vectors.len() - 1;
+        for (i, distance) in distances.iter().enumerate() {
+            target -= distance;
+            if target <= 0.0 {
+                chosen = i;
+                break;
+            }
+        }
+        centroids.push(vectors[chosen].clone());
+    }
+
+    centroids
+}
+
+/// The point farthest from its assigned centroid
+fn farthest_point(vectors: &[Array1<f32>], centroids: &[Array1<f32>], assignments: &[usize]) -> Option<usize> {
+    vectors.iter()
+        .zip(assignments)
+        .map(|(vector, &cluster)| squared_distance(vector, &centroids[cluster]))
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+}
@@ -0,0 +1,158 @@
+pub mod kmeans;
+pub mod topics;
+
+mod classifier;
+mod keywords;
+mod outlier;
+mod summarize;
+
+pub use classifier::{CentroidClassifier, Prediction};
+pub use keywords::{
+    candidate_phrases, extract_keywords, extract_keywords_with, maximal_marginal_relevance,
+    Keyword, KeywordOptions,
+};
+pub use outlier::{OutlierConfig, OutlierDetector, OutlierScore};
+pub use summarize::{rank_sentences, split_sentences, summarize, RankedSentence};
+pub use topics::{discover_topics, Topic, TopicOptions, TopicReport};
+
+use crate::error::{err, Result};
+use ndarray::Array1;
+
+use crate::utils;
+
+/// Computes the normalized mean of a set of embedding vectors
+pub fn centroid(vectors: &[Array1<f32>]) -> Result<Array1<f32>> {
+    let weights = vec![1.0; vectors.len()];
+    weighted_centroid(vectors, &weights)
+}
+
+/// Computes the normalized weighted mean of a set of embedding vectors
+pub fn weighted_centroid(vectors: &[Array1<f32>], weights: &[f32]) -> Result<Array1<f32>> {
+    if vectors.is_empty() {
+        return Err(err!("Cannot compute the centroid of an empty set of vectors"));
+    }
+    
+    if vectors.len() != weights.len() {
+        return Err(err!(
+            "Expected {} weights but got {}", vectors.len(), weights.len()
+        ));
+    }
+    
+    let dimension = check_dimensions(vectors)?;
+    let total_weight: f32 = weights.iter().sum();
+    
+    if total_weight <= 0.0 {
+        return Err(err!("Weights must sum to a positive value"));
+    }
+    
+    // Accumulate the weighted sum, then scale by the total weight
+    let mut result = Array1::<f32>::zeros(dimension);
+    for (vector, &weight) in vectors.iter().zip(weights) {
+        result.scaled_add(weight, vector);
+    }
+    result /= total_weight;
+    
+    utils::normalize(&mut result);
+    Ok(result)
+}
+
+/// Adds two embedding vectors and re-normalizes the result
+pub fn add(a: &Array1<f32>, b: &Array1<f32>) -> Result<Array1<f32>> {
+    check_pair(a, b)?;
+    
+    let mut result = a + b;
+    utils::normalize(&mut result);
+    Ok(result)
+}
+
+/// Subtracts `b` from `a` and re-normalizes the result
+pub fn subtract(a: &Array1<f32>, b: &Array1<f32>) -> Result<Array1<f32>> {
+    check_pair(a, b)?;
+    
+    let mut result = a - b;
+    utils::normalize(&mut result);
+    Ok(result)
+}
+
+/// Finds the centroid most similar to the query, returning its index and similarity
+pub fn nearest_centroid(query: &Array1<f32>, centroids: &[Array1<f32>]) -> Option<(usize, f32)> {
+    centroids.iter()
+        .enumerate()
+        .filter(|(_, centroid)| centroid.len() == query.len())
+        .map(|(i, centroid)| (i, utils::cosine_similarity(query, centroid)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Ensures all vectors share the same dimension and returns it
+fn check_dimensions(vectors: &[Array1<f32>]) -> Result<usize> {
+    let dimension = vectors.first().map(|v| v.len()).unwrap_or(0);
+    
+    if let Some(mismatch) = vectors.iter().find(|v| v.len() != dimension) {
+        return Err(err!(
+            "Dimension mismatch: expected {} but found a vector of dimension {}",
+            dimension, mismatch.len()
+        ));
+    }
+    
+    Ok(dimension)
+}
+
+/// Ensures two vectors share the same dimension
+fn check_pair(a: &Array1<f32>, b: &Array1<f32>) -> Result<()> {
+    if a.len() != b.len() {
+        return Err(err!(
+            "Dimension mismatch: {} vs {}", a.len(), b.len()
+        ));
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    
+    #[test]
+    fn test_centroid_is_normalized_mean() -> Result<()> {
+        let vectors = vec![array![1.0, 0.0], array![0.0, 1.0]];
+        let result = centroid(&vectors)?;
+        
+        let expected = 1.0 / 2.0f32.sqrt();
+        assert!((result[0] - expected).abs() < 1e-6);
+        assert!((result[1] - expected).abs() < 1e-6);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_weighted_centroid_favors_heavier_vector() -> Result<()> {
+        let vectors = vec![array![1.0, 0.0], array![0.0, 1.0]];
+        let result = weighted_centroid(&vectors, &[3.0, 1.0])?;
+        
+        assert!(result[0] > result[1]);
+        assert!((result.dot(&result).sqrt() - 1.0).abs() < 1e-6);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_arithmetic_rejects_dimension_mismatch() {
+        let a = array![1.0, 0.0];
+        let b = array![1.0, 0.0, 0.0];
+        
+        assert!(add(&a, &b).is_err());
+        assert!(subtract(&a, &b).is_err());
+        assert!(centroid(&[a, b]).is_err());
+        assert!(centroid(&[]).is_err());
+    }
+    
+    #[test]
+    fn test_nearest_centroid() {
+        let centroids = vec![array![1.0, 0.0], array![0.0, 1.0]];
+        let query = array![0.2, 0.9];
+        
+        let (index, _) = nearest_centroid(&query, &centroids).unwrap();
+        assert_eq!(index, 1);
+    }
+}
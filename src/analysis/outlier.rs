@@ -0,0 +1,198 @@
+use crate::embedding::Embedder;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use std::collections::VecDeque;
+
+/// Settings for streaming outlier detection
+#[derive(Debug, Clone)]
+pub struct OutlierConfig {
+    /// Number of recent embeddings kept as the baseline
+    pub window_size: usize,
+
+    /// Embeddings less similar than this to the rolling centroid are flagged
+    pub min_similarity: f32,
+
+    /// Number of embeddings observed before anything is flagged
+    pub min_samples: usize,
+
+    /// Keep flagged embeddings out of the baseline so bursts of anomalies don't become normal
+    pub exclude_outliers: bool,
+}
+
+impl Default for OutlierConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 1000,
+            min_similarity: 0.5,
+            min_samples: 20,
+            exclude_outliers: true,
+        }
+    }
+}
+
+/// How an observed embedding compares to the recent baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlierScore {
+    /// Cosine similarity to the rolling centroid
+    pub similarity: f32,
+
+    /// Mean squared z-score across dimensions under the rolling diagonal covariance
+    pub distance: f32,
+
+    /// Whether the embedding was flagged as an outlier
+    pub is_outlier: bool,
+}
+
+/// Flags embeddings that drift away from a rolling window of recent ones
+#[derive(Debug, Clone)]
+pub struct OutlierDetector {
+    config: OutlierConfig,
+    window: VecDeque<Array1<f32>>,
+    sum: Option<Array1<f32>>,
+    sum_squares: Option<Array1<f32>>,
+}
+
+impl OutlierDetector {
+    /// Creates a detector with the given settings
+    pub fn new(config: OutlierConfig) -> Self {
+        Self {
+            config,
+            window: VecDeque::new(),
+            sum: None,
+            sum_squares: None,
+        }
+    }
+
+    /// Number of embeddings in the baseline window
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Returns true if the baseline window is empty
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// Mean of the embeddings in the window
+    pub fn centroid(&self) -> Option<Array1<f32>> {
+        self.sum.as_ref().map(|sum| sum / self.window.len().max(1) as f32)
+    }
+
+    /// Per-dimension variance of the embeddings in the window (the diagonal of the covariance)
+    pub fn variance(&self) -> Option<Array1<f32>> {
+        let n = self.window.len().max(1) as f32;
+        let mean = self.centroid()?;
+        self.sum_squares.as_ref()
+            .map(|sum_squares| (sum_squares / n - &mean * &mean).mapv(|v| v.max(0.0)))
+    }
+
+    /// Scores an embedding against the baseline without recording it
+    pub fn score(&self, embedding: &Array1<f32>) -> Result<Option<OutlierScore>> {
+        let (Some(centroid), Some(variance)) = (self.centroid(), self.variance()) else {
+            return Ok(None);
+        };
+
+        if embedding.len() != centroid.len() {
+            return Err(err!(
+                "Dimension mismatch: detector tracks {} dimensions but got {}",
+                centroid.len(), embedding.len()
+            ));
+        }
+
+        let similarity = utils::cosine_similarity(embedding, &centroid);
+        let distance = embedding.iter()
+            .zip(centroid.iter())
+            .zip(variance.iter())
+            .map(|((x, mean), var)| (x - mean).powi(2) / (var + 1e-6))
+            .sum::<f32>() / embedding.len() as f32;
+
+        let warmed_up = self.window.len() >= self.config.min_samples;
+
+        Ok(Some(OutlierScore {
+            similarity,
+            distance,
+            is_outlier: warmed_up && similarity < self.config.min_similarity,
+        }))
+    }
+
+    /// Scores an embedding, then adds it to the baseline window
+    pub fn observe(&mut self, embedding: &Array1<f32>) -> Result<OutlierScore> {
+        let score = self.score(embedding)?.unwrap_or(OutlierScore {
+            similarity: 1.0,
+            distance: 0.0,
+            is_outlier: false,
+        });
+
+        if !(score.is_outlier && self.config.exclude_outliers) {
+            self.push(embedding.clone());
+        }
+
+        Ok(score)
+    }
+
+    /// Embeds a text and observes it
+    pub fn observe_text<E: Embedder>(&mut self, embedder: &E, text: &str) -> Result<OutlierScore> {
+        let embedding = embedder.embed_text(text)?;
+        self.observe(&embedding)
+    }
+
+    /// Clears the baseline window
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.sum = None;
+        self.sum_squares = None;
+    }
+
+    /// Adds an embedding to the window, evicting the oldest one when full
+    fn push(&mut self, embedding: Array1<f32>) {
+        let squares = &embedding * &embedding;
+        match (&mut self.sum, &mut self.sum_squares) {
+            (Some(sum), Some(sum_squares)) => {
+                *sum += &embedding;
+                *sum_squares += &squares;
+            }
+            _ => {
+                self.sum = Some(embedding.clone());
+                self.sum_squares = Some(squares);
+            }
+        }
+        self.window.push_back(embedding);
+
+        while self.window.len() > self.config.window_size.max(1) {
+            if let Some(oldest) = self.window.pop_front() {
+                if let (Some(sum), Some(sum_squares)) = (&mut self.sum, &mut self.sum_squares) {
+                    *sum -= &oldest;
+                    *sum_squares -= &(&oldest * &oldest);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_flags_embeddings_far_from_baseline() -> Result<()> {
+        let mut detector = OutlierDetector::new(OutlierConfig {
+            window_size: 3,
+            min_similarity: 0.8,
+            min_samples: 2,
+            exclude_outliers: true,
+        });
+
+        for embedding in [array![1.0, 0.0], array![0.9, 0.1], array![1.0, 0.05], array![0.95, 0.0]] {
+            assert!(!detector.observe(&embedding)?.is_outlier);
+        }
+        assert_eq!(detector.len(), 3);
+
+        let score = detector.observe(&array![0.0, 1.0])?;
+        assert!(score.is_outlier);
+        assert_eq!(detector.len(), 3);
+
+        Ok(())
+    }
+}
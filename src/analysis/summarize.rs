@@ -0,0 +1,124 @@
+use crate::embedding::Embedder;
+use crate::utils;
+use crate::error::Result;
+
+/// Abbreviations whose trailing period does not end a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "inc", "ltd", "co", "fig",
+];
+
+/// A sentence and its centrality within the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedSentence {
+    /// The sentence text
+    pub text: String,
+
+    /// Position of the sentence in the document (0-based)
+    pub position: usize,
+
+    /// Similarity between the sentence and the whole document
+    pub score: f32,
+}
+
+/// Splits a document into sentences and ranks them by similarity to the document, most central first
+pub fn rank_sentences<E: Embedder>(embedder: &E, document: &str) -> Result<Vec<RankedSentence>> {
+    let sentences = split_sentences(document);
+    if sentences.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let document_embedding = embedder.embed_text(document)?;
+    let sentence_embeddings = embedder.embed_batch(&sentences)?;
+
+    let mut ranked: Vec<RankedSentence> = sentences.into_iter()
+        .zip(&sentence_embeddings)
+        .enumerate()
+        .map(|(position, (text, embedding))| RankedSentence {
+            text,
+            position,
+            score: utils::cosine_similarity(&document_embedding, embedding),
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(ranked)
+}
+
+/// Builds an extractive summary from the `count` most central sentences, kept in document order
+pub fn summarize<E: Embedder>(embedder: &E, document: &str, count: usize) -> Result<String> {
+    let mut selected = rank_sentences(embedder, document)?;
+    selected.truncate(count);
+    selected.sort_by_key(|sentence| sentence.position);
+
+    Ok(selected.into_iter()
+        .map(|sentence| sentence.text)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Splits text into sentences on terminal punctuation followed by whitespace
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        // Line breaks inside a sentence are treated as spaces, blank lines end a sentence
+        if c == '\n' && chars.peek() == Some(&'\n') {
+            push_sentence(&mut sentences, &mut current);
+            continue;
+        }
+        current.push(if c.is_whitespace() { ' ' } else { c });
+
+        let is_terminal = matches!(c, '.' | '!' | '?');
+        let at_boundary = chars.peek().map(|next| next.is_whitespace()).unwrap_or(true);
+
+        if is_terminal && at_boundary && !(c == '.' && ends_with_abbreviation(&current)) {
+            push_sentence(&mut sentences, &mut current);
+        }
+    }
+    push_sentence(&mut sentences, &mut current);
+
+    sentences
+}
+
+/// Moves the current sentence into the list if it has any content
+fn push_sentence(sentences: &mut Vec<String>, current: &mut String) {
+    let sentence = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+    current.clear();
+}
+
+/// Checks whether the text ends with a known abbreviation or a single initial
+fn ends_with_abbreviation(text: &str) -> bool {
+    let last_word = text.trim_end_matches('.')
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+
+    let is_initial = last_word.len() == 1 && last_word.chars().all(|c| c.is_alphabetic());
+    is_initial || ABBREVIATIONS.contains(&last_word.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sentences() {
+        let sentences = split_sentences(
+            "Dr. Smith arrived at 9.30 today. Was it late? No!\nThe meeting, e.g. the standup, ran long.\n\nNew paragraph"
+        );
+
+        assert_eq!(sentences, vec![
+            "Dr. Smith arrived at 9.30 today.",
+            "Was it late?",
+            "No!",
+            "The meeting, e.g. the standup, ran long.",
+            "New paragraph",
+        ]);
+    }
+}
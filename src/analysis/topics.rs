@@ -0,0 +1,243 @@
+use super::kmeans::{KMeans, KMeansOptions};
+use super::keywords::STOP_WORDS;
+use crate::search::bm25::tokenize;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Options for topic discovery
+#[derive(Debug, Clone)]
+pub struct TopicOptions {
+    /// Number of topics (clusters) to discover
+    pub num_topics: usize,
+
+    /// Number of keywords describing each topic
+    pub keywords_per_topic: usize,
+
+    /// Number of representative documents listed per topic
+    pub representatives: usize,
+
+    /// Seed for clustering, making results reproducible
+    pub seed: u64,
+}
+
+impl Default for TopicOptions {
+    fn default() -> Self {
+        Self {
+            num_topics: 8,
+            keywords_per_topic: 8,
+            representatives: 3,
+            seed: 42,
+        }
+    }
+}
+
+/// A discovered topic
+#[derive(Debug, Clone)]
+pub struct Topic {
+    /// Topic number, topics are sorted by size
+    pub id: usize,
+
+    /// Number of documents in the topic
+    pub size: usize,
+
+    /// Most distinctive terms of the topic with their c-TF-IDF weight
+    pub keywords: Vec<(String, f32)>,
+
+    /// Documents closest to the topic centroid, as indices into the corpus
+    pub representatives: Vec<usize>,
+
+    /// Normalized topic centroid
+    pub centroid: Array1<f32>,
+}
+
+/// The topics of a corpus and the assignment of each document
+#[derive(Debug, Clone)]
+pub struct TopicReport {
+    pub topics: Vec<Topic>,
+
+    /// Topic id of each document, in corpus order
+    pub assignments: Vec<usize>,
+
+    /// The corpus texts, used for rendering representatives
+    texts: Vec<String>,
+}
+
+/// Clusters document embeddings and describes each cluster with c-TF-IDF keywords
+pub fn discover_topics(embeddings: &[Array1<f32>], texts: &[String], options: &TopicOptions) -> Result<TopicReport> {
+    if embeddings.len() != texts.len() {
+        return Err(err!(
+            "Got {} embeddings but {} texts", embeddings.len(), texts.len()
+        ));
+    }
+
+    // Cluster normalized vectors so Euclidean k-means follows cosine similarity
+    let normalized: Vec<Array1<f32>> = embeddings.iter()
+        .map(|embedding| {
+            let mut embedding = embedding.clone();
+            utils::normalize(&mut embedding);
+            embedding
+        })
+        .collect();
+
+    let kmeans_options = KMeansOptions {
+        seed: options.seed,
+        ..KMeansOptions::new(options.num_topics)
+    };
+    let clustering = KMeans::fit(&normalized, &kmeans_options)?;
+
+    // Order clusters by size so topic 0 is the largest
+    let mut clusters: Vec<Vec<usize>> = (0..clustering.k())
+        .map(|cluster| clustering.members(cluster))
+        .filter(|members| !members.is_empty())
+        .collect();
+    clusters.sort_by_key(|members| std::cmp::Reverse(members.len()));
+
+    let cluster_texts: Vec<Vec<&str>> = clusters.iter()
+        .map(|members| members.iter().map(|&i| texts[i].as_str()).collect())
+        .collect();
+    let keywords = class_tf_idf(&cluster_texts, options.keywords_per_topic);
+
+    let mut assignments = vec![0; texts.len()];
+    let topics = clusters.into_iter()
+        .zip(keywords)
+        .enumerate()
+        .map(|(id, (members, keywords))| {
+            for &member in &members {
+                assignments[member] = id;
+            }
+
+            let group: Vec<Array1<f32>> = members.iter().map(|&i| normalized[i].clone()).collect();
+            let centroid = super::centroid(&group)?;
+
+            let mut representatives = members.clone();
+            representatives.sort_by(|&a, &b| {
+                let score_a = normalized[a].dot(&centroid);
+                let score_b = normalized[b].dot(&centroid);
+                score_b.partial_cmp(&score_a).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            representatives.truncate(options.representatives);
+
+            Ok(Topic {
+                id,
+                size: members.len(),
+                keywords,
+                representatives,
+                centroid,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(TopicReport {
+        topics,
+        assignments,
+        texts: texts.to_vec(),
+    })
+}
+
+/// Computes class-based TF-IDF, treating each cluster as one document, and returns the top terms per class
+fn class_tf_idf(classes: &[Vec<&str>], top_n: usize) -> Vec<Vec<(String, f32)>> {
+    let counts: Vec<HashMap<String, f32>> = classes.iter()
+        .map(|texts| {
+            let mut counts = HashMap::new();
+            for text in texts {
+                for term in tokenize(text) {
+                    if term.chars().count() > 2 && !STOP_WORDS.contains(&term.as_str()) {
+                        *counts.entry(term).or_insert(0.0) += 1.0;
+                    }
+                }
+            }
+            counts
+        })
+        .collect();
+
+    // Frequency of each term across all classes and the average class size in words
+    let mut totals: HashMap<&str, f32> = HashMap::new();
+    for class in &counts {
+        for (term, count) in class {
+            *totals.entry(term.as_str()).or_insert(0.0) += count;
+        }
+    }
+    let total_words: f32 = totals.values().sum();
+    let average_words = total_words / classes.len().max(1) as f32;
+
+    counts.iter()
+        .map(|class| {
+            let class_words: f32 = class.values().sum::<f32>().max(1.0);
+            let mut weights: Vec<(String, f32)> = class.iter()
+                .map(|(term, count)| {
+                    let tf = count / class_words;
+                    let idf = (1.0 + average_words / totals[term.as_str()]).ln();
+                    (term.clone(), tf * idf)
+                })
+                .collect();
+
+            weights.sort_by(|a, b| {
+                b.1.partial_cmp(&a.1)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+            weights.truncate(top_n);
+            weights
+        })
+        .collect()
+}
+
+impl TopicReport {
+    /// Text of a document in the analyzed corpus
+    pub fn text(&self, index: usize) -> Option<&str> {
+        self.texts.get(index).map(String::as_str)
+    }
+}
+
+impl fmt::Display for TopicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for topic in &self.topics {
+            let keywords: Vec<&str> = topic.keywords.iter().map(|(term, _)| term.as_str()).collect();
+            writeln!(f, "Topic {} ({} documents): {}", topic.id, topic.size, keywords.join(", "))?;
+
+            for &index in &topic.representatives {
+                let text = self.texts[index].replace('\n', " ");
+                let preview: String = text.chars().take(100).collect();
+                let ellipsis = if text.chars().count() > 100 { "..." } else { "" };
+                writeln!(f, "    - {}{}", preview, ellipsis)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_discovers_separated_topics() -> Result<()> {
+        let texts: Vec<String> = [
+            "rust compiler borrow checker",
+            "rust borrow lifetimes compiler",
+            "rust compiler traits",
+            "baking bread with sourdough",
+            "sourdough bread starter",
+        ].iter().map(|t| t.to_string()).collect();
+        let embeddings = vec![
+            array![1.0, 0.0], array![0.9, 0.1], array![0.95, 0.05],
+            array![0.0, 1.0], array![0.1, 0.9],
+        ];
+
+        let options = TopicOptions { num_topics: 2, keywords_per_topic: 2, ..TopicOptions::default() };
+        let report = discover_topics(&embeddings, &texts, &options)?;
+
+        assert_eq!(report.topics.len(), 2);
+        assert_eq!(report.topics[0].size, 3);
+        assert_eq!(report.topics[0].keywords[0].0, "compiler");
+        assert!(report.topics[1].keywords.iter().any(|(term, _)| term == "sourdough" || term == "bread"));
+        assert_eq!(report.assignments, vec![0, 0, 0, 1, 1]);
+
+        Ok(())
+    }
+}
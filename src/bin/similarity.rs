@@ -20,21 +20,14 @@ struct Args {
 }
 
 fn main() -> Result<()> {
-    // Initialize logging
-    env_logger::init();
+    // Initialize tracing output, filtered by RUST_LOG
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
     
     // Parse command line arguments
     let args = Args::parse();
     
-    // Load the embedding from file
-    println!("Loading embedding from {:?}", args.embedding_file);
-    let (embeddings, texts) = utils::load_embeddings(&args.embedding_file)?;
-    
-    if embeddings.is_empty() {
-        println!("No embeddings found in the file");
-        return Ok(());
-    }
-    
     // Create the MiniLM embedder
     let mut embedder = MiniLMEmbedder::new();
     
@@ -42,6 +35,15 @@ fn main() -> Result<()> {
     println!("Initializing the embedder...");
     embedder.initialize()?;
     
+    // Load the embedding from file, it must come from the same model to be comparable
+    println!("Loading embedding from {:?}", args.embedding_file);
+    let (embeddings, texts) = utils::load_embeddings_checked(&args.embedding_file, &embedder)?;
+    
+    if embeddings.is_empty() {
+        println!("No embeddings found in the file");
+        return Ok(());
+    }
+    
     // Output info about the model
     println!("Using the {} model for generating embeddings.", embedder.model_name());
     println!("Embedding dimension: {}", embedder.dimension());
@@ -0,0 +1,300 @@
+pub mod shard;
+
+pub use shard::{ShardManifest, ShardWriter, ShardedCollection};
+
+use crate::embedding::{ModelInfo, SparseEmbedding};
+use crate::error::{err, Result};
+use crate::proto;
+use crate::utils::storage;
+use ndarray::Array1;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// What kind of input an embedding was computed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    #[default]
+    Text,
+    /// The stored text is the image's path
+    Image,
+}
+
+/// How a collection was produced, for reproducing or debugging it later
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Provenance {
+    /// Version of rust_embed that wrote the collection
+    pub crate_version: String,
+    /// Device the model ran on, such as "cpu" or "cuda:0"
+    pub device: Option<String>,
+    /// How token vectors were pooled into one, such as "mean"
+    pub pooling: Option<String>,
+    /// Whether vectors were scaled to unit length
+    pub normalized: bool,
+    /// SHA-256 of the model weights file
+    pub weights_sha256: Option<String>,
+    /// How inputs were prepared, such as lowercasing or an instruction prefix
+    pub preprocessing: BTreeMap<String, String>,
+    /// Seed, platform and libtorch settings of a reproducible run, empty otherwise
+    pub environment: BTreeMap<String, String>,
+}
+
+impl Provenance {
+    /// Provenance recording only this crate's version and reproducible settings, for writers that don't know the model
+    pub fn current() -> Self {
+        Self {
+            crate_version: crate::VERSION.to_string(),
+            environment: crate::utils::reproducibility::active()
+                .map(|config| config.environment())
+                .unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    /// Provenance of embeddings made by a model, stamped with this crate's version
+    pub fn from_model_info(info: &ModelInfo) -> Self {
+        Self {
+            device: info.device.clone(),
+            pooling: info.pooling.clone(),
+            normalized: info.normalized,
+            weights_sha256: info.weights_sha256.clone(),
+            preprocessing: info.preprocessing.clone(),
+            ..Self::current()
+        }
+    }
+}
+
+/// The vector of a stored embedding
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredVector {
+    Dense(Array1<f32>),
+    Sparse(SparseEmbedding),
+}
+
+/// One embedding of a saved collection, with the text and record fields stored alongside it
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredEmbedding {
+    pub vector: StoredVector,
+    /// Original text, or the image path for image embeddings
+    pub text: Option<String>,
+    /// Caller's identifier of the record, such as a CSV id column
+    pub id: Option<String>,
+    /// Fields carried over from the input record
+    pub metadata: HashMap<String, String>,
+    /// Isolated collection the embedding belongs to in a file shared by several, such as a user or project
+    pub namespace: Option<String>,
+    pub modality: Modality,
+    /// When the embedding was created, in seconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+impl StoredEmbedding {
+    /// A dense text embedding created now
+    pub fn dense(embedding: Array1<f32>, text: Option<String>) -> Self {
+        Self {
+            vector: StoredVector::Dense(embedding),
+            text,
+            id: None,
+            metadata: HashMap::new(),
+            namespace: None,
+            modality: Modality::Text,
+            timestamp: crate::utils::reproducibility::timestamp(),
+        }
+    }
+
+    /// The dense vector, None for sparse embeddings
+    pub fn dense_vector(&self) -> Option<&Array1<f32>> {
+        match &self.vector {
+            StoredVector::Dense(embedding) => Some(embedding),
+            StoredVector::Sparse(_) => None,
+        }
+    }
+}
+
+/// A saved embedding file: the model that made it, its embeddings and how they were produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredCollection {
+    pub model_name: String,
+    pub model_version: String,
+    /// Dimension of each dense vector, or the vocabulary size of sparse ones
+    pub dimension: usize,
+    pub embeddings: Vec<StoredEmbedding>,
+    /// None for files written before provenance was recorded
+    pub provenance: Option<Provenance>,
+}
+
+impl StoredCollection {
+    /// An empty collection for a model's embeddings, with its provenance
+    pub fn for_model(info: &ModelInfo) -> Self {
+        Self {
+            model_name: info.name.clone(),
+            model_version: info.version.clone(),
+            dimension: info.dimension,
+            embeddings: Vec::new(),
+            provenance: Some(Provenance::from_model_info(info)),
+        }
+    }
+
+    /// Reads a collection from a local path or object storage URL
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&storage::read(path)?)
+    }
+
+    /// Writes the collection to a local path or object storage URL
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        storage::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Decodes a collection from its protobuf encoding
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let collection: proto::EmbeddingCollection = prost::Message::decode(bytes)?;
+        collection.try_into()
+    }
+
+    /// Encodes the collection as protobuf
+    pub fn to_bytes(&self) -> Vec<u8> {
+        prost::Message::encode_to_vec(&proto::EmbeddingCollection::from(self))
+    }
+}
+
+impl From<proto::Provenance> for Provenance {
+    fn from(provenance: proto::Provenance) -> Self {
+        let optional = |value: String| (!value.is_empty()).then_some(value);
+        Self {
+            crate_version: provenance.crate_version,
+            device: optional(provenance.device),
+            pooling: optional(provenance.pooling),
+            normalized: provenance.normalized,
+            weights_sha256: optional(provenance.weights_sha256),
+            preprocessing: provenance.preprocessing,
+            environment: provenance.environment,
+        }
+    }
+}
+
+impl From<&Provenance> for proto::Provenance {
+    fn from(provenance: &Provenance) -> Self {
+        Self {
+            crate_version: provenance.crate_version.clone(),
+            device: provenance.device.clone().unwrap_or_default(),
+            pooling: provenance.pooling.clone().unwrap_or_default(),
+            normalized: provenance.normalized,
+            weights_sha256: provenance.weights_sha256.clone().unwrap_or_default(),
+            preprocessing: provenance.preprocessing.clone(),
+            environment: provenance.environment.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::Embedding> for StoredEmbedding {
+    type Error = crate::Error;
+
+    fn try_from(embedding: proto::Embedding) -> Result<Self> {
+        let modality = match embedding.modality() {
+            proto::Modality::Text => Modality::Text,
+            proto::Modality::Image => Modality::Image,
+        };
+        let vector = match embedding.sparse {
+            Some(sparse) if sparse.indices.len() != sparse.values.len() => {
+                return Err(err!("Sparse embedding has {} indices but {} values", sparse.indices.len(), sparse.values.len()));
+            }
+            Some(sparse) => StoredVector::Sparse(SparseEmbedding { indices: sparse.indices, values: sparse.values }),
+            None => StoredVector::Dense(Array1::from(embedding.values)),
+        };
+        let optional = |value: String| (!value.is_empty()).then_some(value);
+        Ok(Self {
+            vector,
+            text: optional(embedding.text),
+            id: optional(embedding.id),
+            metadata: embedding.metadata.into_iter().collect(),
+            namespace: optional(embedding.namespace),
+            modality,
+            timestamp: embedding.timestamp,
+        })
+    }
+}
+
+impl From<&StoredEmbedding> for proto::Embedding {
+    fn from(embedding: &StoredEmbedding) -> Self {
+        let (values, sparse) = match &embedding.vector {
+            StoredVector::Dense(values) => (values.to_vec(), None),
+            StoredVector::Sparse(sparse) => (Vec::new(), Some(proto::SparseVector {
+                indices: sparse.indices.clone(),
+                values: sparse.values.clone(),
+            })),
+        };
+        let mut stored = Self {
+            values,
+            text: embedding.text.clone().unwrap_or_default(),
+            timestamp: embedding.timestamp,
+            sparse,
+            id: embedding.id.clone().unwrap_or_default(),
+            // Sorted so the same embedding always encodes to the same bytes
+            metadata: embedding.metadata.iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+            namespace: embedding.namespace.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+        stored.set_modality(match embedding.modality {
+            Modality::Text => proto::Modality::Text,
+            Modality::Image => proto::Modality::Image,
+        });
+        stored
+    }
+}
+
+impl TryFrom<proto::EmbeddingCollection> for StoredCollection {
+    type Error = crate::Error;
+
+    fn try_from(collection: proto::EmbeddingCollection) -> Result<Self> {
+        Ok(Self {
+            model_name: collection.model_name,
+            model_version: collection.model_version,
+            dimension: collection.dimension.max(0) as usize,
+            embeddings: collection.embeddings.into_iter().map(StoredEmbedding::try_from).collect::<Result<_>>()?,
+            provenance: collection.provenance.map(Provenance::from),
+        })
+    }
+}
+
+impl From<&StoredCollection> for proto::EmbeddingCollection {
+    fn from(collection: &StoredCollection) -> Self {
+        Self {
+            embeddings: collection.embeddings.iter().map(proto::Embedding::from).collect(),
+            model_name: collection.model_name.clone(),
+            model_version: collection.model_version.clone(),
+            dimension: collection.dimension as i32,
+            provenance: collection.provenance.as_ref().map(proto::Provenance::from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_round_trips_through_protobuf() -> Result<()> {
+        let info = ModelInfo { device: Some("mps".to_string()), normalized: true, ..ModelInfo::new("model", "2", 2) };
+        let mut collection = StoredCollection::for_model(&info);
+        collection.embeddings.push(StoredEmbedding {
+            id: Some("doc-1".to_string()),
+            metadata: HashMap::from([("source".to_string(), "a.md".to_string())]),
+            namespace: Some("team-a".to_string()),
+            ..StoredEmbedding::dense(Array1::from(vec![0.6, 0.8]), Some("first".to_string()))
+        });
+        collection.embeddings.push(StoredEmbedding {
+            vector: StoredVector::Sparse(SparseEmbedding::from_pairs([(7, 0.5)])),
+            modality: Modality::Image,
+            ..StoredEmbedding::dense(Array1::zeros(0), None)
+        });
+
+        let decoded = StoredCollection::from_bytes(&collection.to_bytes())?;
+        assert_eq!(decoded, collection);
+        assert_eq!(decoded.provenance.as_ref().map(|p| p.crate_version.as_str()), Some(crate::VERSION));
+        assert_eq!(decoded.embeddings[0].dense_vector(), Some(&Array1::from(vec![0.6, 0.8])));
+        assert!(decoded.embeddings[1].dense_vector().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use super::{StoredCollection, StoredEmbedding};
+use crate::embedding::ModelInfo;
+use crate::error::{err, Context, Result};
+use crate::utils::{self, storage};
+use crate::search::Filter;
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Lists the shards of a sharded collection, stored as JSON next to them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShardManifest {
+    pub model_name: String,
+    pub model_version: String,
+    pub dimension: usize,
+    /// Embeddings across all shards
+    pub embeddings: usize,
+    pub shards: Vec<ShardEntry>,
+}
+
+/// One shard file of a sharded collection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShardEntry {
+    /// File name, relative to the manifest's directory
+    pub file: String,
+    pub embeddings: usize,
+}
+
+/// A match found in a sharded collection, with the stored embedding it matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardHit {
+    /// Position of the match across all shards, in the order they were written
+    pub index: usize,
+    /// Cosine similarity between the query and the match
+    pub score: f32,
+    pub embedding: StoredEmbedding,
+}
+
+/// Writes a collection as numbered shard files, `corpus.pb.000`, `corpus.pb.001` and so on, and a manifest
+///
+/// Embeddings are held in memory only until a shard is full. The manifest, `corpus.pb.manifest.json`, is
+/// rewritten after every shard, so an interrupted run leaves the shards written so far readable.
+pub struct ShardWriter {
+    path: PathBuf,
+    shard_size: usize,
+    template: StoredCollection,
+    pending: Vec<StoredEmbedding>,
+    manifest: ShardManifest,
+}
+
+impl ShardWriter {
+    /// Starts a sharded collection of a model's embeddings at `path`, with at most `shard_size` per shard
+    pub fn create(path: impl Into<PathBuf>, info: &ModelInfo, shard_size: usize) -> Result<Self> {
+        if shard_size == 0 {
+            return Err(err!("Shards must hold at least one embedding"));
+        }
+        let template = StoredCollection::for_model(info);
+        let manifest = ShardManifest {
+            model_name: info.name.clone(),
+            model_version: info.version.clone(),
+            dimension: info.dimension,
+            embeddings: 0,
+            shards: Vec::new(),
+        };
+        Ok(Self { path: path.into(), shard_size, template, pending: Vec::new(), manifest })
+    }
+
+    /// Adds an embedding, writing out the current shard once it is full
+    pub fn push(&mut self, embedding: StoredEmbedding) -> Result<()> {
+        self.pending.push(embedding);
+        if self.pending.len() >= self.shard_size {
+            self.write_shard()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last, partly filled shard and returns the final manifest
+    pub fn finish(mut self) -> Result<ShardManifest> {
+        if !self.pending.is_empty() || self.manifest.shards.is_empty() {
+            self.write_shard()?;
+        }
+        Ok(self.manifest)
+    }
+
+    fn write_shard(&mut self) -> Result<()> {
+        let location = shard_path(&self.path, self.manifest.shards.len());
+        let shard = StoredCollection { embeddings: std::mem::take(&mut self.pending), ..self.template.clone() };
+        shard.save(&location)?;
+        tracing::debug!("Wrote {} embeddings to {}", shard.embeddings.len(), location.display());
+
+        self.manifest.embeddings += shard.embeddings.len();
+        self.manifest.shards.push(ShardEntry {
+            file: location.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            embeddings: shard.embeddings.len(),
+        });
+        storage::write(manifest_path(&self.path), serde_json::to_vec_pretty(&self.manifest)?)?;
+        Ok(())
+    }
+}
+
+/// A sharded collection opened through its manifest, shards are read one at a time as they are needed
+#[derive(Debug, Clone)]
+pub struct ShardedCollection {
+    location: PathBuf,
+    manifest: ShardManifest,
+}
+
+impl ShardedCollection {
+    /// Opens the collection written to `path`, which may also name the manifest itself
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let location = if path.to_string_lossy().ends_with(MANIFEST_SUFFIX) { path.to_path_buf() } else { manifest_path(path) };
+        let bytes = storage::read(&location)?;
+        let manifest = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Invalid shard manifest {}", location.display()))?;
+        Ok(Self { location, manifest })
+    }
+
+    pub fn manifest(&self) -> &ShardManifest {
+        &self.manifest
+    }
+
+    /// Embeddings across all shards
+    pub fn len(&self) -> usize {
+        self.manifest.embeddings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manifest.embeddings == 0
+    }
+
+    /// Fails with `Error::IncompatibleEmbeddings` unless the manifest's model name, version and dimension are the model's
+    pub fn check_model(&self, info: &ModelInfo) -> Result<()> {
+        let mut mismatches = Vec::new();
+        if self.manifest.model_name != info.name {
+            mismatches.push(format!("made by '{}'", self.manifest.model_name));
+        }
+        if self.manifest.model_version != info.version {
+            mismatches.push(format!("version '{}' instead of '{}'", self.manifest.model_version, info.version));
+        }
+        if self.manifest.dimension != info.dimension {
+            mismatches.push(format!("{} dimensions instead of {}", self.manifest.dimension, info.dimension));
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(crate::Error::IncompatibleEmbeddings {
+            path: self.location.display().to_string(),
+            model: info.name.clone(),
+            mismatches: mismatches.join(", "),
+        })
+    }
+
+    /// Reads the shards in order, each only when the iterator reaches it
+    pub fn shards(&self) -> impl Iterator<Item = Result<StoredCollection>> + '_ {
+        self.manifest.shards.iter().map(|entry| {
+            let collection = StoredCollection::load(self.location.with_file_name(&entry.file))?;
+            if collection.embeddings.len() != entry.embeddings {
+                return Err(err!(
+                    "Shard {} holds {} embeddings, the manifest lists {}", entry.file, collection.embeddings.len(), entry.embeddings
+                ));
+            }
+            Ok(collection)
+        })
+    }
+
+    /// Returns the `k` dense embeddings most similar to the query, best first
+    ///
+    /// Only one shard and the best `k` embeddings so far are in memory at a time.
+    pub fn search(&self, query: &Array1<f32>, k: usize) -> Result<Vec<ShardHit>> {
+        self.search_where(query, k, |_| true)
+    }
+
+    /// Returns the `k` embeddings most similar to the query among those whose metadata matches `filter`, best first
+    pub fn search_filtered(&self, query: &Array1<f32>, k: usize, filter: &Filter) -> Result<Vec<ShardHit>> {
+        self.search_where(query, k, |embedding| filter.matches(&embedding.metadata))
+    }
+
+    fn search_where(&self, query: &Array1<f32>, k: usize, keep: impl Fn(&StoredEmbedding) -> bool) -> Result<Vec<ShardHit>> {
+        let mut best: Vec<ShardHit> = Vec::with_capacity(k + 1);
+        let mut offset = 0;
+        for shard in self.shards() {
+            let shard = shard?;
+            let count = shard.embeddings.len();
+            for (position, embedding) in shard.embeddings.into_iter().enumerate() {
+                let score = match embedding.dense_vector() {
+                    Some(vector) if vector.len() == query.len() && keep(&embedding) => utils::cosine_similarity(query, vector),
+                    _ => continue,
+                };
+                // Ties keep the earlier embedding first, as a stable sort of all scores would
+                let at = best.partition_point(|hit| hit.score >= score);
+                if at < k {
+                    best.insert(at, ShardHit { index: offset + position, score, embedding });
+                    best.truncate(k);
+                }
+            }
+            offset += count;
+        }
+        Ok(best)
+    }
+}
+
+/// Whether `path` was written as a sharded collection, checked on local disk only
+pub fn is_sharded(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    !storage::is_remote(path) && (path.to_string_lossy().ends_with(MANIFEST_SUFFIX) || manifest_path(path).is_file())
+}
+
+/// Location of shard `index` of the collection at `path`
+pub fn shard_path(path: &Path, index: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{:03}", path.display(), index))
+}
+
+/// Location of the manifest of the collection at `path`
+pub fn manifest_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}{}", path.display(), MANIFEST_SUFFIX))
+}
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_writes_shards_and_searches_across_them() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust_embed_shards_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("corpus.pb");
+
+        let mut writer = ShardWriter::create(&path, &ModelInfo::new("model", "1", 2), 2)?;
+        for (i, angle) in [0.0f32, 0.5, 1.0, 1.5, 0.1].into_iter().enumerate() {
+            let vector = Array1::from(vec![angle.cos(), angle.sin()]);
+            let metadata = HashMap::from([("part".to_string(), (i % 2).to_string())]);
+            writer.push(StoredEmbedding { metadata, ..StoredEmbedding::dense(vector, Some(format!("text {}", i))) })?;
+        }
+        let manifest = writer.finish()?;
+        assert_eq!(manifest.embeddings, 5);
+        assert_eq!(manifest.shards.iter().map(|shard| shard.file.as_str()).collect::<Vec<_>>(), ["corpus.pb.000", "corpus.pb.001", "corpus.pb.002"]);
+        assert!(is_sharded(&path));
+
+        let collection = ShardedCollection::open(&path)?;
+        assert_eq!(collection.manifest(), &manifest);
+        let hits = collection.search(&Array1::from(vec![1.0, 0.0]), 3)?;
+        assert_eq!(hits.iter().map(|hit| hit.index).collect::<Vec<_>>(), vec![0, 4, 1]);
+        assert_eq!(hits[1].embedding.text.as_deref(), Some("text 4"));
+        let hits = collection.search_filtered(&Array1::from(vec![1.0, 0.0]), 3, &Filter::parse("part == 1")?)?;
+        assert_eq!(hits.iter().map(|hit| hit.index).collect::<Vec<_>>(), vec![1, 3]);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
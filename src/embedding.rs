@@ -1,8 +1,13 @@
-use anyhow::Result;
-use ndarray::Array1;
+use crate::models::ModelConfig;
+use crate::error::{err, Error, Result};
+use ndarray::{Array1, Array2};
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// The Embedder trait defines the interface for text embedding implementations.
 pub trait Embedder: Clone + Send + Sync {
@@ -11,31 +16,60 @@ pub trait Embedder: Clone + Send + Sync {
     
     /// Embeds multiple text strings into vector representations.
     fn embed_batch(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
-        // Default implementation that uses parallel processing for large batches
-        if texts.len() > 10 {
-            // Parallel implementation for larger batches
-            texts.par_iter()
-                .map(|text| self.embed_text(text))
-                .collect()
-        } else {
-            // Sequential processing for small batches
-            texts.iter()
-                .map(|text| self.embed_text(text))
-                .collect()
+        // Default implementation that embeds large batches in parallel where threads are available
+        self.embed_batch_detailed(texts).into_iter().collect()
+    }
+    
+    /// Embeds multiple texts into one contiguous matrix with a row per text, ready for BLAS operations.
+    fn embed_batch_matrix(&self, texts: &[String]) -> Result<Array2<f32>> {
+        // Default implementation copies the separate embeddings, models batching on their own write rows directly
+        let mut matrix = Array2::zeros((texts.len(), self.dimension()));
+        for (mut row, embedding) in matrix.rows_mut().into_iter().zip(self.embed_batch(texts)?) {
+            if embedding.len() != row.len() {
+                return Err(Error::DimensionMismatch { expected: row.len(), actual: embedding.len() });
+            }
+            row.assign(&embedding);
         }
+        Ok(matrix)
     }
     
-    /// Computes the cosine similarity between two embedding vectors.
-    fn cosine_similarity(&self, vec1: &Array1<f32>, vec2: &Array1<f32>) -> f32 {
-        let dot_product = vec1.dot(vec2);
-        let norm1 = vec1.dot(vec1).sqrt();
-        let norm2 = vec2.dot(vec2).sqrt();
-        
-        if norm1 * norm2 == 0.0 {
-            return 0.0;
+    /// Embeds multiple texts into an Arrow list array of `dimension` floats per text, for DataFusion and Polars.
+    #[cfg(feature = "arrow")]
+    fn embed_batch_arrow(&self, texts: &[String]) -> Result<arrow_array::FixedSizeListArray> {
+        crate::utils::arrow::to_fixed_size_list(self.embed_batch_matrix(texts)?)
+    }
+    
+    /// Embeds multiple texts, returning one result per text in input order so failures don't drop or shift embeddings.
+    fn embed_batch_detailed(&self, texts: &[String]) -> Vec<Result<Array1<f32>>> {
+        // Threads are unavailable on wasm32
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        {
+            if texts.len() > 10 {
+                return texts.par_iter()
+                    .map(|text| self.embed_text(text))
+                    .collect();
+            }
         }
         
-        dot_product / (norm1 * norm2)
+        texts.iter()
+            .map(|text| self.embed_text(text))
+            .collect()
+    }
+    
+    /// Embeds multiple texts, handling texts that fail as `policy` says.
+    fn embed_batch_with_policy(&self, texts: &[String], policy: BatchErrorPolicy) -> Result<PolicyBatch> {
+        if policy == BatchErrorPolicy::FailFast {
+            // Keep the model's own batch path when any failure aborts anyway
+            let embeddings: Vec<_> = self.embed_batch(texts)?.into_iter().map(Some).collect();
+            let summary = BatchSummary { succeeded: embeddings.len(), failures: Vec::new() };
+            return Ok(PolicyBatch { embeddings, summary });
+        }
+        policy.apply(self.embed_batch_detailed(texts), self.dimension())
+    }
+    
+    /// Computes the cosine similarity between two embedding vectors.
+    fn cosine_similarity(&self, vec1: &Array1<f32>, vec2: &Array1<f32>) -> f32 {
+        crate::utils::cosine_similarity(vec1, vec2)
     }
     
     /// Returns the name of the model used by this embedder
@@ -47,6 +81,16 @@ pub trait Embedder: Clone + Send + Sync {
     /// Returns the dimension of the embeddings produced by this model
     fn dimension(&self) -> usize;
     
+    /// Describes the loaded model, for services exposing its capabilities.
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo::new(self.model_name(), self.model_version(), self.dimension())
+    }
+    
+    /// Number of tokens the model sees for a text, estimated from its words for models without a tokenizer
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        Ok(crate::utils::estimate_tokens(text))
+    }
+    
     /// Save model to disk
     fn save_model<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         // Default implementation does nothing
@@ -65,6 +109,174 @@ pub trait Embedder: Clone + Send + Sync {
     }
 }
 
+/// Capability metadata of a loaded model, fields the model doesn't know are left empty
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelInfo {
+    pub name: String,
+    pub version: String,
+    pub dimension: usize,
+    /// Longest input in tokens, longer inputs are truncated
+    pub max_tokens: Option<usize>,
+    /// How token vectors are reduced to one vector, such as "mean" or "cls"
+    pub pooling: Option<String>,
+    /// Whether embeddings are scaled to unit length
+    pub normalized: bool,
+    /// SPDX identifier of the weights' license
+    pub license: Option<String>,
+    /// Where the weights are published
+    pub source_url: Option<String>,
+    /// Device the model runs on, such as "cpu", "mps" or "cuda:0"
+    pub device: Option<String>,
+    /// SHA-256 of the weights file, telling apart exports that share a name and version
+    pub weights_sha256: Option<String>,
+    /// How inputs are prepared before tokenizing, such as lowercasing or an instruction prefix
+    pub preprocessing: BTreeMap<String, String>,
+}
+
+impl ModelInfo {
+    /// Metadata with only the name, version and dimension known
+    pub fn new(name: &str, version: &str, dimension: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            dimension,
+            ..Self::default()
+        }
+    }
+}
+
+/// Stats for the embedder
+#[derive(Debug, Clone, Default)]
+pub struct EmbedderStats {
+    pub embeddings_count: usize,
+    pub total_processing_time: Duration,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+}
+
+/// Approximate memory held by an embedder, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryStats {
+    /// Weights of the loaded models
+    pub model_bytes: u64,
+    /// Cached embeddings and their texts
+    pub cache_bytes: u64,
+    /// Part of `model_bytes` placed on the MPS or CUDA device, None on the CPU
+    pub device_bytes: Option<u64>,
+}
+
+impl MemoryStats {
+    /// Model and cache memory together
+    pub fn total_bytes(&self) -> u64 {
+        self.model_bytes + self.cache_bytes
+    }
+}
+
+impl std::fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mb = |bytes: u64| bytes as f64 / 1_048_576.0;
+        write!(f, "model {:.1} MB, cache {:.1} MB", mb(self.model_bytes), mb(self.cache_bytes))?;
+        if let Some(device_bytes) = self.device_bytes {
+            write!(f, ", {:.1} MB on the device", mb(device_bytes))?;
+        }
+        Ok(())
+    }
+}
+
+/// Successes and failures of a batch from `embed_batch_detailed`
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    /// Number of texts embedded
+    pub succeeded: usize,
+    /// Index and error message of each text that failed
+    pub failures: Vec<(usize, String)>,
+}
+
+impl BatchSummary {
+    /// Summarizes per-text results
+    pub fn from_results<T>(results: &[Result<T>]) -> Self {
+        let mut summary = BatchSummary::default();
+        for (index, result) in results.iter().enumerate() {
+            match result {
+                Ok(_) => summary.succeeded += 1,
+                Err(e) => summary.failures.push((index, e.to_string())),
+            }
+        }
+        summary
+    }
+    
+    /// Total number of texts in the batch
+    pub fn total(&self) -> usize {
+        self.succeeded + self.failures.len()
+    }
+}
+
+impl std::fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "embedded {} of {} texts", self.succeeded, self.total())?;
+        for (index, error) in &self.failures {
+            write!(f, "\n  text {}: {}", index, error)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a batch does when some of its texts fail to embed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchErrorPolicy {
+    /// The first failure fails the whole batch
+    #[default]
+    FailFast,
+    /// Failed texts get no embedding and are listed in the summary
+    SkipAndReport,
+    /// Failed texts get a zero vector and are listed in the summary
+    ReplaceWithZeros,
+}
+
+/// Embeddings of a batch under a `BatchErrorPolicy`, in input order
+#[derive(Debug, Clone, Default)]
+pub struct PolicyBatch {
+    /// One entry per text, None where the text was skipped
+    pub embeddings: Vec<Option<Array1<f32>>>,
+    /// Which texts failed
+    pub summary: BatchSummary,
+}
+
+impl BatchErrorPolicy {
+    /// Applies the policy to per-text results, zero vectors have `dimension` components
+    pub fn apply(self, results: Vec<Result<Array1<f32>>>, dimension: usize) -> Result<PolicyBatch> {
+        let summary = BatchSummary::from_results(&results);
+        let mut embeddings = Vec::with_capacity(results.len());
+        for result in results {
+            embeddings.push(match (result, self) {
+                (Ok(embedding), _) => Some(embedding),
+                (Err(e), BatchErrorPolicy::FailFast) => return Err(e),
+                (Err(_), BatchErrorPolicy::SkipAndReport) => None,
+                (Err(_), BatchErrorPolicy::ReplaceWithZeros) => Some(Array1::zeros(dimension)),
+            });
+        }
+        Ok(PolicyBatch { embeddings, summary })
+    }
+}
+
+/// Key of a cached embedding: the identity of the model configuration that made it, and the text
+///
+/// The identity changes with anything that changes the vector of a text, such as the weights or preprocessing,
+/// so a cache never serves a vector another configuration made.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub model: Arc<str>,
+    pub text: String,
+}
+
+impl CacheKey {
+    pub fn new(model: &Arc<str>, text: &str) -> Self {
+        Self { model: model.clone(), text: text.to_string() }
+    }
+}
+
 /// A trait for embedders that can be cached in memory
 pub trait CachedEmbedder: Embedder {
     /// Precompute and cache embeddings for a set of texts
@@ -81,7 +293,7 @@ pub trait CachedEmbedder: Embedder {
 }
 
 /// A struct to hold both the text and its embedding
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EmbeddedText {
     /// The original text
     pub text: String,
@@ -99,19 +311,449 @@ impl EmbeddedText {
         }
     }
     
+    /// Embeds a text with `embedder`
+    pub fn embed<E: Embedder>(embedder: &E, text: impl Into<String>) -> Result<Self> {
+        let text = text.into();
+        let embedding = embedder.embed_text(&text)?;
+        Ok(Self::new(text, embedding))
+    }
+    
+    /// Embeds texts as one batch, in input order
+    pub fn embed_all<E: Embedder>(embedder: &E, texts: &[String]) -> Result<Vec<Self>> {
+        let embeddings = embedder.embed_batch(texts)?;
+        Ok(texts.iter().cloned().zip(embeddings).map(|(text, embedding)| Self::new(text, embedding)).collect())
+    }
+    
     /// Calculate cosine similarity with another EmbeddedText
     pub fn similarity(&self, other: &EmbeddedText) -> f32 {
-        let vec1 = &*self.embedding;
-        let vec2 = &*other.embedding;
+        crate::utils::cosine_similarity(&self.embedding, &other.embedding)
+    }
+    
+    /// Returns the `k` texts most similar to a query embedding, best first, with their cosine similarity
+    ///
+    /// Texts whose embedding has another dimension than the query are skipped.
+    pub fn top_k<'a>(items: &'a [EmbeddedText], query: &Array1<f32>, k: usize) -> Vec<(&'a EmbeddedText, f32)> {
+        let mut hits: Vec<crate::search::SearchHit> = items.iter()
+            .enumerate()
+            .filter(|(_, item)| item.embedding.len() == query.len())
+            .map(|(index, item)| crate::search::SearchHit { index, score: crate::utils::cosine_similarity(query, &item.embedding) })
+            .collect();
+        crate::search::sort_hits(&mut hits);
+        hits.into_iter().take(k).map(|hit| (&items[hit.index], hit.score)).collect()
+    }
+    
+    /// Embeds a query with `embedder` and returns the `k` most similar texts, best first
+    pub fn search<'a, E: Embedder>(embedder: &E, items: &'a [EmbeddedText], query: &str, k: usize) -> Result<Vec<(&'a EmbeddedText, f32)>> {
+        let query = embedder.embed_text(query)?;
+        Ok(Self::top_k(items, &query, k))
+    }
+}
+
+#[cfg(feature = "proto-store")]
+impl From<&EmbeddedText> for crate::collection::StoredEmbedding {
+    fn from(item: &EmbeddedText) -> Self {
+        crate::collection::StoredEmbedding::dense(item.embedding.as_ref().clone(), Some(item.text.clone()))
+    }
+}
+
+/// Sparse embeddings become empty vectors, since `EmbeddedText` holds dense ones
+#[cfg(feature = "proto-store")]
+impl From<crate::collection::StoredEmbedding> for EmbeddedText {
+    fn from(stored: crate::collection::StoredEmbedding) -> Self {
+        let embedding = match stored.vector {
+            crate::collection::StoredVector::Dense(embedding) => embedding,
+            crate::collection::StoredVector::Sparse(_) => Array1::zeros(0),
+        };
+        EmbeddedText::new(stored.text.unwrap_or_default(), embedding)
+    }
+}
+
+/// A sparse embedding: non-zero weights of vocabulary terms, sorted by term id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseEmbedding {
+    /// Term ids with a non-zero weight, ascending
+    pub indices: Vec<u32>,
+    
+    /// Weight of each term in `indices`
+    pub values: Vec<f32>,
+}
+
+impl SparseEmbedding {
+    /// Create a sparse embedding from (term id, weight) pairs, dropping zero weights
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, f32)>) -> Self {
+        let mut pairs: Vec<(u32, f32)> = pairs.into_iter().filter(|(_, w)| *w != 0.0).collect();
+        pairs.sort_by_key(|(index, _)| *index);
+        pairs.dedup_by_key(|(index, _)| *index);
+        
+        let (indices, values) = pairs.into_iter().unzip();
+        Self { indices, values }
+    }
+    
+    /// Number of non-zero terms
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+    
+    /// Returns true if no term has a weight
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    
+    /// Dot product with another sparse embedding
+    pub fn dot(&self, other: &SparseEmbedding) -> f32 {
+        let (mut i, mut j, mut sum) = (0, 0, 0.0);
+        while i < self.indices.len() && j < other.indices.len() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    sum += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        sum
+    }
+    
+    /// The `n` highest weighted (term id, weight) pairs
+    pub fn top_terms(&self, n: usize) -> Vec<(u32, f32)> {
+        let mut pairs: Vec<(u32, f32)> = self.indices.iter().copied().zip(self.values.iter().copied()).collect();
+        pairs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        pairs.truncate(n);
+        pairs
+    }
+}
+
+/// How an ensemble combines the outputs of its members
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnsembleStrategy {
+    /// Concatenate the weighted member embeddings, dimensions add up
+    Concatenate,
+    
+    /// Average the member embeddings by weight, all members must share a dimension
+    WeightedAverage,
+}
+
+/// An embedder that combines several models into one embedding
+#[derive(Clone)]
+pub struct EnsembleEmbedder<E: Embedder> {
+    members: Vec<(E, f32)>,
+    strategy: EnsembleStrategy,
+    name: String,
+    version: String,
+}
+
+impl<E: Embedder> EnsembleEmbedder<E> {
+    /// Create an empty ensemble with the given combination strategy
+    pub fn new(strategy: EnsembleStrategy) -> Self {
+        Self {
+            members: Vec::new(),
+            strategy,
+            name: String::new(),
+            version: String::new(),
+        }
+    }
+    
+    /// Add a member model with a weight
+    pub fn with_member(mut self, embedder: E, weight: f32) -> Result<Self> {
+        if weight <= 0.0 {
+            return Err(err!("Ensemble weights must be positive, got {}", weight));
+        }
+        if let (EnsembleStrategy::WeightedAverage, Some((first, _))) = (self.strategy, self.members.first()) {
+            if first.dimension() != embedder.dimension() {
+                return Err(Error::DimensionMismatch { expected: first.dimension(), actual: embedder.dimension() });
+            }
+        }
+        
+        self.members.push((embedder, weight));
+        self.name = format!(
+            "ensemble({})",
+            self.members.iter().map(|(e, _)| e.model_name()).collect::<Vec<_>>().join("+")
+        );
+        self.version = self.members.iter().map(|(e, _)| e.model_version()).collect::<Vec<_>>().join("+");
+        Ok(self)
+    }
+    
+    /// The member models and their weights
+    pub fn members(&self) -> &[(E, f32)] {
+        &self.members
+    }
+    
+    /// Combine one embedding per member into the ensemble embedding
+    fn combine(&self, parts: Vec<Array1<f32>>) -> Array1<f32> {
+        let total_weight: f32 = self.members.iter().map(|(_, weight)| weight).sum();
         
-        let dot_product = vec1.dot(vec2);
-        let norm1 = vec1.dot(vec1).sqrt();
-        let norm2 = vec2.dot(vec2).sqrt();
+        let mut combined = match self.strategy {
+            EnsembleStrategy::Concatenate => {
+                let values: Vec<f32> = parts.iter()
+                    .zip(&self.members)
+                    .flat_map(|(part, (_, weight))| {
+                        let mut part = part.clone();
+                        crate::utils::normalize(&mut part);
+                        (part * (*weight / total_weight).sqrt()).to_vec()
+                    })
+                    .collect();
+                Array1::from(values)
+            }
+            EnsembleStrategy::WeightedAverage => {
+                let mut sum = Array1::<f32>::zeros(ModelConfig::dimension(self));
+                for (part, (_, weight)) in parts.iter().zip(&self.members) {
+                    let mut part = part.clone();
+                    crate::utils::normalize(&mut part);
+                    sum.scaled_add(*weight / total_weight, &part);
+                }
+                sum
+            }
+        };
         
-        if norm1 * norm2 == 0.0 {
-            return 0.0;
+        crate::utils::normalize(&mut combined);
+        combined
+    }
+}
+
+impl<E: Embedder> Embedder for EnsembleEmbedder<E> {
+    fn embed_text(&self, text: &str) -> Result<Array1<f32>> {
+        if self.members.is_empty() {
+            return Err(err!("Ensemble has no member models"));
         }
         
-        dot_product / (norm1 * norm2)
+        let parts = self.members.iter()
+            .map(|(embedder, _)| embedder.embed_text(text))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.combine(parts))
+    }
+    
+    fn embed_batch(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
+        if self.members.is_empty() {
+            return Err(err!("Ensemble has no member models"));
+        }
+        
+        // Let each member batch its own inputs, then combine per text
+        let mut member_outputs = Vec::with_capacity(self.members.len());
+        for (embedder, _) in &self.members {
+            let outputs = embedder.embed_batch(texts)?;
+            if outputs.len() != texts.len() {
+                return Err(Error::other(format!(
+                    "Ensemble member {} returned {} embeddings for {} texts",
+                    embedder.model_name(), outputs.len(), texts.len()
+                )));
+            }
+            member_outputs.push(outputs);
+        }
+        
+        Ok((0..texts.len())
+            .map(|i| self.combine(member_outputs.iter().map(|outputs| outputs[i].clone()).collect()))
+            .collect())
+    }
+    
+    fn model_name(&self) -> &str {
+        &self.name
     }
-} 
\ No newline at end of file
+    
+    fn model_version(&self) -> &str {
+        &self.version
+    }
+    
+    fn dimension(&self) -> usize {
+        ModelConfig::dimension(self)
+    }
+    
+    fn model_info(&self) -> ModelInfo {
+        // Inputs go to every member, so the shortest limit applies
+        ModelInfo {
+            max_tokens: self.members.iter().filter_map(|(embedder, _)| embedder.model_info().max_tokens).min(),
+            normalized: true,
+            ..ModelInfo::new(&self.name, &self.version, ModelConfig::dimension(self))
+        }
+    }
+}
+
+impl<E: Embedder> ModelConfig for EnsembleEmbedder<E> {
+    fn dimension(&self) -> usize {
+        match self.strategy {
+            EnsembleStrategy::Concatenate => self.members.iter().map(|(e, _)| e.dimension()).sum(),
+            EnsembleStrategy::WeightedAverage => self.members.first().map(|(e, _)| e.dimension()).unwrap_or(0),
+        }
+    }
+    
+    fn model_name(&self) -> &str {
+        &self.name
+    }
+    
+    fn model_version(&self) -> &str {
+        &self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    
+    /// Embeds every text to a fixed vector
+    #[derive(Clone)]
+    struct FixedEmbedder(Array1<f32>);
+    
+    impl Embedder for FixedEmbedder {
+        fn embed_text(&self, _text: &str) -> Result<Array1<f32>> {
+            Ok(self.0.clone())
+        }
+        
+        fn model_name(&self) -> &str {
+            "fixed"
+        }
+        
+        fn model_version(&self) -> &str {
+            "1"
+        }
+        
+        fn dimension(&self) -> usize {
+            self.0.len()
+        }
+    }
+    
+    /// Fails on empty texts
+    #[derive(Clone)]
+    struct NonEmptyEmbedder;
+    
+    impl Embedder for NonEmptyEmbedder {
+        fn embed_text(&self, text: &str) -> Result<Array1<f32>> {
+            if text.is_empty() {
+                return Err(err!("Empty text"));
+            }
+            Ok(array![text.len() as f32])
+        }
+        
+        fn model_name(&self) -> &str {
+            "non-empty"
+        }
+        
+        fn model_version(&self) -> &str {
+            "1"
+        }
+        
+        fn dimension(&self) -> usize {
+            1
+        }
+    }
+    
+    #[test]
+    fn test_embed_batch_detailed_keeps_order() {
+        let texts: Vec<String> = (0..20).map(|i| if i % 7 == 3 { String::new() } else { "x".repeat(i) }).collect();
+        let results = NonEmptyEmbedder.embed_batch_detailed(&texts);
+        assert_eq!(results.len(), texts.len());
+        for (text, result) in texts.iter().zip(&results) {
+            match result {
+                Ok(embedding) => assert_eq!(embedding[0], text.len() as f32),
+                Err(_) => assert!(text.is_empty()),
+            }
+        }
+        
+        let summary = BatchSummary::from_results(&results);
+        assert_eq!(summary.failures.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![0, 3, 10, 17]);
+        assert_eq!(summary.total(), 20);
+    }
+    
+    #[test]
+    fn test_batch_error_policies() -> Result<()> {
+        let texts = vec!["ab".to_string(), String::new(), "abc".to_string()];
+        assert!(NonEmptyEmbedder.embed_batch_with_policy(&texts, BatchErrorPolicy::FailFast).is_err());
+        
+        let skipped = NonEmptyEmbedder.embed_batch_with_policy(&texts, BatchErrorPolicy::SkipAndReport)?;
+        assert_eq!(skipped.embeddings, vec![Some(array![2.0]), None, Some(array![3.0])]);
+        assert_eq!(skipped.summary.failures.len(), 1);
+        assert_eq!(skipped.summary.failures[0].0, 1);
+        
+        let zeroed = NonEmptyEmbedder.embed_batch_with_policy(&texts, BatchErrorPolicy::ReplaceWithZeros)?;
+        assert_eq!(zeroed.embeddings[1], Some(array![0.0]));
+        assert_eq!(zeroed.summary.succeeded, 2);
+        Ok(())
+    }
+    
+    #[test]
+    fn test_embedded_texts_search() -> Result<()> {
+        let texts: Vec<String> = ["a", "abc", "ab"].map(String::from).to_vec();
+        let items = EmbeddedText::embed_all(&NonEmptyEmbedder, &texts)?;
+        assert_eq!(items[1], EmbeddedText::embed(&NonEmptyEmbedder, "abc")?);
+        
+        // One-dimensional embeddings all point the same way, so every text scores 1
+        let hits = EmbeddedText::top_k(&items, &array![1.0], 2);
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|(_, score)| (score - 1.0).abs() < 1e-6));
+        assert!(EmbeddedText::top_k(&items, &array![1.0, 0.0], 2).is_empty());
+        
+        #[cfg(feature = "proto-store")]
+        {
+            let stored = crate::collection::StoredEmbedding::from(&items[2]);
+            assert_eq!((stored.text.as_deref(), stored.dense_vector()), (Some("ab"), Some(&array![2.0])));
+            assert_eq!(EmbeddedText::from(stored), items[2]);
+        }
+        Ok(())
+    }
+    
+    /// Drops the embeddings of texts beyond a limit from its batches
+    #[derive(Clone)]
+    struct TruncatingEmbedder(usize);
+    
+    impl Embedder for TruncatingEmbedder {
+        fn embed_text(&self, _text: &str) -> Result<Array1<f32>> {
+            Ok(array![1.0])
+        }
+        
+        fn embed_batch(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
+            Ok(texts.iter().take(self.0).map(|_| array![1.0]).collect())
+        }
+        
+        fn model_name(&self) -> &str {
+            "truncating"
+        }
+        
+        fn model_version(&self) -> &str {
+            "1"
+        }
+        
+        fn dimension(&self) -> usize {
+            1
+        }
+    }
+    
+    #[test]
+    fn test_ensemble_rejects_short_member_batches() -> Result<()> {
+        let texts: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let ensemble = EnsembleEmbedder::new(EnsembleStrategy::Concatenate)
+            .with_member(TruncatingEmbedder(usize::MAX), 1.0)?
+            .with_member(TruncatingEmbedder(2), 1.0)?;
+        let error = ensemble.embed_batch(&texts).unwrap_err();
+        assert!(matches!(error, Error::Other(_)));
+        assert!(error.to_string().contains("truncating returned 2 embeddings for 3 texts"));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_ensemble_strategies() -> Result<()> {
+        let concatenated = EnsembleEmbedder::new(EnsembleStrategy::Concatenate)
+            .with_member(FixedEmbedder(array![1.0, 0.0]), 1.0)?
+            .with_member(FixedEmbedder(array![0.0, 0.0, 2.0]), 1.0)?;
+        assert_eq!(ModelConfig::dimension(&concatenated), 5);
+        assert_eq!(Embedder::model_name(&concatenated), "ensemble(fixed+fixed)");
+        
+        let embedding = concatenated.embed_text("text")?;
+        let half = 0.5f32.sqrt();
+        assert!((&embedding - &array![half, 0.0, 0.0, 0.0, half]).iter().all(|d| d.abs() < 1e-6));
+        
+        let averaged = EnsembleEmbedder::new(EnsembleStrategy::WeightedAverage)
+            .with_member(FixedEmbedder(array![1.0, 0.0]), 3.0)?
+            .with_member(FixedEmbedder(array![0.0, 1.0]), 1.0)?;
+        let embedding = averaged.embed_text("text")?;
+        assert!(embedding[0] > embedding[1]);
+        assert!((embedding.dot(&embedding) - 1.0).abs() < 1e-6);
+        
+        assert!(EnsembleEmbedder::new(EnsembleStrategy::WeightedAverage)
+            .with_member(FixedEmbedder(array![1.0, 0.0]), 1.0)?
+            .with_member(FixedEmbedder(array![1.0]), 1.0)
+            .is_err());
+        
+        Ok(())
+    }
+}
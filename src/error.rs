@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+/// Errors returned by the embedders and the library's entry points
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("model '{0}' is not initialized, call initialize() first")]
+    ModelNotInitialized(String),
+
+    #[error("failed to download {url}: {source}")]
+    DownloadFailed {
+        url: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("dimension mismatch, expected {expected} but got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+
+    #[error("embeddings in {path} do not match model '{model}': {mismatches}")]
+    IncompatibleEmbeddings { path: String, model: String, mismatches: String },
+
+    #[error("inference timed out after {timeout:?} on {} text(s)", .texts.len())]
+    InferenceTimeout { timeout: std::time::Duration, texts: Vec<String> },
+
+    #[error("{feature} is not supported on {os} ({arch})")]
+    UnsupportedPlatform { feature: String, os: String, arch: String },
+
+    /// Input, configuration or data the library can't use, such as an unknown model name or a malformed file
+    #[error("{0}")]
+    Invalid(String),
+
+    /// A failure of the operation described by `message`
+    #[error("{message}")]
+    Context {
+        message: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[cfg(feature = "proto-store")]
+    #[error("invalid embedding file: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// A failure of a dependency, such as the HTTP client or ONNX Runtime
+    #[error("{0}")]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Result type of the library's public API
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// A download failure for `url`
+    pub fn download_failed(url: impl Into<String>, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::DownloadFailed { url: url.into(), source: source.into() }
+    }
+
+    /// An unsupported feature on the platform this runs on
+    pub fn unsupported_platform(feature: impl Into<String>) -> Self {
+        Error::UnsupportedPlatform {
+            feature: feature.into(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// A failure of a dependency, for use with `map_err`
+    pub fn other(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Other(error.into())
+    }
+
+    /// The message of this error followed by those of its sources, separated by colons
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            report.push_str(": ");
+            report.push_str(&error.to_string());
+            source = error.source();
+        }
+        report
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Error::Other(error)
+    }
+}
+
+/// Failures of the standard library and of dependencies that `?` turns into `Error::Other`
+macro_rules! from_other {
+    ($($(#[$meta:meta])* $error:ty),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            impl From<$error> for Error {
+                fn from(error: $error) -> Self {
+                    Error::Other(Box::new(error))
+                }
+            }
+        )*
+    };
+}
+
+from_other!(
+    std::fmt::Error,
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    std::num::TryFromIntError,
+    std::str::Utf8Error,
+    std::string::FromUtf8Error,
+    std::time::SystemTimeError,
+    std::path::StripPrefixError,
+    std::sync::mpsc::RecvError,
+    toml::de::Error,
+    ndarray::ShapeError,
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    reqwest::Error,
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    zip::result::ZipError,
+    #[cfg(feature = "onnx")]
+    ort::Error,
+    #[cfg(feature = "onnx")]
+    image::ImageError,
+    #[cfg(feature = "torch")]
+    tch::TchError,
+    #[cfg(feature = "torch")]
+    rust_bert::RustBertError,
+    #[cfg(feature = "redis")]
+    redis::RedisError,
+    #[cfg(feature = "duckdb")]
+    duckdb::Error,
+    #[cfg(feature = "polars")]
+    polars::error::PolarsError,
+    #[cfg(feature = "object-store")]
+    object_store::Error,
+);
+
+/// Builds an `Error::Invalid` from a format string
+macro_rules! err {
+    ($($arg:tt)*) => {
+        $crate::error::Error::Invalid(format!($($arg)*))
+    };
+}
+pub(crate) use err;
+
+/// Describes what was being done when a `Result` failed or an `Option` was empty
+pub(crate) trait Context<T> {
+    /// Wraps the error in `Error::Context` with `message`, or turns a missing value into `Error::Invalid`
+    fn context(self, message: impl Display) -> Result<T>;
+
+    /// Like `context`, building the message only on failure
+    fn with_context<M: Display>(self, message: impl FnOnce() -> M) -> Result<T>;
+}
+
+impl<T, E: std::error::Error + Send + Sync + 'static> Context<T> for std::result::Result<T, E> {
+    fn context(self, message: impl Display) -> Result<T> {
+        self.map_err(|error| Error::Context { message: message.to_string(), source: Box::new(error) })
+    }
+
+    fn with_context<M: Display>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|error| Error::Context { message: message().to_string(), source: Box::new(error) })
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, message: impl Display) -> Result<T> {
+        self.ok_or_else(|| Error::Invalid(message.to_string()))
+    }
+
+    fn with_context<M: Display>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.ok_or_else(|| Error::Invalid(message().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_keeps_the_source() {
+        let error = Err::<(), _>(std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"))
+            .context("Failed to read model.bin")
+            .unwrap_err();
+        assert!(matches!(error, Error::Context { .. }));
+        assert_eq!(error.to_string(), "Failed to read model.bin");
+        assert_eq!(error.report(), "Failed to read model.bin: no such file");
+
+        let missing = None::<u8>.with_context(|| format!("No {} found", "tokenizer.json")).unwrap_err();
+        assert!(matches!(missing, Error::Invalid(ref message) if message == "No tokenizer.json found"));
+    }
+
+    #[test]
+    fn test_question_mark_converts_dependency_errors() {
+        fn parse(value: &str) -> Result<u32> {
+            Ok(value.parse::<u32>()?)
+        }
+        assert_eq!(parse("7").unwrap(), 7);
+        assert!(matches!(parse("seven"), Err(Error::Other(_))));
+        assert!(matches!(Error::from(std::io::Error::from(std::io::ErrorKind::NotFound)), Error::Io(_)));
+    }
+}
@@ -0,0 +1,249 @@
+use super::retrieval::{evaluate_retrieval, Qrels, Query, RetrievalCorpus, RetrievalOptions, RetrievalReport};
+use crate::embedding::Embedder;
+use crate::models::registry::ModelSpec;
+use crate::models::InputType;
+use crate::search;
+use crate::error::{err, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// How models are compared
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    /// Cutoffs for recall, nDCG and the overlap with the first model's results, MRR is cut at the largest
+    pub ks: Vec<usize>,
+    /// Corpus texts embedded per batch
+    pub batch_size: usize,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self { ks: vec![1, 5, 10], batch_size: 64 }
+    }
+}
+
+/// Cost and retrieval quality of one model on the compared corpus and queries
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelComparison {
+    pub model: String,
+    pub model_version: String,
+    pub dimension: usize,
+    /// Time to build and initialize the model, zero for an embedder passed in already loaded
+    pub load_time: Duration,
+    /// Time to embed the whole corpus in batches
+    pub corpus_time: Duration,
+    /// Corpus texts embedded per second
+    pub texts_per_second: f32,
+    /// Mean time to embed a single query, as a search request would
+    pub query_latency: Duration,
+    /// Growth of the process's resident memory while the model was loaded and run, None where it cannot be read
+    pub memory_bytes: Option<u64>,
+    /// Recall, nDCG and MRR when relevance judgments were given
+    pub retrieval: Option<RetrievalReport>,
+    /// Mean share of each query's top k shared with the first model's, for k the largest cutoff
+    pub overlap: Option<f32>,
+    /// Corpus positions found for each query, best first
+    #[serde(skip)]
+    pub rankings: Vec<Vec<usize>>,
+}
+
+/// Loads each model through the registry in turn and compares them on the same corpus and queries
+///
+/// Every model embeds the corpus as passages and the queries as queries, so BGE and E5 get their instructions.
+/// A model is dropped before the next one loads, which keeps memory growth per model comparable, although the
+/// allocator may hold on to some of what an earlier model freed.
+pub fn compare_models(
+    specs: &[ModelSpec],
+    texts: &[String],
+    queries: &[Query],
+    qrels: Option<&Qrels>,
+    options: &CompareOptions,
+) -> Result<Vec<ModelComparison>> {
+    let mut comparisons: Vec<ModelComparison> = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let memory_before = resident_memory_bytes();
+        let start = Instant::now();
+        let embedder = ModelSpec { input_type: InputType::Passage, ..spec.clone() }.build()?;
+        let load_time = start.elapsed();
+
+        let query_embedder = embedder.with_input_type(InputType::Query);
+        let mut comparison = compare_model(&embedder, &query_embedder, texts, queries, qrels, options)?;
+        comparison.load_time = load_time;
+        comparison.memory_bytes = memory_before
+            .zip(resident_memory_bytes())
+            .map(|(before, after)| after.saturating_sub(before));
+        comparisons.push(comparison);
+    }
+
+    if let Some((baseline, others)) = comparisons.split_first_mut() {
+        baseline.overlap = Some(1.0);
+        for comparison in others {
+            comparison.overlap = Some(top_k_overlap(&baseline.rankings, &comparison.rankings));
+        }
+    }
+    Ok(comparisons)
+}
+
+/// Measures one loaded model: embeds the corpus, times single queries and searches with them
+///
+/// `corpus_embedder` and `query_embedder` are usually the same model with different input types. Documents
+/// are identified by their position in `texts` for the qrels. The load time, memory and overlap are left for
+/// the caller to fill in.
+pub fn compare_model<E: Embedder>(
+    corpus_embedder: &E,
+    query_embedder: &E,
+    texts: &[String],
+    queries: &[Query],
+    qrels: Option<&Qrels>,
+    options: &CompareOptions,
+) -> Result<ModelComparison> {
+    if texts.is_empty() || queries.is_empty() {
+        return Err(err!("Comparing models needs a corpus and queries, got {} texts and {} queries", texts.len(), queries.len()));
+    }
+    let cutoff = options.ks.iter().copied().max().ok_or_else(|| err!("No cutoffs to compare at"))?;
+
+    let start = Instant::now();
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(options.batch_size.max(1)) {
+        embeddings.extend(corpus_embedder.embed_batch(batch)?);
+    }
+    let corpus_time = start.elapsed();
+
+    let mut query_time = Duration::ZERO;
+    let mut rankings = Vec::with_capacity(queries.len());
+    for query in queries {
+        let start = Instant::now();
+        let embedding = query_embedder.embed_text(&query.text)?;
+        query_time += start.elapsed();
+        rankings.push(search::top_k(&embedding, &embeddings, cutoff).into_iter().map(|hit| hit.index).collect());
+    }
+
+    let retrieval = match qrels {
+        Some(qrels) => {
+            let corpus = RetrievalCorpus {
+                ids: (0..texts.len()).map(|i| i.to_string()).collect(),
+                texts: texts.to_vec(),
+                embeddings,
+            };
+            let options = RetrievalOptions { ks: options.ks.clone(), hybrid: None };
+            Some(evaluate_retrieval(query_embedder, &corpus, queries, qrels, &options)?)
+        }
+        None => None,
+    };
+
+    Ok(ModelComparison {
+        model: corpus_embedder.model_name().to_string(),
+        model_version: corpus_embedder.model_version().to_string(),
+        dimension: corpus_embedder.dimension(),
+        load_time: Duration::ZERO,
+        corpus_time,
+        texts_per_second: texts.len() as f32 / corpus_time.as_secs_f32().max(f32::EPSILON),
+        query_latency: query_time / queries.len() as u32,
+        memory_bytes: None,
+        retrieval,
+        overlap: None,
+        rankings,
+    })
+}
+
+/// Mean share of each ranking's results that also appear in the baseline's ranking for the same query
+pub fn top_k_overlap(baseline: &[Vec<usize>], rankings: &[Vec<usize>]) -> f32 {
+    let shares: Vec<f32> = baseline.iter()
+        .zip(rankings)
+        .filter(|(expected, _)| !expected.is_empty())
+        .map(|(expected, found)| {
+            let expected: HashSet<usize> = expected.iter().copied().collect();
+            found.iter().filter(|index| expected.contains(index)).count() as f32 / expected.len() as f32
+        })
+        .collect();
+    if shares.is_empty() { 0.0 } else { shares.iter().sum::<f32>() / shares.len() as f32 }
+}
+
+/// Formats the comparisons as a table with a column per model and a row per measurement
+pub fn format_comparison(comparisons: &[ModelComparison], options: &CompareOptions) -> String {
+    let cutoff = options.ks.iter().copied().max().unwrap_or_default();
+    let mut rows: Vec<(String, Vec<String>)> = vec![
+        ("model".to_string(), comparisons.iter().map(|c| c.model.clone()).collect()),
+        ("dimension".to_string(), comparisons.iter().map(|c| c.dimension.to_string()).collect()),
+        ("load (s)".to_string(), comparisons.iter().map(|c| format!("{:.2}", c.load_time.as_secs_f32())).collect()),
+        ("corpus (s)".to_string(), comparisons.iter().map(|c| format!("{:.2}", c.corpus_time.as_secs_f32())).collect()),
+        ("texts/s".to_string(), comparisons.iter().map(|c| format!("{:.1}", c.texts_per_second)).collect()),
+        ("query (ms)".to_string(), comparisons.iter().map(|c| format!("{:.2}", c.query_latency.as_secs_f64() * 1000.0)).collect()),
+        ("memory (MB)".to_string(), comparisons.iter()
+            .map(|c| c.memory_bytes.map_or("-".to_string(), |bytes| format!("{:.1}", bytes as f64 / 1_048_576.0)))
+            .collect()),
+        (format!("overlap@{}", cutoff), comparisons.iter()
+            .map(|c| c.overlap.map_or("-".to_string(), |overlap| format!("{:.4}", overlap)))
+            .collect()),
+    ];
+    if comparisons.iter().any(|c| c.retrieval.is_some()) {
+        let metric = |value: &dyn Fn(&RetrievalReport) -> Option<f32>| -> Vec<String> {
+            comparisons.iter()
+                .map(|c| c.retrieval.as_ref().and_then(value).map_or("-".to_string(), |value| format!("{:.4}", value)))
+                .collect()
+        };
+        for &k in &options.ks {
+            rows.push((format!("recall@{}", k), metric(&|report| report.recall.get(&k).copied())));
+        }
+        for &k in &options.ks {
+            rows.push((format!("nDCG@{}", k), metric(&|report| report.ndcg.get(&k).copied())));
+        }
+        rows.push((format!("MRR@{}", cutoff), metric(&|report| Some(report.mrr))));
+    }
+
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or_default();
+    let widths: Vec<usize> = (0..comparisons.len())
+        .map(|column| rows.iter().map(|(_, cells)| cells[column].len()).max().unwrap_or_default())
+        .collect();
+    let mut table = String::new();
+    for (label, cells) in &rows {
+        let _ = write!(table, "{:<width$}", label, width = label_width);
+        for (cell, width) in cells.iter().zip(&widths) {
+            let _ = write!(table, "  {:>width$}", cell, width = width);
+        }
+        table.push('\n');
+    }
+    table
+}
+
+/// Resident memory of this process, read from /proc on Linux
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::test_support::PetEmbedder;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_compares_speed_and_retrieval() -> Result<()> {
+        let texts: Vec<String> = ["cat cat", "dog dog", "fish and a dog"].map(String::from).to_vec();
+        let queries = vec![
+            Query { id: "0".to_string(), text: "cat".to_string() },
+            Query { id: "1".to_string(), text: "fish dog".to_string() },
+        ];
+        let qrels: Qrels = HashMap::from([
+            ("0".to_string(), HashMap::from([("0".to_string(), 1.0)])),
+            ("1".to_string(), HashMap::from([("2".to_string(), 1.0)])),
+        ]);
+        let options = CompareOptions { ks: vec![1, 2], batch_size: 2 };
+
+        let comparison = compare_model(&PetEmbedder, &PetEmbedder, &texts, &queries, Some(&qrels), &options)?;
+        assert_eq!(comparison.rankings, vec![vec![0, 2], vec![2, 1]]);
+        assert_eq!(comparison.retrieval.as_ref().map(|report| report.mrr), Some(1.0));
+        assert!(comparison.texts_per_second > 0.0);
+
+        assert_eq!(top_k_overlap(&comparison.rankings, &[vec![2, 0], vec![0, 1]]), 0.75);
+        let table = format_comparison(&[comparison], &options);
+        assert!(table.starts_with("model "), "{}", table);
+        assert!(table.lines().any(|line| line.starts_with("recall@2") && line.ends_with("1.0000")), "{}", table);
+        Ok(())
+    }
+}
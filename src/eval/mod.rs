@@ -0,0 +1,11 @@
+pub mod compare;
+pub mod retrieval;
+pub mod sts;
+#[cfg(test)]
+pub(crate) mod test_support;
+
+pub use compare::{compare_model, compare_models, format_comparison, CompareOptions, ModelComparison};
+pub use retrieval::{evaluate_retrieval, load_qrels, load_queries, Qrels, Query, RetrievalCorpus, RetrievalOptions, RetrievalReport};
+pub use sts::{evaluate_sts, StsPair, StsReport};
+#[cfg(feature = "tables")]
+pub use sts::load_sts_pairs;
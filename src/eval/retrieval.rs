@@ -0,0 +1,269 @@
+use crate::embedding::Embedder;
+use crate::search::{self, HybridConfig, HybridIndex};
+use crate::utils;
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// Relevance judgments: for each query id, the relevance of each judged document id
+pub type Qrels = HashMap<String, HashMap<String, f32>>;
+
+/// A query to evaluate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub id: String,
+    pub text: String,
+}
+
+/// Embedded documents searched by the evaluation, identified the way the qrels refer to them
+#[derive(Debug, Clone)]
+pub struct RetrievalCorpus {
+    pub ids: Vec<String>,
+    pub texts: Vec<String>,
+    pub embeddings: Vec<Array1<f32>>,
+}
+
+impl RetrievalCorpus {
+    /// Loads a collection saved with its texts, documents are identified by their stored ids or else their position
+    #[cfg(feature = "proto-store")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (embeddings, texts) = utils::load_embeddings(path)?;
+        let texts = texts.ok_or_else(|| err!("{} does not contain texts", path.display()))?;
+        let ids = utils::load_embedding_ids(path)?
+            .unwrap_or_else(|| (0..texts.len()).map(|i| i.to_string()).collect());
+        Ok(Self { ids, texts, embeddings })
+    }
+}
+
+/// How queries are run against the corpus
+#[derive(Debug, Clone)]
+pub struct RetrievalOptions {
+    /// Cutoffs at which recall and nDCG are reported, MRR is cut at the largest
+    pub ks: Vec<usize>,
+    /// Search with BM25 and embeddings fused by these settings instead of embeddings alone
+    pub hybrid: Option<HybridConfig>,
+}
+
+impl Default for RetrievalOptions {
+    fn default() -> Self {
+        Self { ks: vec![1, 5, 10], hybrid: None }
+    }
+}
+
+/// Mean retrieval metrics over the evaluated queries
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievalReport {
+    pub model: String,
+    /// Queries that had relevant documents in the qrels
+    pub queries: usize,
+    /// Queries left out because the qrels judged none of their documents relevant
+    pub skipped: usize,
+    /// Share of each query's relevant documents found in the top k
+    pub recall: BTreeMap<usize, f32>,
+    /// Normalized discounted cumulative gain of the top k, with graded relevance
+    pub ndcg: BTreeMap<usize, f32>,
+    /// Mean reciprocal rank of the first relevant document within the largest cutoff
+    pub mrr: f32,
+}
+
+impl fmt::Display for RetrievalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {} queries:", self.model, self.queries)?;
+        for (k, recall) in &self.recall {
+            write!(f, " recall@{} {:.4},", k, recall)?;
+        }
+        for (k, ndcg) in &self.ndcg {
+            write!(f, " nDCG@{} {:.4},", k, ndcg)?;
+        }
+        let cutoff = self.recall.keys().next_back().copied().unwrap_or_default();
+        write!(f, " MRR@{} {:.4}", cutoff, self.mrr)
+    }
+}
+
+/// Loads queries from JSON Lines with `_id` (or `id`) and `text` fields as in BEIR, or from `id<TAB>text` lines
+///
+/// Lines with neither form are taken as a plain query, identified by its position among the queries.
+pub fn load_queries(path: impl AsRef<Path>) -> Result<Vec<Query>> {
+    let path = path.as_ref();
+    let content = utils::storage::read_to_string(path)?;
+
+    let mut queries = Vec::new();
+    for (line, record) in content.lines().enumerate() {
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let query = if record.starts_with('{') {
+            let value: Value = serde_json::from_str(record)
+                .with_context(|| format!("Invalid JSON on line {} of {}", line + 1, path.display()))?;
+            let id = match value.get("_id").or_else(|| value.get("id")) {
+                Some(Value::String(id)) => id.clone(),
+                Some(Value::Number(id)) => id.to_string(),
+                _ => return Err(err!("Line {} of {} has no _id or id", line + 1, path.display())),
+            };
+            let text = value.get("text").and_then(Value::as_str)
+                .ok_or_else(|| err!("Line {} of {} has no text", line + 1, path.display()))?;
+            Query { id, text: text.to_string() }
+        } else if let Some((id, text)) = record.split_once('\t') {
+            Query { id: id.trim().to_string(), text: text.trim().to_string() }
+        } else {
+            Query { id: queries.len().to_string(), text: record.to_string() }
+        };
+        queries.push(query);
+    }
+    Ok(queries)
+}
+
+/// Loads relevance judgments from BEIR TSV (`query-id`, `corpus-id`, `score`) or TREC (`qid 0 docid rel`) lines
+///
+/// A header row is skipped, and documents judged 0 or below count as not relevant.
+pub fn load_qrels(path: impl AsRef<Path>) -> Result<Qrels> {
+    let path = path.as_ref();
+    let content = utils::storage::read_to_string(path)?;
+
+    let mut qrels = Qrels::new();
+    for (line, record) in content.lines().enumerate() {
+        let fields: Vec<&str> = record.split_whitespace().collect();
+        let (query, document, relevance) = match fields.as_slice() {
+            [] => continue,
+            [query, document, relevance] | [query, _, document, relevance] => (query, document, relevance),
+            _ => return Err(err!("Line {} of {} is not a qrels line", line + 1, path.display())),
+        };
+        let Ok(relevance) = relevance.parse::<f32>() else {
+            if line == 0 {
+                continue;
+            }
+            return Err(err!("Line {} of {} has an invalid relevance '{}'", line + 1, path.display(), relevance));
+        };
+        qrels.entry(query.to_string()).or_default().insert(document.to_string(), relevance);
+    }
+    Ok(qrels)
+}
+
+/// Runs every judged query against the corpus and averages recall@k, nDCG@k and MRR
+///
+/// Queries are embedded in batches of 64 with `embedder`, which must be the model the corpus was embedded with.
+pub fn evaluate_retrieval<E: Embedder>(
+    embedder: &E,
+    corpus: &RetrievalCorpus,
+    queries: &[Query],
+    qrels: &Qrels,
+    options: &RetrievalOptions,
+) -> Result<RetrievalReport> {
+    if corpus.ids.len() != corpus.embeddings.len() || corpus.texts.len() != corpus.embeddings.len() {
+        return Err(err!(
+            "Corpus has {} ids and {} texts for {} embeddings", corpus.ids.len(), corpus.texts.len(), corpus.embeddings.len()
+        ));
+    }
+    let cutoff = options.ks.iter().copied().max().ok_or_else(|| err!("No cutoffs to evaluate at"))?;
+
+    let judged: Vec<(&Query, HashMap<&str, f32>)> = queries.iter()
+        .filter_map(|query| {
+            let relevant: HashMap<&str, f32> = qrels.get(&query.id)?
+                .iter()
+                .filter(|(_, &relevance)| relevance > 0.0)
+                .map(|(document, &relevance)| (document.as_str(), relevance))
+                .collect();
+            (!relevant.is_empty()).then_some((query, relevant))
+        })
+        .collect();
+    if judged.is_empty() {
+        return Err(err!("None of the {} queries has relevant documents in the qrels", queries.len()));
+    }
+
+    let hybrid = options.hybrid.clone()
+        .map(|config| HybridIndex::with_config(corpus.texts.clone(), corpus.embeddings.clone(), config))
+        .transpose()?;
+
+    let mut recall = BTreeMap::new();
+    let mut ndcg = BTreeMap::new();
+    let mut reciprocal_ranks = 0.0;
+    for batch in judged.chunks(64) {
+        let texts: Vec<String> = batch.iter().map(|(query, _)| query.text.clone()).collect();
+        let embeddings = embedder.embed_batch(&texts)?;
+
+        for ((query, relevant), embedding) in batch.iter().zip(&embeddings) {
+            let indices: Vec<usize> = match &hybrid {
+                Some(index) => index.search(&query.text, embedding, cutoff).into_iter().map(|hit| hit.index).collect(),
+                None => search::top_k(embedding, &corpus.embeddings, cutoff).into_iter().map(|hit| hit.index).collect(),
+            };
+            let ranking: Vec<&str> = indices.into_iter().map(|index| corpus.ids[index].as_str()).collect();
+
+            for &k in &options.ks {
+                let top = &ranking[..k.min(ranking.len())];
+                let found = top.iter().filter(|id| relevant.contains_key(*id)).count();
+                *recall.entry(k).or_insert(0.0) += found as f32 / relevant.len() as f32;
+                *ndcg.entry(k).or_insert(0.0) += ndcg_at(top, relevant, k);
+            }
+            if let Some(rank) = ranking.iter().position(|id| relevant.contains_key(id)) {
+                reciprocal_ranks += 1.0 / (rank + 1) as f32;
+            }
+        }
+    }
+
+    let count = judged.len() as f32;
+    Ok(RetrievalReport {
+        model: embedder.model_name().to_string(),
+        queries: judged.len(),
+        skipped: queries.len() - judged.len(),
+        recall: recall.into_iter().map(|(k, total)| (k, total / count)).collect(),
+        ndcg: ndcg.into_iter().map(|(k, total)| (k, total / count)).collect(),
+        mrr: reciprocal_ranks / count,
+    })
+}
+
+/// nDCG of a ranking cut at k, gains are the graded relevances discounted by log2 of the rank plus one
+fn ndcg_at(top: &[&str], relevant: &HashMap<&str, f32>, k: usize) -> f32 {
+    let discount = |rank: usize| 1.0 / ((rank + 2) as f32).log2();
+    let dcg: f32 = top.iter()
+        .enumerate()
+        .map(|(rank, id)| relevant.get(id).copied().unwrap_or(0.0) * discount(rank))
+        .sum();
+
+    let mut ideal: Vec<f32> = relevant.values().copied().collect();
+    ideal.sort_by(|a, b| b.total_cmp(a));
+    let idcg: f32 = ideal.iter().take(k).enumerate().map(|(rank, gain)| gain * discount(rank)).sum();
+    if idcg == 0.0 { 0.0 } else { dcg / idcg }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::test_support::PetEmbedder;
+
+    #[test]
+    fn test_reports_recall_mrr_and_ndcg() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let queries_path = dir.join(format!("rust_embed_queries_{}.jsonl", std::process::id()));
+        let qrels_path = dir.join(format!("rust_embed_qrels_{}.tsv", std::process::id()));
+        std::fs::write(&queries_path, "{\"_id\": \"q1\", \"text\": \"cat\"}\n{\"_id\": 2, \"text\": \"dog\"}\n{\"_id\": \"q3\", \"text\": \"bird\"}\n")?;
+        std::fs::write(&qrels_path, "query-id\tcorpus-id\tscore\nq1\tcats\t1\n2\tfish\t1\n2\tdogs\t0\n")?;
+
+        let texts: Vec<String> = ["cat cat", "dog dog", "fish and a dog"].map(String::from).to_vec();
+        let corpus = RetrievalCorpus {
+            ids: vec!["cats".to_string(), "dogs".to_string(), "fish".to_string()],
+            embeddings: PetEmbedder.embed_batch(&texts)?,
+            texts,
+        };
+        let queries = load_queries(&queries_path)?;
+        let qrels = load_qrels(&qrels_path)?;
+        let options = RetrievalOptions { ks: vec![1, 2], hybrid: None };
+        let report = evaluate_retrieval(&PetEmbedder, &corpus, &queries, &qrels, &options)?;
+
+        // q1 finds its document first, query 2 finds it second behind "dogs", q3 has no judgments
+        assert_eq!((report.queries, report.skipped), (2, 1));
+        assert_eq!(report.recall[&1], 0.5);
+        assert_eq!(report.recall[&2], 1.0);
+        assert!((report.mrr - 0.75).abs() < 1e-6);
+        assert!((report.ndcg[&2] - (1.0 + 1.0 / 3f32.log2()) / 2.0).abs() < 1e-6);
+
+        std::fs::remove_file(&queries_path)?;
+        std::fs::remove_file(&qrels_path)?;
+        Ok(())
+    }
+}
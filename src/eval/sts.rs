@@ -0,0 +1,218 @@
+use crate::embedding::Embedder;
+use crate::utils;
+#[cfg(feature = "tables")]
+use crate::error::Context;
+use crate::error::{err, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "tables")]
+use std::path::Path;
+
+/// Two sentences and how similar annotators judged them, 0 to 5 in STS-B
+#[derive(Debug, Clone, PartialEq)]
+pub struct StsPair {
+    pub sentence1: String,
+    pub sentence2: String,
+    pub score: f32,
+}
+
+/// How closely an embedder's similarities follow the gold scores
+#[derive(Debug, Clone, Serialize)]
+pub struct StsReport {
+    pub model: String,
+    pub model_version: String,
+    pub pairs: usize,
+    /// Spearman rank correlation between cosine similarities and gold scores, the usual STS metric
+    pub spearman: f32,
+    /// Pearson correlation between cosine similarities and gold scores
+    pub pearson: f32,
+}
+
+impl fmt::Display for StsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{} ({}) on {} pairs: Spearman {:.4}, Pearson {:.4}",
+            self.model, self.model_version, self.pairs, self.spearman, self.pearson
+        )
+    }
+}
+
+/// Loads sentence pairs from an STS-B style TSV file
+///
+/// Files with a header row take the `sentence1`, `sentence2` and `score` (or `label`) columns, as in the GLUE
+/// release. Files without one are read as the original STS-B, with the score in the fifth column and the
+/// sentences in the sixth and seventh, or as three columns of two sentences and a score. Quotes are kept as
+/// they are, since STS-B sentences contain unbalanced ones.
+#[cfg(feature = "tables")]
+pub fn load_sts_pairs(path: impl AsRef<Path>) -> Result<Vec<StsPair>> {
+    let path = path.as_ref();
+    let bytes = utils::storage::read(path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .quoting(false)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(bytes.as_slice());
+
+    let mut pairs = Vec::new();
+    let mut columns: Option<(usize, usize, usize)> = None;
+    for (line, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Failed to read line {} of {}", line + 1, path.display()))?;
+        if line == 0 {
+            let position = |names: &[&str]| record.iter().position(|column| names.contains(&column.trim()));
+            if let (Some(first), Some(second), Some(score)) =
+                (position(&["sentence1"]), position(&["sentence2"]), position(&["score", "label"]))
+            {
+                columns = Some((first, second, score));
+                continue;
+            }
+        }
+
+        let (first, second, score) = columns.unwrap_or(if record.len() >= 7 { (5, 6, 4) } else { (0, 1, 2) });
+        let field = |index: usize| record.get(index).map(str::trim).unwrap_or_default();
+        let gold = field(score).parse::<f32>().map_err(|_| err!(
+            "Line {} of {} has no score in column {}", line + 1, path.display(), score + 1
+        ))?;
+        pairs.push(StsPair { sentence1: field(first).to_string(), sentence2: field(second).to_string(), score: gold });
+    }
+    Ok(pairs)
+}
+
+/// Embeds both sentences of every pair and correlates their cosine similarities with the gold scores
+///
+/// Each distinct sentence is embedded once, in batches of 64.
+pub fn evaluate_sts<E: Embedder>(embedder: &E, pairs: &[StsPair]) -> Result<StsReport> {
+    if pairs.len() < 2 {
+        return Err(err!("STS evaluation needs at least 2 pairs, got {}", pairs.len()));
+    }
+
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    let mut sentences: Vec<String> = Vec::new();
+    for sentence in pairs.iter().flat_map(|pair| [&pair.sentence1, &pair.sentence2]) {
+        positions.entry(sentence.as_str()).or_insert_with(|| {
+            sentences.push(sentence.clone());
+            sentences.len() - 1
+        });
+    }
+
+    let mut embeddings = Vec::with_capacity(sentences.len());
+    for batch in sentences.chunks(64) {
+        embeddings.extend(embedder.embed_batch(batch)?);
+    }
+
+    let similarities: Vec<f32> = pairs.iter()
+        .map(|pair| utils::cosine_similarity(
+            &embeddings[positions[pair.sentence1.as_str()]],
+            &embeddings[positions[pair.sentence2.as_str()]],
+        ))
+        .collect();
+    let gold: Vec<f32> = pairs.iter().map(|pair| pair.score).collect();
+
+    Ok(StsReport {
+        model: embedder.model_name().to_string(),
+        model_version: embedder.model_version().to_string(),
+        pairs: pairs.len(),
+        spearman: spearman(&similarities, &gold),
+        pearson: pearson(&similarities, &gold),
+    })
+}
+
+/// Pearson correlation of two equally long samples, 0 when either is constant
+pub fn pearson(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let mean = |values: &[f32]| values[..n].iter().map(|&v| v as f64).sum::<f64>() / n as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (&x, &y) in a[..n].iter().zip(&b[..n]) {
+        let (dx, dy) = (x as f64 - mean_a, y as f64 - mean_b);
+        covariance += dx * dy;
+        variance_a += dx * dx;
+        variance_b += dy * dy;
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return 0.0;
+    }
+    (covariance / (variance_a.sqrt() * variance_b.sqrt())) as f32
+}
+
+/// Spearman rank correlation of two equally long samples, tied values sharing their average rank
+pub fn spearman(a: &[f32], b: &[f32]) -> f32 {
+    pearson(&ranks(a), &ranks(b))
+}
+
+/// Ranks of values counted from 1, ties get the mean of the ranks they span
+fn ranks(values: &[f32]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        // Ranks start..end (0-based) average to this 1-based rank
+        let rank = (start + end + 1) as f32 / 2.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::test_support::PetEmbedder;
+
+    #[test]
+    fn test_spearman_averages_tied_ranks() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+        assert!((spearman(&[1.0, 2.0, 3.0], &[10.0, 20.0, 90.0]) - 1.0).abs() < 1e-6);
+        assert!((spearman(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]) + 1.0).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "tables")]
+    #[test]
+    fn test_loads_glue_and_original_layouts() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let glue = dir.join(format!("rust_embed_sts_glue_{}.tsv", std::process::id()));
+        std::fs::write(&glue, concat!(
+            "index\tgenre\tsentence1\tsentence2\tscore\n",
+            "0\tnews\taaa\taaa\t5.0\n",
+            "1\tnews\taab\taaa\t3.8\n",
+            "2\tnews\t\"abc\tccc\t1.2\n",
+            "3\tnews\tbbb\tccc\t0.0\n",
+        ))?;
+        let original = dir.join(format!("rust_embed_sts_original_{}.tsv", std::process::id()));
+        std::fs::write(&original, "main-news\tMSRvid\t2012test\t0001\t4.2\tA man plays.\tA man is playing.\n")?;
+
+        let pairs = load_sts_pairs(&glue)?;
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs[2].sentence1, "\"abc");
+        assert_eq!(load_sts_pairs(&original)?[0].score, 4.2);
+
+        std::fs::remove_file(&glue)?;
+        std::fs::remove_file(&original)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluates_similarities_against_gold_scores() -> Result<()> {
+        let pairs: Vec<StsPair> = [("cat", "cat", 5.0), ("cat cat dog", "cat", 3.8), ("cat dog", "dog fish", 1.2), ("fish", "cat", 0.0)]
+            .into_iter()
+            .map(|(sentence1, sentence2, score)| StsPair { sentence1: sentence1.to_string(), sentence2: sentence2.to_string(), score })
+            .collect();
+        let report = evaluate_sts(&PetEmbedder, &pairs)?;
+        assert_eq!(report.pairs, 4);
+        assert!((report.spearman - 1.0).abs() < 1e-6, "{}", report);
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::embedding::Embedder;
+use ndarray::Array1;
+
+/// Embeds a text by whether it mentions cats, dogs or fish
+#[derive(Clone)]
+pub(crate) struct PetEmbedder;
+
+impl Embedder for PetEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        Ok(Array1::from_iter(["cat", "dog", "fish"].map(|pet| text.matches(pet).count() as f32 + 0.01)))
+    }
+
+    fn model_name(&self) -> &str {
+        "pets"
+    }
+
+    fn model_version(&self) -> &str {
+        "1"
+    }
+
+    fn dimension(&self) -> usize {
+        3
+    }
+}
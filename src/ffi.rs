@@ -0,0 +1,166 @@
+use crate::models::registry::ModelSpec;
+use crate::server::{HostedModel, HostedModelConfig};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An embedding model usable from C, running on its own worker thread
+///
+/// Models keep per-thread state, so the handle forwards calls to a dedicated thread
+/// and may be shared between threads of the calling application.
+pub struct RustEmbedder {
+    model: HostedModel,
+}
+
+/// Records an error message for `rust_embed_last_error`
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|cell| *cell.borrow_mut() = Some(message));
+}
+
+/// Reads an optional C string argument
+///
+/// # Safety
+/// `value` must be null or point to a NUL-terminated string.
+unsafe fn optional_str<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map(Some)
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+/// Runs an FFI body, turning errors and panics into `fallback` plus a recorded message
+fn guard<T>(fallback: T, body: impl FnOnce() -> Result<T, String>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_last_error(message);
+            fallback
+        }
+        Err(_) => {
+            set_last_error("Panic inside rust_embed".to_string());
+            fallback
+        }
+    }
+}
+
+/// Loads an embedding model, returning NULL on failure
+///
+/// `model` is a registry name such as "minilm" or "bge-small", NULL selects "minilm".
+/// `model_path` is the model directory, required by ONNX models, or NULL.
+///
+/// # Safety
+/// `model` and `model_path` must each be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_embed_new(model: *const c_char, model_path: *const c_char) -> *mut RustEmbedder {
+    guard(ptr::null_mut(), || {
+        let model = optional_str(model, "model")?.unwrap_or("minilm");
+        let model_path = optional_str(model_path, "model_path")?;
+
+        let mut spec = ModelSpec::new(model);
+        spec.model_path = model_path.map(PathBuf::from);
+
+        let mut config = HostedModelConfig::new(model, spec.clone());
+        config.max_batch_delay_ms = 0;
+
+        let model = HostedModel::spawn(config, move || spec.build()).map_err(|e| e.report())?;
+        Ok(Box::into_raw(Box::new(RustEmbedder { model })))
+    })
+}
+
+/// Dimension of the embeddings produced by the model, 0 for a NULL handle
+///
+/// # Safety
+/// `embedder` must be null or a handle returned by `rust_embed_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rust_embed_dimension(embedder: *const RustEmbedder) -> usize {
+    match embedder.as_ref() {
+        Some(embedder) => embedder.model.dimension(),
+        None => 0,
+    }
+}
+
+/// Embeds a text into `out`, which must hold at least `capacity` floats
+///
+/// Returns the number of floats written, or -1 on failure.
+///
+/// # Safety
+/// `embedder` must be a live handle from `rust_embed_new`, `text` a NUL-terminated string
+/// and `out` must be valid for writes of `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn rust_embed_embed_text(
+    embedder: *const RustEmbedder,
+    text: *const c_char,
+    out: *mut f32,
+    capacity: usize,
+) -> i64 {
+    guard(-1, || {
+        let embedder = embedder.as_ref().ok_or("embedder is NULL")?;
+        let text = optional_str(text, "text")?.ok_or("text is NULL")?;
+        if out.is_null() {
+            return Err("out is NULL".to_string());
+        }
+
+        let embedding = embedder.model.embed(vec![text.to_string()])
+            .map_err(|e| e.to_string())?
+            .pop()
+            .ok_or("Model returned no embedding")?;
+        if capacity < embedding.len() {
+            return Err(format!("Output buffer holds {} floats but the embedding has {}", capacity, embedding.len()));
+        }
+
+        let values = std::slice::from_raw_parts_mut(out, embedding.len());
+        for (slot, value) in values.iter_mut().zip(embedding.iter()) {
+            *slot = *value;
+        }
+        Ok(embedding.len() as i64)
+    })
+}
+
+/// Message describing the last failure on the calling thread, or NULL
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn rust_embed_last_error() -> *const c_char {
+    LAST_ERROR.with(|cell| cell.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Releases a handle returned by `rust_embed_new`, NULL is ignored
+///
+/// # Safety
+/// `embedder` must be null or a handle from `rust_embed_new` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rust_embed_free(embedder: *mut RustEmbedder) {
+    if !embedder.is_null() {
+        drop(Box::from_raw(embedder));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_errors_through_last_error() {
+        let model = CString::new("no-such-model").unwrap();
+        let embedder = unsafe { rust_embed_new(model.as_ptr(), ptr::null()) };
+        assert!(embedder.is_null());
+
+        let message = unsafe { CStr::from_ptr(rust_embed_last_error()) }.to_string_lossy();
+        assert!(message.contains("no-such-model"), "{}", message);
+
+        let mut out = [0.0f32; 4];
+        let text = CString::new("text").unwrap();
+        assert_eq!(unsafe { rust_embed_embed_text(ptr::null(), text.as_ptr(), out.as_mut_ptr(), out.len()) }, -1);
+        unsafe { rust_embed_free(ptr::null_mut()) };
+    }
+}
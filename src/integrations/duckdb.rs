@@ -0,0 +1,154 @@
+use crate::collection::{shard, ShardedCollection, StoredCollection};
+use crate::error::{err, Context, Result};
+use duckdb::{params, Connection};
+use ndarray::Array1;
+use std::path::Path;
+
+/// A row of an embedding table returned by `nearest`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableHit {
+    pub id: Option<String>,
+    pub text: Option<String>,
+
+    /// Cosine similarity between the query and the row's embedding
+    pub score: f32,
+}
+
+/// Creates the table `table` holding the dense embeddings of a collection, replacing any table of that name
+///
+/// Each row has `id`, `namespace`, `text`, `metadata` (a JSON object as text), `created_at` and `embedding`,
+/// a `FLOAT[dimension]` array, so queries can join on the ids and rank with DuckDB's own
+/// `array_cosine_similarity(embedding, ...)`. Sparse embeddings are left out. Returns the rows inserted.
+pub fn register_collection(conn: &Connection, table: &str, collection: &StoredCollection) -> Result<usize> {
+    create_table(conn, table, collection.dimension)?;
+    insert(conn, table, collection)
+}
+
+/// Creates the table `table` from an embedding file, reading a sharded collection one shard at a time
+pub fn register_file(conn: &Connection, table: &str, path: impl AsRef<Path>) -> Result<usize> {
+    let path = path.as_ref();
+    if !shard::is_sharded(path) {
+        return register_collection(conn, table, &StoredCollection::load(path)?);
+    }
+
+    let sharded = ShardedCollection::open(path)?;
+    create_table(conn, table, sharded.manifest().dimension)?;
+    let mut rows = 0;
+    for collection in sharded.shards() {
+        rows += insert(conn, table, &collection?)?;
+    }
+    Ok(rows)
+}
+
+/// A query vector as a DuckDB array literal, such as `[0.1, 0.2]::FLOAT[2]`, for pasting into SQL
+pub fn vector_literal(vector: &Array1<f32>) -> String {
+    let values: Vec<String> = vector.iter().map(|value| format!("{:?}", value)).collect();
+    format!("[{}]::FLOAT[{}]", values.join(", "), vector.len())
+}
+
+/// Returns the `k` rows of `table` whose embeddings are most similar to the query, best first
+pub fn nearest(conn: &Connection, table: &str, query: &Array1<f32>, k: usize) -> Result<Vec<TableHit>> {
+    let sql = format!(
+        "SELECT id, text, array_cosine_similarity(embedding, {}) AS score FROM {} ORDER BY score DESC LIMIT {}",
+        vector_literal(query), quote(table), k,
+    );
+    let mut statement = conn.prepare(&sql)?;
+    let hits = statement
+        .query_map([], |row| Ok(TableHit { id: row.get(0)?, text: row.get(1)?, score: row.get(2)? }))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(hits)
+}
+
+fn create_table(conn: &Connection, table: &str, dimension: usize) -> Result<()> {
+    if dimension == 0 {
+        return Err(err!("Collection has no dense dimension to create an embedding column for"));
+    }
+    conn.execute_batch(&format!(
+        "CREATE OR REPLACE TABLE {} (id VARCHAR, namespace VARCHAR, text VARCHAR, metadata VARCHAR, created_at TIMESTAMP, embedding FLOAT[{}])",
+        quote(table), dimension,
+    )).with_context(|| format!("Failed to create DuckDB table {}", table))?;
+    Ok(())
+}
+
+/// Inserts the dense embeddings in one transaction, passing each vector as a list literal cast to the array type
+fn insert(conn: &Connection, table: &str, collection: &StoredCollection) -> Result<usize> {
+    conn.execute_batch("BEGIN TRANSACTION")?;
+    let inserted = (|| -> Result<usize> {
+        let mut statement = conn.prepare(&format!(
+            "INSERT INTO {} VALUES (?, ?, ?, ?, to_timestamp(?), CAST(? AS FLOAT[{}]))",
+            quote(table), collection.dimension,
+        ))?;
+
+        let mut rows = 0;
+        for embedding in &collection.embeddings {
+            let Some(vector) = embedding.dense_vector() else { continue };
+            if vector.len() != collection.dimension {
+                return Err(crate::Error::DimensionMismatch { expected: collection.dimension, actual: vector.len() });
+            }
+            let values: Vec<String> = vector.iter().map(|value| format!("{:?}", value)).collect();
+            statement.execute(params![
+                embedding.id,
+                embedding.namespace,
+                embedding.text,
+                serde_json::to_string(&embedding.metadata)?,
+                embedding.timestamp,
+                format!("[{}]", values.join(", ")),
+            ]).with_context(|| format!("Failed to insert into DuckDB table {}", table))?;
+            rows += 1;
+        }
+        Ok(rows)
+    })();
+
+    match inserted {
+        Ok(rows) => {
+            conn.execute_batch("COMMIT")?;
+            Ok(rows)
+        }
+        Err(error) => {
+            let _ = conn.execute_batch("ROLLBACK");
+            Err(error)
+        }
+    }
+}
+
+/// A table name as a quoted SQL identifier
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::StoredEmbedding;
+
+    #[test]
+    fn test_registers_and_ranks_a_collection() -> Result<()> {
+        let mut collection = StoredCollection {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            dimension: 2,
+            embeddings: Vec::new(),
+            provenance: None,
+        };
+        for (id, vector) in [("north", [0.0, 1.0]), ("east", [1.0, 0.0]), ("northeast", [0.7, 0.7])] {
+            let mut embedding = StoredEmbedding::dense(Array1::from_vec(vector.to_vec()), Some(id.to_uppercase()));
+            embedding.id = Some(id.to_string());
+            embedding.metadata.insert("compass".to_string(), "true".to_string());
+            collection.embeddings.push(embedding);
+        }
+
+        let conn = Connection::open_in_memory()?;
+        assert_eq!(register_collection(&conn, "points", &collection)?, 3);
+
+        let hits = nearest(&conn, "points", &Array1::from_vec(vec![1.0, 0.1]), 2)?;
+        let ids: Vec<_> = hits.iter().map(|hit| hit.id.as_deref()).collect();
+        assert_eq!(ids, vec![Some("east"), Some("northeast")]);
+        assert_eq!(hits[0].text.as_deref(), Some("EAST"));
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM points WHERE json_extract_string(metadata, '$.compass') = 'true'", [], |row| row.get(0))?;
+        assert_eq!(count, 3);
+        assert_eq!(vector_literal(&Array1::from_vec(vec![0.5, 1.0])), "[0.5, 1.0]::FLOAT[2]");
+        Ok(())
+    }
+}
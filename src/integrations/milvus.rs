@@ -0,0 +1,182 @@
+use super::vector_db::{self, typed_value, untyped_value, VectorDbHit, VectorRecord, UPSERT_BATCH};
+use crate::search::filter::{CompareOp, Filter, Literal};
+use crate::error::{err, Result};
+use ndarray::Array1;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+/// Where a `MilvusClient` stores its records
+#[derive(Debug, Clone)]
+pub struct MilvusConfig {
+    /// Address of the Milvus REST API
+    pub url: String,
+
+    /// `user:password`, or a Zilliz Cloud API key, sent as a bearer token
+    pub token: Option<String>,
+
+    pub collection: String,
+
+    /// Timeout for a single request
+    pub timeout: Duration,
+}
+
+impl Default for MilvusConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:19530".to_string(),
+            token: None,
+            collection: "rust_embed".to_string(),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Envelope of every Milvus REST response
+#[derive(Deserialize)]
+struct MilvusResponse {
+    code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Stores and queries embeddings in a Milvus collection through its v2 REST API
+///
+/// Records are entities with a VarChar `id` primary key and a `vector` field compared by cosine similarity.
+/// The text and metadata go in the collection's dynamic field, so `create_collection` needs no schema
+/// beyond the dimension, and filters become Milvus expressions on `metadata["FIELD"]`.
+pub struct MilvusClient {
+    config: MilvusConfig,
+    client: Client,
+}
+
+impl MilvusClient {
+    /// Creates the HTTP client, without contacting Milvus
+    pub fn new(config: MilvusConfig) -> Result<Self> {
+        let client = vector_db::client(&config.url, config.timeout)?;
+        Ok(Self { config, client })
+    }
+
+    /// Creates the collection for vectors of `dimension` unless it exists
+    pub fn create_collection(&self, dimension: usize) -> Result<()> {
+        let exists = self.call("collections/has", json!({ "collectionName": self.config.collection }))?;
+        if exists["has"].as_bool() == Some(true) {
+            return Ok(());
+        }
+        self.call("collections/create", json!({
+            "collectionName": self.config.collection,
+            "dimension": dimension,
+            "metricType": "COSINE",
+            "idType": "VarChar",
+            "primaryFieldName": "id",
+            "vectorFieldName": "vector",
+            "params": { "max_length": 512 },
+        }))?;
+        tracing::info!("Created Milvus collection {} for {}-dimensional vectors", self.config.collection, dimension);
+        Ok(())
+    }
+
+    /// Inserts or replaces records, `UPSERT_BATCH` per request
+    pub fn upsert(&self, records: &[VectorRecord]) -> Result<()> {
+        for batch in records.chunks(UPSERT_BATCH) {
+            let data: Vec<Value> = batch.iter()
+                .map(|record| json!({
+                    "id": record.id,
+                    "vector": record.vector.to_vec(),
+                    "text": record.text.clone().unwrap_or_default(),
+                    "metadata": record.metadata.iter().map(|(key, value)| (key.clone(), typed_value(value))).collect::<Map<_, _>>(),
+                }))
+                .collect();
+            self.call("entities/upsert", json!({ "collectionName": self.config.collection, "data": data }))?;
+        }
+        Ok(())
+    }
+
+    /// Removes records by id
+    pub fn delete(&self, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let filter = format!("id in {}", serde_json::to_string(ids)?);
+        self.call("entities/delete", json!({ "collectionName": self.config.collection, "filter": filter }))?;
+        Ok(())
+    }
+
+    /// Returns the `k` records most similar to the query, among those matching `filter` when given
+    pub fn search(&self, query: &Array1<f32>, k: usize, filter: Option<&Filter>) -> Result<Vec<VectorDbHit>> {
+        let mut body = json!({
+            "collectionName": self.config.collection,
+            "data": [query.to_vec()],
+            "annsField": "vector",
+            "limit": k,
+            "outputFields": ["id", "text", "metadata"],
+        });
+        if let Some(filter) = filter {
+            body["filter"] = Value::String(expression(filter));
+        }
+
+        let data = self.call("entities/search", body)?;
+        let hits = data.as_array().ok_or_else(|| err!("Unexpected Milvus search response"))?;
+        Ok(hits.iter()
+            .map(|hit| VectorDbHit {
+                id: hit["id"].as_str().map_or_else(|| hit["id"].to_string(), str::to_string),
+                // With the cosine metric Milvus reports the similarity itself
+                score: hit["distance"].as_f64().unwrap_or_default() as f32,
+                text: hit["text"].as_str().filter(|text| !text.is_empty()).map(str::to_string),
+                metadata: hit["metadata"].as_object()
+                    .map(|fields| fields.iter().map(|(key, value)| (key.clone(), untyped_value(value))).collect())
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Posts to an endpoint under `/v2/vectordb`, returning the `data` of a successful response
+    fn call(&self, endpoint: &str, body: Value) -> Result<Value> {
+        let request = self.client.post(format!("{}/v2/vectordb/{}", self.config.url.trim_end_matches('/'), endpoint));
+        let response: MilvusResponse = vector_db::send(request, &body, self.config.token.as_deref())?;
+        if response.code != 0 && response.code != 200 {
+            return Err(err!("Milvus {} failed with code {}: {}", endpoint, response.code, response.message));
+        }
+        Ok(response.data)
+    }
+}
+
+/// A filter as a Milvus boolean expression over the `metadata` JSON field
+pub fn expression(filter: &Filter) -> String {
+    match filter {
+        Filter::Compare { field, op, value } => {
+            let op = match op {
+                CompareOp::Eq => "==",
+                CompareOp::Ne => "!=",
+                CompareOp::Lt => "<",
+                CompareOp::Le => "<=",
+                CompareOp::Gt => ">",
+                CompareOp::Ge => ">=",
+            };
+            let value = match value {
+                Literal::Text(text) => Value::String(text.clone()).to_string(),
+                Literal::Number(number) => number.to_string(),
+                Literal::Bool(flag) => flag.to_string(),
+            };
+            format!("metadata[{}] {} {}", Value::String(field.clone()), op, value)
+        }
+        Filter::And(left, right) => format!("({} and {})", expression(left), expression(right)),
+        Filter::Or(left, right) => format!("({} or {})", expression(left), expression(right)),
+        Filter::Not(inner) => format!("not ({})", expression(inner)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_filters_to_expressions() -> Result<()> {
+        let filter = Filter::parse(r#"metadata.lang == "en" && !(year < 2022 || draft == true)"#)?;
+        assert_eq!(expression(&filter), r#"(metadata["lang"] == "en" and not ((metadata["year"] < 2022 or metadata["draft"] == true)))"#);
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+#[cfg(feature = "polars")]
+pub mod polars;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+#[cfg(feature = "milvus")]
+pub mod milvus;
+#[cfg(feature = "weaviate")]
+pub mod weaviate;
+#[cfg(any(feature = "milvus", feature = "weaviate"))]
+pub mod vector_db;
+
+#[cfg(any(feature = "milvus", feature = "weaviate"))]
+pub use vector_db::{VectorDbHit, VectorRecord};
@@ -0,0 +1,54 @@
+use crate::embedding::Embedder;
+use crate::error::{err, Result};
+use polars::prelude::*;
+
+/// Appends a `<text_col>_embedding` column holding a List<f32> embedding of each text in `text_col`
+///
+/// The texts are embedded with one `embed_batch_matrix` call, so the model sees them in token-sized batches
+/// rather than row by row. Null texts get a null embedding.
+pub fn embed_column<E: Embedder>(embedder: &E, df: &mut DataFrame, text_col: &str) -> Result<()> {
+    let column = df.column(text_col)?;
+    let texts = column.str()
+        .map_err(|_| err!("Column '{}' holds {}, expected strings", text_col, column.dtype()))?;
+
+    let present: Vec<String> = texts.into_iter().flatten().map(str::to_string).collect();
+    let matrix = embedder.embed_batch_matrix(&present)?;
+
+    let mut builder = ListPrimitiveChunkedBuilder::<Float32Type>::new(
+        format!("{}_embedding", text_col).into(),
+        texts.len(),
+        matrix.len(),
+        DataType::Float32,
+    );
+    let mut rows = matrix.rows().into_iter();
+    for text in texts {
+        match text.and_then(|_| rows.next()) {
+            Some(row) => builder.append_values_iter(row.iter().copied()),
+            None => builder.append_null(),
+        }
+    }
+
+    df.with_column(builder.finish().into_series())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::LengthEmbedder;
+
+    #[test]
+    fn test_appends_embedding_column() -> Result<()> {
+        let mut df = df!("text" => [Some("one two"), None, Some("three")])?;
+        embed_column(&LengthEmbedder::default(), &mut df, "text")?;
+
+        let embeddings = df.column("text_embedding")?.list()?;
+        let rows: Vec<Option<Vec<f32>>> = embeddings.into_iter()
+            .map(|row| row.map(|row| row.f32().unwrap().into_no_null_iter().collect()))
+            .collect();
+        assert_eq!(rows, vec![Some(vec![7.0, 7.0]), None, Some(vec![5.0, 5.0])]);
+
+        assert!(embed_column(&LengthEmbedder::default(), &mut df, "missing").is_err());
+        Ok(())
+    }
+}
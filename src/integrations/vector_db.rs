@@ -0,0 +1,83 @@
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Records sent per request by `upsert`
+pub const UPSERT_BATCH: usize = 500;
+
+/// An embedding to store in a vector database, with the text and fields kept alongside it
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorRecord {
+    /// Caller's identifier, upserting the same id again replaces the record
+    pub id: String,
+    pub vector: Array1<f32>,
+    pub text: Option<String>,
+    /// Fields to filter on, values that parse as numbers or `true`/`false` are stored as such
+    pub metadata: HashMap<String, String>,
+}
+
+/// A match returned by a vector database
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorDbHit {
+    pub id: String,
+
+    /// Cosine similarity between the query and the match
+    pub score: f32,
+
+    pub text: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+/// HTTP client for a vector database, honoring the proxy and certificate settings of downloads
+pub(crate) fn client(base_url: &str, timeout: Duration) -> Result<Client> {
+    if !crate::utils::http::is_loopback(base_url) {
+        crate::utils::offline::ensure_network_allowed(base_url)?;
+    }
+    crate::utils::http::client_builder()?
+        .timeout(timeout)
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Sends a JSON request, failing on error statuses with the body the server sent
+pub(crate) fn send<T: DeserializeOwned>(request: RequestBuilder, body: &impl Serialize, api_key: Option<&str>) -> Result<T> {
+    let request = match api_key {
+        Some(key) => request.bearer_auth(key),
+        None => request,
+    };
+    let response = request.json(body).send().context("Request to the vector database failed")?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().unwrap_or_default();
+        return Err(err!("Vector database returned {}: {}", status, body));
+    }
+    response.json().context("Failed to parse the vector database response")
+}
+
+/// A metadata value as JSON, typed as a number or boolean when it reads as one so that range filters work
+pub(crate) fn typed_value(value: &str) -> Value {
+    match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => value.parse::<f64>().ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or_else(|| Value::String(value.to_string()), Value::Number),
+    }
+}
+
+/// A JSON metadata value back as the string it was stored from
+pub(crate) fn untyped_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if float.fract() == 0.0 && float.abs() < 1e15 => format!("{}", float as i64),
+            _ => number.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
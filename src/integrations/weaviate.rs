@@ -0,0 +1,244 @@
+use super::vector_db::{self, typed_value, VectorDbHit, VectorRecord, UPSERT_BATCH};
+use crate::search::filter::{CompareOp, Filter, Literal};
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use reqwest::blocking::Client;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Prefix of the properties holding metadata fields, keeping them apart from the record's own
+const METADATA_PREFIX: &str = "meta_";
+
+/// Where a `WeaviateClient` stores its records
+#[derive(Debug, Clone)]
+pub struct WeaviateConfig {
+    /// Address of the Weaviate REST API
+    pub url: String,
+
+    /// Sent as a bearer token, as Weaviate Cloud and instances with API key authentication expect
+    pub api_key: Option<String>,
+
+    /// Collection (class) name, which Weaviate capitalizes
+    pub class: String,
+
+    /// Timeout for a single request
+    pub timeout: Duration,
+}
+
+impl Default for WeaviateConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080".to_string(),
+            api_key: None,
+            class: "RustEmbed".to_string(),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Stores and queries embeddings in a Weaviate collection through its REST and GraphQL APIs
+///
+/// Weaviate identifies objects by UUID, so each record gets one derived from its id, which is kept in the
+/// `record_id` property. Each metadata field becomes a `meta_FIELD` property, created by Weaviate's auto
+/// schema on first use, and filters become `where` clauses on them. The full metadata is also stored as
+/// JSON in `metadata_json` to return it with the hits.
+pub struct WeaviateClient {
+    config: WeaviateConfig,
+    client: Client,
+}
+
+impl WeaviateClient {
+    /// Creates the HTTP client, without contacting Weaviate
+    pub fn new(config: WeaviateConfig) -> Result<Self> {
+        let client = vector_db::client(&config.url, config.timeout)?;
+        Ok(Self { config, client })
+    }
+
+    /// Creates the collection, compared by cosine distance over vectors supplied by the caller, unless it exists
+    pub fn create_class(&self) -> Result<()> {
+        let request = self.authorized(self.client.get(self.endpoint(&format!("schema/{}", self.config.class))));
+        let status = request.send().context("Request to Weaviate failed")?.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let text = |name: &str| json!({ "name": name, "dataType": ["text"] });
+        let _: Value = vector_db::send(self.client.post(self.endpoint("schema")), &json!({
+            "class": self.config.class,
+            "vectorizer": "none",
+            "vectorIndexConfig": { "distance": "cosine" },
+            "properties": [text("record_id"), text("text"), text("metadata_json")],
+        }), self.config.api_key.as_deref())?;
+        tracing::info!("Created Weaviate collection {}", self.config.class);
+        Ok(())
+    }
+
+    /// Inserts or replaces records, `UPSERT_BATCH` per request
+    pub fn upsert(&self, records: &[VectorRecord]) -> Result<()> {
+        for batch in records.chunks(UPSERT_BATCH) {
+            let objects: Vec<Value> = batch.iter()
+                .map(|record| {
+                    let mut properties: Map<String, Value> = record.metadata.iter()
+                        .map(|(key, value)| (property_name(key), typed_value(value)))
+                        .collect();
+                    properties.insert("record_id".to_string(), json!(record.id));
+                    properties.insert("text".to_string(), json!(record.text.clone().unwrap_or_default()));
+                    properties.insert("metadata_json".to_string(), json!(serde_json::to_string(&record.metadata)?));
+                    Ok(json!({
+                        "class": self.config.class,
+                        "id": object_id(&record.id),
+                        "vector": record.vector.to_vec(),
+                        "properties": properties,
+                    }))
+                })
+                .collect::<Result<_>>()?;
+
+            let results: Vec<Value> = vector_db::send(self.client.post(self.endpoint("batch/objects")), &json!({ "objects": objects }), self.config.api_key.as_deref())?;
+            let errors: Vec<&str> = results.iter()
+                .filter_map(|result| result["result"]["errors"]["error"].as_array())
+                .flatten()
+                .filter_map(|error| error["message"].as_str())
+                .collect();
+            if !errors.is_empty() {
+                return Err(err!("Weaviate rejected {} object(s): {}", errors.len(), errors.join("; ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes records by id, ignoring ids that aren't stored
+    pub fn delete(&self, ids: &[String]) -> Result<()> {
+        for id in ids {
+            let url = self.endpoint(&format!("objects/{}/{}", self.config.class, object_id(id)));
+            let response = self.authorized(self.client.delete(url)).send().context("Request to Weaviate failed")?;
+            let status = response.status();
+            if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+                return Err(err!("Weaviate returned {} deleting {}: {}", status, id, response.text().unwrap_or_default()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `k` records most similar to the query, among those matching `filter` when given
+    pub fn search(&self, query: &Array1<f32>, k: usize, filter: Option<&Filter>) -> Result<Vec<VectorDbHit>> {
+        let mut arguments = format!("nearVector: {{vector: {}}}, limit: {}", json!(query.to_vec()), k);
+        if let Some(filter) = filter {
+            arguments.push_str(&format!(", where: {}", where_clause(filter)));
+        }
+        let graphql = format!(
+            "{{ Get {{ {}({}) {{ record_id text metadata_json _additional {{ distance }} }} }} }}",
+            self.config.class, arguments,
+        );
+
+        let response: Value = vector_db::send(self.client.post(self.endpoint("graphql")), &json!({ "query": graphql }), self.config.api_key.as_deref())?;
+        if let Some(errors) = response["errors"].as_array().filter(|errors| !errors.is_empty()) {
+            let messages: Vec<&str> = errors.iter().filter_map(|error| error["message"].as_str()).collect();
+            return Err(err!("Weaviate query failed: {}", messages.join("; ")));
+        }
+
+        let hits = response["data"]["Get"][&self.config.class].as_array()
+            .ok_or_else(|| err!("Unexpected Weaviate query response"))?;
+        hits.iter()
+            .map(|hit| {
+                let metadata: HashMap<String, String> = match hit["metadata_json"].as_str() {
+                    Some(json) if !json.is_empty() => serde_json::from_str(json)?,
+                    _ => HashMap::new(),
+                };
+                Ok(VectorDbHit {
+                    id: hit["record_id"].as_str().unwrap_or_default().to_string(),
+                    score: 1.0 - hit["_additional"]["distance"].as_f64().unwrap_or(1.0) as f32,
+                    text: hit["text"].as_str().filter(|text| !text.is_empty()).map(str::to_string),
+                    metadata,
+                })
+            })
+            .collect()
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/v1/{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    fn authorized(&self, request: reqwest::blocking::RequestBuilder) -> reqwest::blocking::RequestBuilder {
+        match &self.config.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+}
+
+/// Property holding a metadata field, with characters GraphQL names can't contain replaced by `_`
+pub fn property_name(field: &str) -> String {
+    let field: String = field.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    format!("{}{}", METADATA_PREFIX, field)
+}
+
+/// A UUID derived from a record id, so that upserting the same id replaces the object
+pub fn object_id(id: &str) -> String {
+    let mut bytes: [u8; 16] = Sha256::digest(id.as_bytes())[..16].try_into().unwrap_or_default();
+    // Version 5 (name-based) and RFC 4122 variant bits
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// A filter as a GraphQL `where` argument
+///
+/// Weaviate has no negation operator, so `!` is pushed down to the comparisons, inverting them.
+pub fn where_clause(filter: &Filter) -> String {
+    clause(filter, false)
+}
+
+fn clause(filter: &Filter, negated: bool) -> String {
+    match (filter, negated) {
+        (Filter::Compare { field, op, value }, _) => {
+            let op = match (op, negated) {
+                (CompareOp::Eq, false) | (CompareOp::Ne, true) => "Equal",
+                (CompareOp::Ne, false) | (CompareOp::Eq, true) => "NotEqual",
+                (CompareOp::Lt, false) | (CompareOp::Ge, true) => "LessThan",
+                (CompareOp::Le, false) | (CompareOp::Gt, true) => "LessThanEqual",
+                (CompareOp::Gt, false) | (CompareOp::Le, true) => "GreaterThan",
+                (CompareOp::Ge, false) | (CompareOp::Lt, true) => "GreaterThanEqual",
+            };
+            let value = match value {
+                Literal::Text(text) => format!("valueText: {}", Value::String(text.clone())),
+                Literal::Number(number) => format!("valueNumber: {}", number),
+                Literal::Bool(flag) => format!("valueBoolean: {}", flag),
+            };
+            format!("{{path: [{}], operator: {}, {}}}", Value::String(property_name(field)), op, value)
+        }
+        (Filter::And(left, right), false) | (Filter::Or(left, right), true) => {
+            format!("{{operator: And, operands: [{}, {}]}}", clause(left, negated), clause(right, negated))
+        }
+        (Filter::Or(left, right), false) | (Filter::And(left, right), true) => {
+            format!("{{operator: Or, operands: [{}, {}]}}", clause(left, negated), clause(right, negated))
+        }
+        (Filter::Not(inner), _) => clause(inner, !negated),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_filters_and_ids() -> Result<()> {
+        let filter = Filter::parse(r#"metadata.lang == "en" && !(year < 2022 || draft == true)"#)?;
+        assert_eq!(
+            where_clause(&filter),
+            concat!(
+                r#"{operator: And, operands: [{path: ["meta_lang"], operator: Equal, valueText: "en"}, "#,
+                r#"{operator: And, operands: [{path: ["meta_year"], operator: GreaterThanEqual, valueNumber: 2022}, "#,
+                r#"{path: ["meta_draft"], operator: NotEqual, valueBoolean: true}]}]}"#,
+            ),
+        );
+
+        let id = object_id("guide.md#3");
+        assert_eq!((id.len(), &id[14..15]), (36, "5"));
+        assert_eq!(id, object_id("guide.md#3"));
+        assert_eq!(property_name("source-file"), "meta_source_file");
+        Ok(())
+    }
+}
@@ -1,17 +1,41 @@
 pub mod embedding;
-pub mod utils;  // This refers to the src/utils directory with mod.rs
+pub mod error;
+pub mod utils;
 pub mod models;
+pub mod analysis;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod metrics;
+#[cfg(all(feature = "server", not(target_arch = "wasm32")))]
+pub mod server;
+pub mod pipeline;
+pub mod search;
+pub mod rag;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod eval;
+pub mod integrations;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
 
-// Define the protobuf module
-pub mod proto {
-    // Include the generated rust code from the protobuf compiler
+#[cfg(feature = "proto-store")]
+pub mod collection;
+
+// Types generated from proto/embeddings.proto, the public API uses the wrappers in `collection`
+#[cfg(feature = "proto-store")]
+pub(crate) mod proto {
     include!(concat!(env!("OUT_DIR"), "/embeddings.rs"));
 }
 
+#[cfg(test)]
+pub(crate) mod test_support;
+
 // Re-export commonly used items
-pub use embedding::{Embedder, CachedEmbedder, EmbeddedText};
+pub use error::{Error, Result};
+pub use embedding::{Embedder, CachedEmbedder, EmbeddedText, EnsembleEmbedder, EnsembleStrategy, SparseEmbedding};
+#[cfg(feature = "torch")]
 pub use models::mini_lm::MiniLMEmbedder;
 pub use models::ModelConfig;
+pub use utils::concurrency::ConcurrencyConfig;
 
 /// Version of the rust-embed library
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -35,6 +59,11 @@ pub const HAS_MPS: bool = true;
 pub const HAS_MPS: bool = false;
 
 /// Initialize the library
-pub fn initialize() -> anyhow::Result<()> {
+pub fn initialize() -> Result<()> {
     utils::initialize()
+}
+
+/// Initialize the library with explicit thread limits for rayon and libtorch
+pub fn initialize_with(concurrency: &ConcurrencyConfig) -> Result<()> {
+    utils::initialize_with(concurrency)
 } 
\ No newline at end of file
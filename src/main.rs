@@ -1,12 +1,26 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use ndarray::s;
 use rust_embed::{
+    analysis::{self, KeywordOptions, TopicOptions},
+    collection::{shard, Modality, ShardWriter, ShardedCollection, StoredCollection, StoredEmbedding, StoredVector},
+    embedding::BatchSummary,
+    eval,
+    ConcurrencyConfig,
+    models::clip::{ClipConfig, ClipEmbedder},
+    models::code::CodeEmbedder,
     models::mini_lm::MiniLMEmbedder,
-    utils,
+    models::{registry::ModelSpec, InputType},
+    Embedder,
+    pipeline::{self, code::{self, CodeOptions}, watch::{self, WatchConfig}, Chunker, Extractor, Pipeline, Preprocessor},
+    search::{self, HybridConfig},
+    server::{self, daemon::{self, DaemonClient}, HostedModelConfig, McpServer, ModelRouter, SearchCorpus, ServerConfig},
+    utils::{self, package::{self, PackageConfig, PackageTarget}, reproducibility::ReproducibilityConfig, SaveOptions},
 };
-use std::path::PathBuf;
-use log::{info, warn, debug};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn, debug};
+use tracing_subscriber::EnvFilter;
 
 /// Command line arguments
 #[derive(Parser, Debug)]
@@ -16,14 +30,50 @@ struct Args {
     #[arg(short, long)]
     text: Option<String>,
     
-    /// File containing text to embed (one text per line)
+    /// File containing text to embed (one text per line), or a PDF embedded in chunks of each page
     #[arg(short, long)]
     file: Option<PathBuf>,
     
+    /// CSV or TSV file whose --text-column holds the texts to embed
+    #[arg(long, conflicts_with = "file")]
+    csv: Option<PathBuf>,
+    
+    /// Column of the --csv file holding the texts
+    #[arg(long, default_value = "text", requires = "csv")]
+    text_column: String,
+    
+    /// Column of the --csv file holding an id stored with each embedding
+    #[arg(long, requires = "csv")]
+    id_column: Option<String>,
+    
+    /// JSON Lines file whose --text-field holds the texts to embed
+    #[arg(long, conflicts_with_all = ["file", "csv"])]
+    jsonl: Option<PathBuf>,
+    
+    /// Field of the --jsonl records holding the texts, a dotted path for nested objects
+    #[arg(long, default_value = "text", requires = "jsonl")]
+    text_field: String,
+    
+    /// Comma-separated fields of the --jsonl records stored as metadata with each embedding
+    #[arg(long, value_delimiter = ',', requires = "jsonl")]
+    meta_fields: Vec<String>,
+    
+    /// Image to embed with CLIP (can be repeated)
+    #[arg(long)]
+    image: Vec<PathBuf>,
+    
+    /// Directory with the CLIP ONNX export used for --image
+    #[arg(long)]
+    clip_model: Option<PathBuf>,
+    
     /// Output file for the embeddings
     #[arg(short, long)]
     output: Option<PathBuf>,
     
+    /// Write the output as shards of this many embeddings plus a manifest, for corpora too large for one file
+    #[arg(long, requires = "output")]
+    shard_size: Option<usize>,
+    
     /// Create a standalone binary package
     #[arg(long)]
     package: Option<PathBuf>,
@@ -31,24 +81,481 @@ struct Args {
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
+    
+    /// Load the model in this process even when a daemon is running
+    #[arg(long)]
+    no_daemon: bool,
+    
+    /// Seconds a batch may run before its texts are skipped as failed, so a hung GPU doesn't stall the job (same as RUST_EMBED_INFERENCE_TIMEOUT)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    inference_timeout: Option<u64>,
+    
+    /// Forbid network access, libtorch and model weights must already be on disk (same as RUST_EMBED_OFFLINE=1)
+    #[arg(long, global = true)]
+    offline: bool,
+    
+    /// Threads for parallel batches (same as RUST_EMBED_MAX_THREADS)
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+    
+    /// Threads libtorch uses within one operation (same as RUST_EMBED_TORCH_THREADS)
+    #[arg(long, global = true)]
+    torch_threads: Option<usize>,
+    
+    /// Write byte-identical files on every run, optionally with a seed (same as RUST_EMBED_REPRODUCIBLE)
+    #[arg(long, global = true, value_name = "SEED", num_args = 0..=1, default_missing_value = "0")]
+    reproducible: Option<u64>,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Subcommands, the flags above embed texts when no subcommand is given
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve embeddings over HTTP from one or more models
+    Serve {
+        /// TOML file declaring the server settings and hosted models
+        #[arg(short, long)]
+        config: PathBuf,
+        
+        /// Serve JSON-RPC on this Unix domain socket instead of HTTP
+        #[arg(long, conflicts_with = "stdio")]
+        uds: Option<PathBuf>,
+        
+        /// Serve JSON-RPC over stdin and stdout instead of HTTP
+        #[arg(long)]
+        stdio: bool,
+    },
+    
+    /// Keep the model loaded and embed for later invocations of the CLI over a local socket
+    Daemon {
+        /// TOML file declaring the hosted models, as for `serve`, MiniLM is hosted without one
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+        
+        /// Socket to listen on, defaults to RUST_EMBED_DAEMON_SOCKET or rust_embed.sock in the runtime directory
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+    
+    /// Serve embed and search tools to MCP clients over stdin and stdout
+    Mcp {
+        /// TOML file declaring the hosted models, as for `serve`
+        #[arg(short, long)]
+        config: PathBuf,
+        
+        /// Embedding file with stored texts to offer through the search tool
+        #[arg(long)]
+        corpus: Option<PathBuf>,
+    },
+    
+    /// Run a declarative embedding pipeline described in a TOML file
+    Run {
+        /// Pipeline definition
+        pipeline: PathBuf,
+    },
+    
+    /// Run a streaming embedding worker described in a TOML file, consuming from Kafka or NATS
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    Stream {
+        /// Stream worker definition
+        config: PathBuf,
+    },
+    
+    /// Keep an index of a directory's files embedded, re-embedding files as they change
+    Watch {
+        /// Directory to monitor
+        #[arg(short, long)]
+        dir: PathBuf,
+        
+        /// Index file holding the embedded chunks, created if missing, one under the indexes directory by default
+        #[arg(short, long)]
+        index: Option<PathBuf>,
+        
+        /// Only embed files with these extensions, such as md or txt
+        #[arg(short, long)]
+        extension: Vec<String>,
+    },
+    
+    /// Embed a codebase for semantic code search, one chunk per function, type or class
+    Embed {
+        /// Directory of source files to walk
+        #[arg(long)]
+        repo: PathBuf,
+        
+        /// Language of the files to embed, such as rust or python, or auto for every supported language
+        #[arg(long, default_value = "auto")]
+        lang: String,
+        
+        /// Longest chunk in lines, longer definitions are split
+        #[arg(long, default_value_t = 120)]
+        max_lines: usize,
+        
+        /// Directory with a code model's ONNX export, MiniLM is used without one
+        #[arg(long)]
+        code_model: Option<PathBuf>,
+        
+        /// Output file for the embeddings, each stored with its file path and line range
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    
+    /// Extract the keywords that best represent a document
+    Keywords {
+        /// Document text
+        #[arg(short, long, conflicts_with = "file")]
+        text: Option<String>,
+        
+        /// File containing the document
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        
+        /// Number of keywords to return
+        #[arg(short = 'k', long, default_value_t = 5)]
+        top_k: usize,
+        
+        /// Maximum number of words per keyword phrase
+        #[arg(long, default_value_t = 2)]
+        max_ngram: usize,
+        
+        /// Diversify results with maximal marginal relevance (0.0 to 1.0)
+        #[arg(long)]
+        diversity: Option<f32>,
+    },
+    
+    /// Discover topics in a stored embedding collection
+    Topics {
+        /// Embedding file with stored texts
+        #[arg(short = 'e', long)]
+        embedding_file: PathBuf,
+        
+        /// Number of topics to discover
+        #[arg(short = 'n', long, default_value_t = 8)]
+        num_topics: usize,
+        
+        /// Number of keywords per topic
+        #[arg(short = 'k', long, default_value_t = 8)]
+        keywords: usize,
+        
+        /// Seed for clustering
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    
+    /// Bundle the binary, libtorch and models into a standalone release package
+    Package {
+        /// Directory to write the package into
+        output: PathBuf,
+        
+        /// Platform to package for (macos-arm64, macos-x86_64, linux-x86_64, linux-aarch64, windows-x86_64), this one by default
+        #[arg(long)]
+        target: Option<PackageTarget>,
+        
+        /// rust_embed binary built for the target, required when packaging for another platform
+        #[arg(long)]
+        binary: Option<PathBuf>,
+        
+        /// libtorch installation for the target, required when packaging for another platform
+        #[arg(long)]
+        libtorch: Option<PathBuf>,
+        
+        /// Model directory to bundle (can be repeated)
+        #[arg(long)]
+        model: Vec<PathBuf>,
+        
+        /// Also write the package as a .tar.gz archive
+        #[arg(long)]
+        tarball: bool,
+    },
+    
+    /// Check libtorch, MPS, the model cache and its permissions, and print how to fix what is wrong
+    Doctor,
+    
+    /// Manage the downloaded models in the cache
+    Model {
+        #[command(subcommand)]
+        command: ModelCommand,
+    },
+    
+    /// Build and tune approximate search indexes
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+    
+    /// Measure embedding quality on a benchmark
+    Eval {
+        #[command(subcommand)]
+        command: EvalCommand,
+    },
+    
+    /// Show the model and provenance recorded in an embedding file
+    Info {
+        /// Embedding file to describe, or the path a sharded collection was written to
+        embedding_file: PathBuf,
+    },
+    
+    /// Combine collections made by the same model, such as the outputs of jobs on several machines
+    Merge {
+        /// Collections to combine, in order, each a file or the path a sharded collection was written to
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        
+        /// File to write the combined collection to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    
+    /// Split a collection into parts of nearly equal size, written as NAME-000.pb, NAME-001.pb and so on
+    Split {
+        /// Collection to split
+        input: PathBuf,
+        
+        /// Number of parts
+        #[arg(long)]
+        chunks: usize,
+        
+        /// Directory for the parts, the input's directory by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Find the stored texts most similar to a query, reading a sharded collection one shard at a time
+    Search {
+        /// Embedding file, or the path a sharded collection was written to
+        #[arg(short = 'e', long)]
+        embedding_file: PathBuf,
+        
+        /// Text to search for
+        #[arg(short, long)]
+        query: String,
+        
+        /// Number of results
+        #[arg(short = 'k', long, default_value_t = 10)]
+        top_k: usize,
+        
+        /// Only search embeddings whose metadata matches, e.g. 'metadata.lang == "en" && metadata.year >= 2022'
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    
+    /// Compare models side by side on the latency, memory and retrieval quality of the same corpus and queries
+    Compare {
+        /// Comma-separated registry names, each optionally followed by =DIR with its weights (e.g. minilm,bge-small=models/bge-small)
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        models: Vec<String>,
+        
+        /// Corpus with one document per line
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Queries as plain lines, id<TAB>text lines or BEIR JSON Lines
+        #[arg(short, long)]
+        queries: PathBuf,
+        
+        /// Relevance judgments for recall, nDCG and MRR, documents are numbered from 0 in file order, skipping blank lines
+        #[arg(long)]
+        qrels: Option<PathBuf>,
+        
+        /// Comma-separated cutoffs for the retrieval metrics
+        #[arg(short, long, value_delimiter = ',', default_value = "1,5,10")]
+        k: Vec<usize>,
+        
+        /// Device to run the models on (auto, cpu, mps, cuda or cuda:N)
+        #[arg(long)]
+        device: Option<String>,
+    },
+}
+
+/// Approximate search index tools
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Sweep the IVF nprobe setting, measuring recall against exact search and latency, and recommend one
+    Tune {
+        /// Embedding file, or sharded collection, the index is built over
+        #[arg(long)]
+        index: PathBuf,
+        
+        /// Embedding file of query vectors, made by the same model
+        #[arg(short, long)]
+        queries: PathBuf,
+        
+        /// Inverted lists, the square root of the number of vectors by default
+        #[arg(long)]
+        nlist: Option<usize>,
+        
+        /// Compress the index with this many product quantization subspaces
+        #[arg(long)]
+        pq_subspaces: Option<usize>,
+        
+        /// Results compared with exact search per query
+        #[arg(short, long, default_value_t = 10)]
+        k: usize,
+        
+        /// Recall@k the recommended nprobe must reach
+        #[arg(long, default_value_t = 0.95)]
+        target_recall: f32,
+    },
+}
+
+/// Quality benchmarks
+#[derive(Subcommand, Debug)]
+enum EvalCommand {
+    /// Spearman correlation of cosine similarities with the gold scores of STS-B style sentence pairs
+    Sts {
+        /// TSV file of sentence pairs, such as the STS-B dev or test split
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        #[command(flatten)]
+        model: EvalModel,
+        
+        /// Fail when the Spearman correlation is below this, to guard a backend or quantization change in CI
+        #[arg(long)]
+        min_spearman: Option<f32>,
+    },
+    
+    /// Recall@k, MRR and nDCG of searching an embedded corpus with judged queries
+    Retrieval {
+        /// Embedding file of the corpus, saved with its texts and with ids matching the qrels (or judged by position)
+        #[arg(short, long)]
+        corpus: PathBuf,
+        
+        /// Queries as BEIR JSON Lines (`_id`, `text`) or id<TAB>text lines
+        #[arg(short, long)]
+        queries: PathBuf,
+        
+        /// Relevance judgments as BEIR TSV or TREC qrels
+        #[arg(long)]
+        qrels: PathBuf,
+        
+        /// Comma-separated cutoffs for recall and nDCG
+        #[arg(short, long, value_delimiter = ',', default_value = "1,5,10")]
+        k: Vec<usize>,
+        
+        /// Search with BM25 and embeddings fused by reciprocal rank fusion
+        #[arg(long)]
+        hybrid: bool,
+        
+        /// Weight of the embedding ranking in hybrid search
+        #[arg(long, default_value_t = 1.0, requires = "hybrid")]
+        dense_weight: f32,
+        
+        /// Weight of the BM25 ranking in hybrid search
+        #[arg(long, default_value_t = 1.0, requires = "hybrid")]
+        lexical_weight: f32,
+        
+        #[command(flatten)]
+        model: EvalModel,
+    },
+}
+
+/// The model an evaluation runs
+#[derive(clap::Args, Debug)]
+struct EvalModel {
+    /// Registry name of the model to evaluate
+    #[arg(short, long, default_value = "minilm")]
+    model: String,
+    
+    /// Local directory with the model weights, required for ONNX models
+    #[arg(long)]
+    model_path: Option<PathBuf>,
+    
+    /// Device to run on (auto, cpu, mps, cuda or cuda:N)
+    #[arg(long)]
+    device: Option<String>,
+    
+    /// Evaluate the int8 quantized export of an ONNX model
+    #[arg(long)]
+    quantized: bool,
+}
+
+impl EvalModel {
+    /// Registry spec of the model
+    fn spec(self) -> ModelSpec {
+        ModelSpec { device: self.device, model_path: self.model_path, quantized: self.quantized, ..ModelSpec::new(&self.model) }
+    }
+}
+
+/// Model cache maintenance
+#[derive(Subcommand, Debug)]
+enum ModelCommand {
+    /// Remove incomplete downloads and corrupt models from the cache
+    Gc {
+        /// Also remove models that have not been loaded for this many days
+        #[arg(long)]
+        max_unused_days: Option<u64>,
+        
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Load the default model and print its metadata and memory use
+    Info,
+    
+    /// Pack a model directory into one archive for MiniLMEmbedder::from_bundle or the embedded-model feature
+    Bundle {
+        /// Archive to write, conventionally ending in .tar.zst
+        output: PathBuf,
+        
+        /// Registry name of the model the directory holds
+        #[arg(short, long, default_value = "minilm")]
+        model: String,
+        
+        /// Directory with the model files, MiniLM is downloaded into the cache when omitted
+        #[arg(long)]
+        model_path: Option<PathBuf>,
+        
+        /// The directory holds the int8 export of an ONNX model
+        #[arg(long)]
+        quantized: bool,
+    },
 }
 
 fn main() -> Result<()> {
-    // Initialize logging
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    // Variables libtorch reads, such as PYTORCH_ENABLE_MPS_FALLBACK, are set by restarting before any thread exists
+    utils::libtorch::reexec_with_runtime_env()?;
+    
+    // Initialize tracing output, filtered by RUST_LOG, on stderr so stdout stays free for MCP messages
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_writer(std::io::stderr)
+        .init();
     
     // Parse command line arguments
     let args = Args::parse();
     
     if args.verbose {
-        log::info!("Verbose mode enabled");
+        tracing::info!("Verbose mode enabled");
+    }
+    
+    if args.offline {
+        utils::offline::enable();
+    }
+    if utils::offline::is_enabled() {
+        info!("Offline mode, network access is disabled");
+    }
+    
+    // The environment is diagnosed as it is, before initialization downloads libtorch or changes anything
+    if matches!(args.command, Some(Command::Doctor)) {
+        return run_doctor();
     }
     
     // Initialize Apple Silicon specific utilities
-    match utils::initialize() {
+    let concurrency = ConcurrencyConfig { max_threads: args.threads, torch_intraop_threads: args.torch_threads };
+    match utils::initialize_with(&concurrency) {
         Ok(_) => info!("Initialization successful"),
         Err(e) => warn!("Initialization warning: {}", e),
     }
+    // Applied after the thread limits, which it overrides to run libtorch on one thread
+    let reproducibility = args.reproducible
+        .map(ReproducibilityConfig::with_seed)
+        .or_else(ReproducibilityConfig::from_env);
+    if let Some(config) = reproducibility {
+        config.apply();
+    }
     
     // Report architecture
     if utils::is_apple_silicon() {
@@ -57,8 +564,7 @@ fn main() -> Result<()> {
             info!("Metal Performance Shaders acceleration enabled");
         }
     } else {
-        warn!("Running on non-Apple Silicon architecture");
-        warn!("This build is optimized for Apple M-series processors");
+        info!("Running on {}, using the CPU backend", std::env::consts::ARCH);
     }
     
     // If packaging is requested, create a standalone binary
@@ -70,14 +576,36 @@ fn main() -> Result<()> {
         return Ok(());
     }
     
-    // Create the MiniLM embedder
-    let mut embedder = MiniLMEmbedder::new();
+    // Subcommands manage their own models
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
     
-    // Initialize the model (download and load both tokenizer and model)
-    info!("Initializing the embedder...");
-    embedder.initialize()?;
+    // Images are embedded with CLIP rather than MiniLM
+    if !args.image.is_empty() {
+        return run_images(&args.image, args.clip_model, args.output.as_deref());
+    }
     
-    // Output info about the model
+    // A running daemon already has the model loaded, which saves loading it again
+    if !args.no_daemon {
+        if let Some(client) = DaemonClient::connect(daemon::default_socket_path()) {
+            info!("Embedding through the daemon on {}", client.path().display());
+            return embed_inputs(&client, args);
+        }
+    }
+    
+    // Create the MiniLM embedder, downloading and loading the tokenizer and model
+    let mut embedder = default_embedder()?;
+    if let Some(seconds) = args.inference_timeout {
+        embedder.set_inference_timeout(Some(std::time::Duration::from_secs(seconds)));
+    }
+    embed_inputs(&embedder, args)?;
+    info!("Memory use: {}", embedder.memory_stats());
+    Ok(())
+}
+
+/// Embeds the text, file, table or JSON Lines input and saves the embeddings to the output
+fn embed_inputs<E: rust_embed::Embedder>(embedder: &E, args: Args) -> Result<()> {
     info!("Using the {} model for generating embeddings.", embedder.model_name());
     info!("Embedding dimension: {}", embedder.dimension());
     
@@ -91,69 +619,653 @@ fn main() -> Result<()> {
         // Save to file if output is specified
         if let Some(output) = &args.output {
             let text_vec = vec![text];
-            utils::save_embeddings(
-                &[embedding], 
-                Some(&text_vec),
-                embedder.model_name(),
-                embedder.model_version(),
-                embedder.dimension() as i32,
-                output
-            )?;
+            let options = SaveOptions { texts: Some(&text_vec), provenance: true, ..SaveOptions::default() };
+            utils::save_embeddings_with(&[embedding], &embedder.model_info(), options, output)?;
             info!("Embedding saved to {}", output.display());
         }
-    } else if let Some(file) = args.file {
-        info!("Embedding texts from file: {}", file.display());
-        
-        // Read file line by line
-        let content = std::fs::read_to_string(file)?;
-        let texts: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+    } else if args.file.is_some() || args.csv.is_some() || args.jsonl.is_some() {
+        // Read a column of a table, a field of JSON records, a file line by line, or a PDF page by page
+        let (texts, ids, metadata) = match (&args.csv, &args.jsonl, &args.file) {
+            (Some(table), _, _) => {
+                info!("Embedding the {} column of {}", args.text_column, table.display());
+                let rows = utils::table::read_text_column(table, &args.text_column, args.id_column.as_deref())?;
+                if rows.skipped > 0 {
+                    warn!("Skipped {} row(s) with an empty {} column", rows.skipped, args.text_column);
+                }
+                (rows.texts, rows.ids, None)
+            }
+            (None, Some(jsonl), _) => {
+                info!("Embedding the {} field of {}", args.text_field, jsonl.display());
+                let records = utils::table::read_jsonl_field(jsonl, &args.text_field, &args.meta_fields)?;
+                if records.skipped > 0 {
+                    warn!("Skipped {} record(s) without a {} field", records.skipped, args.text_field);
+                }
+                (records.texts, None, Some(records.metadata))
+            }
+            (None, None, Some(file)) => {
+                info!("Embedding texts from file: {}", file.display());
+                (read_texts(file)?, None, None)
+            }
+            (None, None, None) => unreachable!(),
+        };
         
-        // Embed each line
-        let mut embeddings = Vec::with_capacity(texts.len());
+        // Embed each line, keeping each result next to its text, one shard at a time when sharding
         info!("Processing {} texts", texts.len());
+        let mut writer = match (&args.output, args.shard_size) {
+            (Some(output), Some(shard_size)) => Some(ShardWriter::create(output, &embedder.model_info(), shard_size)?),
+            _ => None,
+        };
+        let slice_size = args.shard_size.unwrap_or(texts.len()).max(1);
         
-        // Use rayon for parallel processing if we have multiple texts
-        use rayon::prelude::*;
-        if texts.len() > 1 {
-            info!("Using parallel processing for multiple texts");
-            embeddings = texts.par_iter()
-                .map(|text| {
-                    let mut local_embedder = embedder.clone();
-                    local_embedder.embed_text(text)
-                })
-                .filter_map(Result::ok)
-                .collect();
-        } else {
-            for text in &texts {
-                match embedder.embed_text(text) {
-                    Ok(embedding) => embeddings.push(embedding),
-                    Err(e) => warn!("Failed to embed text: {}", e),
+        // Failed lines are left out of the output, their texts, ids and metadata with them
+        let mut summary = BatchSummary::default();
+        let (mut kept_texts, mut embeddings) = (Vec::new(), Vec::new());
+        let (mut kept_ids, mut kept_metadata) = (Vec::new(), Vec::new());
+        let mut ids = ids.map(Vec::into_iter);
+        let mut metadata = metadata.map(Vec::into_iter);
+        for (slice_index, slice) in texts.chunks(slice_size).enumerate() {
+            let results = embedder.embed_batch_detailed(slice);
+            let slice_summary = BatchSummary::from_results(&results);
+            summary.succeeded += slice_summary.succeeded;
+            summary.failures.extend(slice_summary.failures.into_iter().map(|(index, error)| (slice_index * slice_size + index, error)));
+            
+            for (text, result) in slice.iter().zip(results) {
+                let id = ids.as_mut().and_then(Iterator::next);
+                let fields = metadata.as_mut().and_then(Iterator::next);
+                let Ok(embedding) = result else { continue };
+                match &mut writer {
+                    Some(writer) => writer.push(StoredEmbedding {
+                        id,
+                        metadata: fields.unwrap_or_default(),
+                        ..StoredEmbedding::dense(embedding, Some(text.clone()))
+                    })?,
+                    None => {
+                        kept_texts.push(text.clone());
+                        embeddings.push(embedding);
+                        kept_ids.extend(id);
+                        kept_metadata.extend(fields);
+                    }
                 }
             }
         }
-        
-        info!("Successfully embedded {} of {} texts", embeddings.len(), texts.len());
+        if summary.failures.is_empty() {
+            info!("Successfully {}", summary);
+        } else {
+            warn!("Only {}", summary);
+        }
         
         // Save to file if output is specified
         if let Some(output) = &args.output {
-            utils::save_embeddings(
-                &embeddings, 
-                Some(&texts),
-                embedder.model_name(),
-                embedder.model_version(),
-                embedder.dimension() as i32,
-                output
-            )?;
-            info!("Embeddings saved to {}", output.display());
+            if let Some(writer) = writer {
+                let manifest = writer.finish()?;
+                info!("Embeddings saved to {} shard(s) listed in {}", manifest.shards.len(), shard::manifest_path(output).display());
+            } else {
+                let options = SaveOptions {
+                    texts: Some(&kept_texts),
+                    ids: ids.is_some().then_some(kept_ids.as_slice()),
+                    metadata: &kept_metadata,
+                    provenance: true,
+                    ..SaveOptions::default()
+                };
+                utils::save_embeddings_with(&embeddings, &embedder.model_info(), options, output)?;
+                info!("Embeddings saved to {}", output.display());
+            }
         }
     } else {
-        warn!("Please provide either --text or --file argument");
+        warn!("Please provide either --text, --file, --csv or --jsonl argument");
         println!("For usage information, run with --help");
     }
     
     Ok(())
 }
 
+/// Runs a subcommand
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Serve { config, uds, stdio } => run_serve(config, uds, stdio),
+        Command::Daemon { config, socket } => run_daemon(config, socket),
+        Command::Mcp { config, corpus } => run_mcp(config, corpus),
+        Command::Run { pipeline } => run_pipeline(pipeline),
+        #[cfg(any(feature = "kafka", feature = "nats"))]
+        Command::Stream { config } => {
+            let report = rust_embed::pipeline::stream::StreamConfig::from_file(&config)?.run()?;
+            info!("Stream finished: {} message(s) in {} batch(es), {} skipped", report.messages, report.batches, report.skipped);
+            Ok(())
+        }
+        Command::Watch { dir, index, extension } => {
+            let index = index.unwrap_or_else(|| utils::paths::current().index_for(&dir));
+            info!("Keeping the index in {}", index.display());
+            let mut config = WatchConfig::new(dir, index);
+            config.options.extensions = extension;
+            run_watch(&config)
+        }
+        Command::Embed { repo, lang, max_lines, code_model, output } => {
+            let language = match lang.as_str() {
+                "auto" => None,
+                name => Some(name.parse()?),
+            };
+            let chunks = code::collect_code_chunks(&repo, &CodeOptions { language, max_lines })?;
+            info!("Found {} chunk(s) in {}", chunks.len(), repo.display());
+            match code_model {
+                Some(model_dir) => embed_code(&CodeEmbedder::new(model_dir)?, &chunks, &output),
+                None => embed_code(&default_embedder()?, &chunks, &output),
+            }
+        }
+        Command::Keywords { text, file, top_k, max_ngram, diversity } => {
+            let document = read_document(text, file)?;
+            let options = KeywordOptions {
+                ngram_range: (1, max_ngram),
+                top_k,
+                diversity,
+                ..KeywordOptions::default()
+            };
+            run_keywords(&document, &options)
+        }
+        Command::Topics { embedding_file, num_topics, keywords, seed } => {
+            let options = TopicOptions {
+                num_topics,
+                keywords_per_topic: keywords,
+                seed,
+                ..TopicOptions::default()
+            };
+            run_topics(embedding_file, &options)
+        }
+        Command::Package { output, target, binary, libtorch, model, tarball } => {
+            let target = match target {
+                Some(target) => target,
+                None => PackageTarget::host()?,
+            };
+            let config = PackageConfig { binary, libtorch, models: model, tarball, ..PackageConfig::new(target) };
+            let report = package::package(&config, &output)?;
+            info!("Package for {} created in {}", target, report.directory.display());
+            if let Some(tarball) = report.tarball {
+                info!("Archive written to {}", tarball.display());
+            }
+            Ok(())
+        }
+        Command::Model { command: ModelCommand::Gc { max_unused_days, dry_run } } => {
+            let options = utils::model_cache::GcOptions {
+                max_unused: max_unused_days.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60)),
+                dry_run,
+            };
+            run_model_gc(&options)
+        }
+        Command::Doctor => run_doctor(),
+        Command::Model { command: ModelCommand::Info } => run_model_info(),
+        Command::Model { command: ModelCommand::Bundle { output, model, model_path, quantized } } => {
+            run_model_bundle(&output, ModelSpec { model_path, quantized, ..ModelSpec::new(&model) })
+        }
+        Command::Index { command: IndexCommand::Tune { index, queries, nlist, pq_subspaces, k, target_recall } } => {
+            let options = search::TuneOptions { k, target_recall, ..search::TuneOptions::default() };
+            run_index_tune(&index, &queries, nlist, pq_subspaces, &options)
+        }
+        Command::Eval { command: EvalCommand::Sts { file, model, min_spearman } } => {
+            run_eval_sts(&file, &model.spec(), min_spearman)
+        }
+        Command::Eval { command: EvalCommand::Retrieval { corpus, queries, qrels, k, hybrid, dense_weight, lexical_weight, model } } => {
+            let options = eval::RetrievalOptions {
+                ks: k,
+                hybrid: hybrid.then(|| HybridConfig { dense_weight, lexical_weight, ..HybridConfig::default() }),
+            };
+            // Queries get the query instruction of models that have one, the corpus was embedded as passages
+            let spec = ModelSpec { input_type: InputType::Query, ..model.spec() };
+            run_eval_retrieval(&corpus, &queries, &qrels, &spec, &options)
+        }
+        Command::Info { embedding_file } => run_info(&embedding_file),
+        Command::Merge { inputs, output } => {
+            let merged = utils::merge_collections(&inputs)?;
+            merged.save(&output)?;
+            info!("Merged {} embedding(s) from {} collection(s) into {}", merged.embeddings.len(), inputs.len(), output.display());
+            Ok(())
+        }
+        Command::Split { input, chunks, output } => run_split(&input, chunks, output.as_deref()),
+        Command::Search { embedding_file, query, top_k, filter } => {
+            let filter = filter.as_deref().map(search::Filter::parse).transpose()?;
+            run_search(&embedding_file, &query, top_k, filter.as_ref())
+        }
+        Command::Compare { models, file, queries, qrels, k, device } => {
+            let specs = models.iter()
+                .map(|model| {
+                    let (name, model_path) = match model.split_once('=') {
+                        Some((name, path)) => (name, Some(PathBuf::from(path))),
+                        None => (model.as_str(), None),
+                    };
+                    ModelSpec { device: device.clone(), model_path, ..ModelSpec::new(name.trim()) }
+                })
+                .collect::<Vec<_>>();
+            let options = eval::CompareOptions { ks: k, ..eval::CompareOptions::default() };
+            run_compare(&specs, &file, &queries, qrels.as_deref(), &options)
+        }
+    }
+}
+
+/// Builds an IVF index over a collection and prints how its recall and latency change with nprobe
+fn run_index_tune(
+    path: &Path,
+    queries: &Path,
+    nlist: Option<usize>,
+    pq_subspaces: Option<usize>,
+    options: &search::TuneOptions,
+) -> Result<()> {
+    let vectors: Vec<_> = utils::merge_collections(&[path])?.embeddings
+        .into_iter()
+        .filter_map(|embedding| match embedding.vector {
+            StoredVector::Dense(vector) => Some(vector),
+            StoredVector::Sparse(_) => None,
+        })
+        .collect();
+    let (queries, _) = utils::load_embeddings(queries)?;
+    
+    let config = search::IvfConfig {
+        nlist: nlist.unwrap_or_else(|| (vectors.len() as f64).sqrt().round().max(1.0) as usize),
+        pq: pq_subspaces.map(|subspaces| search::PqConfig { subspaces, ..search::PqConfig::default() }),
+        ..search::IvfConfig::default()
+    };
+    info!("Building an index of {} lists over {} vectors", config.nlist, vectors.len());
+    let mut index = search::IvfIndex::build(&vectors, &config)?;
+    info!("Index takes {:.1} MB", index.memory_bytes() as f64 / 1_048_576.0);
+    
+    println!("{}", search::tune_nprobe(&mut index, &vectors, &queries, options));
+    Ok(())
+}
+
+/// Evaluates a model on STS pairs and prints its correlations
+fn run_eval_sts(file: &Path, spec: &ModelSpec, min_spearman: Option<f32>) -> Result<()> {
+    let pairs = eval::load_sts_pairs(file)?;
+    info!("Loaded {} sentence pair(s) from {}", pairs.len(), file.display());
+    let embedder = spec.build()?;
+    
+    let report = eval::evaluate_sts(&embedder, &pairs)?;
+    println!("{}", report);
+    match min_spearman {
+        Some(min) if report.spearman < min => Err(anyhow::anyhow!(
+            "Spearman correlation {:.4} is below the required {:.4}", report.spearman, min
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Evaluates searching a corpus with judged queries and prints the metrics
+fn run_eval_retrieval(corpus: &Path, queries: &Path, qrels: &Path, spec: &ModelSpec, options: &eval::RetrievalOptions) -> Result<()> {
+    let corpus = eval::RetrievalCorpus::load(corpus)?;
+    let queries = eval::load_queries(queries)?;
+    let qrels = eval::load_qrels(qrels)?;
+    info!("Searching {} document(s) with {} queries", corpus.ids.len(), queries.len());
+    let embedder = spec.build()?;
+    
+    let report = eval::evaluate_retrieval(&embedder, &corpus, &queries, &qrels, options)?;
+    if report.skipped > 0 {
+        warn!("Skipped {} queries without relevant documents in the qrels", report.skipped);
+    }
+    println!("{}", report);
+    Ok(())
+}
+
+/// Compares models on a corpus and queries and prints a table with a column per model
+fn run_compare(specs: &[ModelSpec], file: &Path, queries: &Path, qrels: Option<&Path>, options: &eval::CompareOptions) -> Result<()> {
+    for spec in specs {
+        spec.kind()?;
+    }
+    let texts: Vec<String> = utils::storage::read_to_string(file)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    let queries = eval::load_queries(queries)?;
+    let qrels = qrels.map(eval::load_qrels).transpose()?;
+    info!("Comparing {} model(s) on {} document(s) and {} queries", specs.len(), texts.len(), queries.len());
+    
+    let comparisons = eval::compare_models(specs, &texts, &queries, qrels.as_ref(), options)?;
+    print!("{}", eval::format_comparison(&comparisons, options));
+    Ok(())
+}
+
+/// Prints the model that made a collection and how it was run
+fn run_info(embedding_file: &Path) -> Result<()> {
+    // Every shard records the same model and provenance, so the first one describes them all
+    let (collection, total) = if shard::is_sharded(embedding_file) {
+        let sharded = ShardedCollection::open(embedding_file)?;
+        println!("Shards: {}", sharded.manifest().shards.len());
+        let first = sharded.shards().next().transpose()?;
+        (first.unwrap_or_else(|| StoredCollection::for_model(&Default::default())), sharded.len())
+    } else {
+        let collection = StoredCollection::load(embedding_file)?;
+        let total = collection.embeddings.len();
+        (collection, total)
+    };
+    let sparse = collection.embeddings.iter().any(|embedding| matches!(embedding.vector, StoredVector::Sparse(_)));
+    println!("Model: {} {}", collection.model_name, collection.model_version);
+    println!("Dimension: {}", collection.dimension);
+    println!("Embeddings: {}{}", total, if sparse { " (sparse)" } else { "" });
+    
+    let Some(provenance) = collection.provenance else {
+        println!("Provenance: not recorded, the file predates it");
+        return Ok(());
+    };
+    let unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+    println!("Written by: rust_embed {}", provenance.crate_version);
+    println!("Device: {}", unknown(provenance.device));
+    println!("Pooling: {}", unknown(provenance.pooling));
+    println!("Normalized: {}", provenance.normalized);
+    println!("Weights SHA-256: {}", unknown(provenance.weights_sha256));
+    for (option, value) in provenance.preprocessing {
+        println!("Preprocessing: {} = {}", option, value);
+    }
+    for (setting, value) in provenance.environment {
+        println!("Reproducible: {} = {}", setting, value);
+    }
+    Ok(())
+}
+
+/// Splits a collection into numbered parts named after it
+fn run_split(input: &Path, chunks: usize, output: Option<&Path>) -> Result<()> {
+    let parts = utils::split_collection(StoredCollection::load(input)?, chunks)?;
+    let directory = output.or_else(|| input.parent()).unwrap_or(Path::new(""));
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().map_or(String::new(), |extension| format!(".{}", extension.to_string_lossy()));
+    for (index, part) in parts.iter().enumerate() {
+        let path = directory.join(format!("{}-{:03}{}", stem, index, extension));
+        part.save(&path)?;
+        info!("Wrote {} embedding(s) to {}", part.embeddings.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Prints the stored texts closest to a query, among those whose metadata matches `filter` when there is one
+fn run_search(embedding_file: &Path, query: &str, k: usize, filter: Option<&search::Filter>) -> Result<()> {
+    let embedder = default_embedder()?;
+    let query = rust_embed::Embedder::embed_text(&embedder, query)?;
+    let hits: Vec<(usize, f32, Option<String>)> = if shard::is_sharded(embedding_file) {
+        let collection = ShardedCollection::open(embedding_file)?;
+        collection.check_model(&rust_embed::Embedder::model_info(&embedder))?;
+        let hits = match filter {
+            Some(filter) => collection.search_filtered(&query, k, filter)?,
+            None => collection.search(&query, k)?,
+        };
+        hits.into_iter()
+            .map(|hit| (hit.index, hit.score, hit.embedding.text))
+            .collect()
+    } else if let Some(filter) = filter {
+        // The filter needs each embedding's metadata, so the file is read once as a whole collection
+        let (embeddings, records): (Vec<_>, Vec<_>) = utils::load_collection_checked(embedding_file, &embedder)?
+            .embeddings
+            .into_iter()
+            .map(|embedding| {
+                let vector = match embedding.vector {
+                    StoredVector::Dense(vector) => vector,
+                    // Never scored, its length doesn't match the query
+                    StoredVector::Sparse(_) => ndarray::Array1::zeros(0),
+                };
+                (vector, (embedding.text, embedding.metadata))
+            })
+            .unzip();
+        search::top_k_where(&query, &embeddings, k, |index| filter.matches(&records[index].1))
+            .into_iter()
+            .map(|hit| (hit.index, hit.score, records[hit.index].0.clone()))
+            .collect()
+    } else {
+        let (embeddings, texts) = utils::load_embeddings_checked(embedding_file, &embedder)?;
+        search::top_k(&query, &embeddings, k)
+            .into_iter()
+            .map(|hit| (hit.index, hit.score, texts.as_ref().map(|texts| texts[hit.index].clone())))
+            .collect()
+    };
+    
+    for (index, score, text) in hits {
+        println!("{:.4}  #{}  {}", score, index, text.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Prints the default model's metadata and how much memory it takes once loaded
+fn run_model_info() -> Result<()> {
+    let embedder = default_embedder()?;
+    let info = rust_embed::Embedder::model_info(&embedder);
+    println!("Model: {} {}", info.name, info.version);
+    println!("Dimension: {}", info.dimension);
+    if let Some(max_tokens) = info.max_tokens {
+        println!("Max tokens: {}", max_tokens);
+    }
+    if let Some(pooling) = &info.pooling {
+        println!("Pooling: {}", pooling);
+    }
+    println!("Normalized: {}", info.normalized);
+    if let Some(license) = &info.license {
+        println!("License: {}", license);
+    }
+    if let Some(source_url) = &info.source_url {
+        println!("Source: {}", source_url);
+    }
+    println!("Memory: {}", embedder.memory_stats());
+    let mps = utils::libtorch::mps_status();
+    println!("MPS: {} (detected by {})", if mps.available { "available" } else { "unavailable" }, mps.detection);
+    Ok(())
+}
+
+/// Prints the environment checks, failing when any of them failed
+fn run_doctor() -> Result<()> {
+    let report = utils::doctor::diagnose();
+    print!("{}", report);
+    match report.failures() {
+        0 => Ok(()),
+        failures => anyhow::bail!("{} check(s) failed", failures),
+    }
+}
+
+/// Packs a model directory, or the downloaded default model, into a bundle
+fn run_model_bundle(output: &Path, spec: ModelSpec) -> Result<()> {
+    use rust_embed::models::{mini_lm, registry::ModelKind, HubSource};
+    
+    let kind = spec.kind()?;
+    let model_dir = match (&spec.model_path, kind) {
+        (Some(model_path), _) => model_path.clone(),
+        (None, ModelKind::MiniLm) => HubSource::default().fetch(mini_lm::HUB_MODEL_FILES, "the MiniLM weights")?,
+        (None, _) => anyhow::bail!("Bundling '{}' needs --model-path", kind.name()),
+    };
+    
+    // Loading the model checks the directory holds what the bundle claims and reads its identity
+    info!("Loading {} from {} to check it", kind.name(), model_dir.display());
+    let embedder = ModelSpec { model_path: Some(model_dir.clone()), ..spec.clone() }.build()?;
+    let info = rust_embed::Embedder::model_info(&embedder);
+    let model = utils::bundle::BundleModel {
+        kind: kind.name().to_string(),
+        model_name: info.name,
+        model_version: info.version,
+        dimension: info.dimension,
+        quantized: spec.quantized,
+    };
+    let manifest = utils::bundle::create_bundle(&model_dir, output, model)?;
+    let bytes: u64 = manifest.files.values().map(|file| file.size).sum();
+    println!("Bundled {} files ({:.1} MB) into {}", manifest.files.len(), bytes as f64 / 1_048_576.0, output.display());
+    Ok(())
+}
+
+/// Prunes the model cache and prints what was removed
+fn run_model_gc(options: &utils::model_cache::GcOptions) -> Result<()> {
+    let report = utils::model_cache::gc(options)?;
+    for (path, reason) in &report.removed {
+        println!("{}\t{}", path.display(), reason);
+    }
+    println!(
+        "{} {} entr{}, {:.1} MB",
+        if options.dry_run { "Would remove" } else { "Removed" },
+        report.removed.len(),
+        if report.removed.len() == 1 { "y" } else { "ies" },
+        report.freed_bytes as f64 / 1_048_576.0
+    );
+    Ok(())
+}
+
+/// Prints the topics found in a stored embedding collection
+fn run_topics(embedding_file: PathBuf, options: &TopicOptions) -> Result<()> {
+    let (embeddings, texts) = utils::load_embeddings(&embedding_file)?;
+    let texts = texts.ok_or_else(|| anyhow::anyhow!("{} does not contain texts", embedding_file.display()))?;
+    
+    let report = analysis::discover_topics(&embeddings, &texts, options)?;
+    print!("{}", report);
+    Ok(())
+}
+
+/// Embeds images with CLIP and optionally saves them
+fn run_images(images: &[PathBuf], clip_model: Option<PathBuf>, output: Option<&std::path::Path>) -> Result<()> {
+    let model_dir = clip_model.unwrap_or_else(|| utils::cache_home().join("clip-vit-base-patch32"));
+    info!("Loading CLIP from {}", model_dir.display());
+    let embedder = ClipEmbedder::new(ClipConfig::new(model_dir))?;
+    
+    let embeddings = embedder.embed_images(images)?;
+    info!("Embedded {} image(s) into {} dimensions", embeddings.len(), embedder.dimension());
+    
+    if let Some(output) = output {
+        let paths: Vec<String> = images.iter().map(|path| path.display().to_string()).collect();
+        let options = SaveOptions { texts: Some(&paths), modality: Modality::Image, provenance: true, ..SaveOptions::default() };
+        utils::save_embeddings_with(&embeddings, &embedder.model_info(), options, output)?;
+        info!("Embeddings saved to {}", output.display());
+    }
+    
+    Ok(())
+}
+
+/// Reads a document given either inline or as a file
+fn read_document(text: Option<String>, file: Option<PathBuf>) -> Result<String> {
+    match (text, file) {
+        (Some(text), _) => Ok(text),
+        (None, Some(file)) => Ok(utils::storage::read_to_string(file)?),
+        (None, None) => Err(anyhow::anyhow!("Please provide either --text or --file")),
+    }
+}
+
+/// Reads the texts to embed from a file, one per line, or as the chunks of each page of a PDF
+fn read_texts(file: &std::path::Path) -> Result<Vec<String>> {
+    if !pipeline::is_pdf(file) {
+        let content = utils::storage::read_to_string(file)?;
+        return Ok(content.lines().map(|s| s.to_string()).collect());
+    }
+    
+    let pages = Extractor::Auto.extract(file)?;
+    let (chunker, preprocessor) = (Chunker::default(), Preprocessor::default());
+    let texts: Vec<String> = pages.iter()
+        .flat_map(|page| chunker.chunk(&page.text))
+        .map(|chunk| preprocessor.apply(&chunk))
+        .filter(|chunk| !chunk.is_empty())
+        .collect();
+    info!("Split {} page(s) into {} chunk(s)", pages.len(), texts.len());
+    Ok(texts)
+}
+
+/// Loads the default embedder for subcommands that need one
+fn default_embedder() -> Result<MiniLMEmbedder> {
+    // A MiniLM bundle compiled in with the embedded-model feature needs no download
+    let mut embedder = match MiniLMEmbedder::from_embedded() {
+        Ok(Some(embedder)) => embedder,
+        Ok(None) => MiniLMEmbedder::new(),
+        Err(error) => {
+            debug!("Not using the embedded model bundle: {}", error);
+            MiniLMEmbedder::new()
+        }
+    };
+    info!("Initializing the embedder...");
+    embedder.initialize()?;
+    Ok(embedder)
+}
+
+/// Embeds a directory into its index and keeps it up to date until interrupted
+fn run_watch(config: &WatchConfig) -> Result<()> {
+    let embedder = default_embedder()?;
+    watch::watch(&embedder, config, |report| {
+        info!(
+            "Index {} updated: {} chunk(s) embedded, {} unchanged, {} removed",
+            config.index.display(), report.embedded, report.unchanged, report.removed
+        );
+        true
+    })?;
+    Ok(())
+}
+
+/// Embeds code chunks and saves them with their path, language and line range as metadata
+fn embed_code<E: rust_embed::Embedder>(embedder: &E, chunks: &[code::CodeChunk], output: &Path) -> Result<()> {
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+    let metadata: Vec<_> = chunks.iter().map(code::CodeChunk::metadata).collect();
+    let embeddings = rust_embed::Embedder::embed_batch(embedder, &texts)?;
+    
+    let options = SaveOptions { texts: Some(&texts), metadata: &metadata, provenance: true, ..SaveOptions::default() };
+    utils::save_embeddings_with(&embeddings, &embedder.model_info(), options, output)?;
+    info!("Embeddings of {} chunk(s) saved to {}", chunks.len(), output.display());
+    Ok(())
+}
+
+/// Prints the keywords of a document
+fn run_keywords(document: &str, options: &KeywordOptions) -> Result<()> {
+    let embedder = default_embedder()?;
+    let keywords = analysis::extract_keywords_with(&embedder, document, options)?;
+    
+    for keyword in keywords {
+        println!("{:.4}\t{}", keyword.score, keyword.phrase);
+    }
+    Ok(())
+}
+
+/// Runs an embedding pipeline and reports what it produced
+fn run_pipeline(path: PathBuf) -> Result<()> {
+    let pipeline = Pipeline::from_file(&path)?;
+    let report = pipeline.run()?;
+    
+    info!(
+        "Pipeline finished: {} document(s), {} chunk(s), {} failed, {} dimensions in {:.2?}",
+        report.documents, report.chunks, report.failed, report.dimension, report.elapsed
+    );
+    Ok(())
+}
+
+/// Hosts the configured models and serves them over HTTP, a Unix domain socket or stdio
+fn run_serve(config: PathBuf, uds: Option<PathBuf>, stdio: bool) -> Result<()> {
+    let config = ServerConfig::from_file(&config)?;
+    
+    info!("Loading {} model(s)...", config.models.len());
+    let router = Arc::new(ModelRouter::from_config(&config)?);
+    
+    match uds {
+        Some(path) => server::rpc::serve_uds(router, &path)?,
+        None if stdio => server::rpc::serve_stdio(router)?,
+        None => server::http::serve(router, &config.server)?,
+    }
+    Ok(())
+}
+
+/// Hosts the configured models, or MiniLM, and answers CLI invocations on the daemon socket
+fn run_daemon(config: Option<PathBuf>, socket: Option<PathBuf>) -> Result<()> {
+    let router = match config {
+        Some(config) => {
+            let config = ServerConfig::from_file(&config)?;
+            info!("Loading {} model(s)...", config.models.len());
+            ModelRouter::from_config(&config)?
+        }
+        None => {
+            let mut router = ModelRouter::new();
+            router.host(HostedModelConfig::new("minilm", ModelSpec::new("minilm")))?;
+            router
+        }
+    };
+    
+    daemon::run_daemon(Arc::new(router), &socket.unwrap_or_else(daemon::default_socket_path))?;
+    Ok(())
+}
+
+/// Hosts the configured models and serves them as MCP tools over stdio
+fn run_mcp(config: PathBuf, corpus: Option<PathBuf>) -> Result<()> {
+    let config = ServerConfig::from_file(&config)?;
+    
+    info!("Loading {} model(s)...", config.models.len());
+    let mut server = McpServer::new(Arc::new(ModelRouter::from_config(&config)?));
+    if let Some(corpus) = corpus {
+        let corpus = SearchCorpus::load(&corpus)?;
+        info!("Loaded {} document(s) for search", corpus.len());
+        server = server.with_corpus(corpus)?;
+    }
+    
+    server::mcp::serve_stdio(&server)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
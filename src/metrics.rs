@@ -0,0 +1,252 @@
+use crate::embedding::EmbedderStats;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Upper bounds of the latency buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Upper bounds of the batch size buckets, in texts
+const BATCH_SIZE_BUCKETS: &[f64] = &[1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0];
+
+/// Name, help text and accessor of a metric rendered for every model
+type MetricField<T> = (&'static str, &'static str, fn(&ModelMetrics) -> &T);
+
+/// A cumulative histogram with fixed buckets
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    state: Mutex<HistogramState>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct HistogramState {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// Creates a histogram with the given bucket upper bounds, in increasing order
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            state: Mutex::new(HistogramState {
+                counts: vec![0; bounds.len()],
+                ..HistogramState::default()
+            }),
+        }
+    }
+
+    /// Records one observation
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for (count, bound) in state.counts.iter_mut().zip(self.bounds) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+
+    /// Number of observations recorded
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).count
+    }
+
+    /// Writes the histogram in the Prometheus text format
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for (count, bound) in state.counts.iter().zip(self.bounds) {
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, state.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, state.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, state.count);
+    }
+}
+
+/// Counters and histograms for one model
+#[derive(Debug)]
+pub struct ModelMetrics {
+    device: String,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    embeddings: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    batch_sizes: Histogram,
+    batch_latency: Histogram,
+    request_latency: Histogram,
+}
+
+impl ModelMetrics {
+    /// Creates empty metrics for a model running on the given device
+    pub fn new(device: &str) -> Self {
+        Self {
+            device: device.to_string(),
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            embeddings: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            batch_sizes: Histogram::new(BATCH_SIZE_BUCKETS),
+            batch_latency: Histogram::new(LATENCY_BUCKETS),
+            request_latency: Histogram::new(LATENCY_BUCKETS),
+        }
+    }
+
+    /// Records a forward pass over `size` texts that took `elapsed`
+    pub fn record_batch(&self, size: usize, elapsed: Duration) {
+        self.embeddings.fetch_add(size as u64, Ordering::Relaxed);
+        self.batch_sizes.observe(size as f64);
+        self.batch_latency.observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a finished request and how long it took end to end
+    pub fn record_request(&self, succeeded: bool, elapsed: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !succeeded {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.request_latency.observe(elapsed.as_secs_f64());
+    }
+
+    /// Adds the totals from an embedder's stats, so each snapshot should be recorded once
+    ///
+    /// `EmbedderStats` only keeps totals, so the processing time is observed as one batch.
+    pub fn record_stats(&self, stats: &EmbedderStats) {
+        self.cache_hits.fetch_add(stats.cache_hits as u64, Ordering::Relaxed);
+        self.cache_misses.fetch_add(stats.cache_misses as u64, Ordering::Relaxed);
+        if stats.embeddings_count > 0 {
+            self.record_batch(stats.embeddings_count, stats.total_processing_time);
+        }
+    }
+
+    /// Number of embeddings produced
+    pub fn embeddings(&self) -> u64 {
+        self.embeddings.load(Ordering::Relaxed)
+    }
+
+    /// Share of cache lookups that hit, if any lookups were made
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let lookups = hits + self.cache_misses.load(Ordering::Relaxed);
+        (lookups > 0).then(|| hits as f64 / lookups as f64)
+    }
+}
+
+/// A set of per-model metrics rendered together
+#[derive(Debug, Default)]
+pub struct Metrics {
+    models: RwLock<BTreeMap<String, Arc<ModelMetrics>>>,
+}
+
+impl Metrics {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metrics of a model, creating them on first use
+    pub fn model(&self, name: &str, device: &str) -> Arc<ModelMetrics> {
+        if let Some(metrics) = self.models.read().unwrap_or_else(|e| e.into_inner()).get(name) {
+            return Arc::clone(metrics);
+        }
+
+        let mut models = self.models.write().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(models.entry(name.to_string()).or_insert_with(|| Arc::new(ModelMetrics::new(device))))
+    }
+
+    /// Adds metrics kept elsewhere under a model name
+    pub fn register(&self, name: &str, metrics: Arc<ModelMetrics>) {
+        self.models.write().unwrap_or_else(|e| e.into_inner()).insert(name.to_string(), metrics);
+    }
+
+    /// Renders every model's metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let models = self.models.read().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        let counters: [MetricField<AtomicU64>; 5] = [
+            ("rust_embed_requests_total", "Embedding requests served", |m| &m.requests),
+            ("rust_embed_request_errors_total", "Embedding requests that failed", |m| &m.errors),
+            ("rust_embed_embeddings_total", "Embeddings produced", |m| &m.embeddings),
+            ("rust_embed_cache_hits_total", "Embedding cache hits", |m| &m.cache_hits),
+            ("rust_embed_cache_misses_total", "Embedding cache misses", |m| &m.cache_misses),
+        ];
+
+        let _ = writeln!(out, "# HELP rust_embed_model_info Hosted model and the device it runs on");
+        let _ = writeln!(out, "# TYPE rust_embed_model_info gauge");
+        for (name, metrics) in models.iter() {
+            let _ = writeln!(out, "rust_embed_model_info{{model=\"{}\",device=\"{}\"}} 1", escape(name), escape(&metrics.device));
+        }
+
+        for (metric, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {} {}", metric, help);
+            let _ = writeln!(out, "# TYPE {} counter", metric);
+            for (name, metrics) in models.iter() {
+                let _ = writeln!(out, "{}{{model=\"{}\"}} {}", metric, escape(name), counter(metrics).load(Ordering::Relaxed));
+            }
+        }
+
+        let _ = writeln!(out, "# HELP rust_embed_cache_hit_ratio Share of cache lookups that hit");
+        let _ = writeln!(out, "# TYPE rust_embed_cache_hit_ratio gauge");
+        for (name, metrics) in models.iter() {
+            if let Some(rate) = metrics.cache_hit_rate() {
+                let _ = writeln!(out, "rust_embed_cache_hit_ratio{{model=\"{}\"}} {}", escape(name), rate);
+            }
+        }
+
+        let histograms: [MetricField<Histogram>; 3] = [
+            ("rust_embed_batch_size", "Texts per forward pass", |m| &m.batch_sizes),
+            ("rust_embed_batch_duration_seconds", "Duration of a forward pass", |m| &m.batch_latency),
+            ("rust_embed_request_duration_seconds", "Duration of a request, including queueing", |m| &m.request_latency),
+        ];
+
+        for (metric, help, histogram) in histograms {
+            let _ = writeln!(out, "# HELP {} {}", metric, help);
+            let _ = writeln!(out, "# TYPE {} histogram", metric);
+            for (name, metrics) in models.iter() {
+                histogram(metrics).render(&mut out, metric, &format!("model=\"{}\"", escape(name)));
+            }
+        }
+
+        out
+    }
+}
+
+/// Escapes a Prometheus label value
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_prometheus_text() {
+        let metrics = Metrics::new();
+        let model = metrics.model("fast", "mps");
+        model.record_batch(3, Duration::from_millis(20));
+        model.record_request(true, Duration::from_millis(30));
+        model.record_stats(&EmbedderStats {
+            embeddings_count: 0,
+            total_processing_time: Duration::ZERO,
+            cache_hits: 3,
+            cache_misses: 1,
+        });
+
+        let text = metrics.render();
+        assert!(text.contains("rust_embed_model_info{model=\"fast\",device=\"mps\"} 1"));
+        assert!(text.contains("rust_embed_embeddings_total{model=\"fast\"} 3"));
+        assert!(text.contains("rust_embed_cache_hit_ratio{model=\"fast\"} 0.75"));
+        assert!(text.contains("rust_embed_batch_size_bucket{model=\"fast\",le=\"2\"} 0"));
+        assert!(text.contains("rust_embed_batch_size_bucket{model=\"fast\",le=\"4\"} 1"));
+        assert!(text.contains("rust_embed_request_duration_seconds_count{model=\"fast\"} 1"));
+    }
+}
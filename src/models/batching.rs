@@ -0,0 +1,275 @@
+#[cfg(feature = "onnx")]
+use crate::error::{err, Error, Result};
+#[cfg(feature = "onnx")]
+use ndarray::Array2;
+#[cfg(feature = "onnx")]
+use ort::Session;
+#[cfg(feature = "onnx")]
+use std::sync::mpsc;
+#[cfg(feature = "onnx")]
+use tokenizers::Tokenizer;
+
+/// Padded tokens per forward pass unless a model configures its own budget
+pub(crate) const DEFAULT_BATCH_TOKENS: usize = 8192;
+
+/// Runs items through a model in batches of similar token counts and returns the outputs in input order
+///
+/// Every batch is padded to its longest item, so grouping short texts with short ones keeps mixed-length
+/// corpora from spending most of the compute on padding. Batches are filled up to `batch_tokens` padded
+/// tokens, packing many short texts together while long documents go a few at a time, and an item over
+/// the budget is run alone.
+///
+/// Batches run one after the other on the calling thread, for models such as MiniLM whose rust-bert pipeline
+/// tokenizes inside inference and so has nothing to prepare ahead. `run` must return one output per item.
+#[cfg(feature = "torch")]
+pub(crate) fn batch_by_tokens<I, T, E>(
+    items: &[I],
+    batch_tokens: usize,
+    tokens: impl Fn(&I) -> usize,
+    mut run: impl FnMut(&[&I]) -> Result<Vec<T>, E>,
+) -> Result<Vec<T>, E> {
+    let mut outputs: Vec<Option<T>> = (0..items.len()).map(|_| None).collect();
+    for batch in plan_batches(&items.iter().map(tokens).collect::<Vec<_>>(), batch_tokens) {
+        let batch_items: Vec<&I> = batch.iter().map(|&i| &items[i]).collect();
+        for (i, output) in batch.into_iter().zip(run(&batch_items)?) {
+            outputs[i] = Some(output);
+        }
+    }
+    Ok(outputs.into_iter().flatten().collect())
+}
+
+/// Runs texts through a model in batches like `batch_by_tokens`, preparing the next batch while one runs
+///
+/// `prepare` tokenizes a batch on a second thread while `run` infers the previous one, so the CPU and the
+/// accelerator work at the same time. `run` must return one output per item.
+#[cfg(feature = "onnx")]
+pub(crate) fn pipeline_by_tokens<I: Sync, P: Send, T, E: Send>(
+    items: &[I],
+    batch_tokens: usize,
+    tokens: impl Fn(&I) -> usize,
+    prepare: impl Fn(&[&I]) -> Result<P, E> + Send,
+    mut run: impl FnMut(P) -> Result<Vec<T>, E>,
+) -> Result<Vec<T>, E> {
+    let mut outputs: Vec<Option<T>> = (0..items.len()).map(|_| None).collect();
+    for_each_batch(items, batch_tokens, tokens, prepare, |indices, prepared| {
+        for (&i, output) in indices.iter().zip(run(prepared)?) {
+            outputs[i] = Some(output);
+        }
+        Ok(())
+    })?;
+    Ok(outputs.into_iter().flatten().collect())
+}
+
+/// Runs texts through a model like `pipeline_by_tokens`, copying each batch's rows into one (items × `dimension`) matrix
+///
+/// `run` returns the embeddings of a batch as a matrix with one row per item.
+#[cfg(feature = "onnx")]
+pub(crate) fn batch_matrix_by_tokens<I: Sync, P: Send, E: Send + From<Error>>(
+    items: &[I],
+    batch_tokens: usize,
+    dimension: usize,
+    tokens: impl Fn(&I) -> usize,
+    prepare: impl Fn(&[&I]) -> Result<P, E> + Send,
+    mut run: impl FnMut(P) -> Result<Array2<f32>, E>,
+) -> Result<Array2<f32>, E> {
+    let mut output = Array2::zeros((items.len(), dimension));
+    for_each_batch(items, batch_tokens, tokens, prepare, |indices, prepared| {
+        let rows = run(prepared)?;
+        if rows.dim() != (indices.len(), dimension) {
+            return Err(err!(
+                "Expected embeddings of shape [{}, {}], got {:?}", indices.len(), dimension, rows.shape()
+            ));
+        }
+        for (&i, row) in indices.iter().zip(rows.rows()) {
+            output.row_mut(i).assign(&row);
+        }
+        Ok(())
+    })?;
+    Ok(output)
+}
+
+/// Prepares batches on a second thread and hands each to `run` with the indices of its items
+#[cfg(feature = "onnx")]
+fn for_each_batch<I: Sync, P: Send, E: Send>(
+    items: &[I],
+    batch_tokens: usize,
+    tokens: impl Fn(&I) -> usize,
+    prepare: impl Fn(&[&I]) -> Result<P, E> + Send,
+    mut run: impl FnMut(&[usize], P) -> Result<(), E>,
+) -> Result<(), E> {
+    let batches = plan_batches(&items.iter().map(tokens).collect::<Vec<_>>(), batch_tokens);
+
+    std::thread::scope(|scope| {
+        // One prepared batch waits while another runs, bounding the memory held ahead of inference
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let planned = batches.clone();
+        scope.spawn(move || {
+            for batch in planned {
+                let batch_items: Vec<&I> = batch.iter().map(|&i| &items[i]).collect();
+                let prepared = tracing::debug_span!("prepare_batch", items = batch.len()).in_scope(|| prepare(&batch_items));
+                let failed = prepared.is_err();
+                // The receiver is gone once inference failed
+                if sender.send(prepared).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        for (batch, prepared) in batches.iter().zip(receiver) {
+            run(batch, prepared?)?;
+        }
+        Ok(())
+    })
+}
+
+/// Groups item indices into batches, shortest first, holding at most `batch_tokens` padded tokens each
+fn plan_batches(tokens: &[usize], batch_tokens: usize) -> Vec<Vec<usize>> {
+    let mut order: Vec<(usize, usize)> = tokens.iter().map(|&tokens| tokens.max(1)).enumerate().collect();
+    order.sort_by_key(|&(_, tokens)| tokens);
+
+    let mut batches = Vec::new();
+    let mut start = 0;
+    while start < order.len() {
+        // Sorted ascending, so the last item sets the padded length of the batch
+        let mut end = start + 1;
+        while end < order.len() && (end - start + 1) * order[end].1 <= batch_tokens {
+            end += 1;
+        }
+        batches.push(order[start..end].iter().map(|&(i, _)| i).collect());
+        start = end;
+    }
+    batches
+}
+
+/// Token ids of a batch of texts, padded with zeros to the longest one
+#[cfg(feature = "onnx")]
+pub(crate) struct TokenBatch {
+    pub input_ids: Array2<i64>,
+    pub attention_mask: Array2<i64>,
+    pub token_type_ids: Array2<i64>,
+}
+
+#[cfg(feature = "onnx")]
+impl TokenBatch {
+    /// Tokenizes texts into padded id, mask and type matrices
+    pub(crate) fn encode(tokenizer: &Tokenizer, texts: &[&String]) -> Result<Self> {
+        let encodings = tracing::debug_span!("tokenize", texts = texts.len())
+            .in_scope(|| tokenizer.encode_batch(texts.iter().map(|text| text.as_str()).collect(), true))
+            .map_err(|e| err!("Tokenization failed: {}", e))?;
+
+        let length = encodings.iter().map(|e| e.get_ids().len()).max().unwrap_or(0);
+        let mut batch = Self {
+            input_ids: Array2::zeros((encodings.len(), length)),
+            attention_mask: Array2::zeros((encodings.len(), length)),
+            token_type_ids: Array2::zeros((encodings.len(), length)),
+        };
+        for (row, encoding) in encodings.iter().enumerate() {
+            for (col, &id) in encoding.get_ids().iter().enumerate() {
+                batch.input_ids[[row, col]] = id as i64;
+            }
+            for (col, &mask) in encoding.get_attention_mask().iter().enumerate() {
+                batch.attention_mask[[row, col]] = mask as i64;
+            }
+            for (col, &type_id) in encoding.get_type_ids().iter().enumerate() {
+                batch.token_type_ids[[row, col]] = type_id as i64;
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Number of texts in the batch
+    pub(crate) fn len(&self) -> usize {
+        self.input_ids.nrows()
+    }
+
+    /// Session inputs for the matrices the graph declares, some exports drop token_type_ids
+    pub(crate) fn inputs<'s>(&self, session: &'s Session) -> Result<Vec<(&'s str, ort::Tensor<i64>)>> {
+        let mut inputs = Vec::new();
+        for input in &session.inputs {
+            let value = match input.name.as_str() {
+                "input_ids" => ort::Value::from_array(self.input_ids.clone())?,
+                "attention_mask" => ort::Value::from_array(self.attention_mask.clone())?,
+                "token_type_ids" => ort::Value::from_array(self.token_type_ids.clone())?,
+                other => return Err(err!("Unsupported model input '{}'", other)),
+            };
+            inputs.push((input.name.as_str(), value));
+        }
+        Ok(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_batches_by_tokens_and_restores_order() {
+        let texts = ["a long text", "a", "medium", "ab", "the longest text"];
+        let batches = std::sync::Mutex::new(Vec::new());
+        let outputs = pipeline_by_tokens(&texts, 18, |text| text.len(), |batch| {
+            batches.lock().unwrap().push(batch.iter().map(|text| text.to_string()).collect::<Vec<_>>());
+            Ok::<_, ()>(batch.iter().map(|text| text.to_uppercase()).collect::<Vec<_>>())
+        }, Ok).unwrap();
+
+        assert_eq!(batches.into_inner().unwrap(), vec![vec!["a", "ab", "medium"], vec!["a long text"], vec!["the longest text"]]);
+        assert_eq!(outputs, texts.iter().map(|text| text.to_uppercase()).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_prepares_the_next_batch_while_one_runs() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+
+        let prepared = AtomicUsize::new(0);
+        let mut overlapped = Vec::new();
+        pipeline_by_tokens(&[1usize, 2, 3], 1, |_| 1, |batch| {
+            prepared.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, ()>(batch.iter().map(|&&item| item).collect::<Vec<_>>())
+        }, |batch| {
+            // While batch N runs, batch N + 1 is prepared without waiting for it
+            let item = batch[0];
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while item < 3 && prepared.load(Ordering::SeqCst) <= item && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            overlapped.push(item == 3 || prepared.load(Ordering::SeqCst) > item);
+            Ok(batch)
+        }).unwrap();
+        assert_eq!(overlapped, vec![true, true, true]);
+    }
+
+    #[cfg(feature = "torch")]
+    #[test]
+    fn test_runs_batches_in_place_and_restores_order() {
+        let texts = ["a long text", "a", "medium", "ab", "the longest text"];
+        let caller = std::thread::current().id();
+        let mut batches = Vec::new();
+        let outputs = batch_by_tokens(&texts, 18, |text| text.len(), |batch| {
+            assert_eq!(std::thread::current().id(), caller);
+            batches.push(batch.iter().map(|text| text.to_string()).collect::<Vec<_>>());
+            Ok::<_, ()>(batch.iter().map(|text| text.to_uppercase()).collect())
+        }).unwrap();
+
+        assert_eq!(batches, vec![vec!["a", "ab", "medium"], vec!["a long text"], vec!["the longest text"]]);
+        assert_eq!(outputs, texts.iter().map(|text| text.to_uppercase()).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_batch_matrix_rows_follow_input_order() {
+        let lengths = [3usize, 1, 2];
+        let matrix = batch_matrix_by_tokens(&lengths, 2, 2, |&length| length, |batch| {
+            Ok::<_, Error>(batch.iter().map(|&&length| length).collect::<Vec<_>>())
+        }, |batch| {
+            Ok(Array2::from_shape_fn((batch.len(), 2), |(row, col)| (batch[row] * 10 + col) as f32))
+        }).unwrap();
+        assert_eq!(matrix, ndarray::arr2(&[[30.0, 31.0], [10.0, 11.0], [20.0, 21.0]]));
+
+        let wrong_width = batch_matrix_by_tokens(&lengths, 8, 3, |&length| length, |_| Ok::<_, Error>(()), |_| {
+            Ok(Array2::zeros((3, 2)))
+        });
+        assert!(wrong_width.is_err());
+    }
+}
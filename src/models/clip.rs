@@ -0,0 +1,270 @@
+use crate::embedding::{Embedder, ModelInfo};
+use crate::models::batching::{pipeline_by_tokens, TokenBatch, DEFAULT_BATCH_TOKENS};
+use crate::utils;
+use crate::error::{err, Context, Result};
+use image::imageops::FilterType;
+use ndarray::{Array1, Array4, Axis, Ix2};
+use ort::{GraphOptimizationLevel, Session, SessionOutputs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// Per-channel mean and standard deviation CLIP was trained with
+const IMAGE_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_7];
+const IMAGE_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+
+/// Number of images sent through the vision encoder at once
+const BATCH_SIZE: usize = 16;
+
+/// Configuration for a CLIP model exported to ONNX
+#[derive(Debug, Clone)]
+pub struct ClipConfig {
+    /// Directory containing `text_model.onnx`, `vision_model.onnx` and `tokenizer.json`
+    pub model_dir: PathBuf,
+
+    /// Side length of the square images the vision model expects
+    pub image_size: u32,
+
+    /// Maximum number of text tokens, CLIP uses a context of 77
+    pub max_length: usize,
+
+    /// Dimension of the shared embedding space, read from `config.json` when present
+    pub dimension: usize,
+
+    /// Name reported as the model name
+    pub name: String,
+
+    /// Padded text tokens per forward pass of the text encoder
+    pub batch_tokens: usize,
+}
+
+impl ClipConfig {
+    /// Creates a configuration for openai/clip-vit-base-patch32 stored in the given directory
+    pub fn new(model_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            model_dir: model_dir.into(),
+            image_size: 224,
+            max_length: 77,
+            dimension: 512,
+            name: "openai/clip-vit-base-patch32".to_string(),
+            batch_tokens: DEFAULT_BATCH_TOKENS,
+        }
+    }
+}
+
+/// Embeds texts and images into the same vector space for cross-modal search
+#[derive(Clone)]
+pub struct ClipEmbedder {
+    config: ClipConfig,
+    text_session: Arc<Session>,
+    vision_session: Arc<Session>,
+    tokenizer: Arc<Tokenizer>,
+}
+
+impl ClipEmbedder {
+    /// Loads the text and vision encoders from the configured directory
+    pub fn new(mut config: ClipConfig) -> Result<Self> {
+        let model_dir = config.model_dir.clone();
+        let text_session = load_session(&model_dir.join("text_model.onnx"))?;
+        let vision_session = load_session(&model_dir.join("vision_model.onnx"))?;
+
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| err!("Failed to load CLIP tokenizer from {}: {}", model_dir.display(), e))?;
+        tokenizer.with_truncation(Some(TruncationParams {
+            max_length: config.max_length,
+            ..TruncationParams::default()
+        }));
+
+        // Larger CLIP variants project into more dimensions
+        if let Some(dimension) = std::fs::read_to_string(model_dir.join("config.json"))
+            .ok()
+            .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok())
+            .and_then(|config| config.get("projection_dim").and_then(|v| v.as_u64()))
+        {
+            config.dimension = dimension as usize;
+        }
+
+        Ok(Self {
+            config,
+            text_session: Arc::new(text_session),
+            vision_session: Arc::new(vision_session),
+            tokenizer: Arc::new(tokenizer),
+        })
+    }
+
+    /// Embeds an image file
+    pub fn embed_image(&self, path: impl AsRef<Path>) -> Result<Array1<f32>> {
+        self.embed_images(&[path.as_ref().to_path_buf()])?
+            .pop()
+            .ok_or_else(|| err!("Model returned no embedding"))
+    }
+
+    /// Embeds several image files
+    #[tracing::instrument(skip_all, fields(images = paths.len()))]
+    pub fn embed_images(&self, paths: &[PathBuf]) -> Result<Vec<Array1<f32>>> {
+        let mut embeddings = Vec::with_capacity(paths.len());
+
+        for chunk in paths.chunks(BATCH_SIZE) {
+            let size = self.config.image_size as usize;
+            let mut pixels = Array4::<f32>::zeros((chunk.len(), 3, size, size));
+            for (i, path) in chunk.iter().enumerate() {
+                pixels.index_axis_mut(Axis(0), i).assign(&self.preprocess_image(path)?);
+            }
+
+            let inputs = ort::inputs!["pixel_values" => pixels]?;
+            let outputs = tracing::debug_span!("inference").in_scope(|| self.vision_session.run(inputs))?;
+            embeddings.extend(extract_embeddings(&outputs, "image_embeds")?);
+        }
+
+        Ok(embeddings)
+    }
+
+    /// Opens an image and preprocesses it into a CHW tensor
+    #[tracing::instrument(level = "debug", skip(self))]
+    fn preprocess_image(&self, path: &Path) -> Result<ndarray::Array3<f32>> {
+        let image = image::open(path)
+            .with_context(|| format!("Failed to open image {}", path.display()))?;
+        Ok(preprocess_image(&image, self.config.image_size))
+    }
+
+    /// Embeds texts with the text encoder, in batches of similar length within the token budget
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
+        let max_length = self.config.max_length;
+        let tokenizer = &self.tokenizer;
+        pipeline_by_tokens(
+            texts,
+            self.config.batch_tokens,
+            |text| utils::estimate_tokens(text).min(max_length),
+            |batch| TokenBatch::encode(tokenizer, batch),
+            |batch| self.forward_text(batch),
+        )
+    }
+
+    /// Runs a batch of texts through the text encoder
+    #[tracing::instrument(name = "clip_text_forward", skip_all, fields(texts = batch.len()))]
+    fn forward_text(&self, batch: TokenBatch) -> Result<Vec<Array1<f32>>> {
+        let inputs = ort::inputs![
+            "input_ids" => batch.input_ids,
+            "attention_mask" => batch.attention_mask,
+        ]?;
+        let outputs = tracing::debug_span!("inference").in_scope(|| self.text_session.run(inputs))?;
+        extract_embeddings(&outputs, "text_embeds")
+    }
+}
+
+impl Embedder for ClipEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        self.embed_texts(&[text.to_string()])?
+            .pop()
+            .ok_or_else(|| err!("Model returned no embedding"))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        self.embed_texts(texts)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn model_version(&self) -> &str {
+        "onnx"
+    }
+
+    fn dimension(&self) -> usize {
+        self.config.dimension
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        // CLIP projects the end-of-text token rather than pooling
+        ModelInfo {
+            max_tokens: Some(self.config.max_length),
+            normalized: true,
+            source_url: Some(format!("https://huggingface.co/{}", self.config.name)),
+            ..ModelInfo::new(&self.config.name, "onnx", self.config.dimension)
+        }
+    }
+
+    fn count_tokens(&self, text: &str) -> crate::Result<usize> {
+        let encoding = self.tokenizer.encode(text, true)
+            .map_err(|e| err!("Tokenization failed: {}", e))?;
+        Ok(encoding.len())
+    }
+}
+
+/// Opens an ONNX session for one of the CLIP encoders
+fn load_session(path: &Path) -> Result<Session> {
+    if !path.exists() {
+        return Err(err!("CLIP encoder not found at {}", path.display()));
+    }
+
+    Ok(Session::builder()?
+        .with_optimization_level(GraphOptimizationLevel::Level3)?
+        .commit_from_file(path)?)
+}
+
+/// Reads the projected embeddings from a named output (or the first one) and normalizes them
+fn extract_embeddings(outputs: &SessionOutputs, name: &str) -> Result<Vec<Array1<f32>>> {
+    let output = outputs.get(name).unwrap_or(&outputs[0]);
+    let values = output.try_extract_tensor::<f32>()?;
+    let values = values.view()
+        .into_dimensionality::<Ix2>()
+        .map_err(|_| err!("Expected '{}' of shape [batch, dimension]", name))?;
+
+    Ok(values.outer_iter()
+        .map(|row| {
+            let mut embedding = row.to_owned();
+            utils::normalize(&mut embedding);
+            embedding
+        })
+        .collect())
+}
+
+/// Resizes, center-crops and normalizes an image into a CHW tensor of `size` × `size` pixels
+fn preprocess_image(image: &image::DynamicImage, size: u32) -> ndarray::Array3<f32> {
+    // Scale the shorter side to the target size, then crop the center square
+    let (width, height) = (image.width().max(1), image.height().max(1));
+    let scale = size as f32 / width.min(height) as f32;
+    let resized = image.resize_exact(
+        ((width as f32 * scale).round() as u32).max(size),
+        ((height as f32 * scale).round() as u32).max(size),
+        FilterType::CatmullRom,
+    );
+    let left = (resized.width() - size) / 2;
+    let top = (resized.height() - size) / 2;
+    let cropped = resized.crop_imm(left, top, size, size).to_rgb8();
+
+    let size = size as usize;
+    let mut tensor = ndarray::Array3::<f32>::zeros((3, size, size));
+    for (x, y, pixel) in cropped.enumerate_pixels() {
+        for channel in 0..3 {
+            let value = pixel[channel] as f32 / 255.0;
+            tensor[[channel, y as usize, x as usize]] = (value - IMAGE_MEAN[channel]) / IMAGE_STD[channel];
+        }
+    }
+    tensor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn test_preprocess_image_crops_the_center_and_normalizes() {
+        // A wide image, black on the left and right thirds and white in the middle
+        let image = RgbImage::from_fn(30, 10, |x, _| if (10..20).contains(&x) { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) });
+        let tensor = preprocess_image(&DynamicImage::ImageRgb8(image), 10);
+
+        assert_eq!(tensor.shape(), &[3, 10, 10]);
+        for channel in 0..3 {
+            let white = (1.0 - IMAGE_MEAN[channel]) / IMAGE_STD[channel];
+            assert!((tensor[[channel, 5, 5]] - white).abs() < 1e-3);
+        }
+
+        let black = DynamicImage::ImageRgb8(RgbImage::new(8, 16));
+        let tensor = preprocess_image(&black, 4);
+        assert_eq!(tensor.shape(), &[3, 4, 4]);
+        assert!((tensor[[0, 0, 0]] + IMAGE_MEAN[0] / IMAGE_STD[0]).abs() < 1e-6);
+    }
+}
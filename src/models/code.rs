@@ -0,0 +1,108 @@
+#[cfg(feature = "onnx")]
+use crate::embedding::{Embedder, ModelInfo};
+#[cfg(feature = "onnx")]
+use crate::models::onnx::{OnnxConfig, OnnxEmbedder, OnnxModel};
+#[cfg(feature = "onnx")]
+use crate::error::Result;
+#[cfg(feature = "onnx")]
+use ndarray::{Array1, Array2};
+#[cfg(feature = "onnx")]
+use std::path::PathBuf;
+
+/// Embedder for source code, running a code-specialized ONNX model
+#[cfg(feature = "onnx")]
+#[derive(Clone)]
+pub struct CodeEmbedder {
+    inner: OnnxEmbedder,
+}
+
+#[cfg(feature = "onnx")]
+impl CodeEmbedder {
+    /// Loads jina-embeddings-v2-base-code from a local ONNX export
+    pub fn new(model_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::with_config(OnnxConfig::new(OnnxModel::JinaCode, model_dir))
+    }
+
+    /// Loads a code model with a custom ONNX configuration
+    pub fn with_config(config: OnnxConfig) -> Result<Self> {
+        Ok(Self {
+            inner: OnnxEmbedder::new(config)?,
+        })
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl Embedder for CodeEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        self.inner.embed_text(&preprocess_code(text))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        let processed: Vec<String> = texts.iter().map(|text| preprocess_code(text)).collect();
+        self.inner.embed_batch(&processed)
+    }
+
+    fn embed_batch_matrix(&self, texts: &[String]) -> crate::Result<Array2<f32>> {
+        let processed: Vec<String> = texts.iter().map(|text| preprocess_code(text)).collect();
+        self.inner.embed_batch_matrix(&processed)
+    }
+
+    fn model_name(&self) -> &str {
+        self.inner.model_name()
+    }
+
+    fn model_version(&self) -> &str {
+        self.inner.model_version()
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        self.inner.model_info()
+    }
+
+    fn count_tokens(&self, text: &str) -> crate::Result<usize> {
+        self.inner.count_tokens(&preprocess_code(text))
+    }
+}
+
+/// Normalizes source code for embedding, keeping case and indentation intact
+///
+/// Line endings are unified, trailing whitespace is stripped, leading and trailing
+/// blank lines are dropped and runs of blank lines are reduced to one.
+pub fn preprocess_code(code: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut previous_blank = true;
+
+    for line in code.lines() {
+        let line = line.trim_end();
+        let blank = line.is_empty();
+        if !(blank && previous_blank) {
+            lines.push(line);
+        }
+        previous_blank = blank;
+    }
+
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preprocess_code_keeps_indentation() {
+        let code = "\r\n\nfn Main() {  \r\n    let X = 1;\n\n\n\tprintln!(\"{}\", X);\n}\n\n";
+
+        assert_eq!(
+            preprocess_code(code),
+            "fn Main() {\n    let X = 1;\n\n\tprintln!(\"{}\", X);\n}"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Hub queried when neither the source nor `HF_ENDPOINT` names one
+pub const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+/// Repository of the default MiniLM model
+pub const DEFAULT_REPO: &str = "sentence-transformers/all-MiniLM-L6-v2";
+pub const DEFAULT_REVISION: &str = "main";
+
+/// Where to download a model from: a HuggingFace-compatible hub, a repository on it and a revision
+///
+/// Pinning `revision` to a commit hash keeps the weights from changing under a deployment. Each repository
+/// and revision is cached in its own directory, except the default model, which keeps the cache directory
+/// earlier versions used.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HubSource {
+    /// Address of the hub or a mirror of it, `HF_ENDPOINT` or huggingface.co when unset
+    pub endpoint: Option<String>,
+
+    /// Repository id, such as "sentence-transformers/all-MiniLM-L6-v2"
+    pub repo_id: String,
+
+    /// Branch, tag or commit hash
+    pub revision: String,
+
+    /// Access token for gated or private repositories, `HF_TOKEN` when unset
+    pub token: Option<String>,
+}
+
+impl Default for HubSource {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            repo_id: DEFAULT_REPO.to_string(),
+            revision: DEFAULT_REVISION.to_string(),
+            token: None,
+        }
+    }
+}
+
+impl HubSource {
+    /// The main branch of a repository on the default hub
+    pub fn new(repo_id: &str) -> Self {
+        Self { repo_id: repo_id.to_string(), ..Self::default() }
+    }
+
+    /// Hub address without a trailing slash
+    pub fn endpoint(&self) -> String {
+        self.endpoint.clone()
+            .or_else(|| std::env::var("HF_ENDPOINT").ok())
+            .filter(|endpoint| !endpoint.is_empty())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string())
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// Access token sent with downloads, if any
+    pub fn token(&self) -> Option<String> {
+        self.token.clone()
+            .or_else(|| std::env::var("HF_TOKEN").ok())
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Page of the repository on the hub
+    pub fn repo_url(&self) -> String {
+        format!("{}/{}", self.endpoint(), self.repo_id)
+    }
+
+    /// Download URL of a file of the repository at the revision
+    pub fn file_url(&self, file: &str) -> String {
+        format!("{}/resolve/{}/{}", self.repo_url(), self.revision, file)
+    }
+
+    /// Whether this is the default model's main branch on huggingface.co, which rust-bert can fetch itself
+    pub fn is_default(&self) -> bool {
+        self.repo_id == DEFAULT_REPO && self.revision == DEFAULT_REVISION && self.endpoint() == DEFAULT_ENDPOINT
+    }
+
+    /// Directory in the cache holding this repository's files at the revision
+    pub fn cache_dir(&self) -> PathBuf {
+        if self.repo_id == DEFAULT_REPO && self.revision == DEFAULT_REVISION {
+            return crate::utils::cache_home().join("all-MiniLM-L6-v2");
+        }
+        crate::utils::cache_home()
+            .join("hub")
+            .join(self.repo_id.replace('/', "--"))
+            .join(self.revision.replace('/', "--"))
+    }
+
+    /// Downloads the files missing from `cache_dir`, checks the cached ones and returns the directory
+    ///
+    /// In offline mode missing files are an error naming `what` is needed.
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    pub fn fetch(&self, files: &[&str], what: &str) -> crate::Result<PathBuf> {
+        use crate::error::Context;
+
+        let model_dir = self.cache_dir();
+        let missing: Vec<PathBuf> = files.iter()
+            .map(|file| model_dir.join(file))
+            .filter(|path| !path.exists())
+            .collect();
+        if !missing.is_empty() {
+            crate::utils::offline::ensure_download_allowed(what, &missing, "Copy them there or set model_path")?;
+        }
+
+        let token = self.token();
+        for path in &missing {
+            let parent = path.parent().unwrap_or(&model_dir);
+            std::fs::create_dir_all(parent).context("Failed to create model cache directory")?;
+            let file = path.strip_prefix(&model_dir).unwrap_or(path).to_string_lossy().replace('\\', "/");
+            crate::utils::model_cache::download(&model_dir, &file, &self.file_url(&file), token.as_deref())?;
+        }
+
+        crate::utils::model_cache::verify(&model_dir)?;
+        Ok(model_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_urls_and_cache_dirs() {
+        let pinned = HubSource {
+            endpoint: Some("https://hf-mirror.example.com/".to_string()),
+            revision: "7dbbc90392e2f80f3d3c277d6e90027e55de9125".to_string(),
+            ..HubSource::new("acme/minilm-tuned")
+        };
+        assert_eq!(
+            pinned.file_url("config.json"),
+            "https://hf-mirror.example.com/acme/minilm-tuned/resolve/7dbbc90392e2f80f3d3c277d6e90027e55de9125/config.json",
+        );
+        assert!(!pinned.is_default());
+        assert!(pinned.cache_dir().ends_with("hub/acme--minilm-tuned/7dbbc90392e2f80f3d3c277d6e90027e55de9125"));
+        assert!(HubSource::default().cache_dir().ends_with("all-MiniLM-L6-v2"));
+    }
+}
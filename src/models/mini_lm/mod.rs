@@ -1,21 +1,36 @@
-use crate::embedding::{self, EmbeddedText, Embedder};
-use crate::models::ModelConfig;
+use crate::embedding::{self, CacheKey, EmbeddedText, Embedder, ModelInfo};
+use crate::models::batching::{batch_by_tokens, DEFAULT_BATCH_TOKENS};
+use crate::models::{CheckpointInfo, HubSource, ModelConfig};
 use crate::utils;
-use anyhow::{anyhow, Result};
+use crate::utils::concurrency::ConcurrencyConfig;
+use crate::error::{err, Error, Result};
 use ndarray::Array1;
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tch::{Device, Tensor};
-use std::cell::RefCell;
+use tch::Tensor;
+use std::cell::{Cell, RefCell};
 use tokenizers::Tokenizer;
 
+mod tokens;
+mod watchdog;
+
+pub use watchdog::INFERENCE_TIMEOUT_ENV;
+
 // Thread-local storage for model instances
 thread_local! {
     static MODEL_INSTANCE: RefCell<Option<rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel>> = RefCell::new(None);
+    // Whether this thread's model currently runs in f16
+    static HALF_PRECISION: Cell<bool> = const { Cell::new(false) };
+    // Device this thread's model was loaded on
+    static MODEL_DEVICE: Cell<tch::Device> = const { Cell::new(tch::Device::Cpu) };
+    // Identity of the configuration this thread's model was loaded for
+    static MODEL_IDENTITY: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
 }
 
 // Constants for the MiniLM model
@@ -23,6 +38,87 @@ pub const MODEL_NAME: &str = "MiniLM-L6-v2";
 pub const MODEL_VERSION: &str = "2.0";
 pub const EMBEDDING_DIM: usize = 384;
 pub const MODEL_URL: &str = "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main/";
+/// Parameters of all-MiniLM-L6-v2, for sizing its weights without the checkpoint file
+const MODEL_PARAMETERS: u64 = 22_713_216;
+// Files of a local sentence-transformers export, looked for in the cache in offline mode
+const SENTENCE_MODEL_FILES: &[&str] = &["modules.json", "config.json", "rust_model.ot", "1_Pooling/config.json"];
+/// How texts are prepared before tokenizing, as recorded in `ModelInfo::preprocessing` and the cache identity
+const PREPROCESSING: &[(&str, &str)] = &[("lowercase", "true"), ("collapse_whitespace", "true")];
+/// Files downloaded from a hub repository other than rust-bert's default, which must include a rust-bert export
+pub const HUB_MODEL_FILES: &[&str] = &[
+    "modules.json", "config.json", "rust_model.ot", "1_Pooling/config.json", "sentence_bert_config.json",
+    "tokenizer_config.json", "special_tokens_map.json", "vocab.txt", "tokenizer.json",
+];
+
+/// Numeric precision of the model weights
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// 32-bit floats, supported on every device
+    #[default]
+    Full,
+    /// 16-bit floats on MPS and CUDA, about twice the throughput at half the memory, the CPU stays at f32
+    Half,
+}
+
+impl Precision {
+    /// Whether the weights run in f16 on `device`, the CPU falls back to f32
+    fn is_half_on(self, device: tch::Device) -> bool {
+        self == Precision::Half && device != tch::Device::Cpu
+    }
+}
+
+/// Device to run MiniLM on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Device {
+    /// The first of MPS, CUDA and the CPU that is available, the CPU in reproducible mode. A batch that fails on the GPU, such as one hitting
+    /// an operation MPS has no kernel for, is run again on the CPU with a warning instead of failing the job
+    #[default]
+    Auto,
+    Cpu,
+    Mps,
+    Cuda(usize),
+}
+
+impl Device {
+    /// The libtorch device this selects on this machine
+    pub fn resolve(self) -> tch::Device {
+        match self {
+            // MPS and CUDA kernels may sum in a different order from run to run
+            Device::Auto if utils::reproducibility::active().is_some() => tch::Device::Cpu,
+            Device::Auto => AvailableDevices::detect().preferred(),
+            Device::Cpu => tch::Device::Cpu,
+            Device::Mps => tch::Device::Mps,
+            Device::Cuda(index) => tch::Device::Cuda(index),
+        }
+    }
+}
+
+/// Accelerators present on this machine, which `Device::Auto` picks from
+#[derive(Debug, Clone, Copy, Default)]
+struct AvailableDevices {
+    mps: bool,
+    cuda: bool,
+}
+
+impl AvailableDevices {
+    fn detect() -> Self {
+        Self {
+            mps: utils::is_apple_silicon() && utils::has_mps(),
+            cuda: tch::Cuda::is_available(),
+        }
+    }
+
+    /// MPS, then the first CUDA device, then the CPU
+    fn preferred(self) -> tch::Device {
+        if self.mps {
+            tch::Device::Mps
+        } else if self.cuda {
+            tch::Device::Cuda(0)
+        } else {
+            tch::Device::Cpu
+        }
+    }
+}
 
 /// Configuration for the MiniLM model
 #[derive(Debug, Clone)]
@@ -31,10 +127,21 @@ pub struct MiniLMConfig {
     pub model_version: String,
     pub dimension: usize,
     pub model_path: Option<PathBuf>,
+    /// Hub repository and revision to download from when `model_path` is unset
+    pub hub: HubSource,
     pub device: Device,
     pub cache_embeddings: bool,
     pub cache_size_limit: usize,
     pub verify_silicon: bool,
+    pub concurrency: ConcurrencyConfig,
+    pub precision: Precision,
+    /// Run forward passes without recording autograd state, disable only to take gradients through the model
+    pub no_grad: bool,
+    /// Padded tokens per forward pass of `embed_batch`
+    pub batch_tokens: usize,
+    /// How long a batch may run before it is abandoned and its texts fail, `RUST_EMBED_INFERENCE_TIMEOUT` when
+    /// unset. Inference then runs on a separate thread, since a hung libtorch call can't be interrupted
+    pub inference_timeout: Option<Duration>,
 }
 
 impl Default for MiniLMConfig {
@@ -44,10 +151,16 @@ impl Default for MiniLMConfig {
             model_version: MODEL_VERSION.to_string(),
             dimension: EMBEDDING_DIM,
             model_path: None,
-            device: Device::Cpu,
+            hub: HubSource::default(),
+            device: Device::Auto,
             cache_embeddings: true,
             cache_size_limit: 10000, // Cache up to 10K embeddings
             verify_silicon: true,
+            concurrency: ConcurrencyConfig::default(),
+            precision: Precision::Full,
+            no_grad: true,
+            batch_tokens: DEFAULT_BATCH_TOKENS,
+            inference_timeout: None,
         }
     }
 }
@@ -66,20 +179,14 @@ impl ModelConfig for MiniLMConfig {
     }
 }
 
-/// Stats for the embedder
-#[derive(Debug, Clone, Default)]
-pub struct EmbedderStats {
-    pub embeddings_count: usize,
-    pub total_processing_time: Duration,
-    pub cache_hits: usize,
-    pub cache_misses: usize,
-}
+pub use crate::embedding::{EmbedderStats, MemoryStats};
 
 /// MiniLM embedder implementation
 #[derive(Clone)]
 pub struct MiniLMEmbedder {
     config: MiniLMConfig,
-    embedding_cache: HashMap<String, Array1<f32>>,
+    identity: Arc<str>,
+    embedding_cache: HashMap<CacheKey, Array1<f32>>,
     stats: EmbedderStats,
     is_initialized: bool,
 }
@@ -94,10 +201,11 @@ impl MiniLMEmbedder {
     pub fn with_config(config: MiniLMConfig) -> Self {
         // Initialize Apple Silicon specific utilities if needed
         if config.verify_silicon && utils::is_apple_silicon() {
-            utils::initialize().expect("Failed to initialize for Apple Silicon");
+            utils::initialize_with(&config.concurrency).expect("Failed to initialize for Apple Silicon");
         }
         
         Self {
+            identity: model_identity(&config),
             config,
             embedding_cache: HashMap::new(),
             stats: EmbedderStats::default(),
@@ -105,6 +213,37 @@ impl MiniLMEmbedder {
         }
     }
 
+    /// Create an embedder for the model packed in a bundle by `utils::bundle::create_bundle`
+    ///
+    /// The bundle is extracted into the cache the first time, later calls reuse the extracted files.
+    #[cfg(feature = "bundle")]
+    pub fn from_bundle(path: impl AsRef<Path>) -> Result<Self> {
+        let (model_dir, manifest) = utils::bundle::extract_bundle(path.as_ref())?;
+        Self::from_extracted_bundle(model_dir, manifest.model)
+    }
+
+    /// Create an embedder for the bundle compiled into the binary, None without the `embedded-model` feature
+    #[cfg(feature = "bundle")]
+    pub fn from_embedded() -> Result<Option<Self>> {
+        utils::bundle::extract_embedded()?
+            .map(|(model_dir, manifest)| Self::from_extracted_bundle(model_dir, manifest.model))
+            .transpose()
+    }
+
+    #[cfg(feature = "bundle")]
+    fn from_extracted_bundle(model_dir: PathBuf, model: utils::bundle::BundleModel) -> Result<Self> {
+        if model.kind != "minilm" {
+            return Err(err!("The bundle holds a '{}' model, not MiniLM", model.kind));
+        }
+        Ok(Self::with_config(MiniLMConfig {
+            model_name: model.model_name,
+            model_version: model.model_version,
+            dimension: model.dimension,
+            model_path: Some(model_dir),
+            ..MiniLMConfig::default()
+        }))
+    }
+
     /// Get the model name
     pub fn model_name(&self) -> &str {
         &self.config.model_name
@@ -120,17 +259,89 @@ impl MiniLMEmbedder {
         self.config.dimension
     }
 
+    /// Hash of what decides the vector of a text: the model, where its weights come from, the precision and the
+    /// preprocessing. Part of every cache key, and what `invalidate_for_model` takes
+    pub fn model_identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Switches to another configuration, loading its model on next use if the identity changed
+    ///
+    /// Cached embeddings of the previous configuration are kept but never served for this one, switching back
+    /// reuses them. `invalidate_for_model` drops them.
+    pub fn reconfigure(&mut self, config: MiniLMConfig) {
+        let identity = model_identity(&config);
+        if identity != self.identity {
+            self.is_initialized = false;
+        }
+        self.identity = identity;
+        self.config = config;
+    }
+
+    /// Sets how long a batch may run before it is abandoned, which doesn't change the model identity
+    pub fn set_inference_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.inference_timeout = timeout;
+    }
+
     /// Get embedder statistics
     pub fn stats(&self) -> &EmbedderStats {
         &self.stats
     }
     
+    /// Approximate memory of the loaded weights, the embedding cache and the weights placed on the device
+    ///
+    /// Models are kept per thread, so the weights loaded by the calling thread are counted. Activations,
+    /// which only live during a forward pass, are not included.
+    pub fn memory_stats(&self) -> MemoryStats {
+        // The f32 checkpoint when at hand, otherwise the size of all-MiniLM-L6-v2
+        let checkpoint_bytes = self.config.model_path.as_deref()
+            .and_then(|model_path| fs::metadata(model_path.join("rust_model.ot")).ok())
+            .map(|metadata| metadata.len())
+            .unwrap_or(MODEL_PARAMETERS * std::mem::size_of::<f32>() as u64);
+        
+        let sentence_bytes = match (MODEL_INSTANCE.with(|cell| cell.borrow().is_some()), HALF_PRECISION.with(Cell::get)) {
+            (false, _) => 0,
+            (true, true) => checkpoint_bytes / 2,
+            (true, false) => checkpoint_bytes,
+        };
+        // Token embeddings load a second copy of the transformer, always in f32
+        let token_bytes = if tokens::token_model_loaded() { checkpoint_bytes } else { 0 };
+        let model_bytes = sentence_bytes + token_bytes;
+        
+        MemoryStats {
+            model_bytes,
+            cache_bytes: cache_bytes(&self.embedding_cache),
+            device_bytes: (self.resolve_device() != tch::Device::Cpu).then_some(model_bytes),
+        }
+    }
+    
+    /// Initializes the model, and loads it again if another configuration's model took this thread's place
+    fn ensure_model(&mut self) -> Result<()> {
+        if !self.is_initialized {
+            return self.initialize();
+        }
+        let loaded = MODEL_IDENTITY.with(|cell| cell.borrow().as_deref().map(|identity| identity == &*self.identity));
+        match loaded {
+            Some(true) => {}
+            Some(false) => {
+                tracing::info!("Another model configuration was loaded on this thread, loading this one again");
+                self.load_or_download_model()?;
+            }
+            // Such as an inference worker started after the embedder was initialized
+            None => self.load_or_download_model()?,
+        }
+        Ok(())
+    }
+    
     /// Initializes the model and tokenizer
     pub fn initialize(&mut self) -> Result<()> {
         if self.is_initialized {
             return Ok(());
         }
         
+        // Limit threads before libtorch and rayon start their pools
+        self.config.concurrency.or_env()?.apply()?;
+        
         // Load model which also loads the tokenizer
         self.load_or_download_model()?;
         
@@ -138,143 +349,378 @@ impl MiniLMEmbedder {
         Ok(())
     }
     
+    /// Initializes the model and runs a dummy forward pass, so the first real request doesn't pay for kernel compilation on MPS
+    #[tracing::instrument(skip_all)]
+    pub fn warm_up(&mut self) -> Result<()> {
+        self.infer_loaded()?;
+        
+        let start = Instant::now();
+        // Also settles the precision, an f16 model without the kernels it needs falls back to f32 here
+        self.infer(&["warm up".to_string()])?;
+        tracing::info!("Model warmed up in {:.2?}", start.elapsed());
+        Ok(())
+    }
+    
+    /// Drops the model weights and cached embeddings to release memory while idle, the next request loads them again
+    ///
+    /// Models are kept per thread, so this releases the instances of the calling thread.
+    pub fn unload(&mut self) {
+        MODEL_INSTANCE.with(|cell| cell.borrow_mut().take());
+        MODEL_IDENTITY.with(|cell| cell.borrow_mut().take());
+        tokens::unload_token_model();
+        watchdog::stop();
+        self.embedding_cache = HashMap::new();
+        self.is_initialized = false;
+        tracing::info!("Model unloaded");
+    }
+    
     /// Download and prepare the model
+    #[tracing::instrument(skip_all)]
     pub fn load_or_download_model(&mut self) -> Result<()> {
         use rust_bert::pipelines::sentence_embeddings::{
             SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType
         };
         
-        // Configure for Apple Silicon if applicable
-        let device = if utils::is_apple_silicon() && utils::has_mps() {
-            log::info!("Using MPS backend for model acceleration");
-            Device::Mps
-        } else {
-            self.config.device
-        };
+        let mut device = self.resolve_device();
+        if device == tch::Device::Mps {
+            tracing::info!("Using MPS backend for model acceleration");
+        }
+        
+        tracing::info!("Loading the MiniLM model...");
         
-        log::info!("Loading the MiniLM model...");
+        // Offline, the model can only come from a local export in the cache. rust-bert fetches its
+        // default model itself, any other repository or revision is downloaded into the cache first
+        let model_path = match &self.config.model_path {
+            Some(model_path) => Some(model_path.clone()),
+            None if utils::offline::is_enabled() => Some(self.offline_model_dir()?),
+            None if self.config.hub.is_default() && self.config.hub.token().is_none() => None,
+            None => Some(self.config.hub.fetch(HUB_MODEL_FILES, "the MiniLM weights")?),
+        };
         
         // Use the builder pattern to create and load the model
-        if let Some(model_path) = &self.config.model_path {
+        let create = |device: tch::Device| match &model_path {
             // Use custom local model
-            let sentence_embeddings = SentenceEmbeddingsBuilder::local(model_path.to_string_lossy().to_string())
+            Some(model_path) => SentenceEmbeddingsBuilder::local(model_path.to_string_lossy().to_string())
                 .with_device(device)
-                .create_model()?;
-                
-            // Store it in thread-local storage
-            MODEL_INSTANCE.with(|cell| {
-                *cell.borrow_mut() = Some(sentence_embeddings);
-            });
-        } else {
-            // Use remote model
-            let model_id = SentenceEmbeddingsModelType::AllMiniLmL6V2;
-            // Let rust-bert handle the tokenizer loading through the SentenceEmbeddingsBuilder
-            let sentence_embeddings = SentenceEmbeddingsBuilder::remote(model_id)
+                .create_model(),
+            // Use remote model, rust-bert handles the tokenizer loading
+            None => SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
                 .with_device(device)
-                .create_model()?;
-            
-            // Store it in thread-local storage
-            MODEL_INSTANCE.with(|cell| {
-                *cell.borrow_mut() = Some(sentence_embeddings);
-            });
+                .create_model(),
+        };
+        let mut sentence_embeddings = match create(device) {
+            Ok(model) => model,
+            // An automatically chosen GPU that can't hold the model leaves the CPU
+            Err(e) if self.config.device == Device::Auto && device != tch::Device::Cpu => {
+                tracing::warn!("Loading the model on {} failed, using the CPU: {}", device_name(device), e);
+                device = tch::Device::Cpu;
+                create(device)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        
+        let half = self.config.precision.is_half_on(device);
+        if half {
+            sentence_embeddings.half()?;
+            tracing::info!("Running the model in f16 on {:?}", device);
+        } else if self.config.precision == Precision::Half {
+            tracing::info!("f16 needs MPS or CUDA, running the model in f32 on the CPU");
         }
         
-        log::info!("Model loaded successfully");
+        // Store it in thread-local storage
+        MODEL_INSTANCE.with(|cell| {
+            *cell.borrow_mut() = Some(sentence_embeddings);
+        });
+        HALF_PRECISION.with(|cell| cell.set(half));
+        MODEL_DEVICE.with(|cell| cell.set(device));
+        MODEL_IDENTITY.with(|cell| *cell.borrow_mut() = Some(self.identity.clone()));
+        
+        tracing::info!("Model loaded successfully");
         Ok(())
     }
 
+    /// Cached export of the hub model, which must be complete since offline mode can't download it
+    fn offline_model_dir(&self) -> Result<PathBuf> {
+        let model_dir = self.config.hub.cache_dir();
+        let missing: Vec<PathBuf> = SENTENCE_MODEL_FILES.iter()
+            .map(|file| model_dir.join(file))
+            .filter(|path| !path.exists())
+            .collect();
+        if !missing.is_empty() {
+            utils::offline::ensure_download_allowed(
+                "the MiniLM weights",
+                &missing,
+                &format!("Copy a rust-bert export of {} there or set model_path", self.config.hub.repo_id),
+            )?;
+        }
+        utils::model_cache::verify(&model_dir)?;
+        Ok(model_dir)
+    }
+
+    /// Device the model runs on: where this thread loaded it, or where it would be loaded
+    fn resolve_device(&self) -> tch::Device {
+        if MODEL_INSTANCE.with(|cell| cell.borrow().is_some()) {
+            return MODEL_DEVICE.with(Cell::get);
+        }
+        self.config.device.resolve()
+    }
+
     /// Embed a text into a vector representation
+    #[tracing::instrument(name = "minilm_embed_text", skip_all, fields(chars = text.len()))]
     pub fn embed_text(&mut self, text: &str) -> Result<Array1<f32>> {
         let start = Instant::now();
 
-        // Initialize if not already done
-        if !self.is_initialized {
-            self.initialize()?;
-        }
-
         // Check if in cache (if caching is enabled)
         if self.config.cache_embeddings {
-            if let Some(embedding) = self.embedding_cache.get(text) {
+            let key = CacheKey::new(&self.identity, text);
+            let cached = tracing::trace_span!("cache_lookup")
+                .in_scope(|| self.embedding_cache.get(&key).cloned());
+            if let Some(embedding) = cached {
                 self.stats.cache_hits += 1;
-                return Ok(embedding.clone());
+                return Ok(embedding);
             }
             self.stats.cache_misses += 1;
         }
         
         // Preprocess the text
         let processed_text = utils::preprocess_text(text);
-        
-        // Get model from thread-local storage or return error
-        let embedding = MODEL_INSTANCE.with(|cell| -> Result<Array1<f32>> {
-            let mut model_cell = cell.borrow_mut();
-            
-            if let Some(model) = &mut *model_cell {
-                // Encode the text
-                let embeddings = model.encode(&[processed_text])?;
-                
-                // Convert to ndarray
-                let embedding = Array1::from_vec(embeddings[0].clone());
-                
-                // Normalize the embedding
-                let mut normalized = embedding.clone();
-                utils::normalize(&mut normalized);
-                
-                Ok(normalized)
-            } else {
-                Err(anyhow!("Model not initialized. Call initialize() first."))
-            }
-        })?;
+        let embedding = self.infer(&[processed_text])
+            .map_err(|e| with_original_texts(e, || vec![text.to_string()]))?
+            .pop()
+            .ok_or_else(|| err!("Model returned no embedding"))?;
         
         // Update statistics
         self.stats.embeddings_count += 1;
         self.stats.total_processing_time += start.elapsed();
         
-        // Cache the embedding if enabled
-        if self.config.cache_embeddings {
-            self.embedding_cache.insert(text.to_string(), embedding.clone());
-            
-            // Limit cache size
-            if self.embedding_cache.len() > self.config.cache_size_limit {
-                if let Some(key) = self.embedding_cache.keys().next().cloned() {
-                    self.embedding_cache.remove(&key);
-                }
-            }
-        }
+        self.cache_embedding(text, &embedding);
         
         Ok(embedding)
     }
 
     /// Embed multiple texts in batch
+    ///
+    /// Texts missing from the cache are encoded together, sorted into batches of similar length so short texts
+    /// aren't padded to the longest one, with at most `batch_tokens` padded tokens per batch.
+    #[tracing::instrument(name = "minilm_embed_batch", skip_all, fields(texts = texts.len()))]
     pub fn embed_batch(&mut self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
-        // For Apple Silicon, use rayon for parallel processing
-        if utils::is_apple_silicon() && texts.len() > 1 {
-            use rayon::prelude::*;
-            
-            texts.par_iter()
-                .map(|text| {
-                    let mut local_embedder = self.clone();
-                    local_embedder.embed_text(text)
+        let start = Instant::now();
+        
+        let mut embeddings: Vec<Option<Array1<f32>>> = texts.iter()
+            .map(|text| {
+                self.config.cache_embeddings
+                    .then(|| self.embedding_cache.get(&CacheKey::new(&self.identity, text)).cloned())
+                    .flatten()
+            })
+            .collect();
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| embeddings[i].is_none()).collect();
+        if self.config.cache_embeddings {
+            self.stats.cache_hits += texts.len() - missing.len();
+            self.stats.cache_misses += missing.len();
+        }
+        
+        // rust-bert tokenizes inside encode, so batches are sized by estimated token counts and run one
+        // after the other, there is nothing to prepare ahead of inference
+        let processed: Vec<String> = missing.iter().map(|&i| utils::preprocess_text(&texts[i])).collect();
+        let encoded = batch_by_tokens(
+            &processed,
+            self.config.batch_tokens,
+            |text| estimate_tokens(text),
+            |batch| self.infer(&batch.iter().map(|text| text.to_string()).collect::<Vec<_>>()),
+        )?;
+        
+        self.stats.embeddings_count += encoded.len();
+        self.stats.total_processing_time += start.elapsed();
+        for (i, embedding) in missing.into_iter().zip(encoded) {
+            self.cache_embedding(&texts[i], &embedding);
+            embeddings[i] = Some(embedding);
+        }
+        
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+    
+    /// Embeds texts like `embed_batch`, with one result per text in input order so failures don't fail the rest
+    ///
+    /// Every text of a batch that exceeds `inference_timeout` fails with `Error::InferenceTimeout` naming it, and
+    /// the next batch runs. A batch failing otherwise is embedded again text by text, so only the texts that fail
+    /// get an error.
+    #[tracing::instrument(name = "minilm_embed_batch_detailed", skip_all, fields(texts = texts.len()))]
+    pub fn embed_batch_detailed(&mut self, texts: &[String]) -> Vec<Result<Array1<f32>>> {
+        self.embed_batch_detailed_with(texts, Self::infer)
+    }
+
+    /// `embed_batch_detailed` encoding preprocessed texts with `infer`
+    fn embed_batch_detailed_with(
+        &mut self,
+        texts: &[String],
+        mut infer: impl FnMut(&mut Self, &[String]) -> Result<Vec<Array1<f32>>>,
+    ) -> Vec<Result<Array1<f32>>> {
+        let start = Instant::now();
+        let mut results: Vec<Option<Result<Array1<f32>>>> = texts.iter()
+            .map(|text| {
+                self.config.cache_embeddings
+                    .then(|| self.embedding_cache.get(&CacheKey::new(&self.identity, text)).cloned().map(Ok))
+                    .flatten()
+            })
+            .collect();
+        let pending: Vec<(usize, String)> = (0..texts.len())
+            .filter(|&i| results[i].is_none())
+            .map(|i| (i, utils::preprocess_text(&texts[i])))
+            .collect();
+        if self.config.cache_embeddings {
+            self.stats.cache_hits += texts.len() - pending.len();
+            self.stats.cache_misses += pending.len();
+        }
+
+        // Errors are kept per text, so `run` never fails
+        let encoded = batch_by_tokens(
+            &pending,
+            self.config.batch_tokens,
+            |(_, text)| estimate_tokens(text),
+            |batch| {
+                let (indices, processed): (Vec<usize>, Vec<String>) = batch.iter().map(|(i, text)| (*i, text.clone())).unzip();
+                Ok::<_, Infallible>(match infer(self, &processed) {
+                    Ok(embeddings) => embeddings.into_iter().map(Ok).collect(),
+                    Err(Error::InferenceTimeout { timeout, .. }) => indices.iter()
+                        .map(|&i| Err(Error::InferenceTimeout { timeout, texts: vec![texts[i].clone()] }))
+                        .collect(),
+                    // Statistics and the cache are updated once below, for every result alike
+                    Err(_) => indices.iter().zip(&processed)
+                        .map(|(&i, text)| {
+                            infer(self, std::slice::from_ref(text))
+                                .map_err(|e| with_original_texts(e, || vec![texts[i].clone()]))?
+                                .pop()
+                                .ok_or_else(|| err!("Model returned no embedding"))
+                        })
+                        .collect::<Vec<_>>(),
                 })
-                .collect()
-        } else {
-            // Sequential processing
-            texts.iter()
-                .map(|text| self.embed_text(text))
-                .collect()
+            },
+        ).unwrap_or_else(|never| match never {});
+
+        for ((i, _), result) in pending.into_iter().zip(encoded) {
+            if let Ok(embedding) = &result {
+                self.stats.embeddings_count += 1;
+                self.cache_embedding(&texts[i], embedding);
+            }
+            results[i] = Some(result);
         }
+        self.stats.total_processing_time += start.elapsed();
+        results.into_iter().flatten().collect()
     }
 
-    /// Calculate cosine similarity between two vectors
-    pub fn cosine_similarity(&self, a: &Array1<f32>, b: &Array1<f32>) -> f32 {
-        let dot_product = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>();
-        let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-        
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
+    /// Encodes preprocessed texts, on this thread or, with an `inference_timeout`, on this thread's inference worker
+    fn infer(&mut self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
+        let no_grad = self.config.no_grad;
+        let fallback = self.config.device == Device::Auto;
+        let Some(timeout) = self.inference_timeout() else {
+            self.ensure_model()?;
+            return Self::encode(texts, no_grad, fallback);
+        };
+
+        self.infer_loaded()?;
+        let batch = texts.to_vec();
+        match watchdog::run(Some(timeout), move || Self::encode(&batch, no_grad, fallback))? {
+            Some(result) => result,
+            None => {
+                let previews: Vec<String> = texts.iter().map(|text| text.chars().take(80).collect()).collect();
+                tracing::warn!("Inference timed out after {:?}, abandoning a batch of {} texts: {:?}", timeout, texts.len(), previews);
+                Err(Error::InferenceTimeout { timeout, texts: texts.to_vec() })
+            }
+        }
+    }
+
+    /// Loads the model where `infer` runs it, on the inference worker with no time limit when there is a timeout
+    fn infer_loaded(&mut self) -> Result<()> {
+        if self.inference_timeout().is_none() {
+            return self.ensure_model();
+        }
+        if !self.is_initialized {
+            self.config.concurrency.or_env()?.apply()?;
+        }
+        // The worker keeps its own model, loaded by a copy of this embedder without the cache when it has
+        // none or another configuration's
+        let mut worker_embedder = Self {
+            config: self.config.clone(),
+            identity: self.identity.clone(),
+            embedding_cache: HashMap::new(),
+            stats: EmbedderStats::default(),
+            is_initialized: true,
+        };
+        watchdog::run(None, move || worker_embedder.ensure_model())?
+            .expect("jobs without a timeout always finish")?;
+        self.is_initialized = true;
+        Ok(())
+    }
+
+    /// `inference_timeout`, or `RUST_EMBED_INFERENCE_TIMEOUT` when unset
+    fn inference_timeout(&self) -> Option<Duration> {
+        self.config.inference_timeout.or_else(watchdog::timeout_from_env)
+    }
+
+    /// Encodes preprocessed texts with this thread's model into normalized embeddings
+    ///
+    /// A model in f16 that hits an operation without a half-precision kernel is cast back to f32 and retried.
+    /// With `fallback`, a batch that still fails on the GPU is run on the CPU, and the model moved back after.
+    fn encode(texts: &[String], no_grad: bool, fallback: bool) -> Result<Vec<Array1<f32>>> {
+        MODEL_INSTANCE.with(|cell| match &mut *cell.borrow_mut() {
+            Some(model) => {
+                // tch panics on kernels the device lacks, so panics are caught to retry like errors
+                let run = |model: &mut rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel| {
+                    tracing::debug_span!("inference", texts = texts.len())
+                        .in_scope(|| std::panic::catch_unwind(AssertUnwindSafe(|| grad_mode(no_grad, || model.encode(texts)))))
+                };
+                let failed = |attempt: &std::thread::Result<std::result::Result<_, _>>| !matches!(attempt, Ok(Ok(_)));
+
+                let mut attempt = run(model);
+                if failed(&attempt) && HALF_PRECISION.with(Cell::get) {
+                    tracing::warn!("f16 inference failed, falling back to f32");
+                    model.float()?;
+                    HALF_PRECISION.with(|cell| cell.set(false));
+                    attempt = run(model);
+                }
+                let device = MODEL_DEVICE.with(Cell::get);
+                if failed(&attempt) && fallback && device != tch::Device::Cpu {
+                    tracing::warn!("Inference on {} failed, running this batch of {} texts on the CPU", device_name(device), texts.len());
+                    model.set_device(tch::Device::Cpu);
+                    attempt = run(model);
+                    model.set_device(device);
+                }
+
+                let embeddings = match attempt {
+                    Ok(Ok(embeddings)) => embeddings,
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                };
+                Ok(embeddings.into_iter()
+                    .map(|embedding| {
+                        let mut embedding = Array1::from_vec(embedding);
+                        utils::normalize(&mut embedding);
+                        embedding
+                    })
+                    .collect())
+            }
+            None => Err(Error::ModelNotInitialized(MODEL_NAME.to_string())),
+        })
+    }
+    
+    /// Caches an embedding if caching is enabled, evicting an entry beyond the size limit
+    fn cache_embedding(&mut self, text: &str, embedding: &Array1<f32>) {
+        if !self.config.cache_embeddings {
+            return;
         }
+        self.embedding_cache.insert(CacheKey::new(&self.identity, text), embedding.clone());
         
-        dot_product / (norm_a * norm_b)
+        // Limit cache size
+        if self.embedding_cache.len() > self.config.cache_size_limit {
+            if let Some(key) = self.embedding_cache.keys().next().cloned() {
+                self.embedding_cache.remove(&key);
+            }
+        }
+    }
+
+    /// Calculate cosine similarity between two vectors
+    pub fn cosine_similarity(&self, a: &Array1<f32>, b: &Array1<f32>) -> f32 {
+        utils::cosine_similarity(a, b)
     }
 
     /// Clear the embedding cache
@@ -282,6 +728,13 @@ impl MiniLMEmbedder {
         self.embedding_cache.clear();
     }
 
+    /// Drops the cached embeddings made by the configuration with this `model_identity`, returning how many
+    pub fn invalidate_for_model(&mut self, identity: &str) -> usize {
+        let before = self.embedding_cache.len();
+        self.embedding_cache.retain(|key, _| &*key.model != identity);
+        before - self.embedding_cache.len()
+    }
+
     /// Get the number of cached embeddings
     pub fn cache_size(&self) -> usize {
         self.embedding_cache.len()
@@ -289,6 +742,24 @@ impl MiniLMEmbedder {
     
     /// Find the most similar texts to the query
     pub fn find_similar(&mut self, query: &str, texts: &[String], top_k: usize) -> Result<Vec<(String, f32)>> {
+        let similarities = self.rank_texts(query, texts)?;
+        
+        // Return top-k results
+        Ok(similarities.into_iter().take(top_k).collect())
+    }
+    
+    /// Find all texts whose similarity to the query is at least `min_score`
+    pub fn find_above(&mut self, query: &str, texts: &[String], min_score: f32) -> Result<Vec<(String, f32)>> {
+        let similarities = self.rank_texts(query, texts)?;
+        
+        // Similarities are sorted, so stop at the first one below the threshold
+        Ok(similarities.into_iter()
+            .take_while(|(_, similarity)| *similarity >= min_score)
+            .collect())
+    }
+    
+    /// Embeds the query and texts and returns the texts sorted by similarity (descending)
+    fn rank_texts(&mut self, query: &str, texts: &[String]) -> Result<Vec<(String, f32)>> {
         let query_embedding = self.embed_text(query)?;
         
         // Calculate similarities and sort
@@ -307,23 +778,28 @@ impl MiniLMEmbedder {
         // Sort by similarity (descending)
         similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
         
-        // Return top-k results
-        Ok(similarities.into_iter().take(top_k).collect())
+        Ok(similarities)
     }
 }
 
 // Implement the Embedder trait for MiniLMEmbedder
 impl Embedder for MiniLMEmbedder {
     fn embed_text(&self, text: &str) -> Result<Array1<f32>> {
-        // Clone self to get a mutable version since our methods require &mut self
+        // Clone self to get a mutable version since our methods require &mut self.
+        // Call the inherent method explicitly, method syntax resolves back to this trait method.
         let mut embedder = self.clone();
-        embedder.embed_text(text)
+        MiniLMEmbedder::embed_text(&mut embedder, text)
     }
     
     fn embed_batch(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
         // Clone self to get a mutable version
         let mut embedder = self.clone();
-        embedder.embed_batch(texts)
+        MiniLMEmbedder::embed_batch(&mut embedder, texts)
+    }
+    
+    fn embed_batch_detailed(&self, texts: &[String]) -> Vec<Result<Array1<f32>>> {
+        let mut embedder = self.clone();
+        MiniLMEmbedder::embed_batch_detailed(&mut embedder, texts)
     }
     
     fn model_name(&self) -> &str {
@@ -337,6 +813,88 @@ impl Embedder for MiniLMEmbedder {
     fn dimension(&self) -> usize {
         self.dimension()
     }
+    
+    fn model_info(&self) -> ModelInfo {
+        // A local export records its own limits, the default remote model is all-MiniLM-L6-v2
+        let checkpoint = CheckpointInfo::read(&self.config.model_path.clone().unwrap_or_else(|| self.config.hub.cache_dir()));
+        ModelInfo {
+            max_tokens: checkpoint.max_tokens.or(Some(tokens::MAX_TOKENS)),
+            pooling: checkpoint.pooling.or(Some("mean".to_string())),
+            normalized: true,
+            license: (self.config.model_path.is_none() && self.config.hub.repo_id == crate::models::hub::DEFAULT_REPO)
+                .then(|| "Apache-2.0".to_string()),
+            source_url: Some(match &self.config.model_path {
+                Some(model_path) => model_path.display().to_string(),
+                None => self.config.hub.repo_url(),
+            }),
+            device: Some(device_name(self.resolve_device())),
+            // The remote model's weights are only at hand once token embeddings downloaded them to the cache
+            weights_sha256: utils::model_cache::file_sha256(
+                &self.config.model_path.clone().unwrap_or_else(|| self.config.hub.cache_dir()),
+                "rust_model.ot",
+            ),
+            preprocessing: PREPROCESSING.iter()
+                .map(|(option, value)| (option.to_string(), value.to_string()))
+                .collect(),
+            ..ModelInfo::new(self.model_name(), self.model_version(), self.dimension())
+        }
+    }
+    
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        Ok(estimate_tokens(&utils::preprocess_text(text)))
+    }
+}
+
+/// A timeout naming the texts as the caller passed them instead of preprocessed, other errors as they are
+fn with_original_texts(error: Error, texts: impl FnOnce() -> Vec<String>) -> Error {
+    match error {
+        Error::InferenceTimeout { timeout, .. } => Error::InferenceTimeout { timeout, texts: texts() },
+        error => error,
+    }
+}
+
+/// Runs `forward` under `tch::no_grad` unless gradients were asked for
+fn grad_mode<T>(no_grad: bool, forward: impl FnOnce() -> T) -> T {
+    if no_grad {
+        tch::no_grad(forward)
+    } else {
+        forward()
+    }
+}
+
+/// Name of a device as the registry parses it, such as "cpu" or "cuda:1"
+fn device_name(device: tch::Device) -> String {
+    match device {
+        tch::Device::Cpu => "cpu".to_string(),
+        tch::Device::Mps => "mps".to_string(),
+        tch::Device::Cuda(index) => format!("cuda:{}", index),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+/// Identity of the vectors a configuration produces, hashed from the model, its source, precision and preprocessing
+fn model_identity(config: &MiniLMConfig) -> Arc<str> {
+    use sha2::{Digest, Sha256};
+    
+    let source = match &config.model_path {
+        Some(model_path) => model_path.display().to_string(),
+        None => format!("{}@{}", config.hub.repo_url(), config.hub.revision),
+    };
+    let preprocessing: Vec<String> = PREPROCESSING.iter().map(|(option, value)| format!("{}={}", option, value)).collect();
+    let fields = [
+        config.model_name.clone(),
+        config.model_version.clone(),
+        source,
+        format!("{:?}", config.precision),
+        preprocessing.join(","),
+    ];
+    let hash = format!("{:x}", Sha256::digest(fields.join("\n").as_bytes()));
+    Arc::from(&hash[..16])
+}
+
+/// Estimated tokens of a preprocessed text, which is truncated to the model's sequence length
+fn estimate_tokens(text: &str) -> usize {
+    utils::estimate_tokens(text).min(tokens::MAX_TOKENS)
 }
 
 /// Helper functions
@@ -346,4 +904,156 @@ fn truncate_text(text: &str, max_len: usize) -> String {
     } else {
         format!("{}...", &text[..max_len])
     }
-} 
\ No newline at end of file
+} 
+/// Estimated bytes of a cache: the vector, key text and map slot of each entry
+fn cache_bytes(cache: &HashMap<CacheKey, Array1<f32>>) -> u64 {
+    cache.iter()
+        .map(|(key, embedding)| {
+            key.text.capacity() + embedding.len() * std::mem::size_of::<f32>() + std::mem::size_of::<(CacheKey, Array1<f32>)>()
+        })
+        .sum::<usize>() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MiniLMConfig {
+        MiniLMConfig { verify_silicon: false, ..MiniLMConfig::default() }
+    }
+
+    #[test]
+    fn test_identity_follows_what_changes_the_vectors() {
+        let base = model_identity(&config());
+        let mut revision = config();
+        revision.hub.revision = "0123abcd".to_string();
+        let changed = [
+            MiniLMConfig { model_path: Some(PathBuf::from("/models/minilm")), ..config() },
+            revision,
+            MiniLMConfig { precision: Precision::Half, ..config() },
+        ];
+        for config in &changed {
+            assert_ne!(model_identity(config), base);
+        }
+        assert_eq!(model_identity(&MiniLMConfig { inference_timeout: Some(Duration::from_secs(5)), ..config() }), base);
+    }
+
+    #[test]
+    fn test_invalidates_only_the_cache_of_one_identity() {
+        let mut embedder = MiniLMEmbedder::with_config(config());
+        let full = embedder.model_identity().to_string();
+        embedder.cache_embedding("shared", &Array1::from(vec![1.0, 0.0]));
+        embedder.cache_embedding("full only", &Array1::from(vec![0.0, 1.0]));
+
+        // Only the timeout differs, so the loaded model is kept
+        embedder.is_initialized = true;
+        embedder.reconfigure(MiniLMConfig { inference_timeout: Some(Duration::from_secs(5)), ..config() });
+        assert_eq!(embedder.model_identity(), full);
+        assert!(embedder.is_initialized);
+
+        embedder.reconfigure(MiniLMConfig { precision: Precision::Half, ..config() });
+        assert_ne!(embedder.model_identity(), full);
+        assert!(!embedder.is_initialized);
+        embedder.cache_embedding("shared", &Array1::from(vec![0.5, 0.5]));
+        assert_eq!(embedder.cache_size(), 3);
+
+        assert_eq!(embedder.invalidate_for_model(&full), 2);
+        let half = embedder.model_identity().to_string();
+        assert!(embedder.embedding_cache.keys().all(|key| *key.model == *half));
+        assert_eq!(embedder.invalidate_for_model(&full), 0);
+        assert_eq!(embedder.cache_size(), 1);
+    }
+
+    #[test]
+    fn test_counts_texts_embedded_after_a_batch_failure_once() {
+        let mut embedder = MiniLMEmbedder::with_config(config());
+        embedder.cache_embedding("cached", &Array1::from(vec![1.0, 0.0]));
+        let texts: Vec<String> = ["cached", "one", "Two", "bad"].iter().map(|text| text.to_string()).collect();
+
+        // Batches fail, so each text is embedded on its own
+        let results = embedder.embed_batch_detailed_with(&texts, |_, batch| match batch {
+            [text] if text == "bad" => Err(err!("unreadable")),
+            [text] => Ok(vec![Array1::from(vec![text.len() as f32, 1.0])]),
+            _ => Err(err!("batch failed")),
+        });
+        assert_eq!(results[2].as_ref().unwrap(), &Array1::from(vec![3.0, 1.0]));
+        assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(), vec![true, true, true, false]);
+
+        let stats = embedder.stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (1, 3));
+        assert_eq!(stats.embeddings_count, 2);
+        assert_eq!(embedder.cache_size(), 3);
+    }
+
+    #[test]
+    fn test_cache_bytes_counts_vectors_keys_and_slots() {
+        let mut embedder = MiniLMEmbedder::with_config(config());
+        assert_eq!(cache_bytes(&embedder.embedding_cache), 0);
+
+        embedder.cache_embedding("ab", &Array1::zeros(384));
+        embedder.cache_embedding("cde", &Array1::zeros(384));
+        let slot = std::mem::size_of::<(CacheKey, Array1<f32>)>() as u64;
+        assert_eq!(cache_bytes(&embedder.embedding_cache), 2 * 384 * 4 + 5 + 2 * slot);
+        assert_eq!(embedder.memory_stats().cache_bytes, cache_bytes(&embedder.embedding_cache));
+    }
+
+    fn model_loaded() -> bool {
+        MODEL_INSTANCE.with(|cell| cell.borrow().is_some())
+    }
+
+    #[test]
+    fn test_unload_drops_the_cache_and_initialization() {
+        let mut embedder = MiniLMEmbedder::with_config(config());
+        embedder.cache_embedding("cached", &Array1::from(vec![1.0, 0.0]));
+        embedder.is_initialized = true;
+
+        embedder.unload();
+        assert_eq!(embedder.cache_size(), 0);
+        assert!(!embedder.is_initialized);
+        assert!(!model_loaded());
+        assert!(MODEL_IDENTITY.with(|cell| cell.borrow().is_none()));
+        assert!(!tokens::token_model_loaded());
+    }
+
+    #[test]
+    #[ignore = "downloads the MiniLM weights, run with --ignored"]
+    fn test_embed_text_reloads_an_unloaded_model() -> Result<()> {
+        let mut embedder = MiniLMEmbedder::with_config(config());
+        embedder.warm_up()?;
+        let before = embedder.embed_text("reloaded lazily")?;
+        assert!(model_loaded());
+
+        embedder.unload();
+        assert!(!model_loaded());
+        assert_eq!(embedder.cache_size(), 0);
+
+        let after = embedder.embed_text("reloaded lazily")?;
+        assert!(model_loaded());
+        assert_eq!(after.len(), EMBEDDING_DIM);
+        assert!((&after - &before).iter().all(|difference| difference.abs() < 1e-5));
+        embedder.unload();
+        Ok(())
+    }
+
+    #[test]
+    fn test_half_precision_falls_back_to_full_on_the_cpu() {
+        assert!(!Precision::Half.is_half_on(tch::Device::Cpu));
+        assert!(Precision::Half.is_half_on(tch::Device::Mps));
+        assert!(Precision::Half.is_half_on(tch::Device::Cuda(0)));
+        assert!(!Precision::Full.is_half_on(tch::Device::Mps));
+
+        // The requested precision feeds the identity, even on the CPU where it runs in f32
+        let half = MiniLMConfig { precision: Precision::Half, device: Device::Cpu, ..config() };
+        let full = MiniLMConfig { precision: Precision::Full, device: Device::Cpu, ..config() };
+        assert_ne!(model_identity(&half), model_identity(&full));
+    }
+
+    #[test]
+    fn test_auto_prefers_mps_then_cuda_then_the_cpu() {
+        let both = AvailableDevices { mps: true, cuda: true };
+        assert_eq!(both.preferred(), tch::Device::Mps);
+        assert_eq!(AvailableDevices { mps: true, cuda: false }.preferred(), tch::Device::Mps);
+        assert_eq!(AvailableDevices { mps: false, cuda: true }.preferred(), tch::Device::Cuda(0));
+        assert_eq!(AvailableDevices::default().preferred(), tch::Device::Cpu);
+    }
+}
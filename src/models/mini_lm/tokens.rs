@@ -0,0 +1,180 @@
+use super::{grad_mode, MiniLMEmbedder};
+use crate::error::{err, Result};
+use ndarray::Array2;
+use rust_bert::bert::{BertConfig, BertEmbeddings, BertModel};
+use rust_bert::Config;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use tch::{nn::VarStore, Device, Kind, Tensor};
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// Maximum sequence length all-MiniLM-L6-v2 was trained with
+pub(super) const MAX_TOKENS: usize = 256;
+
+/// Files needed to run the transformer outside the sentence embeddings pipeline
+const TOKEN_MODEL_FILES: &[&str] = &["config.json", "tokenizer.json", "rust_model.ot"];
+
+/// The bare transformer, without pooling, used for token-level embeddings
+struct TokenModel {
+    model: BertModel<BertEmbeddings>,
+    // Owns the weights referenced by the model
+    _var_store: VarStore,
+    tokenizer: Tokenizer,
+    device: Device,
+    no_grad: bool,
+}
+
+// Thread-local storage for the token model, like the pooled model instance
+thread_local! {
+    static TOKEN_MODEL: RefCell<Option<TokenModel>> = const { RefCell::new(None) };
+}
+
+/// Whether the calling thread has loaded the token model
+pub(super) fn token_model_loaded() -> bool {
+    TOKEN_MODEL.with(|cell| cell.borrow().is_some())
+}
+
+/// Drops the calling thread's token model, see `MiniLMEmbedder::unload`
+pub(super) fn unload_token_model() {
+    TOKEN_MODEL.with(|cell| cell.borrow_mut().take());
+}
+
+impl MiniLMEmbedder {
+    /// Embeds each token of a text, returning the tokens and their vectors before pooling
+    ///
+    /// Rows of the matrix line up with the tokens, including the [CLS] and [SEP] markers.
+    pub fn embed_tokens(&mut self, text: &str) -> Result<(Vec<String>, Array2<f32>)> {
+        TOKEN_MODEL.with(|cell| {
+            let mut cell = cell.borrow_mut();
+            if cell.is_none() {
+                *cell = Some(self.load_token_model()?);
+            }
+
+            match &*cell {
+                Some(model) => model.forward(text),
+                None => Err(err!("Token model failed to load")),
+            }
+        })
+    }
+
+    /// Loads the transformer weights and tokenizer for token-level embeddings
+    fn load_token_model(&self) -> Result<TokenModel> {
+        let model_dir = self.token_model_dir()?;
+        let device = self.resolve_device();
+        tracing::info!("Loading the MiniLM transformer for token embeddings from {}", model_dir.display());
+
+        let config = BertConfig::from_file(model_dir.join("config.json"));
+        let mut var_store = VarStore::new(device);
+        let model = BertModel::<BertEmbeddings>::new(var_store.root(), &config);
+        var_store.load(model_dir.join("rust_model.ot"))?;
+        if self.config.no_grad {
+            // Weights that never need gradients don't keep autograd state around
+            var_store.freeze();
+        }
+
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| err!("Failed to load tokenizer: {}", e))?;
+        tokenizer.with_truncation(Some(TruncationParams {
+            max_length: MAX_TOKENS,
+            ..TruncationParams::default()
+        }));
+
+        Ok(TokenModel {
+            model,
+            _var_store: var_store,
+            tokenizer,
+            device,
+            no_grad: self.config.no_grad,
+        })
+    }
+
+    /// Directory with the transformer files, downloading them into the cache if needed
+    fn token_model_dir(&self) -> Result<PathBuf> {
+        if let Some(model_path) = &self.config.model_path {
+            if let Some(missing) = TOKEN_MODEL_FILES.iter().find(|file| !model_path.join(file).exists()) {
+                return Err(err!(
+                    "Token embeddings need {} in {}", missing, model_path.display()
+                ));
+            }
+            return Ok(model_path.clone());
+        }
+
+        self.config.hub.fetch(TOKEN_MODEL_FILES, "the MiniLM transformer files")
+    }
+}
+
+impl TokenModel {
+    /// Runs the transformer over one text and returns its last hidden state
+    fn forward(&self, text: &str) -> Result<(Vec<String>, Array2<f32>)> {
+        let encoding = self.tokenizer.encode(text, true)
+            .map_err(|e| err!("Tokenization failed: {}", e))?;
+
+        let ids: Vec<i64> = encoding.get_ids().iter().map(|&id| id as i64).collect();
+        let mask: Vec<i64> = encoding.get_attention_mask().iter().map(|&m| m as i64).collect();
+        let types: Vec<i64> = encoding.get_type_ids().iter().map(|&t| t as i64).collect();
+        let length = ids.len();
+
+        let input_ids = Tensor::from_slice(&ids).view((1, -1)).to(self.device);
+        let attention_mask = Tensor::from_slice(&mask).view((1, -1)).to(self.device);
+        let token_type_ids = Tensor::from_slice(&types).view((1, -1)).to(self.device);
+
+        let output = grad_mode(self.no_grad, || {
+            self.model.forward_t(
+                Some(&input_ids),
+                Some(&attention_mask),
+                Some(&token_type_ids),
+                None,
+                None,
+                None,
+                None,
+                false,
+            )
+        })?;
+
+        let hidden = output.hidden_state
+            .squeeze_dim(0)
+            .to_kind(Kind::Float)
+            .to_device(Device::Cpu)
+            .flatten(0, -1);
+        let values = Vec::<f32>::try_from(&hidden)?;
+
+        let dimension = values.len().checked_div(length).unwrap_or(0);
+        let vectors = Array2::from_shape_vec((length, dimension), values)?;
+
+        Ok((encoding.get_tokens().to_vec(), vectors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mini_lm::MiniLMConfig;
+
+    #[test]
+    fn test_token_model_dir_names_the_missing_file() {
+        let dir = std::env::temp_dir().join(format!("rust_embed_tokens_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.json"), "{}").unwrap();
+        let config = MiniLMConfig { model_path: Some(dir.clone()), verify_silicon: false, ..MiniLMConfig::default() };
+
+        let error = MiniLMEmbedder::with_config(config).token_model_dir().unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.to_string(), format!("Token embeddings need tokenizer.json in {}", dir.display()));
+    }
+
+    #[test]
+    #[ignore = "downloads the MiniLM transformer, run with --ignored"]
+    fn test_embed_tokens_returns_a_row_per_token() -> Result<()> {
+        let mut embedder = MiniLMEmbedder::with_config(MiniLMConfig { verify_silicon: false, ..MiniLMConfig::default() });
+        let (tokens, vectors) = embedder.embed_tokens("Hello world")?;
+        unload_token_model();
+
+        assert_eq!(tokens.len(), vectors.nrows());
+        assert_eq!(vectors.ncols(), embedder.dimension());
+        // The markers the tokenizer adds get rows of their own
+        assert_eq!(tokens.first().map(String::as_str), Some("[CLS]"));
+        assert_eq!(tokens.last().map(String::as_str), Some("[SEP]"));
+        assert_eq!(tokens[1..tokens.len() - 1], ["hello", "world"]);
+        Ok(())
+    }
+}
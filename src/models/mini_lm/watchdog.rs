@@ -0,0 +1,107 @@
+use crate::error::{err, Context, Result};
+use std::cell::RefCell;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Environment variable with the seconds a batch may run, used when `MiniLMConfig::inference_timeout` is unset
+pub const INFERENCE_TIMEOUT_ENV: &str = "RUST_EMBED_INFERENCE_TIMEOUT";
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Queue of the thread running inference for the calling thread, which keeps its own model
+thread_local! {
+    static WORKER: RefCell<Option<mpsc::Sender<Job>>> = const { RefCell::new(None) };
+}
+
+/// Reads `RUST_EMBED_INFERENCE_TIMEOUT`, None when unset or not a positive number of seconds
+pub(super) fn timeout_from_env() -> Option<Duration> {
+    let value = std::env::var(INFERENCE_TIMEOUT_ENV).ok().filter(|value| !value.trim().is_empty())?;
+    match value.trim().parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Some(Duration::from_secs_f64(seconds)),
+        _ => {
+            tracing::warn!("{} must be a positive number of seconds, got '{}', inference has no timeout", INFERENCE_TIMEOUT_ENV, value);
+            None
+        }
+    }
+}
+
+/// Runs `job` on the calling thread's inference worker, None if it hasn't finished after `timeout`
+///
+/// libtorch calls can't be interrupted, so a job that times out is left running on its worker and the next job
+/// starts a new one, which loads the model again. The abandoned worker exits once the call returns, if it ever
+/// does. Without a timeout, such as for loading the model, it waits however long the job takes. A job that
+/// panics panics the calling thread.
+pub(super) fn run<T: Send + 'static>(timeout: Option<Duration>, job: impl FnOnce() -> T + Send + 'static) -> Result<Option<T>> {
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let job: Job = Box::new(move || {
+        let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(job)));
+    });
+    WORKER.with(|cell| -> Result<()> {
+        let mut worker = cell.borrow_mut();
+        let queue = match worker.take() {
+            Some(queue) => queue,
+            None => spawn()?,
+        };
+        // The queue only closes if the worker died, so a new one takes the job
+        let queue = match queue.send(job) {
+            Ok(()) => queue,
+            Err(mpsc::SendError(job)) => {
+                let queue = spawn()?;
+                queue.send(job).map_err(|_| err!("The inference thread exited"))?;
+                queue
+            }
+        };
+        *worker = Some(queue);
+        Ok(())
+    })?;
+
+    let output = match timeout {
+        Some(timeout) => receiver.recv_timeout(timeout),
+        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+    };
+    match output {
+        Ok(Ok(output)) => Ok(Some(output)),
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        Err(RecvTimeoutError::Timeout) => {
+            stop();
+            Ok(None)
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(err!("The inference thread exited without a result")),
+    }
+}
+
+/// Lets the calling thread's worker exit after its current job, dropping its model
+pub(super) fn stop() {
+    WORKER.with(|cell| cell.borrow_mut().take());
+}
+
+/// Starts a worker running the jobs of its queue in order until the queue is dropped
+fn spawn() -> Result<mpsc::Sender<Job>> {
+    let (queue, jobs) = mpsc::channel::<Job>();
+    thread::Builder::new()
+        .name("rust_embed-inference".to_string())
+        .spawn(move || {
+            for job in jobs {
+                job();
+            }
+        })
+        .context("Failed to start the inference thread")?;
+    Ok(queue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abandons_jobs_that_time_out() -> Result<()> {
+        let caller = thread::current().id();
+        assert_eq!(run(None, move || thread::current().id() != caller)?, Some(true));
+        assert_eq!(run(Some(Duration::from_millis(20)), || thread::sleep(Duration::from_secs(2)))?, None);
+        // A new worker takes over while the abandoned one is still sleeping
+        assert_eq!(run(None, || 42)?, Some(42));
+        Ok(())
+    }
+}
@@ -1,8 +1,33 @@
+#[cfg(any(feature = "torch", feature = "onnx"))]
+pub(crate) mod batching;
+#[cfg(feature = "onnx")]
+pub mod clip;
+pub mod code;
+pub mod hub;
+#[cfg(feature = "torch")]
 pub mod mini_lm;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod registry;
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+pub mod remote;
+#[cfg(feature = "onnx")]
+pub mod splade;
+pub mod static_embed;
 
-// Include the generated Protobuf code
-pub mod proto {
-    include!(concat!(env!("OUT_DIR"), "/embeddings.rs"));
+pub use hub::HubSource;
+
+use serde::Deserialize;
+#[cfg(feature = "torch")]
+use std::path::Path;
+
+/// Whether a text is a search query or a document to be searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputType {
+    Query,
+    #[default]
+    Passage,
 }
 
 // Common model traits and utilities
@@ -11,4 +36,58 @@ pub trait ModelConfig {
     fn dimension(&self) -> usize;
     fn model_name(&self) -> &str;
     fn model_version(&self) -> &str;
-} 
\ No newline at end of file
+}
+
+/// Maximum sequence length and pooling recorded in a sentence-transformers checkpoint
+#[cfg(feature = "torch")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct CheckpointInfo {
+    pub max_tokens: Option<usize>,
+    pub pooling: Option<String>,
+}
+
+#[cfg(feature = "torch")]
+impl CheckpointInfo {
+    /// Reads `sentence_bert_config.json`, `tokenizer_config.json` and `1_Pooling/config.json`, whichever exist
+    pub(crate) fn read(model_dir: &Path) -> Self {
+        let read_json = |file: &str| -> Option<serde_json::Value> {
+            serde_json::from_slice(&std::fs::read(model_dir.join(file)).ok()?).ok()
+        };
+
+        // Tokenizers without a limit record a huge sentinel as model_max_length
+        let max_tokens = read_json("sentence_bert_config.json")
+            .and_then(|config| config.get("max_seq_length")?.as_u64())
+            .or_else(|| read_json("tokenizer_config.json")
+                .and_then(|config| config.get("model_max_length")?.as_u64())
+                .filter(|&length| length <= 1 << 20))
+            .map(|length| length as usize);
+
+        let pooling = read_json("1_Pooling/config.json").and_then(|config| {
+            [("pooling_mode_cls_token", "cls"), ("pooling_mode_mean_tokens", "mean"), ("pooling_mode_max_tokens", "max")]
+                .into_iter()
+                .find(|(key, _)| config.get(key).and_then(|v| v.as_bool()).unwrap_or(false))
+                .map(|(_, name)| name.to_string())
+        });
+
+        Self { max_tokens, pooling }
+    }
+}
+
+#[cfg(all(test, feature = "torch"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_checkpoint_info() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust_embed_checkpoint_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("1_Pooling"))?;
+        std::fs::write(dir.join("tokenizer_config.json"), r#"{"model_max_length": 1000000000000000019884624838656}"#)?;
+        std::fs::write(dir.join("1_Pooling/config.json"), r#"{"pooling_mode_cls_token": false, "pooling_mode_mean_tokens": true}"#)?;
+        assert_eq!(CheckpointInfo::read(&dir), CheckpointInfo { max_tokens: None, pooling: Some("mean".to_string()) });
+
+        std::fs::write(dir.join("sentence_bert_config.json"), r#"{"max_seq_length": 256}"#)?;
+        assert_eq!(CheckpointInfo::read(&dir).max_tokens, Some(256));
+
+        std::fs::remove_dir_all(&dir)
+    }
+}
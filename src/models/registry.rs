@@ -0,0 +1,480 @@
+use crate::embedding::{Embedder, ModelInfo};
+#[cfg(feature = "onnx")]
+use crate::models::clip::{ClipConfig, ClipEmbedder};
+#[cfg(feature = "onnx")]
+use crate::models::code::CodeEmbedder;
+#[cfg(feature = "torch")]
+use crate::models::mini_lm::{MiniLMConfig, MiniLMEmbedder};
+#[cfg(feature = "onnx")]
+use crate::models::onnx::{ExecutionProvider, OnnxConfig, OnnxEmbedder, OnnxModel};
+use crate::models::static_embed::StaticEmbedder;
+use crate::models::{HubSource, InputType};
+use crate::error::{err, Error, Result};
+use ndarray::{Array1, Array2};
+use serde::Deserialize;
+use std::path::PathBuf;
+#[cfg(feature = "torch")]
+use crate::models::mini_lm::Device;
+
+/// Embedding models known to the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelKind {
+    /// all-MiniLM-L6-v2 (384 dimensions)
+    MiniLm,
+    /// bge-small-en-v1.5 via ONNX (384 dimensions)
+    BgeSmall,
+    /// bge-base-en-v1.5 via ONNX (768 dimensions)
+    BgeBase,
+    /// e5-small-v2 via ONNX (384 dimensions)
+    E5Small,
+    /// e5-base-v2 via ONNX (768 dimensions)
+    E5Base,
+    /// jina-embeddings-v2-base-code via ONNX with code-aware preprocessing (768 dimensions)
+    JinaCode,
+    /// all-mpnet-base-v2 via ONNX (768 dimensions)
+    Mpnet,
+    /// CLIP ViT-B/32 text encoder via ONNX, sharing its space with image embeddings (512 dimensions)
+    Clip,
+    /// Averaged static token vectors (model2vec or fastText/GloVe text files), needs no libtorch
+    Static,
+}
+
+impl ModelKind {
+    /// All registered model kinds
+    pub const ALL: &'static [ModelKind] = &[
+        ModelKind::MiniLm,
+        ModelKind::BgeSmall,
+        ModelKind::BgeBase,
+        ModelKind::E5Small,
+        ModelKind::E5Base,
+        ModelKind::JinaCode,
+        ModelKind::Mpnet,
+        ModelKind::Clip,
+        ModelKind::Static,
+    ];
+
+    /// Looks up a model kind by one of its names (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        Self::ALL.iter()
+            .copied()
+            .find(|kind| kind.names().iter().any(|n| *n == name))
+    }
+
+    /// The canonical registry name of this model
+    pub fn name(&self) -> &'static str {
+        self.names()[0]
+    }
+
+    /// Names accepted for this model, the first one is canonical
+    pub fn names(&self) -> &'static [&'static str] {
+        match self {
+            ModelKind::MiniLm => &["minilm", "all-minilm-l6-v2", "minilm-l6-v2"],
+            ModelKind::BgeSmall => &["bge-small", "bge-small-en-v1.5"],
+            ModelKind::BgeBase => &["bge-base", "bge-base-en-v1.5"],
+            ModelKind::E5Small => &["e5-small", "e5-small-v2"],
+            ModelKind::E5Base => &["e5-base", "e5-base-v2"],
+            ModelKind::JinaCode => &["jina-code", "jina-embeddings-v2-base-code", "code"],
+            ModelKind::Mpnet => &["mpnet", "all-mpnet-base-v2"],
+            ModelKind::Clip => &["clip", "clip-vit-b-32", "clip-vit-base-patch32"],
+            ModelKind::Static => &["static", "model2vec", "word-vectors"],
+        }
+    }
+
+    /// Dimension of the embeddings produced by this model, None if it depends on the loaded weights
+    pub fn dimension(&self) -> Option<usize> {
+        match self {
+            ModelKind::MiniLm | ModelKind::BgeSmall | ModelKind::E5Small => Some(384),
+            ModelKind::BgeBase | ModelKind::E5Base | ModelKind::JinaCode | ModelKind::Mpnet => Some(768),
+            ModelKind::Clip => Some(512),
+            ModelKind::Static => None,
+        }
+    }
+
+    /// Cargo feature this model's backend is compiled under, None if always available
+    pub fn required_feature(&self) -> Option<&'static str> {
+        match self {
+            ModelKind::MiniLm => Some("torch"),
+            ModelKind::Static => None,
+            _ => Some("onnx"),
+        }
+    }
+
+    /// The ONNX model backing this kind, if it runs on ONNX Runtime
+    #[cfg(feature = "onnx")]
+    pub fn onnx_model(&self) -> Option<OnnxModel> {
+        match self {
+            ModelKind::MiniLm | ModelKind::Clip | ModelKind::Static => None,
+            ModelKind::BgeSmall => Some(OnnxModel::BgeSmall),
+            ModelKind::BgeBase => Some(OnnxModel::BgeBase),
+            ModelKind::E5Small => Some(OnnxModel::E5Small),
+            ModelKind::E5Base => Some(OnnxModel::E5Base),
+            ModelKind::JinaCode => Some(OnnxModel::JinaCode),
+            ModelKind::Mpnet => Some(OnnxModel::AllMpnetBaseV2),
+        }
+    }
+}
+
+impl std::str::FromStr for ModelKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_name(s).ok_or_else(|| {
+            let known: Vec<&str> = Self::ALL.iter().map(|k| k.name()).collect();
+            err!("Unknown model '{}', expected one of: {}", s, known.join(", "))
+        })
+    }
+}
+
+/// Declarative description of a model to build, as found in config files
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelSpec {
+    /// Registry name of the model (e.g. "minilm")
+    pub model: String,
+
+    /// Device to run on ("auto", "cpu", "mps", "cuda" or "cuda:N", or "cpu", "coreml" or "ane" for ONNX models)
+    #[serde(default)]
+    pub device: Option<String>,
+
+    /// Local directory with model weights instead of the remote default (required for ONNX models)
+    #[serde(default)]
+    pub model_path: Option<PathBuf>,
+
+    /// Hub repository, revision and token to download MiniLM weights from instead of the default model
+    #[serde(default)]
+    pub hub: Option<HubSource>,
+
+    /// Whether inputs are queries or passages, selecting the instruction prefix of BGE and E5 models
+    #[serde(default)]
+    pub input_type: InputType,
+
+    /// Load the int8 quantized export of an ONNX model, trading about 1% accuracy for 2-3x CPU throughput
+    #[serde(default)]
+    pub quantized: bool,
+}
+
+impl ModelSpec {
+    /// Creates a spec for the given registry model with default options
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            device: None,
+            model_path: None,
+            hub: None,
+            input_type: InputType::default(),
+            quantized: false,
+        }
+    }
+
+    /// Resolves the model kind named in the spec
+    pub fn kind(&self) -> Result<ModelKind> {
+        self.model.parse()
+    }
+
+    /// Builds and initializes the embedder described by this spec
+    pub fn build(&self) -> Result<RegisteredEmbedder> {
+        build(self)
+    }
+}
+
+/// An embedder created through the registry
+#[derive(Clone)]
+pub enum RegisteredEmbedder {
+    #[cfg(feature = "torch")]
+    MiniLm(MiniLMEmbedder),
+    #[cfg(feature = "onnx")]
+    Onnx(OnnxEmbedder),
+    #[cfg(feature = "onnx")]
+    Code(CodeEmbedder),
+    #[cfg(feature = "onnx")]
+    Clip(ClipEmbedder),
+    Static(StaticEmbedder),
+}
+
+impl RegisteredEmbedder {
+    /// A copy sharing the loaded model that embeds its inputs as the given input type
+    ///
+    /// Only BGE and E5 have instructions per input type, other models are returned unchanged.
+    #[cfg_attr(not(feature = "onnx"), allow(unused_variables))]
+    pub fn with_input_type(&self, input_type: InputType) -> Self {
+        match self {
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => RegisteredEmbedder::Onnx(embedder.with_input_type(input_type)),
+            other => other.clone(),
+        }
+    }
+}
+
+impl Embedder for RegisteredEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => Embedder::embed_text(embedder, text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => Embedder::embed_text(embedder, text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => Embedder::embed_text(embedder, text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => Embedder::embed_text(embedder, text),
+            RegisteredEmbedder::Static(embedder) => Embedder::embed_text(embedder, text),
+        }
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => Embedder::embed_batch(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => Embedder::embed_batch(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => Embedder::embed_batch(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => Embedder::embed_batch(embedder, texts),
+            RegisteredEmbedder::Static(embedder) => Embedder::embed_batch(embedder, texts),
+        }
+    }
+
+    fn embed_batch_matrix(&self, texts: &[String]) -> crate::Result<Array2<f32>> {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => Embedder::embed_batch_matrix(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => Embedder::embed_batch_matrix(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => Embedder::embed_batch_matrix(embedder, texts),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => Embedder::embed_batch_matrix(embedder, texts),
+            RegisteredEmbedder::Static(embedder) => Embedder::embed_batch_matrix(embedder, texts),
+        }
+    }
+
+    fn model_name(&self) -> &str {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => embedder.model_name(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => embedder.model_name(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => embedder.model_name(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => embedder.model_name(),
+            RegisteredEmbedder::Static(embedder) => embedder.model_name(),
+        }
+    }
+
+    fn model_version(&self) -> &str {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => embedder.model_version(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => embedder.model_version(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => embedder.model_version(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => embedder.model_version(),
+            RegisteredEmbedder::Static(embedder) => embedder.model_version(),
+        }
+    }
+
+    fn dimension(&self) -> usize {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => embedder.dimension(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => embedder.dimension(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => embedder.dimension(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => embedder.dimension(),
+            RegisteredEmbedder::Static(embedder) => embedder.dimension(),
+        }
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => embedder.model_info(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => embedder.model_info(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => embedder.model_info(),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => embedder.model_info(),
+            RegisteredEmbedder::Static(embedder) => embedder.model_info(),
+        }
+    }
+
+    fn count_tokens(&self, text: &str) -> crate::Result<usize> {
+        match self {
+            #[cfg(feature = "torch")]
+            RegisteredEmbedder::MiniLm(embedder) => embedder.count_tokens(text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Onnx(embedder) => embedder.count_tokens(text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Code(embedder) => embedder.count_tokens(text),
+            #[cfg(feature = "onnx")]
+            RegisteredEmbedder::Clip(embedder) => embedder.count_tokens(text),
+            RegisteredEmbedder::Static(embedder) => embedder.count_tokens(text),
+        }
+    }
+}
+
+/// The spec with `model_path` set to the bundle compiled into the binary, if it holds this kind of model
+///
+/// Specs that name a directory or a hub repository keep their own source.
+#[cfg(all(feature = "embedded-model", not(target_arch = "wasm32")))]
+fn embedded_spec(spec: &ModelSpec, kind: ModelKind) -> Result<Option<ModelSpec>> {
+    if spec.model_path.is_some() || spec.hub.is_some() {
+        return Ok(None);
+    }
+    let Some((model_dir, manifest)) = crate::utils::bundle::extract_embedded()? else {
+        return Ok(None);
+    };
+    if ModelKind::from_name(&manifest.model.kind) != Some(kind) || manifest.model.quantized != spec.quantized {
+        return Ok(None);
+    }
+    Ok(Some(ModelSpec { model_path: Some(model_dir), ..spec.clone() }))
+}
+
+/// Builds and initializes an embedder from a model spec
+pub fn build(spec: &ModelSpec) -> Result<RegisteredEmbedder> {
+    let kind = spec.kind()?;
+    if spec.quantized && matches!(kind, ModelKind::MiniLm | ModelKind::Clip | ModelKind::Static) {
+        return Err(err!("Model '{}' has no quantized variant, only the ONNX text models do", kind.name()));
+    }
+    if spec.hub.is_some() && kind != ModelKind::MiniLm {
+        return Err(err!("Model '{}' runs from model_path, only minilm downloads from a hub", kind.name()));
+    }
+    #[cfg(all(feature = "embedded-model", not(target_arch = "wasm32")))]
+    let embedded = embedded_spec(spec, kind)?;
+    #[cfg(all(feature = "embedded-model", not(target_arch = "wasm32")))]
+    let spec = embedded.as_ref().unwrap_or(spec);
+
+    match kind {
+        #[cfg(feature = "torch")]
+        ModelKind::MiniLm => {
+            let mut config = MiniLMConfig {
+                model_path: spec.model_path.clone(),
+                hub: spec.hub.clone().unwrap_or_default(),
+                ..MiniLMConfig::default()
+            };
+            if let Some(device) = spec.device.as_deref() {
+                config.device = parse_device(device)?;
+            }
+
+            let mut embedder = MiniLMEmbedder::with_config(config);
+            embedder.initialize()?;
+            Ok(RegisteredEmbedder::MiniLm(embedder))
+        }
+        #[cfg(feature = "onnx")]
+        ModelKind::Clip => {
+            let model_dir = spec.model_path.clone().ok_or_else(|| {
+                err!("CLIP runs from local ONNX weights, set model_path to a directory with text_model.onnx and vision_model.onnx")
+            })?;
+            Ok(RegisteredEmbedder::Clip(ClipEmbedder::new(ClipConfig::new(model_dir))?))
+        }
+        ModelKind::Static => {
+            let path = spec.model_path.as_ref().ok_or_else(|| {
+                err!("Static embeddings need model_path set to a model2vec directory or word vector file")
+            })?;
+            Ok(RegisteredEmbedder::Static(StaticEmbedder::load(path)?))
+        }
+        #[cfg(feature = "onnx")]
+        kind if kind.onnx_model().is_some() => {
+            let model = kind.onnx_model()
+                .ok_or_else(|| err!("Model '{}' has no ONNX backend", kind.name()))?;
+            let model_dir = spec.model_path.clone().ok_or_else(|| {
+                err!("Model '{}' runs from local ONNX weights, set model_path to an export of {}", kind.name(), model.repo_id())
+            })?;
+            let execution_provider = spec.device.as_deref()
+                .map(ExecutionProvider::from_name)
+                .transpose()?
+                .unwrap_or_default();
+
+            let config = OnnxConfig {
+                input_type: spec.input_type,
+                quantized: spec.quantized,
+                execution_provider,
+                ..OnnxConfig::new(model, model_dir)
+            };
+            if kind == ModelKind::JinaCode {
+                return Ok(RegisteredEmbedder::Code(CodeEmbedder::with_config(config)?));
+            }
+            Ok(RegisteredEmbedder::Onnx(OnnxEmbedder::new(config)?))
+        }
+        #[allow(unreachable_patterns)]
+        kind => Err(err!(
+            "Model '{}' needs the '{}' feature, which this build was compiled without",
+            kind.name(), kind.required_feature().unwrap_or_default()
+        )),
+    }
+}
+
+/// Parses a device name such as "auto", "cpu", "mps", "cuda" or "cuda:1"
+#[cfg(feature = "torch")]
+pub fn parse_device(name: &str) -> Result<Device> {
+    let name = name.trim().to_lowercase();
+
+    match name.as_str() {
+        "auto" => Ok(Device::Auto),
+        "cpu" => Ok(Device::Cpu),
+        "mps" => Ok(Device::Mps),
+        "cuda" => Ok(Device::Cuda(0)),
+        _ => {
+            if let Some(index) = name.strip_prefix("cuda:") {
+                let index = index.parse::<usize>()
+                    .map_err(|_| err!("Invalid CUDA device index in '{}'", name))?;
+                Ok(Device::Cuda(index))
+            } else {
+                Err(err!("Unknown device '{}', expected auto, cpu, mps, cuda or cuda:N", name))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers_mpnet() {
+        assert_eq!(ModelKind::from_name("all-mpnet-base-v2"), Some(ModelKind::Mpnet));
+        assert_eq!(ModelKind::Mpnet.dimension(), Some(768));
+        assert_eq!(ModelKind::Mpnet.required_feature(), Some("onnx"));
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_mpnet_runs_on_onnx_with_mean_pooling() {
+        use crate::models::onnx::Pooling;
+
+        let model = ModelKind::Mpnet.onnx_model();
+        assert_eq!(model, Some(OnnxModel::AllMpnetBaseV2));
+        assert_eq!(model.map(|model| model.dimension()), ModelKind::Mpnet.dimension());
+        assert_eq!(model.map(|model| model.pooling()), Some(Pooling::Mean));
+        assert_eq!(model.map(|model| model.prefix(InputType::Query)), Some(""));
+    }
+
+    #[cfg(all(feature = "embedded-model", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_embedded_bundle_resolves_to_its_model() -> Result<()> {
+        let (model_dir, manifest) = crate::utils::bundle::extract_embedded()?
+            .ok_or_else(|| err!("Built with embedded-model but no bundle is embedded"))?;
+        let kind = ModelKind::from_name(&manifest.model.kind)
+            .ok_or_else(|| err!("Unknown model kind '{}' in the embedded bundle", manifest.model.kind))?;
+        if let Some(dimension) = kind.dimension() {
+            assert_eq!(manifest.model.dimension, dimension);
+        }
+
+        let spec = ModelSpec { quantized: manifest.model.quantized, ..ModelSpec::new(kind.name()) };
+        let embedded = embedded_spec(&spec, kind)?.and_then(|spec| spec.model_path);
+        assert_eq!(embedded, Some(model_dir));
+
+        // Specs with a source of their own, or of another quantization, keep it
+        let local = ModelSpec { model_path: Some(PathBuf::from("/models/local")), ..spec.clone() };
+        assert!(embedded_spec(&local, kind)?.is_none());
+        let other = ModelSpec { quantized: !spec.quantized, ..spec };
+        assert!(embedded_spec(&other, kind)?.is_none());
+        Ok(())
+    }
+}
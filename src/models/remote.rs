@@ -0,0 +1,331 @@
+use crate::embedding::{Embedder, ModelInfo};
+use crate::models::InputType;
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default number of texts sent in one request
+const BATCH_SIZE: usize = 96;
+
+/// Request and response shape of the embedding API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFormat {
+    /// `POST {base_url}/embeddings` with `input`, answered with `data[].embedding`
+    #[default]
+    OpenAi,
+    /// `POST {base_url}/embed` with `texts`, answered with `embeddings`
+    Cohere,
+}
+
+/// Configuration for a hosted embedding API
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    /// Base URL of the API, without the endpoint path
+    pub base_url: String,
+
+    /// Bearer token sent with every request, if the API needs one
+    pub api_key: Option<String>,
+
+    /// Name of the remote model
+    pub model: String,
+
+    /// Dimension of the returned embeddings, probed with one request when unknown
+    pub dimension: Option<usize>,
+
+    /// Request and response format
+    pub format: ApiFormat,
+
+    /// Whether texts are queries or documents, sent to APIs that distinguish them
+    pub input_type: InputType,
+
+    /// Maximum number of texts per request
+    pub batch_size: usize,
+
+    /// Timeout for a single request
+    pub timeout: Duration,
+}
+
+impl ApiConfig {
+    /// Creates a configuration for an OpenAI-compatible API at the given base URL
+    pub fn new(base_url: &str, model: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            model: model.to_string(),
+            dimension: known_dimension(model),
+            format: ApiFormat::OpenAi,
+            input_type: InputType::default(),
+            batch_size: BATCH_SIZE,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// OpenAI's API, reading the key from `OPENAI_API_KEY`
+    pub fn openai(model: &str) -> Self {
+        Self {
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            ..Self::new("https://api.openai.com/v1", model)
+        }
+    }
+
+    /// Cohere's API, reading the key from `COHERE_API_KEY`
+    pub fn cohere(model: &str) -> Self {
+        Self {
+            api_key: std::env::var("COHERE_API_KEY").ok(),
+            format: ApiFormat::Cohere,
+            ..Self::new("https://api.cohere.com/v1", model)
+        }
+    }
+
+    /// Sets the API key
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+}
+
+/// Dimensions of well-known hosted models
+fn known_dimension(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-3-small" | "text-embedding-ada-002" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        "embed-english-v3.0" | "embed-multilingual-v3.0" => Some(1024),
+        "embed-english-light-v3.0" | "embed-multilingual-light-v3.0" => Some(384),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+struct OpenAiRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct CohereRequest<'a> {
+    model: &'a str,
+    texts: &'a [String],
+    input_type: &'static str,
+}
+
+#[derive(Deserialize)]
+struct CohereResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Embeds texts through a hosted embedding API
+#[derive(Clone)]
+pub struct ApiEmbedder {
+    config: ApiConfig,
+    client: Arc<Client>,
+    dimension: usize,
+}
+
+impl ApiEmbedder {
+    /// Creates the HTTP client, probing the embedding dimension if it isn't configured
+    pub fn new(config: ApiConfig) -> Result<Self> {
+        if config.batch_size == 0 {
+            return Err(err!("Batch size must be at least 1"));
+        }
+        if !crate::utils::http::is_loopback(&config.base_url) {
+            crate::utils::offline::ensure_network_allowed(&config.base_url)?;
+        }
+
+        let client = crate::utils::http::client_builder()?
+            .timeout(config.timeout)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let mut embedder = Self {
+            config,
+            client: Arc::new(client),
+            dimension: 0,
+        };
+
+        embedder.dimension = match embedder.config.dimension {
+            Some(dimension) => dimension,
+            None => embedder.embed_text("dimension probe")?.len(),
+        };
+
+        Ok(embedder)
+    }
+
+    /// The configuration this embedder was created with
+    pub fn config(&self) -> &ApiConfig {
+        &self.config
+    }
+
+    /// Sends one request for a batch of texts
+    fn request(&self, texts: &[String]) -> Result<Vec<Array1<f32>>> {
+        let path = match self.config.format {
+            ApiFormat::OpenAi => "embeddings",
+            ApiFormat::Cohere => "embed",
+        };
+        let request = self.client
+            .post(format!("{}/{}", self.config.base_url, path))
+            .json(&request_body(&self.config, texts)?);
+        let request = match &self.config.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        };
+
+        let response = request.send()
+            .with_context(|| format!("Request to {} failed", self.config.base_url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(err!("Embedding API returned {}: {}", status, body));
+        }
+
+        let body = response.text().context("Failed to read embedding response")?;
+        parse_response(self.config.format, &body, texts.len())
+    }
+}
+
+/// JSON body of a request embedding `texts` in the configured format
+fn request_body(config: &ApiConfig, texts: &[String]) -> Result<serde_json::Value> {
+    let body = match config.format {
+        ApiFormat::OpenAi => serde_json::to_value(OpenAiRequest { model: &config.model, input: texts }),
+        ApiFormat::Cohere => serde_json::to_value(CohereRequest {
+            model: &config.model,
+            texts,
+            input_type: match config.input_type {
+                InputType::Query => "search_query",
+                InputType::Passage => "search_document",
+            },
+        }),
+    };
+    body.context("Failed to encode embedding request")
+}
+
+/// Embeddings of a response body in request order, checking there is one per text sent
+fn parse_response(format: ApiFormat, body: &str, expected: usize) -> Result<Vec<Array1<f32>>> {
+    let embeddings = match format {
+        ApiFormat::OpenAi => {
+            // Entries carry their position, which need not match the response order
+            let mut data = serde_json::from_str::<OpenAiResponse>(body)
+                .context("Failed to parse embedding response")?
+                .data;
+            data.sort_by_key(|entry| entry.index);
+            data.into_iter().map(|entry| entry.embedding).collect::<Vec<_>>()
+        }
+        ApiFormat::Cohere => serde_json::from_str::<CohereResponse>(body)
+            .context("Failed to parse embedding response")?
+            .embeddings,
+    };
+
+    if embeddings.len() != expected {
+        return Err(err!("Sent {} texts but got {} embeddings", expected, embeddings.len()));
+    }
+
+    Ok(embeddings.into_iter().map(Array1::from).collect())
+}
+
+impl Embedder for ApiEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        self.request(&[text.to_string()])?
+            .pop()
+            .ok_or_else(|| err!("API returned no embedding"))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(self.config.batch_size) {
+            embeddings.extend(self.request(chunk)?);
+        }
+        Ok(embeddings)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.config.model
+    }
+
+    fn model_version(&self) -> &str {
+        "remote"
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            source_url: Some(self.config.base_url.clone()),
+            ..ModelInfo::new(&self.config.model, "remote", self.dimension)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn texts() -> Vec<String> {
+        vec!["first".to_string(), "second".to_string()]
+    }
+
+    #[test]
+    fn test_builds_request_bodies() -> Result<()> {
+        let openai = ApiConfig::new("http://localhost:8080/v1/", "text-embedding-3-small");
+        assert_eq!(request_body(&openai, &texts())?, json!({
+            "model": "text-embedding-3-small",
+            "input": ["first", "second"],
+        }));
+
+        let mut cohere = ApiConfig { format: ApiFormat::Cohere, ..ApiConfig::new("http://localhost:8080", "embed-english-v3.0") };
+        assert_eq!(request_body(&cohere, &texts())?, json!({
+            "model": "embed-english-v3.0",
+            "texts": ["first", "second"],
+            "input_type": "search_document",
+        }));
+        cohere.input_type = InputType::Query;
+        assert_eq!(request_body(&cohere, &texts())?["input_type"], "search_query");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parses_responses_in_request_order() -> Result<()> {
+        let openai = json!({
+            "object": "list",
+            "data": [
+                { "object": "embedding", "index": 1, "embedding": [0.0, 1.0] },
+                { "object": "embedding", "index": 0, "embedding": [1.0, 0.0] },
+            ],
+            "model": "text-embedding-3-small",
+        });
+        let embeddings = parse_response(ApiFormat::OpenAi, &openai.to_string(), 2)?;
+        assert_eq!(embeddings, vec![Array1::from(vec![1.0, 0.0]), Array1::from(vec![0.0, 1.0])]);
+
+        let cohere = json!({ "id": "1", "embeddings": [[1.0, 0.0], [0.0, 1.0]] });
+        let embeddings = parse_response(ApiFormat::Cohere, &cohere.to_string(), 2)?;
+        assert_eq!(embeddings, vec![Array1::from(vec![1.0, 0.0]), Array1::from(vec![0.0, 1.0])]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_responses_with_another_count() {
+        let openai = json!({ "data": [{ "index": 0, "embedding": [1.0, 0.0] }] });
+        let error = parse_response(ApiFormat::OpenAi, &openai.to_string(), 2).unwrap_err();
+        assert_eq!(error.to_string(), "Sent 2 texts but got 1 embeddings");
+
+        let cohere = json!({ "embeddings": [[1.0], [0.0], [0.5]] });
+        assert!(parse_response(ApiFormat::Cohere, &cohere.to_string(), 2).is_err());
+        assert!(parse_response(ApiFormat::Cohere, "not json", 2).is_err());
+    }
+}
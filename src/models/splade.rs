@@ -0,0 +1,217 @@
+use crate::embedding::SparseEmbedding;
+use crate::models::batching::{pipeline_by_tokens, TokenBatch, DEFAULT_BATCH_TOKENS};
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::{ArrayView2, ArrayView3, Axis, Ix3};
+use ort::{GraphOptimizationLevel, Session};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// Configuration for a SPLADE model exported to ONNX
+#[derive(Debug, Clone)]
+pub struct SpladeConfig {
+    /// Directory containing `model.onnx` (or `onnx/model.onnx`) and `tokenizer.json`
+    pub model_dir: PathBuf,
+
+    /// Maximum number of tokens per input, longer inputs are truncated
+    pub max_length: usize,
+
+    /// Terms weighted below this are dropped, trading recall for smaller vectors
+    pub min_weight: f32,
+
+    /// Padded tokens per forward pass, so batches of long documents hold fewer texts
+    pub batch_tokens: usize,
+
+    /// Name reported as the model name
+    pub name: String,
+}
+
+impl SpladeConfig {
+    /// Creates a configuration for a SPLADE export stored in the given directory
+    pub fn new(model_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            model_dir: model_dir.into(),
+            max_length: 512,
+            min_weight: 0.0,
+            batch_tokens: DEFAULT_BATCH_TOKENS,
+            name: "splade".to_string(),
+        }
+    }
+}
+
+/// Produces sparse term-weight embeddings with a SPLADE masked-language model
+#[derive(Clone)]
+pub struct SpladeEmbedder {
+    config: SpladeConfig,
+    session: Arc<Session>,
+    tokenizer: Arc<Tokenizer>,
+}
+
+impl SpladeEmbedder {
+    /// Loads the ONNX model and tokenizer from the configured directory
+    pub fn new(config: SpladeConfig) -> Result<Self> {
+        let model_file = [config.model_dir.join("model.onnx"), config.model_dir.join("onnx").join("model.onnx")]
+            .into_iter()
+            .find(|path| path.exists())
+            .ok_or_else(|| err!("No model.onnx found in {}", config.model_dir.display()))?;
+
+        let mut tokenizer = Tokenizer::from_file(config.model_dir.join("tokenizer.json"))
+            .map_err(|e| err!("Failed to load tokenizer: {}", e))?;
+        tokenizer.with_truncation(Some(TruncationParams {
+            max_length: config.max_length,
+            ..TruncationParams::default()
+        }));
+
+        let session = Session::builder()?
+            .with_optimization_level(GraphOptimizationLevel::Level3)?
+            .commit_from_file(&model_file)?;
+
+        Ok(Self {
+            config,
+            session: Arc::new(session),
+            tokenizer: Arc::new(tokenizer),
+        })
+    }
+
+    /// Name of the model
+    pub fn model_name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Number of terms in the vocabulary, the dimension of the sparse space
+    pub fn vocab_size(&self) -> usize {
+        self.tokenizer.get_vocab_size(true)
+    }
+
+    /// Embeds a text into sparse term weights
+    pub fn embed_sparse(&self, text: &str) -> Result<SparseEmbedding> {
+        self.embed_sparse_batch(&[text.to_string()])?
+            .pop()
+            .ok_or_else(|| err!("Model returned no embedding"))
+    }
+
+    /// Embeds several texts into sparse term weights
+    pub fn embed_sparse_batch(&self, texts: &[String]) -> Result<Vec<SparseEmbedding>> {
+        let max_length = self.config.max_length;
+        let tokenizer = &self.tokenizer;
+        pipeline_by_tokens(
+            texts,
+            self.config.batch_tokens,
+            |text| utils::estimate_tokens(text).min(max_length),
+            |batch| TokenBatch::encode(tokenizer, batch),
+            |batch| self.forward(batch),
+        )
+    }
+
+    /// The highest weighted terms of a sparse embedding, as readable tokens
+    pub fn top_terms(&self, embedding: &SparseEmbedding, n: usize) -> Vec<(String, f32)> {
+        named_terms(embedding, n, |id| self.tokenizer.id_to_token(id))
+    }
+
+    /// Runs a batch through the model and max-pools log-saturated term activations
+    #[tracing::instrument(name = "splade_forward", skip_all, fields(texts = batch.len()))]
+    fn forward(&self, batch: TokenBatch) -> Result<Vec<SparseEmbedding>> {
+        let inputs = batch.inputs(&self.session)?;
+        let outputs = tracing::debug_span!("inference").in_scope(|| self.session.run(inputs))?;
+        let logits = outputs[0].try_extract_tensor::<f32>()?;
+        let logits = logits.view()
+            .into_dimensionality::<Ix3>()
+            .map_err(|_| err!("Expected logits of shape [batch, tokens, vocabulary]"))?;
+
+        Ok(pool(logits, batch.attention_mask.view(), self.config.min_weight))
+    }
+}
+
+/// SPLADE weight of each term: max over unmasked tokens of log(1 + relu(logit)), keeping weights above `min_weight`
+fn pool(logits: ArrayView3<f32>, attention_mask: ArrayView2<i64>, min_weight: f32) -> Vec<SparseEmbedding> {
+    logits.outer_iter()
+        .zip(attention_mask.outer_iter())
+        .map(|(tokens, mask)| {
+            let mut weights = vec![0.0f32; tokens.len_of(Axis(1))];
+            for (token_logits, _) in tokens.outer_iter().zip(mask.iter()).filter(|(_, mask)| **mask != 0) {
+                for (weight, &logit) in weights.iter_mut().zip(token_logits.iter()) {
+                    *weight = weight.max(logit.max(0.0).ln_1p());
+                }
+            }
+
+            SparseEmbedding::from_pairs(weights.into_iter()
+                .enumerate()
+                .filter(|(_, weight)| *weight > min_weight)
+                .map(|(id, weight)| (id as u32, weight)))
+        })
+        .collect()
+}
+
+/// The `n` highest weighted terms of `embedding`, named by `token` or `#id` for ids it doesn't know
+fn named_terms(embedding: &SparseEmbedding, n: usize, token: impl Fn(u32) -> Option<String>) -> Vec<(String, f32)> {
+    embedding.top_terms(n)
+        .into_iter()
+        .map(|(id, weight)| (token(id).unwrap_or_else(|| format!("#{}", id)), weight))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Array3};
+
+    fn logits() -> Array3<f32> {
+        // Two texts of two tokens over a vocabulary of four terms, the second text padded after one token
+        array![
+            [[1.0, -2.0, 0.0, 3.0], [2.0, 0.5, -1.0, 0.0]],
+            [[0.0, 4.0, 0.0, 0.0], [9.0, 9.0, 9.0, 9.0]],
+        ]
+    }
+
+    #[test]
+    fn test_pool_max_pools_log_saturated_logits_over_unmasked_tokens() {
+        let embeddings = pool(logits().view(), array![[1, 1], [1, 0]].view(), 0.0);
+
+        assert_eq!(embeddings[0].indices, vec![0, 1, 3]);
+        let expected = [2.0f32.ln_1p(), 0.5f32.ln_1p(), 3.0f32.ln_1p()];
+        for (weight, expected) in embeddings[0].values.iter().zip(expected) {
+            assert!((weight - expected).abs() < 1e-6);
+        }
+        // The padded token's logits are ignored
+        assert_eq!(embeddings[1], SparseEmbedding::from_pairs([(1, 4.0f32.ln_1p())]));
+    }
+
+    #[test]
+    fn test_pool_drops_terms_at_or_below_min_weight() {
+        let embeddings = pool(logits().view(), array![[1, 1], [1, 0]].view(), 1.0);
+        assert_eq!(embeddings[0].indices, vec![0, 3]);
+        assert_eq!(embeddings[1].indices, vec![1]);
+    }
+
+    #[test]
+    fn test_named_terms_orders_by_weight_and_names_unknown_ids() {
+        let embedding = SparseEmbedding::from_pairs([(0, 0.5), (1, 2.0), (2, 1.0)]);
+        let vocabulary = ["the", "cat"];
+        let terms = named_terms(&embedding, 2, |id| vocabulary.get(id as usize).map(|term| term.to_string()));
+        assert_eq!(terms, vec![("cat".to_string(), 2.0), ("#2".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn test_dot_sums_products_of_shared_terms() {
+        let query = SparseEmbedding::from_pairs([(1, 2.0), (5, 1.0), (9, 0.5)]);
+        let document = SparseEmbedding::from_pairs([(0, 4.0), (5, 3.0), (9, 2.0)]);
+        assert_eq!(query.dot(&document), 4.0);
+        assert_eq!(query.dot(&SparseEmbedding::default()), 0.0);
+    }
+
+    #[cfg(feature = "proto-store")]
+    #[test]
+    fn test_sparse_embeddings_round_trip_through_protobuf() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_splade_{}.pb", std::process::id()));
+        let embeddings = pool(logits().view(), array![[1, 1], [1, 0]].view(), 0.0);
+        let texts = vec!["first".to_string(), "second".to_string()];
+
+        utils::save_sparse_embeddings(&embeddings, Some(&texts), "splade", "1", 4, &path)?;
+        let (loaded, loaded_texts) = utils::load_sparse_embeddings(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded, embeddings);
+        assert_eq!(loaded_texts, Some(texts));
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use crate::embedding::{Embedder, ModelInfo};
+use crate::utils;
+use crate::error::{err, Context, Result};
+use ndarray::{Array1, Array2};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+
+/// How texts are split into the units that have a static vector
+enum Vocabulary {
+    /// Whole words, as in fastText, word2vec and GloVe text files
+    Words(HashMap<String, usize>),
+    /// Subword tokens from a Hugging Face tokenizer, as in model2vec models
+    Tokenizer { tokenizer: Tokenizer, unknown_id: Option<u32> },
+}
+
+/// Embeds text as the average of static token vectors, without running a transformer
+#[derive(Clone)]
+pub struct StaticEmbedder {
+    name: String,
+    vectors: Arc<Array2<f32>>,
+    vocabulary: Arc<Vocabulary>,
+    normalize: bool,
+}
+
+impl StaticEmbedder {
+    /// Creates an embedder from in-memory word vectors, one row per word
+    pub fn from_vectors(name: &str, words: Vec<String>, vectors: Array2<f32>) -> Result<Self> {
+        if words.len() != vectors.nrows() {
+            return Err(err!("Got {} words but {} vectors", words.len(), vectors.nrows()));
+        }
+
+        let index = words.into_iter()
+            .enumerate()
+            .map(|(i, word)| (word, i))
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            vectors: Arc::new(vectors),
+            vocabulary: Arc::new(Vocabulary::Words(index)),
+            normalize: true,
+        })
+    }
+
+    /// Loads word vectors in the fastText/word2vec `.vec` or GloVe text format
+    pub fn from_word_vectors(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open word vectors at {}", path.display()))?;
+
+        let mut words = Vec::new();
+        let mut values = Vec::new();
+        let mut dimension = 0;
+
+        for (line_number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let Some(word) = parts.next() else { continue };
+            let vector: Vec<f32> = parts.map(str::parse).collect::<Result<_, _>>()
+                .map_err(|_| err!("Invalid number on line {} of {}", line_number + 1, path.display()))?;
+
+            // fastText and word2vec files start with a "<count> <dimension>" header
+            if line_number == 0 && vector.len() == 1 {
+                continue;
+            }
+
+            if dimension == 0 {
+                dimension = vector.len();
+            } else if vector.len() != dimension {
+                return Err(err!(
+                    "Line {} of {} has {} values, expected {}",
+                    line_number + 1, path.display(), vector.len(), dimension
+                ));
+            }
+
+            words.push(word.to_string());
+            values.extend(vector);
+        }
+
+        if words.is_empty() {
+            return Err(err!("No word vectors found in {}", path.display()));
+        }
+
+        let vectors = Array2::from_shape_vec((words.len(), dimension), values)?;
+        let name = path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "static".to_string());
+
+        Self::from_vectors(&name, words, vectors)
+    }
+
+    /// Loads a model2vec model directory (`model.safetensors`, `tokenizer.json` and optionally `config.json`)
+    pub fn from_model2vec(model_dir: impl AsRef<Path>) -> Result<Self> {
+        let model_dir = model_dir.as_ref();
+        let safetensors_path = model_dir.join("model.safetensors");
+        let safetensors = fs::read(&safetensors_path)
+            .with_context(|| format!("Failed to read {}", safetensors_path.display()))?;
+        let tokenizer_path = model_dir.join("tokenizer.json");
+        let tokenizer = fs::read(&tokenizer_path)
+            .with_context(|| format!("Failed to read {}", tokenizer_path.display()))?;
+
+        // model2vec records whether embeddings should be normalized in its config
+        let normalize = fs::read_to_string(model_dir.join("config.json"))
+            .ok()
+            .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok())
+            .and_then(|config| config.get("normalize").and_then(|v| v.as_bool()))
+            .unwrap_or(true);
+
+        let name = model_dir.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "model2vec".to_string());
+
+        Self::from_model2vec_bytes(&name, &safetensors, &tokenizer, normalize)
+            .with_context(|| format!("Failed to load model2vec model from {}", model_dir.display()))
+    }
+
+    /// Creates a model2vec embedder from the contents of `model.safetensors` and `tokenizer.json`, without touching the filesystem
+    pub fn from_model2vec_bytes(name: &str, safetensors: &[u8], tokenizer_json: &[u8], normalize: bool) -> Result<Self> {
+        let vectors = read_safetensors_matrix(safetensors, "embeddings")?;
+
+        let tokenizer = Tokenizer::from_bytes(tokenizer_json)
+            .map_err(|e| err!("Failed to load tokenizer: {}", e))?;
+        let unknown_id = tokenizer.token_to_id("[UNK]");
+
+        Ok(Self {
+            name: name.to_string(),
+            vectors: Arc::new(vectors),
+            vocabulary: Arc::new(Vocabulary::Tokenizer { tokenizer, unknown_id }),
+            normalize,
+        })
+    }
+
+    /// Loads either a model2vec directory or a word vector file, depending on what the path points to
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::from_model2vec(path)
+        } else {
+            Self::from_word_vectors(path)
+        }
+    }
+
+    /// Number of tokens with a vector
+    pub fn vocab_size(&self) -> usize {
+        self.vectors.nrows()
+    }
+
+    /// Sets whether embeddings are normalized to unit length
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Rows of the vector table for the tokens of a text, unknown tokens are skipped
+    #[tracing::instrument(name = "tokenize", level = "trace", skip_all)]
+    fn token_rows(&self, text: &str) -> Result<Vec<usize>> {
+        match self.vocabulary.as_ref() {
+            Vocabulary::Words(index) => Ok(text
+                .split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '-'))
+                .filter(|word| !word.is_empty())
+                .filter_map(|word| index.get(word).or_else(|| index.get(&word.to_lowercase())).copied())
+                .collect()),
+            Vocabulary::Tokenizer { tokenizer, unknown_id } => {
+                let encoding = tokenizer.encode(text, false)
+                    .map_err(|e| err!("Tokenization failed: {}", e))?;
+                Ok(encoding.get_ids().iter()
+                    .filter(|&&id| Some(id) != *unknown_id)
+                    .map(|&id| id as usize)
+                    .filter(|&row| row < self.vectors.nrows())
+                    .collect())
+            }
+        }
+    }
+}
+
+impl Embedder for StaticEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        let rows = self.token_rows(text)?;
+
+        // Texts without any known token embed to the zero vector
+        let mut embedding = Array1::<f32>::zeros(self.dimension());
+        for &row in &rows {
+            embedding += &self.vectors.row(row);
+        }
+        if !rows.is_empty() {
+            embedding /= rows.len() as f32;
+        }
+
+        if self.normalize {
+            utils::normalize(&mut embedding);
+        }
+        Ok(embedding)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.name
+    }
+
+    fn model_version(&self) -> &str {
+        "static"
+    }
+
+    fn dimension(&self) -> usize {
+        self.vectors.ncols()
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            pooling: Some("mean".to_string()),
+            normalized: self.normalize,
+            ..ModelInfo::new(&self.name, "static", self.dimension())
+        }
+    }
+
+    fn count_tokens(&self, text: &str) -> crate::Result<usize> {
+        Ok(self.token_rows(text)?.len())
+    }
+}
+
+/// Reads a 2-D float32 tensor from the contents of a safetensors file
+fn read_safetensors_matrix(bytes: &[u8], tensor: &str) -> Result<Array2<f32>> {
+    // Layout: 8-byte little-endian header length, JSON header, then raw tensor data
+    let header_len = bytes.get(..8)
+        .map(|len| u64::from_le_bytes(len.try_into().unwrap_or_default()))
+        .ok_or_else(|| err!("Not a safetensors file"))?;
+    let data_start = usize::try_from(header_len).ok()
+        .and_then(|header_len| header_len.checked_add(8))
+        .filter(|&data_start| data_start <= bytes.len())
+        .ok_or_else(|| err!("Truncated safetensors header"))?;
+    let header: serde_json::Value = serde_json::from_slice(&bytes[8..data_start])?;
+
+    let info = header.get(tensor)
+        .ok_or_else(|| err!("Tensor '{}' not found", tensor))?;
+    let dtype = info["dtype"].as_str().unwrap_or_default();
+    if dtype != "F32" {
+        return Err(err!("Tensor '{}' has dtype {}, only F32 is supported", tensor, dtype));
+    }
+
+    let shape: Vec<usize> = info["shape"].as_array()
+        .map(|dims| dims.iter().filter_map(|d| d.as_u64()).map(|d| d as usize).collect())
+        .unwrap_or_default();
+    let offsets: Vec<usize> = info["data_offsets"].as_array()
+        .map(|offsets| offsets.iter().filter_map(|o| o.as_u64()).map(|o| o as usize).collect())
+        .unwrap_or_default();
+    if shape.len() != 2 || offsets.len() != 2 {
+        return Err(err!("Tensor '{}' is not a 2-D matrix", tensor));
+    }
+
+    let data = data_start.checked_add(offsets[0])
+        .zip(data_start.checked_add(offsets[1]))
+        .and_then(|(start, end)| bytes.get(start..end))
+        .ok_or_else(|| err!("Tensor '{}' data is out of bounds", tensor))?;
+    let values: Vec<f32> = data.chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+
+    Ok(Array2::from_shape_vec((shape[0], shape[1]), values)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_averages_known_words() -> Result<()> {
+        let words = vec!["rust".to_string(), "fast".to_string()];
+        let embedder = StaticEmbedder::from_vectors("test", words, array![[1.0, 0.0], [0.0, 1.0]])?
+            .with_normalize(false);
+
+        assert_eq!(embedder.embed_text("Rust is fast!")?, array![0.5, 0.5]);
+        assert_eq!(embedder.embed_text("unknown words")?, array![0.0, 0.0]);
+
+        Ok(())
+    }
+
+    /// A safetensors file with one F32 tensor "embeddings" of the given shape, data offsets and data
+    fn safetensors(shape: [u64; 2], offsets: [u64; 2], data: &[f32]) -> Vec<u8> {
+        let header = serde_json::json!({
+            "embeddings": { "dtype": "F32", "shape": shape, "data_offsets": offsets },
+        })
+        .to_string();
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend(data.iter().flat_map(|value| value.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn test_reads_safetensors_matrix() -> Result<()> {
+        let bytes = safetensors([2, 2], [0, 16], &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(read_safetensors_matrix(&bytes, "embeddings")?, array![[1.0, 2.0], [3.0, 4.0]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_oversized_safetensors() {
+        let error = |bytes: &[u8]| read_safetensors_matrix(bytes, "embeddings").unwrap_err().to_string();
+
+        let bytes = safetensors([2, 2], [0, 16], &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(error(&bytes[..4]), "Not a safetensors file");
+        assert_eq!(error(&bytes[..20]), "Truncated safetensors header");
+
+        let mut oversized = bytes.clone();
+        oversized[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(error(&oversized), "Truncated safetensors header");
+
+        let out_of_bounds = safetensors([2, 2], [0, u64::MAX], &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(error(&out_of_bounds), "Tensor 'embeddings' data is out of bounds");
+        let past_the_end = safetensors([2, 2], [0, 32], &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(error(&past_the_end), "Tensor 'embeddings' data is out of bounds");
+    }
+}
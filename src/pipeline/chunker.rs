@@ -0,0 +1,109 @@
+use serde::Deserialize;
+
+/// Splits document text into the pieces that get embedded
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Chunker {
+    /// Embed each document as a whole
+    #[default]
+    None,
+
+    /// One chunk per non-empty line
+    Lines,
+
+    /// One chunk per blank-line separated paragraph
+    Paragraphs,
+
+    /// Fixed-size windows of words with optional overlap
+    Words {
+        size: usize,
+        #[serde(default)]
+        overlap: usize,
+    },
+}
+
+impl Chunker {
+    /// Splits a text into chunks, skipping empty ones
+    pub fn chunk(&self, text: &str) -> Vec<String> {
+        match self {
+            Chunker::None => non_empty(std::iter::once(text)),
+            Chunker::Lines => non_empty(text.lines()),
+            Chunker::Paragraphs => {
+                let mut paragraphs = Vec::new();
+                let mut current: Vec<&str> = Vec::new();
+
+                for line in text.lines() {
+                    if line.trim().is_empty() {
+                        if !current.is_empty() {
+                            paragraphs.push(current.join("\n"));
+                            current.clear();
+                        }
+                    } else {
+                        current.push(line);
+                    }
+                }
+                if !current.is_empty() {
+                    paragraphs.push(current.join("\n"));
+                }
+
+                paragraphs
+            }
+            Chunker::Words { size, overlap } => {
+                let words: Vec<&str> = text.split_whitespace().collect();
+                let size = (*size).max(1);
+                // Always advance by at least one word so overlapping windows terminate
+                let step = size.saturating_sub(*overlap).max(1);
+
+                let mut chunks = Vec::new();
+                let mut start = 0;
+                while start < words.len() {
+                    let end = (start + size).min(words.len());
+                    chunks.push(words[start..end].join(" "));
+                    if end == words.len() {
+                        break;
+                    }
+                    start += step;
+                }
+
+                chunks
+            }
+        }
+    }
+}
+
+/// Trims pieces and drops the empty ones
+fn non_empty<'a>(pieces: impl Iterator<Item = &'a str>) -> Vec<String> {
+    pieces
+        .map(str::trim)
+        .filter(|piece| !piece.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_windows_overlap() {
+        let chunker = Chunker::Words { size: 3, overlap: 1 };
+        let chunks = chunker.chunk("a b c d e f g");
+
+        assert_eq!(chunks, vec!["a b c", "c d e", "e f g"]);
+    }
+
+    #[test]
+    fn test_overlap_larger_than_size_still_advances() {
+        let chunker = Chunker::Words { size: 2, overlap: 5 };
+        let chunks = chunker.chunk("a b c");
+
+        assert_eq!(chunks, vec!["a b", "b c"]);
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let chunks = Chunker::Paragraphs.chunk("first\nstill first\n\n\nsecond\n");
+
+        assert_eq!(chunks, vec!["first\nstill first", "second"]);
+    }
+}
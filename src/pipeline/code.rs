@@ -0,0 +1,437 @@
+use crate::error::{err, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Directories never read when walking a repository: build output and installed dependencies
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "vendor", "dist", "build", "__pycache__"];
+
+/// Programming language of a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Go,
+    Java,
+    C,
+    Cpp,
+}
+
+impl Language {
+    /// Every supported language
+    pub const ALL: [Language; 8] = [
+        Language::Rust, Language::Python, Language::JavaScript, Language::TypeScript,
+        Language::Go, Language::Java, Language::C, Language::Cpp,
+    ];
+
+    /// Lowercase name, as accepted by `--lang` and stored as metadata
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::Go => "go",
+            Language::Java => "java",
+            Language::C => "c",
+            Language::Cpp => "cpp",
+        }
+    }
+
+    /// File extensions of the language, without the dot
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &["rs"],
+            Language::Python => &["py", "pyi"],
+            Language::JavaScript => &["js", "jsx", "mjs", "cjs"],
+            Language::TypeScript => &["ts", "tsx", "mts", "cts"],
+            Language::Go => &["go"],
+            Language::Java => &["java"],
+            Language::C => &["c", "h"],
+            Language::Cpp => &["cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+        }
+    }
+
+    /// Detects the language of a file from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        Self::ALL.into_iter().find(|language| language.extensions().iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    }
+
+    /// Whether definitions are found with a tree-sitter grammar in this build, rather than by layout
+    pub fn has_grammar(&self) -> bool {
+        cfg!(feature = "tree-sitter")
+            && matches!(self, Language::Rust | Language::Python | Language::JavaScript | Language::TypeScript | Language::Go)
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Language {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        let alias = match name.as_str() {
+            "rs" => "rust",
+            "py" => "python",
+            "js" => "javascript",
+            "ts" => "typescript",
+            "golang" => "go",
+            "c++" => "cpp",
+            name => name,
+        };
+        Self::ALL.into_iter().find(|language| language.name() == alias).ok_or_else(|| err!(
+            "Unknown language '{}', expected one of {}",
+            name, Self::ALL.map(|language| language.name()).join(", ")
+        ))
+    }
+}
+
+/// A definition, or a run of top-level code between definitions, cut from a source file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeChunk {
+    /// Path of the file, joined to the walked repository's path
+    pub path: String,
+    pub language: Language,
+    /// First line of the chunk, counted from 1
+    pub start_line: usize,
+    /// Last line of the chunk, inclusive
+    pub end_line: usize,
+    /// Syntax node the chunk was cut at, such as `function_item`, or `block` for code split by layout
+    pub kind: String,
+    /// Name of the defined function, type or class, when it has one
+    pub name: Option<String>,
+    pub text: String,
+}
+
+impl CodeChunk {
+    /// Metadata stored with the chunk's embedding: path, language, line range, kind and name
+    pub fn metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::from([
+            ("path".to_string(), self.path.clone()),
+            ("lang".to_string(), self.language.name().to_string()),
+            ("start_line".to_string(), self.start_line.to_string()),
+            ("end_line".to_string(), self.end_line.to_string()),
+            ("kind".to_string(), self.kind.clone()),
+        ]);
+        if let Some(name) = &self.name {
+            metadata.insert("name".to_string(), name.clone());
+        }
+        metadata
+    }
+}
+
+/// How `collect_code_chunks` reads a repository
+#[derive(Debug, Clone)]
+pub struct CodeOptions {
+    /// Only read files of this language, every supported language if None
+    pub language: Option<Language>,
+    /// Longest chunk in lines, longer definitions are split into windows of this many lines
+    pub max_lines: usize,
+}
+
+impl Default for CodeOptions {
+    fn default() -> Self {
+        Self { language: None, max_lines: 120 }
+    }
+}
+
+/// Walks a source tree and cuts every file of a supported language into chunks at its definitions
+///
+/// Hidden directories, and directories holding build output or dependencies such as `target` and
+/// `node_modules`, are skipped. Files that aren't UTF-8 are left out.
+pub fn collect_code_chunks(repo: &Path, options: &CodeOptions) -> Result<Vec<CodeChunk>> {
+    let mut files = Vec::new();
+    if repo.is_dir() {
+        collect_source_files(repo, &mut files)?;
+        files.sort();
+    } else {
+        files.push(repo.to_path_buf());
+    }
+
+    let mut chunks = Vec::new();
+    for file in files {
+        let Some(language) = Language::from_path(&file) else { continue };
+        if options.language.is_some_and(|wanted| wanted != language) {
+            continue;
+        }
+        let bytes = std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let Ok(source) = String::from_utf8(bytes) else {
+            tracing::debug!("Skipping {}, it isn't UTF-8", file.display());
+            continue;
+        };
+        chunks.extend(chunk_code(&file.to_string_lossy(), &source, language, options.max_lines));
+    }
+    Ok(chunks)
+}
+
+/// Cuts a source file into chunks at its definitions
+///
+/// With the `tree-sitter` feature, Rust, Python, JavaScript, TypeScript and Go files are parsed and each
+/// top-level function, type or class becomes a chunk together with its doc comments and attributes.
+/// Classes, impls and traits longer than `max_lines` are cut at their methods instead. Other languages, and
+/// every language without the feature, are split by layout: a new chunk starts at an unindented line after a
+/// blank line.
+pub fn chunk_code(path: &str, source: &str, language: Language, max_lines: usize) -> Vec<CodeChunk> {
+    let lines: Vec<&str> = source.lines().collect();
+    let max_lines = max_lines.max(1);
+
+    #[cfg(feature = "tree-sitter")]
+    let spans = grammar::spans(source, language, max_lines).unwrap_or_else(|| layout_spans(&lines));
+    #[cfg(not(feature = "tree-sitter"))]
+    let spans = layout_spans(&lines);
+
+    let mut chunks = Vec::new();
+    for span in spans {
+        // Long spans go in windows, each keeping the kind and name of the definition it came from
+        let mut start = span.start;
+        while start <= span.end {
+            let end = span.end.min(start + max_lines - 1);
+            let text = lines[start..=end].join("\n");
+            // A window of closing brackets alone isn't worth a chunk
+            if text.chars().any(char::is_alphanumeric) {
+                chunks.push(CodeChunk {
+                    path: path.to_string(),
+                    language,
+                    start_line: start + 1,
+                    end_line: end + 1,
+                    kind: span.kind.clone(),
+                    name: span.name.clone(),
+                    text,
+                });
+            }
+            start = end + 1;
+        }
+    }
+    chunks
+}
+
+/// Zero-based inclusive line range of a chunk
+#[derive(Debug, Clone)]
+struct Span {
+    start: usize,
+    end: usize,
+    kind: String,
+    name: Option<String>,
+}
+
+/// Splits lines into blocks starting at unindented lines that follow a blank line
+fn layout_spans(lines: &[&str]) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut previous_blank = true;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            previous_blank = true;
+            continue;
+        }
+        let unindented = !line.starts_with([' ', '\t', '}', ')', ']']);
+        match spans.last_mut() {
+            Some(span) if !(unindented && previous_blank) => span.end = i,
+            _ => spans.push(Span { start: i, end: i, kind: "block".to_string(), name: None }),
+        }
+        previous_blank = false;
+    }
+    spans
+}
+
+/// Walks a directory for files with a supported extension
+fn collect_source_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+        if path.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name) {
+                collect_source_files(&path, files)?;
+            }
+        } else if Language::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tree-sitter")]
+mod grammar {
+    use super::{Language, Span};
+    use tree_sitter::{Node, Parser};
+
+    /// Syntax nodes that precede a definition and belong to it
+    const LEADING: &[&str] = &["line_comment", "block_comment", "comment", "attribute_item", "decorator"];
+
+    impl Language {
+        /// Tree-sitter grammar of the language, None for languages chunked by layout
+        fn grammar(&self) -> Option<tree_sitter::Language> {
+            match self {
+                Language::Rust => Some(tree_sitter_rust::LANGUAGE.into()),
+                Language::Python => Some(tree_sitter_python::LANGUAGE.into()),
+                Language::JavaScript => Some(tree_sitter_javascript::LANGUAGE.into()),
+                Language::TypeScript => Some(tree_sitter_typescript::LANGUAGE_TSX.into()),
+                Language::Go => Some(tree_sitter_go::LANGUAGE.into()),
+                _ => None,
+            }
+        }
+
+        /// Node kinds cut into chunks of their own
+        fn definitions(&self) -> &'static [&'static str] {
+            match self {
+                Language::Rust => &[
+                    "function_item", "function_signature_item", "struct_item", "enum_item", "union_item",
+                    "trait_item", "impl_item", "mod_item", "macro_definition", "type_item", "const_item",
+                    "static_item",
+                ],
+                Language::Python => &["function_definition", "class_definition", "decorated_definition"],
+                Language::JavaScript | Language::TypeScript => &[
+                    "function_declaration", "generator_function_declaration", "class_declaration",
+                    "abstract_class_declaration", "method_definition", "interface_declaration",
+                    "type_alias_declaration", "enum_declaration", "internal_module", "export_statement",
+                    "lexical_declaration",
+                ],
+                Language::Go => &[
+                    "function_declaration", "method_declaration", "type_declaration", "const_declaration",
+                    "var_declaration",
+                ],
+                _ => &[],
+            }
+        }
+    }
+
+    /// Line spans of the definitions in a source file, None when it has no grammar or fails to parse
+    pub(super) fn spans(source: &str, language: Language, max_lines: usize) -> Option<Vec<Span>> {
+        let mut parser = Parser::new();
+        parser.set_language(&language.grammar()?).ok()?;
+        let tree = parser.parse(source, None)?;
+
+        let mut spans = Vec::new();
+        cut(tree.root_node(), source.as_bytes(), language, max_lines, &mut spans);
+        Some(spans)
+    }
+
+    /// Cuts the children of a node into spans, descending into long classes, impls and traits
+    fn cut(node: Node, source: &[u8], language: Language, max_lines: usize, spans: &mut Vec<Span>) {
+        // First line of the comments and attributes waiting for the next definition
+        let mut leading: Option<usize> = None;
+        // Top-level code between definitions, such as imports, kept together
+        let mut loose: Option<Span> = None;
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            let (start, end) = (child.start_position().row, last_row(&child));
+            if LEADING.contains(&child.kind()) {
+                leading.get_or_insert(start);
+                continue;
+            }
+            if !language.definitions().contains(&child.kind()) {
+                let start = leading.take().unwrap_or(start);
+                match &mut loose {
+                    Some(span) => span.end = end,
+                    None => loose = Some(Span { start, end, kind: "module".to_string(), name: None }),
+                }
+                continue;
+            }
+            spans.extend(loose.take());
+
+            // Exports and decorators wrap the class whose body is cut
+            let body = child.child_by_field_name("body").or_else(|| {
+                ["declaration", "definition"].iter().find_map(|field| {
+                    child.child_by_field_name(field).and_then(|inner| inner.child_by_field_name("body"))
+                })
+            });
+            match body {
+                Some(body) if end - start + 1 > max_lines && has_definitions(body, language) => {
+                    // The container's comments and header line go with its first member
+                    let first = spans.len();
+                    cut(body, source, language, max_lines, spans);
+                    if let Some(span) = spans.get_mut(first) {
+                        span.start = leading.unwrap_or(start);
+                    }
+                }
+                _ => spans.push(Span {
+                    start: leading.unwrap_or(start),
+                    end,
+                    kind: child.kind().to_string(),
+                    name: name(&child, source),
+                }),
+            }
+            leading = None;
+        }
+        spans.extend(loose);
+    }
+
+    /// Whether a class or impl body holds definitions to cut it at
+    fn has_definitions(body: Node, language: Language) -> bool {
+        let mut cursor = body.walk();
+        let found = body.named_children(&mut cursor).any(|child| language.definitions().contains(&child.kind()));
+        found
+    }
+
+    /// Last line of a node, not counting a trailing newline
+    fn last_row(node: &Node) -> usize {
+        let end = node.end_position();
+        if end.column == 0 && end.row > node.start_position().row {
+            end.row - 1
+        } else {
+            end.row
+        }
+    }
+
+    /// Name of a definition, looking through exports, decorators and declarations
+    fn name(node: &Node, source: &[u8]) -> Option<String> {
+        let named = node.child_by_field_name("name")
+            .or_else(|| node.child_by_field_name("type"))
+            .or_else(|| ["declaration", "definition"].iter().find_map(|field| {
+                node.child_by_field_name(field).and_then(|inner| inner.child_by_field_name("name"))
+            }))
+            .or_else(|| {
+                // Go type and const declarations, JavaScript let and const, name their first specifier
+                let mut cursor = node.walk();
+                let first = node.named_children(&mut cursor).find_map(|child| child.child_by_field_name("name"));
+                first
+            })?;
+        named.utf8_text(source).ok().map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "use std::fmt;\n\n/// A point\n#[derive(Debug)]\nstruct Point {\n    x: i32,\n}\n\nfn origin() -> Point {\n    Point { x: 0 }\n}\n";
+
+    #[test]
+    fn test_chunks_record_line_ranges() {
+        let chunks = chunk_code("src/point.rs", SOURCE, Language::Rust, 120);
+        let ranges: Vec<(usize, usize)> = chunks.iter().map(|chunk| (chunk.start_line, chunk.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 1), (3, 7), (9, 11)]);
+        assert!(chunks[1].text.starts_with("/// A point\n#[derive(Debug)]\nstruct Point {"));
+        assert_eq!(chunks[2].metadata()["start_line"], "9");
+        assert_eq!(chunks[2].metadata()["path"], "src/point.rs");
+
+        if Language::Rust.has_grammar() {
+            assert_eq!(chunks[1].kind, "struct_item");
+            assert_eq!(chunks[2].name.as_deref(), Some("origin"));
+        }
+    }
+
+    #[test]
+    fn test_long_chunks_split_into_windows() {
+        let chunks = chunk_code("a.py", "def f():\n    a = 1\n    b = 2\n    return a + b\n", Language::Python, 3);
+        let ranges: Vec<(usize, usize)> = chunks.iter().map(|chunk| (chunk.start_line, chunk.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 3), (4, 4)]);
+        assert_eq!("ts".parse::<Language>().unwrap(), Language::TypeScript);
+        assert_eq!(Language::from_path(Path::new("lib/app.TSX")), Some(Language::TypeScript));
+    }
+}
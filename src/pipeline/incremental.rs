@@ -0,0 +1,258 @@
+use super::{collect_files, Chunker, Extractor, Preprocessor};
+use crate::embedding::Embedder;
+use crate::utils;
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// An embedded chunk kept in an `EmbeddingStore`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredChunk {
+    pub source: String,
+    /// Page number for documents extracted page by page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// Titles of the headings the chunk sits under
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<String>,
+    pub chunk: usize,
+    /// SHA-256 of the preprocessed chunk text
+    pub hash: String,
+    pub model: String,
+    pub model_version: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Embedded chunks and their content hashes, kept in a JSONL file between runs
+#[derive(Debug, Clone)]
+pub struct EmbeddingStore {
+    path: PathBuf,
+    chunks: Vec<StoredChunk>,
+}
+
+/// How `incremental_embed_with` reads and splits its input
+#[derive(Debug, Clone)]
+pub struct IncrementalOptions {
+    pub extractor: Extractor,
+    pub chunker: Chunker,
+    pub preprocessor: Preprocessor,
+    /// File extensions read from a directory, all files if empty
+    pub extensions: Vec<String>,
+    /// Whether subdirectories are read too
+    pub recursive: bool,
+    /// Number of chunks sent to the embedder at once
+    pub batch_size: usize,
+}
+
+impl Default for IncrementalOptions {
+    fn default() -> Self {
+        Self {
+            extractor: Extractor::default(),
+            chunker: Chunker::default(),
+            preprocessor: Preprocessor::default(),
+            extensions: Vec::new(),
+            recursive: true,
+            batch_size: 32,
+        }
+    }
+}
+
+/// Summary of an incremental run
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalReport {
+    pub documents: usize,
+    pub chunks: usize,
+    /// Chunks that were new or changed and went through the model
+    pub embedded: usize,
+    /// Chunks whose embedding was taken from the store
+    pub unchanged: usize,
+    /// Stored chunks of the input that no longer exist
+    pub removed: usize,
+}
+
+impl EmbeddingStore {
+    /// Opens a store, starting empty if the file doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !utils::storage::is_remote(&path) && !path.exists() {
+            return Ok(Self { path, chunks: Vec::new() });
+        }
+
+        let content = utils::storage::read_to_string(&path)?;
+        let chunks = content.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid record on line {} of {}", i + 1, path.display()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { path, chunks })
+    }
+
+    /// The stored chunks, in the order they were embedded
+    pub fn chunks(&self) -> &[StoredChunk] {
+        &self.chunks
+    }
+
+    /// Writes the store back to its file
+    pub fn save(&self) -> Result<()> {
+        let mut writer = Vec::new();
+        for chunk in &self.chunks {
+            serde_json::to_writer(&mut writer, chunk)?;
+            writer.write_all(b"\n")?;
+        }
+        utils::storage::write(&self.path, writer)
+    }
+}
+
+/// Embeds the new and changed chunks of a file or directory into a store and saves it, with default options
+pub fn incremental_embed<E: Embedder>(embedder: &E, dir_or_file: &Path, store: &mut EmbeddingStore) -> Result<IncrementalReport> {
+    incremental_embed_with(embedder, dir_or_file, store, &IncrementalOptions::default())
+}
+
+/// Embeds the new and changed chunks of a file or directory into a store and saves it
+///
+/// Each chunk is hashed after preprocessing. A chunk whose hash is already stored for the same model name and
+/// version reuses that embedding, so re-running over an evolving document set only embeds what changed.
+/// Stored chunks of files under `dir_or_file` that are gone or changed are dropped, other sources are kept.
+#[tracing::instrument(name = "incremental_embed", skip_all, fields(input = %dir_or_file.display()))]
+pub fn incremental_embed_with<E: Embedder>(
+    embedder: &E,
+    dir_or_file: &Path,
+    store: &mut EmbeddingStore,
+    options: &IncrementalOptions,
+) -> Result<IncrementalReport> {
+    let files = if dir_or_file.is_dir() {
+        let mut files = Vec::new();
+        collect_files(dir_or_file, &options.extensions, options.recursive, &mut files)?;
+        files.sort();
+        // A store kept inside the directory isn't a document
+        if let Ok(store_path) = store.path.canonicalize() {
+            files.retain(|file| file.canonicalize().ok().as_ref() != Some(&store_path));
+        }
+        files
+    } else {
+        vec![dir_or_file.to_path_buf()]
+    };
+    let documents = files.iter()
+        .map(|file| options.extractor.extract(file))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let model = embedder.model_name().to_string();
+    let model_version = embedder.model_version().to_string();
+    let mut chunks: Vec<StoredChunk> = Vec::new();
+    for document in &documents {
+        for (index, text) in options.chunker.chunk(&document.text).into_iter().enumerate() {
+            let text = options.preprocessor.apply(&text);
+            if text.is_empty() {
+                continue;
+            }
+            chunks.push(StoredChunk {
+                source: document.source.clone(),
+                page: document.page,
+                headings: document.headings.clone(),
+                chunk: index,
+                hash: format!("{:x}", Sha256::digest(text.as_bytes())),
+                model: model.clone(),
+                model_version: model_version.clone(),
+                text,
+                embedding: Vec::new(),
+            });
+        }
+    }
+
+    // Embeddings of the same model and version can be reused from any source, identical chunks are embedded once
+    let mut known: HashMap<String, Vec<f32>> = store.chunks.iter()
+        .filter(|stored| stored.model == model && stored.model_version == model_version)
+        .map(|stored| (stored.hash.clone(), stored.embedding.clone()))
+        .collect();
+    let mut pending: Vec<usize> = Vec::new();
+    let mut queued = HashSet::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        if !known.contains_key(&chunk.hash) && queued.insert(chunk.hash.clone()) {
+            pending.push(i);
+        }
+    }
+    tracing::info!("{} of {} chunk(s) are new or changed", pending.len(), chunks.len());
+
+    for batch in pending.chunks(options.batch_size.max(1)) {
+        let texts: Vec<String> = batch.iter().map(|&i| chunks[i].text.clone()).collect();
+        let embeddings = tracing::debug_span!("embed_batch", texts = texts.len())
+            .in_scope(|| embedder.embed_batch(&texts))?;
+        for (&i, embedding) in batch.iter().zip(embeddings) {
+            known.insert(chunks[i].hash.clone(), embedding.to_vec());
+        }
+    }
+    for chunk in &mut chunks {
+        chunk.embedding = known.get(&chunk.hash).cloned().unwrap_or_default();
+    }
+
+    // Stored chunks from this input are replaced by the current ones
+    let current: HashSet<(&str, &str)> = chunks.iter()
+        .map(|chunk| (chunk.source.as_str(), chunk.hash.as_str()))
+        .collect();
+    let (previous, kept): (Vec<StoredChunk>, Vec<StoredChunk>) = std::mem::take(&mut store.chunks)
+        .into_iter()
+        .partition(|stored| Path::new(&stored.source).starts_with(dir_or_file));
+    let removed = previous.iter()
+        .filter(|stored| !current.contains(&(stored.source.as_str(), stored.hash.as_str())))
+        .count();
+
+    let report = IncrementalReport {
+        documents: documents.len(),
+        chunks: chunks.len(),
+        embedded: pending.len(),
+        unchanged: chunks.len() - pending.len(),
+        removed,
+    };
+    store.chunks = kept;
+    store.chunks.extend(chunks);
+    store.save()?;
+    tracing::info!("Embedding store saved to {}", store.path.display());
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::CountingEmbedder;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_only_changed_documents_are_embedded() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust_embed_incremental_{}", std::process::id()));
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(&docs)?;
+        std::fs::write(docs.join("a.txt"), "first document")?;
+        std::fs::write(docs.join("b.txt"), "second document")?;
+        let store_path = dir.join("store.jsonl");
+
+        let embedder = CountingEmbedder::default();
+        let mut store = EmbeddingStore::open(&store_path)?;
+        let report = incremental_embed(&embedder, &docs, &mut store)?;
+        assert_eq!((report.embedded, report.unchanged), (2, 0));
+
+        std::fs::write(docs.join("b.txt"), "second document, edited")?;
+        std::fs::remove_file(docs.join("a.txt"))?;
+        let mut store = EmbeddingStore::open(&store_path)?;
+        let report = incremental_embed(&embedder, &docs, &mut store)?;
+        assert_eq!((report.embedded, report.unchanged, report.removed), (1, 0, 2));
+        assert_eq!(embedder.texts.load(Ordering::SeqCst), 3);
+
+        let report = incremental_embed(&embedder, &docs, &mut EmbeddingStore::open(&store_path)?)?;
+        assert_eq!((report.embedded, report.unchanged, report.removed), (0, 1, 0));
+        assert_eq!(EmbeddingStore::open(&store_path)?.chunks().len(), 1);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+/// Elements left out of HTML text: navigation, page chrome and anything that isn't prose
+const BOILERPLATE: &[&str] = &[
+    "head", "nav", "header", "footer", "aside", "form", "button", "noscript", "svg", "iframe", "template", "select",
+];
+/// Elements whose content is raw text rather than markup
+const RAW_TEXT: &[&str] = &["script", "style"];
+/// Elements that start a new line of text
+const BLOCKS: &[&str] = &[
+    "address", "article", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption", "figure", "hr", "li", "main",
+    "ol", "p", "pre", "section", "table", "td", "th", "tr", "ul",
+];
+/// Elements holding a page's main content, which is kept alone when present
+const CONTENT: &[&str] = &["main", "article"];
+
+/// A stretch of a document under one heading
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Titles of the enclosing headings, outermost first
+    pub headings: Vec<String>,
+    /// Text of the section, starting with its own heading's title
+    pub text: String,
+}
+
+/// Converts HTML to plain text, keeping headings as Markdown `#` lines
+///
+/// Scripts, styles, navigation, headers, footers and similar boilerplate are dropped. When the page marks its
+/// content with `<main>` or `<article>`, only the first such element is kept, much like readability tools do.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut skipped = 0usize;
+    // Name, start, nesting depth and end of the content element
+    let mut content: Option<(String, usize, usize, Option<usize>)> = None;
+
+    let mut rest = html;
+    while let Some(open) = rest.find('<') {
+        if skipped == 0 {
+            push_text(&mut text, &rest[..open]);
+        }
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|end| &comment[end + 3..]).unwrap_or("");
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag.trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if RAW_TEXT.contains(&name.as_str()) && !closing {
+            rest = skip_raw_text(rest, &name);
+            continue;
+        }
+        if BOILERPLATE.contains(&name.as_str()) && !tag.ends_with('/') {
+            if closing {
+                skipped = skipped.saturating_sub(1);
+            } else {
+                skipped += 1;
+            }
+            continue;
+        }
+        if skipped > 0 {
+            continue;
+        }
+
+        if CONTENT.contains(&name.as_str()) {
+            match &mut content {
+                None if !closing => content = Some((name.clone(), text.len(), 1, None)),
+                Some((content_name, _, depth, end @ None)) if *content_name == name => {
+                    if closing {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            *end = Some(text.len());
+                        }
+                    } else {
+                        *depth += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match heading_level(&name) {
+            Some(level) if !closing => {
+                text.push_str("\n\n");
+                text.push_str(&"#".repeat(level));
+                text.push(' ');
+            }
+            Some(_) => text.push_str("\n\n"),
+            None if BLOCKS.contains(&name.as_str()) => text.push('\n'),
+            None => {}
+        }
+    }
+    if skipped == 0 {
+        push_text(&mut text, rest);
+    }
+
+    let text = match content {
+        Some((_, start, _, end)) => &text[start..end.unwrap_or(text.len())],
+        None => &text,
+    };
+    tidy_lines(text)
+}
+
+/// Splits Markdown into sections at its `#` headings, each knowing the path of headings above it
+///
+/// Text before the first heading forms a section without headings. Lines in fenced code blocks are never
+/// taken for headings.
+pub fn markdown_sections(markdown: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut text = String::new();
+    let mut fence: Option<&str> = None;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        } else if let Some((level, title)) = parse_heading(line) {
+            push_section(&mut sections, &path, &mut text);
+            path.retain(|(parent, _)| *parent < level);
+            path.push((level, title.to_string()));
+            text.push_str(title);
+            text.push('\n');
+            continue;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    push_section(&mut sections, &path, &mut text);
+    sections
+}
+
+/// Level and title of an ATX heading line such as `## Install`
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    // Up to three spaces of indentation, more is a code block
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, title))
+}
+
+/// Adds the collected text as a section unless it is blank
+fn push_section(sections: &mut Vec<Section>, path: &[(usize, String)], text: &mut String) {
+    if !text.trim().is_empty() {
+        sections.push(Section {
+            headings: path.iter().map(|(_, title)| title.clone()).collect(),
+            text: text.trim().to_string(),
+        });
+    }
+    text.clear();
+}
+
+/// Level of an `<h1>` to `<h6>` element
+fn heading_level(name: &str) -> Option<usize> {
+    match name.strip_prefix('h')?.parse::<usize>() {
+        Ok(level) if (1..=6).contains(&level) => Some(level),
+        _ => None,
+    }
+}
+
+/// Skips past the closing tag of a script or style element
+fn skip_raw_text<'a>(html: &'a str, name: &str) -> &'a str {
+    let closing = format!("</{}", name);
+    let end = html.to_ascii_lowercase().find(&closing);
+    match end.and_then(|end| html[end..].find('>').map(|close| end + close + 1)) {
+        Some(after) => &html[after..],
+        None => "",
+    }
+}
+
+/// Appends a text node with entities decoded and whitespace collapsed
+fn push_text(text: &mut String, raw: &str) {
+    let decoded = decode_entities(raw);
+    let mut words = decoded.split_whitespace().peekable();
+    if words.peek().is_none() {
+        if !decoded.is_empty() && !text.ends_with([' ', '\n']) {
+            text.push(' ');
+        }
+        return;
+    }
+    if decoded.starts_with(char::is_whitespace) && !text.ends_with([' ', '\n']) {
+        text.push(' ');
+    }
+    text.push_str(&words.collect::<Vec<_>>().join(" "));
+    if decoded.ends_with(char::is_whitespace) {
+        text.push(' ');
+    }
+}
+
+/// Decodes the common named entities and numeric character references
+fn decode_entities(raw: &str) -> String {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').filter(|&end| end <= 10).map(|end| &rest[1..end]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Trims every line and collapses runs of blank lines into one
+fn tidy_lines(text: &str) -> String {
+    let mut tidy = String::with_capacity(text.len());
+    let mut blank = false;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            blank = !tidy.is_empty();
+            continue;
+        }
+        if blank {
+            tidy.push('\n');
+            blank = false;
+        }
+        tidy.push_str(line);
+        tidy.push('\n');
+    }
+    tidy.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_keeps_main_content() {
+        let html = r#"<html><head><title>Site</title><style>p { color: red }</style></head>
+            <body><nav><a href="/">Home</a></nav>
+            <main><h1>Guide</h1><p>Fish &amp; chips<br>for&nbsp;two</p>
+            <script>if (a < b) { alert("x") }</script><h2>Serving</h2><p>Hot.</p></main>
+            <footer>Copyright</footer></body></html>"#;
+        assert_eq!(html_to_text(html), "# Guide\n\nFish & chips\nfor two\n\n## Serving\n\nHot.");
+    }
+
+    #[test]
+    fn test_markdown_sections_follow_headings() {
+        let markdown = "Intro\n# Install\nSteps\n```sh\n# not a heading\n```\n## From source\nBuild it\n# Usage ##\nRun it";
+        let sections = markdown_sections(markdown);
+        let paths: Vec<Vec<&str>> = sections.iter()
+            .map(|section| section.headings.iter().map(String::as_str).collect())
+            .collect();
+        assert_eq!(paths, vec![vec![], vec!["Install"], vec!["Install", "From source"], vec!["Usage"]]);
+        assert_eq!(sections[1].text, "Install\nSteps\n```sh\n# not a heading\n```");
+        assert_eq!(sections[3].text, "Usage\nRun it");
+    }
+}
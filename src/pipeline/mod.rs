@@ -0,0 +1,614 @@
+pub mod chunker;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod code;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod incremental;
+pub mod markup;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub mod stream;
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+pub mod watch;
+
+pub use chunker::Chunker;
+#[cfg(not(target_arch = "wasm32"))]
+pub use incremental::{incremental_embed, EmbeddingStore};
+
+use crate::embedding::{BatchErrorPolicy, Embedder};
+use crate::models::code::preprocess_code;
+use crate::models::registry::ModelSpec;
+use crate::utils;
+use crate::error::{err, Context, Result};
+use ndarray::{s, Array1};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A declarative embedding job: loader → extractor → chunker → preprocessor → embedder → transforms → sink
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pipeline {
+    /// Where the documents come from
+    pub loader: Loader,
+
+    /// How raw document bytes become text
+    #[serde(default)]
+    pub extractor: Extractor,
+
+    /// How documents are split before embedding
+    #[serde(default)]
+    pub chunker: Chunker,
+
+    /// Text normalization applied to each chunk
+    #[serde(default)]
+    pub preprocessor: Preprocessor,
+
+    /// The model producing the embeddings
+    pub embedder: EmbedderStage,
+
+    /// Transforms applied to each embedding, in order
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+
+    /// Where the embeddings are written
+    pub sink: Sink,
+
+    /// JSONL file listing the chunks that failed to embed, when `on_error` lets the run continue
+    #[serde(default)]
+    pub error_report: Option<PathBuf>,
+}
+
+/// Source of the documents fed into a pipeline
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Loader {
+    /// A single file
+    File { path: PathBuf },
+
+    /// All files in a directory, optionally filtered by extension
+    Directory {
+        path: PathBuf,
+        #[serde(default)]
+        extensions: Vec<String>,
+        #[serde(default)]
+        recursive: bool,
+    },
+
+    /// Texts given inline in the config
+    Inline { texts: Vec<String> },
+}
+
+/// Turns raw document bytes into text
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Extractor {
+    /// PDFs, HTML and Markdown by their extension, everything else as text
+    #[default]
+    Auto,
+
+    /// UTF-8 text, invalid sequences are replaced
+    Text,
+
+    /// Web pages without scripts, navigation and other boilerplate, one document per heading
+    Html,
+
+    /// Markdown, one document per heading
+    Markdown,
+
+    /// The text of each page of a PDF
+    #[cfg(feature = "pdf")]
+    Pdf,
+}
+
+/// Text normalization options
+#[derive(Debug, Clone, Deserialize)]
+pub struct Preprocessor {
+    #[serde(default = "default_true")]
+    pub trim: bool,
+
+    #[serde(default)]
+    pub lowercase: bool,
+
+    #[serde(default = "default_true")]
+    pub collapse_whitespace: bool,
+
+    /// Treat chunks as source code: keep case and indentation, only tidy blank lines and trailing spaces
+    #[serde(default)]
+    pub code: bool,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self {
+            trim: true,
+            lowercase: false,
+            collapse_whitespace: true,
+            code: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The model stage of a pipeline
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbedderStage {
+    #[serde(flatten)]
+    pub spec: ModelSpec,
+
+    /// Number of chunks sent to the embedder at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// What happens to texts that fail to embed
+    #[serde(default)]
+    pub on_error: BatchErrorPolicy,
+}
+
+fn default_batch_size() -> usize {
+    32
+}
+
+/// A transform applied to each embedding after inference
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    /// Scale to unit length
+    Normalize,
+
+    /// Keep only the first `dimension` components (for Matryoshka-style models)
+    Truncate { dimension: usize },
+}
+
+/// Destination of the pipeline output
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Sink {
+    /// Protocol Buffers collection, as written by the CLI
+    #[cfg(feature = "proto-store")]
+    Proto { path: PathBuf },
+
+    /// One JSON record per chunk with its source and embedding
+    Jsonl { path: PathBuf },
+}
+
+/// A loaded document
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// File path or inline position the document came from
+    pub source: String,
+
+    /// Extracted text
+    pub text: String,
+
+    /// Page number, counted from 1, for documents extracted page by page
+    pub page: Option<usize>,
+
+    /// Titles of the headings the text sits under, outermost first
+    pub headings: Vec<String>,
+}
+
+/// A piece of a document ready to be embedded
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub source: String,
+    pub page: Option<usize>,
+    pub headings: Vec<String>,
+    pub index: usize,
+    pub text: String,
+}
+
+/// Summary of a pipeline run
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReport {
+    pub documents: usize,
+    pub chunks: usize,
+    pub failed: usize,
+    pub dimension: usize,
+    pub elapsed: Duration,
+}
+
+/// A single line of the error report
+#[derive(Serialize)]
+struct FailureRecord<'a> {
+    source: &'a str,
+    chunk: usize,
+    error: String,
+}
+
+/// A single line of the JSONL sink
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    source: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    headings: &'a [String],
+    chunk: usize,
+    text: &'a str,
+    embedding: Vec<f32>,
+}
+
+impl Pipeline {
+    /// Loads a pipeline declaration from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pipeline {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse pipeline {}", path.display()))
+    }
+
+    /// Runs the pipeline with the embedder declared in its config
+    pub fn run(&self) -> Result<PipelineReport> {
+        tracing::info!("Loading the {} model for the pipeline...", self.embedder.spec.model);
+        let embedder = self.embedder.spec.build()?;
+        self.run_with(&embedder)
+    }
+
+    /// Runs the pipeline with an already constructed embedder
+    #[tracing::instrument(name = "pipeline", skip_all, fields(model = embedder.model_name()))]
+    pub fn run_with<E: Embedder>(&self, embedder: &E) -> Result<PipelineReport> {
+        let start = Instant::now();
+
+        let documents = tracing::info_span!("load").in_scope(|| self.load())?;
+        tracing::info!("Loaded {} document(s)", documents.len());
+
+        let chunks: Vec<Chunk> = documents.iter()
+            .flat_map(|document| {
+                self.chunker.chunk(&document.text)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, text)| Chunk {
+                        source: document.source.clone(),
+                        page: document.page,
+                        headings: document.headings.clone(),
+                        index,
+                        text: self.preprocessor.apply(&text),
+                    })
+            })
+            .filter(|chunk| !chunk.text.is_empty())
+            .collect();
+        tracing::info!("Embedding {} chunk(s)", chunks.len());
+
+        // Chunks skipped by the error policy are dropped from the output along with their embeddings
+        let mut embedded = Vec::with_capacity(chunks.len());
+        let mut embeddings = Vec::with_capacity(chunks.len());
+        let mut failures = Vec::new();
+        for batch in chunks.chunks(self.embedder.batch_size.max(1)) {
+            let _span = tracing::debug_span!("embed_batch", texts = batch.len()).entered();
+            let texts: Vec<String> = batch.iter().map(|chunk| chunk.text.clone()).collect();
+            let output = embedder.embed_batch_with_policy(&texts, self.embedder.on_error)?;
+            for (index, error) in output.summary.failures {
+                let chunk = &batch[index];
+                tracing::warn!("Failed to embed chunk {} of {}: {}", chunk.index, chunk.source, error);
+                failures.push(FailureRecord { source: &chunk.source, chunk: chunk.index, error });
+            }
+            for (chunk, embedding) in batch.iter().zip(output.embeddings) {
+                if let Some(embedding) = embedding {
+                    embedded.push(chunk.clone());
+                    embeddings.push(self.transform(embedding)?);
+                }
+            }
+            tracing::debug!("Embedded {}/{} chunks", embeddings.len(), chunks.len());
+        }
+
+        let dimension = embeddings.first().map(|e| e.len()).unwrap_or(embedder.dimension());
+        self.write(embedder, &embedded, &embeddings, dimension)?;
+        if !failures.is_empty() {
+            tracing::warn!("{} of {} chunk(s) failed to embed", failures.len(), chunks.len());
+        }
+        if let Some(path) = &self.error_report {
+            self.write_error_report(path, &failures)?;
+        }
+
+        Ok(PipelineReport {
+            documents: documents.len(),
+            chunks: chunks.len(),
+            failed: failures.len(),
+            dimension,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Loads and extracts all documents
+    fn load(&self) -> Result<Vec<Document>> {
+        match &self.loader {
+            Loader::Inline { texts } => Ok(texts.iter()
+                .enumerate()
+                .map(|(i, text)| Document {
+                    source: format!("inline:{}", i),
+                    text: text.clone(),
+                    page: None,
+                    headings: Vec::new(),
+                })
+                .collect()),
+            Loader::File { path } => self.extractor.extract(path),
+            Loader::Directory { path, extensions, recursive } => {
+                let mut files = Vec::new();
+                collect_files(path, extensions, *recursive, &mut files)?;
+                files.sort();
+
+                let documents = files.iter()
+                    .map(|file| self.extractor.extract(file))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(documents.into_iter().flatten().collect())
+            }
+        }
+    }
+
+    /// Applies the configured transforms to an embedding
+    fn transform(&self, embedding: Array1<f32>) -> Result<Array1<f32>> {
+        Transform::apply_all(&self.transforms, embedding)
+    }
+
+    /// Writes one JSON record per failed chunk
+    fn write_error_report(&self, path: &Path, failures: &[FailureRecord]) -> Result<()> {
+        let mut writer = Vec::new();
+        for failure in failures {
+            serde_json::to_writer(&mut writer, failure)?;
+            writer.write_all(b"\n")?;
+        }
+        utils::storage::write(path, writer)?;
+        tracing::info!("Error report saved to {}", path.display());
+        Ok(())
+    }
+
+    /// Writes the embeddings to the sink
+    #[cfg_attr(not(feature = "proto-store"), allow(unused_variables))]
+    #[tracing::instrument(skip_all, fields(embeddings = embeddings.len()))]
+    fn write<E: Embedder>(&self, embedder: &E, chunks: &[Chunk], embeddings: &[Array1<f32>], dimension: usize) -> Result<()> {
+        match &self.sink {
+            #[cfg(feature = "proto-store")]
+            Sink::Proto { path } => {
+                let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+                utils::save_embeddings(
+                    embeddings,
+                    Some(&texts),
+                    embedder.model_name(),
+                    embedder.model_version(),
+                    dimension as i32,
+                    path,
+                )?;
+                tracing::info!("Embeddings saved to {}", path.display());
+            }
+            Sink::Jsonl { path } => {
+                let mut writer = Vec::new();
+                for (chunk, embedding) in chunks.iter().zip(embeddings) {
+                    let record = JsonlRecord {
+                        source: &chunk.source,
+                        page: chunk.page,
+                        headings: &chunk.headings,
+                        chunk: chunk.index,
+                        text: &chunk.text,
+                        embedding: embedding.to_vec(),
+                    };
+                    serde_json::to_writer(&mut writer, &record)?;
+                    writer.write_all(b"\n")?;
+                }
+                utils::storage::write(path, writer)?;
+                tracing::info!("Embeddings saved to {}", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Transform {
+    /// Applies the transform to an embedding
+    pub fn apply(&self, mut embedding: Array1<f32>) -> Result<Array1<f32>> {
+        match self {
+            Transform::Normalize => {
+                utils::normalize(&mut embedding);
+                Ok(embedding)
+            }
+            Transform::Truncate { dimension } => {
+                if *dimension > embedding.len() {
+                    return Err(err!(
+                        "Cannot truncate a {}-dimensional embedding to {} dimensions",
+                        embedding.len(), dimension
+                    ));
+                }
+                Ok(embedding.slice(s![..*dimension]).to_owned())
+            }
+        }
+    }
+
+    /// Applies several transforms in order
+    pub fn apply_all(transforms: &[Transform], mut embedding: Array1<f32>) -> Result<Array1<f32>> {
+        for transform in transforms {
+            embedding = transform.apply(embedding)?;
+        }
+        Ok(embedding)
+    }
+}
+
+impl Extractor {
+    /// Reads a file and extracts its text, as one document per page for PDFs and per heading for HTML and Markdown
+    pub fn extract(&self, path: &Path) -> Result<Vec<Document>> {
+        let bytes = utils::storage::read(path)?;
+        let source = path.display().to_string();
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+
+        match (self, extension.as_str()) {
+            #[cfg(feature = "pdf")]
+            (Extractor::Pdf, _) | (Extractor::Auto, "pdf") => extract_pdf_pages(source, &bytes),
+            #[cfg(not(feature = "pdf"))]
+            (Extractor::Auto, "pdf") => Err(err!("Reading {} needs the pdf feature", source)),
+            (Extractor::Html, _) | (Extractor::Auto, "html" | "htm" | "xhtml") => {
+                let text = markup::html_to_text(&String::from_utf8_lossy(&bytes));
+                Ok(sections(source, &text))
+            }
+            (Extractor::Markdown, _) | (Extractor::Auto, "md" | "markdown") => {
+                Ok(sections(source, &String::from_utf8_lossy(&bytes)))
+            }
+            (Extractor::Auto | Extractor::Text, _) => Ok(vec![Document {
+                source,
+                text: String::from_utf8_lossy(&bytes).into_owned(),
+                page: None,
+                headings: Vec::new(),
+            }]),
+        }
+    }
+}
+
+/// One document per Markdown section, carrying its heading path
+fn sections(source: String, markdown: &str) -> Vec<Document> {
+    markup::markdown_sections(markdown)
+        .into_iter()
+        .map(|section| Document { source: source.clone(), text: section.text, page: None, headings: section.headings })
+        .collect()
+}
+
+/// Extracts the text of each page of a PDF
+#[cfg(feature = "pdf")]
+fn extract_pdf_pages(source: String, bytes: &[u8]) -> Result<Vec<Document>> {
+    let pages = pdf_extract::extract_text_from_mem_by_pages(bytes)
+        .with_context(|| format!("Failed to extract the text of {}", source))?;
+    Ok(pages.into_iter()
+        .enumerate()
+        .map(|(i, text)| Document { source: source.clone(), text, page: Some(i + 1), headings: Vec::new() })
+        .collect())
+}
+
+/// Whether a file is a PDF, going by its extension
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false)
+}
+
+impl Preprocessor {
+    /// Normalizes a chunk of text
+    pub fn apply(&self, text: &str) -> String {
+        if self.code {
+            return preprocess_code(text);
+        }
+
+        let mut text = if self.collapse_whitespace {
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else if self.trim {
+            text.trim().to_string()
+        } else {
+            text.to_string()
+        };
+
+        if self.lowercase {
+            text = text.to_lowercase();
+        }
+
+        text
+    }
+}
+
+/// Collects the files in a directory matching the extension filter
+fn collect_files(dir: &Path, extensions: &[String], recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if recursive {
+                collect_files(&path, extensions, recursive, files)?;
+            }
+            continue;
+        }
+
+        let matches = extensions.is_empty() || path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext)))
+            .unwrap_or(false);
+
+        if matches {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "pdf")]
+    use crate::test_support::LengthEmbedder;
+
+    /// Builds a PDF with one line of Helvetica text per page
+    #[cfg(feature = "pdf")]
+    fn pdf(pages: &[&str]) -> Vec<u8> {
+        let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + 2 * i)).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+        for (i, text) in pages.iter().enumerate() {
+            let content = format!("BT /F1 12 Tf 20 100 Td ({}) Tj ET", text);
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents {} 0 R /Resources << /Font << /F1 3 0 R >> >> >>",
+                5 + 2 * i
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content));
+        }
+
+        let mut bytes = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(bytes.len());
+            bytes.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).into_bytes());
+        }
+        let xref = bytes.len();
+        bytes.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for offset in offsets {
+            bytes.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+        }
+        bytes.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).into_bytes());
+        bytes
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_pdf_chunks_record_their_page() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust_embed_pdf_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("report.pdf"), pdf(&["First page", "Second page"]))?;
+
+        let pipeline: Pipeline = toml::from_str(&format!(
+            "loader = {{ type = \"file\", path = {:?} }}\nembedder = {{ model = \"length\" }}\nsink = {{ type = \"jsonl\", path = {:?} }}",
+            dir.join("report.pdf"), dir.join("out.jsonl")
+        ))?;
+        let report = pipeline.run_with(&LengthEmbedder::default())?;
+        assert_eq!((report.documents, report.chunks), (2, 2));
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(dir.join("out.jsonl"))?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        assert_eq!(records[0]["text"], "First page");
+        assert_eq!(records[1]["page"], 2);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_files_have_no_pages() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_extract_{}.txt", std::process::id()));
+        std::fs::write(&path, "plain text")?;
+        let documents = Extractor::Auto.extract(&path)?;
+        assert_eq!(documents.len(), 1);
+        assert_eq!((documents[0].text.as_str(), documents[0].page), ("plain text", None));
+        assert!(is_pdf(Path::new("Report.PDF")));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use super::{EmbedderStage, Preprocessor, Transform};
+use crate::embedding::Embedder;
+use crate::error::{err, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// A long-running job: consume texts from a topic, embed them in batches, publish embedding records
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamConfig {
+    /// Message broker to connect to
+    pub broker: Broker,
+
+    /// Topic (Kafka) or subject (NATS) the texts are read from
+    pub input: String,
+
+    /// Topic or subject the embedding records are published to
+    pub output: String,
+
+    /// The model producing the embeddings, `batch_size` caps how many messages are embedded at once
+    pub embedder: EmbedderStage,
+
+    /// How long to wait for more messages to fill a batch once one has arrived
+    #[serde(default = "default_max_batch_delay_ms")]
+    pub max_batch_delay_ms: u64,
+
+    /// Text normalization applied to each message
+    #[serde(default)]
+    pub preprocessor: Preprocessor,
+
+    /// Transforms applied to each embedding, in order
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+}
+
+/// Message broker a stream worker connects to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Broker {
+    /// Kafka, offsets are committed once a batch has been published
+    Kafka {
+        /// Comma-separated `host:port` list
+        brokers: String,
+        /// Consumer group shared by all workers of one deployment
+        group_id: String,
+    },
+
+    /// NATS core messaging
+    Nats {
+        /// Server URL, e.g. `nats://localhost:4222`
+        url: String,
+        /// Queue group spreading messages across workers, every worker gets every message if unset
+        #[serde(default)]
+        queue_group: Option<String>,
+    },
+}
+
+fn default_max_batch_delay_ms() -> u64 {
+    20
+}
+
+/// Totals of a stream worker run
+#[derive(Debug, Clone, Default)]
+pub struct StreamReport {
+    pub messages: usize,
+    pub batches: usize,
+    pub skipped: usize,
+}
+
+/// A text received from the input topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMessage {
+    /// Identifier echoed in the output record, from a JSON `id` field or the Kafka key
+    pub id: Option<String>,
+
+    /// Text to embed
+    pub text: String,
+}
+
+/// JSON payload form of an input message
+#[derive(Deserialize)]
+struct JsonMessage {
+    #[serde(default)]
+    id: Option<String>,
+    text: String,
+}
+
+/// A record published to the output topic
+#[derive(Serialize)]
+struct StreamRecord<'a> {
+    id: Option<&'a str>,
+    text: &'a str,
+    model: &'a str,
+    embedding: Vec<f32>,
+}
+
+impl StreamMessage {
+    /// Parses a payload, either `{"id": ..., "text": ...}` or plain UTF-8 text
+    pub fn parse(payload: &[u8], key: Option<&[u8]>) -> Result<Self> {
+        let key = key.map(|key| String::from_utf8_lossy(key).into_owned());
+
+        if let Ok(message) = serde_json::from_slice::<JsonMessage>(payload) {
+            return Ok(Self { id: message.id.or(key), text: message.text });
+        }
+
+        let text = std::str::from_utf8(payload)
+            .map_err(|_| err!("Message payload is neither JSON nor UTF-8 text"))?;
+        Ok(Self { id: key, text: text.to_string() })
+    }
+}
+
+impl StreamConfig {
+    /// Loads a stream worker declaration from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read stream config {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse stream config {}", path.display()))
+    }
+
+    /// Runs the worker with the embedder declared in its config, until the input closes
+    pub fn run(&self) -> Result<StreamReport> {
+        tracing::info!("Loading the {} model for the stream...", self.embedder.spec.model);
+        let embedder = self.embedder.spec.build()?;
+        self.run_with(&embedder)
+    }
+
+    /// Runs the worker with an already constructed embedder, until the input closes
+    ///
+    /// Models are not shared across threads, so the broker client runs on a
+    /// single-threaded runtime and batches are embedded on the calling thread.
+    #[tracing::instrument(name = "stream", skip_all, fields(input = %self.input, output = %self.output))]
+    pub fn run_with<E: Embedder>(&self, embedder: &E) -> Result<StreamReport> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        runtime.block_on(async {
+            let mut connection = Connection::open(&self.broker, &self.input).await?;
+            tracing::info!("Consuming {} and publishing to {}", self.input, self.output);
+
+            let max_batch_size = self.embedder.batch_size.max(1);
+            let max_delay = Duration::from_millis(self.max_batch_delay_ms);
+            let mut report = StreamReport::default();
+
+            while let Some(first) = connection.recv().await? {
+                // Wait briefly for more messages so the model sees full batches
+                let mut batch = vec![first];
+                let deadline = tokio::time::Instant::now() + max_delay;
+                let mut closed = false;
+                while batch.len() < max_batch_size {
+                    match tokio::time::timeout_at(deadline, connection.recv()).await {
+                        Ok(Ok(Some(message))) => batch.push(message),
+                        Ok(Ok(None)) => {
+                            closed = true;
+                            break;
+                        }
+                        Ok(Err(e)) => return Err(e),
+                        Err(_) => break,
+                    }
+                }
+
+                let messages: Vec<StreamMessage> = batch.into_iter()
+                    .filter_map(|message| match message {
+                        Ok(message) => Some(message),
+                        Err(e) => {
+                            tracing::warn!("Skipping message: {}", e);
+                            report.skipped += 1;
+                            None
+                        }
+                    })
+                    .collect();
+
+                if !messages.is_empty() {
+                    report.skipped += self.process(embedder, &connection, &messages).await?;
+                    report.messages += messages.len();
+                    report.batches += 1;
+                }
+                connection.commit()?;
+
+                if closed {
+                    break;
+                }
+            }
+
+            tracing::info!("Input closed after {} message(s) in {} batch(es)", report.messages, report.batches);
+            Ok(report)
+        })
+    }
+
+    /// Embeds a batch and publishes one record per message, returning how many the error policy skipped
+    async fn process<E: Embedder>(&self, embedder: &E, connection: &Connection, messages: &[StreamMessage]) -> Result<usize> {
+        let texts: Vec<String> = messages.iter()
+            .map(|message| self.preprocessor.apply(&message.text))
+            .collect();
+        let output = tracing::debug_span!("embed_batch", texts = texts.len())
+            .in_scope(|| embedder.embed_batch_with_policy(&texts, self.embedder.on_error))?;
+        for (index, error) in &output.summary.failures {
+            tracing::warn!("Failed to embed message {}: {}", messages[*index].id.as_deref().unwrap_or("without id"), error);
+        }
+
+        let mut deliveries = Vec::with_capacity(messages.len());
+        let mut skipped = 0;
+        for (message, embedding) in messages.iter().zip(output.embeddings) {
+            let Some(embedding) = embedding else {
+                skipped += 1;
+                continue;
+            };
+            let record = StreamRecord {
+                id: message.id.as_deref(),
+                text: &message.text,
+                model: embedder.model_name(),
+                embedding: Transform::apply_all(&self.transforms, embedding)?.to_vec(),
+            };
+            deliveries.push(connection.publish(&self.output, message.id.as_deref(), serde_json::to_vec(&record)?));
+        }
+
+        // Records of a batch are delivered concurrently, a failure stops the worker before committing
+        futures::future::try_join_all(deliveries).await?;
+        connection.flush().await?;
+        Ok(skipped)
+    }
+}
+
+/// A live broker connection with an input subscription
+enum Connection {
+    #[cfg(feature = "kafka")]
+    Kafka {
+        consumer: rdkafka::consumer::StreamConsumer,
+        producer: rdkafka::producer::FutureProducer,
+    },
+    #[cfg(feature = "nats")]
+    Nats {
+        client: async_nats::Client,
+        subscriber: async_nats::Subscriber,
+    },
+}
+
+impl Connection {
+    /// Connects to the broker and subscribes to the input
+    async fn open(broker: &Broker, input: &str) -> Result<Self> {
+        match broker {
+            #[cfg(feature = "kafka")]
+            Broker::Kafka { brokers, group_id } => {
+                use rdkafka::consumer::Consumer;
+
+                let mut config = rdkafka::ClientConfig::new();
+                config.set("bootstrap.servers", brokers);
+                let consumer: rdkafka::consumer::StreamConsumer = config.clone()
+                    .set("group.id", group_id)
+                    .set("enable.auto.commit", "false")
+                    .create()
+                    .with_context(|| format!("Failed to connect to Kafka at {}", brokers))?;
+                consumer.subscribe(&[input]).map_err(Error::other)?;
+                let producer = config.create().map_err(Error::other)?;
+
+                Ok(Connection::Kafka { consumer, producer })
+            }
+            #[cfg(feature = "nats")]
+            Broker::Nats { url, queue_group } => {
+                let client = async_nats::connect(url.as_str()).await
+                    .with_context(|| format!("Failed to connect to NATS at {}", url))?;
+                let subscriber = match queue_group {
+                    Some(group) => client.queue_subscribe(input.to_string(), group.clone()).await.map_err(Error::other)?,
+                    None => client.subscribe(input.to_string()).await.map_err(Error::other)?,
+                };
+
+                Ok(Connection::Nats { client, subscriber })
+            }
+            #[allow(unreachable_patterns)]
+            broker => Err(err!("The {} broker needs the '{}' feature", broker.name(), broker.name())),
+        }
+    }
+
+    /// Waits for the next message, `None` once the input is closed
+    ///
+    /// The inner result is an unreadable message, which is skipped rather than stopping the worker.
+    async fn recv(&mut self) -> Result<Option<Result<StreamMessage>>> {
+        match self {
+            #[cfg(feature = "kafka")]
+            Connection::Kafka { consumer, .. } => {
+                use rdkafka::Message;
+
+                let message = consumer.recv().await.map_err(Error::other)?;
+                Ok(Some(StreamMessage::parse(message.payload().unwrap_or_default(), message.key())))
+            }
+            #[cfg(feature = "nats")]
+            Connection::Nats { subscriber, .. } => {
+                use futures::StreamExt;
+
+                Ok(subscriber.next().await.map(|message| StreamMessage::parse(&message.payload, None)))
+            }
+        }
+    }
+
+    /// Publishes a record to the output
+    async fn publish(&self, output: &str, key: Option<&str>, payload: Vec<u8>) -> Result<()> {
+        match self {
+            #[cfg(feature = "kafka")]
+            Connection::Kafka { producer, .. } => {
+                let mut record = rdkafka::producer::FutureRecord::<str, Vec<u8>>::to(output).payload(&payload);
+                if let Some(key) = key {
+                    record = record.key(key);
+                }
+                producer.send(record, rdkafka::util::Timeout::Never).await
+                    .map_err(|(e, _)| err!("Failed to publish to {}: {}", output, e))?;
+                Ok(())
+            }
+            #[cfg(feature = "nats")]
+            Connection::Nats { client, .. } => {
+                let _ = key;
+                client.publish(output.to_string(), payload.into()).await
+                    .with_context(|| format!("Failed to publish to {}", output))
+            }
+        }
+    }
+
+    /// Waits until published records have left the client
+    async fn flush(&self) -> Result<()> {
+        match self {
+            #[cfg(feature = "kafka")]
+            Connection::Kafka { .. } => Ok(()),
+            #[cfg(feature = "nats")]
+            Connection::Nats { client, .. } => client.flush().await.map_err(Error::other),
+        }
+    }
+
+    /// Marks the consumed messages as processed, so a restarted worker resumes after them
+    fn commit(&self) -> Result<()> {
+        match self {
+            #[cfg(feature = "kafka")]
+            Connection::Kafka { consumer, .. } => {
+                use rdkafka::consumer::{CommitMode, Consumer};
+
+                consumer.commit_consumer_state(CommitMode::Async)
+                    .or_else(|e| match e.rdkafka_error_code() {
+                        // Nothing consumed since the last commit
+                        Some(rdkafka::types::RDKafkaErrorCode::NoOffset) => Ok(()),
+                        _ => Err(e),
+                    })
+                    .map_err(Error::other)?;
+                Ok(())
+            }
+            #[cfg(feature = "nats")]
+            Connection::Nats { .. } => Ok(()),
+        }
+    }
+}
+
+impl Broker {
+    /// Name of the broker, which is also the cargo feature enabling it
+    fn name(&self) -> &'static str {
+        match self {
+            Broker::Kafka { .. } => "kafka",
+            Broker::Nats { .. } => "nats",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_json_and_plain_messages() -> Result<()> {
+        let json = StreamMessage::parse(br#"{"id": "doc-1", "text": "Rust is fast"}"#, Some(b"key"))?;
+        assert_eq!(json, StreamMessage { id: Some("doc-1".to_string()), text: "Rust is fast".to_string() });
+
+        let plain = StreamMessage::parse(b"Rust is fast", Some(b"key"))?;
+        assert_eq!(plain, StreamMessage { id: Some("key".to_string()), text: "Rust is fast".to_string() });
+
+        assert!(StreamMessage::parse(&[0xff, 0xfe], None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use super::incremental::{incremental_embed_with, EmbeddingStore, IncrementalOptions, IncrementalReport};
+use crate::embedding::Embedder;
+use crate::error::{err, Error, Result};
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// A directory kept embedded into an index file as it changes
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    /// Directory to monitor
+    pub dir: PathBuf,
+    /// `EmbeddingStore` file holding the embedded chunks
+    pub index: PathBuf,
+    /// How files are read and split, `recursive` also decides whether subdirectories are monitored
+    pub options: IncrementalOptions,
+    /// How long to wait for a burst of changes, such as an editor saving several files, to settle
+    pub debounce: Duration,
+}
+
+impl WatchConfig {
+    /// Watches a directory with the default options and a 500 ms debounce
+    pub fn new<D: Into<PathBuf>, I: Into<PathBuf>>(dir: D, index: I) -> Self {
+        Self {
+            dir: dir.into(),
+            index: index.into(),
+            options: IncrementalOptions::default(),
+            debounce: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Embeds a directory into its index, then re-embeds it whenever its files change
+///
+/// Each sync runs `incremental_embed_with` over the whole directory, so only new or edited chunks go through
+/// the model and chunks of deleted files leave the index. `on_sync` receives the report of every sync,
+/// the first one included, and the watch ends when it returns false.
+pub fn watch<E: Embedder>(
+    embedder: &E,
+    config: &WatchConfig,
+    mut on_sync: impl FnMut(&IncrementalReport) -> bool,
+) -> Result<()> {
+    if !config.dir.is_dir() {
+        return Err(err!("{} is not a directory", config.dir.display()));
+    }
+
+    // Events are collected before the first sync so changes made during it aren't missed
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(Error::other)?;
+    let mode = if config.options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(&config.dir, mode).map_err(Error::other)?;
+    tracing::info!("Watching {} for changes", config.dir.display());
+
+    let index = config.index.canonicalize().unwrap_or_else(|_| config.index.clone());
+    loop {
+        let mut store = EmbeddingStore::open(&config.index)?;
+        let report = incremental_embed_with(embedder, &config.dir, &mut store, &config.options)?;
+        if !on_sync(&report) {
+            return Ok(());
+        }
+
+        // Wait for a change to anything but the index, then for the burst to settle
+        loop {
+            let event = receiver.recv().map_err(|_| err!("File watcher stopped"))?.map_err(Error::other)?;
+            if event.paths.iter().any(|path| !is_index(path, &index)) {
+                break;
+            }
+        }
+        while receiver.recv_timeout(config.debounce).is_ok() {}
+    }
+}
+
+/// Whether an event path is the index file, which every sync rewrites
+fn is_index(path: &Path, index: &Path) -> bool {
+    path == index || path.canonicalize().map(|path| path == index).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::LengthEmbedder;
+
+    #[test]
+    fn test_reembeds_changed_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust_embed_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("a.txt"), "first note")?;
+        let config = WatchConfig { debounce: Duration::from_millis(50), ..WatchConfig::new(&dir, dir.join("index.jsonl")) };
+
+        let mut reports = Vec::new();
+        watch(&LengthEmbedder::default(), &config, |report| {
+            reports.push((report.embedded, report.unchanged));
+            if reports.len() == 1 {
+                std::fs::write(dir.join("b.txt"), "second note").unwrap();
+            }
+            reports.len() < 2
+        })?;
+        assert_eq!(reports, vec![(1, 0), (1, 1)]);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use crate::embedding::Embedder;
+use crate::search;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use serde::Serialize;
+use std::collections::HashMap;
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// A chunk of text that can be retrieved as context, with the metadata it is cited by
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub text: String,
+    pub embedding: Array1<f32>,
+    /// Where the text came from, such as `source` and `page` or `path` and `start_line`
+    pub metadata: HashMap<String, String>,
+}
+
+/// How a `Retriever` picks context chunks
+#[derive(Debug, Clone)]
+pub struct RetrieverConfig {
+    /// Nearest passages considered for each requested chunk, leaving room for duplicates and oversized chunks
+    pub candidates_per_chunk: usize,
+    /// Passages less similar to the query than this are never returned
+    pub min_score: Option<f32>,
+    /// A passage at least this similar to one already picked is taken for a duplicate and skipped
+    pub duplicate_similarity: f32,
+}
+
+impl Default for RetrieverConfig {
+    fn default() -> Self {
+        Self {
+            candidates_per_chunk: 4,
+            min_score: None,
+            duplicate_similarity: 0.95,
+        }
+    }
+}
+
+/// A context chunk picked for a query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextChunk {
+    /// Position of the passage in the retriever
+    pub index: usize,
+    pub text: String,
+    /// Cosine similarity between the query and the passage
+    pub score: f32,
+    /// Tokens of the text as counted by the embedder's model
+    pub tokens: usize,
+    /// Metadata of the passage, for citing it
+    pub metadata: HashMap<String, String>,
+}
+
+impl ContextChunk {
+    /// Short reference to the chunk's source, such as `report.pdf, page 3` or `src/lib.rs:10-24`
+    ///
+    /// Built from the `source`, `path`, `page`, `start_line`, `end_line`, `headings` and `id` metadata the
+    /// pipelines and `embed --repo` store, falling back to the passage's position.
+    pub fn citation(&self) -> String {
+        let get = |key: &str| self.metadata.get(key).filter(|value| !value.is_empty());
+        let mut citation = match (get("path").or_else(|| get("source")), get("id")) {
+            (Some(source), _) => source.clone(),
+            (None, Some(id)) => id.clone(),
+            (None, None) => format!("#{}", self.index),
+        };
+        match (get("start_line"), get("end_line")) {
+            (Some(start), Some(end)) if start != end => citation.push_str(&format!(":{}-{}", start, end)),
+            (Some(start), _) => citation.push_str(&format!(":{}", start)),
+            _ => {}
+        }
+        if let Some(page) = get("page") {
+            citation.push_str(&format!(", page {}", page));
+        }
+        if let Some(headings) = get("headings") {
+            citation.push_str(&format!(" ({})", headings));
+        }
+        citation
+    }
+}
+
+/// Finds the passages to put in a prompt for a query, within a token budget
+///
+/// Wraps an embedder and the passages it embedded. `retrieve` embeds the query, ranks the passages by
+/// similarity, drops duplicates and fills the budget best first, returning each chunk with its citation.
+pub struct Retriever<E: Embedder> {
+    embedder: E,
+    texts: Vec<String>,
+    embeddings: Vec<Array1<f32>>,
+    metadata: Vec<HashMap<String, String>>,
+    config: RetrieverConfig,
+}
+
+impl<E: Embedder> Retriever<E> {
+    /// Builds a retriever over passages embedded with `embedder`
+    pub fn new(embedder: E, passages: Vec<Passage>) -> Result<Self> {
+        Self::with_config(embedder, passages, RetrieverConfig::default())
+    }
+
+    /// Builds a retriever with custom candidate, score and duplicate settings
+    pub fn with_config(embedder: E, passages: Vec<Passage>, config: RetrieverConfig) -> Result<Self> {
+        if let Some(passage) = passages.iter().find(|passage| passage.embedding.len() != embedder.dimension()) {
+            return Err(err!(
+                "Passage embeddings have {} dimensions but model '{}' produces {}",
+                passage.embedding.len(), embedder.model_name(), embedder.dimension()
+            ));
+        }
+        let mut retriever = Self { embedder, texts: Vec::new(), embeddings: Vec::new(), metadata: Vec::new(), config };
+        for passage in passages {
+            retriever.texts.push(passage.text);
+            retriever.embeddings.push(passage.embedding);
+            retriever.metadata.push(passage.metadata);
+        }
+        Ok(retriever)
+    }
+
+    /// Loads a collection saved with its texts, keeping its ids and metadata for citations
+    ///
+    /// Fails if the collection was embedded with a model other than `embedder`'s.
+    #[cfg(feature = "proto-store")]
+    pub fn from_file<P: AsRef<Path>>(embedder: E, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let (embeddings, texts) = utils::load_embeddings_checked(path, &embedder)?;
+        let texts = texts.ok_or_else(|| err!("{} does not contain texts", path.display()))?;
+        let mut metadata = utils::load_embedding_metadata(path)?;
+        if let Some(ids) = utils::load_embedding_ids(path)? {
+            for (fields, id) in metadata.iter_mut().zip(ids) {
+                fields.insert("id".to_string(), id);
+            }
+        }
+
+        let passages = texts.into_iter()
+            .zip(embeddings)
+            .zip(metadata)
+            .map(|((text, embedding), metadata)| Passage { text, embedding, metadata })
+            .collect();
+        Self::new(embedder, passages)
+    }
+
+    /// Builds a retriever over the chunks of an incremental embedding store, cited by source, page and headings
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_store(embedder: E, store: &crate::pipeline::EmbeddingStore) -> Result<Self> {
+        let passages = store.chunks().iter()
+            .map(|chunk| {
+                let mut metadata = HashMap::from([
+                    ("source".to_string(), chunk.source.clone()),
+                    ("chunk".to_string(), chunk.chunk.to_string()),
+                ]);
+                if let Some(page) = chunk.page {
+                    metadata.insert("page".to_string(), page.to_string());
+                }
+                if !chunk.headings.is_empty() {
+                    metadata.insert("headings".to_string(), chunk.headings.join(" > "));
+                }
+                Passage { text: chunk.text.clone(), embedding: Array1::from(chunk.embedding.clone()), metadata }
+            })
+            .collect();
+        Self::new(embedder, passages)
+    }
+
+    /// The wrapped embedder
+    pub fn embedder(&self) -> &E {
+        &self.embedder
+    }
+
+    /// Number of passages chunks are retrieved from
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    /// Returns true if there are no passages to retrieve
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Returns up to `k` distinct passages for a query, most similar first, holding at most `max_tokens` tokens together
+    ///
+    /// Passages with the same text as, or nearly the same embedding as, a better one are skipped. A passage too
+    /// long for what is left of the budget is passed over for shorter ones further down the ranking.
+    pub fn retrieve(&self, query: &str, k: usize, max_tokens: usize) -> Result<Vec<ContextChunk>> {
+        let query_embedding = self.embedder.embed_text(query)?;
+        let candidates = search::top_k(&query_embedding, &self.embeddings, k.saturating_mul(self.config.candidates_per_chunk.max(1)));
+
+        let mut picked: Vec<ContextChunk> = Vec::new();
+        let mut budget = max_tokens;
+        for hit in candidates {
+            if picked.len() == k || self.config.min_score.is_some_and(|min| hit.score < min) {
+                break;
+            }
+            let text = &self.texts[hit.index];
+            let duplicate = picked.iter().any(|chosen| {
+                chosen.text.trim() == text.trim()
+                    || utils::cosine_similarity(&self.embeddings[chosen.index], &self.embeddings[hit.index])
+                        >= self.config.duplicate_similarity
+            });
+            if duplicate {
+                continue;
+            }
+            let tokens = self.embedder.count_tokens(text)?;
+            if tokens > budget {
+                continue;
+            }
+            budget -= tokens;
+            picked.push(ContextChunk {
+                index: hit.index,
+                text: text.clone(),
+                score: hit.score,
+                tokens,
+                metadata: self.metadata[hit.index].clone(),
+            });
+        }
+        Ok(picked)
+    }
+}
+
+/// Formats retrieved chunks as numbered, cited blocks for a prompt
+///
+/// Each block starts with `[n] citation` on its own line, so an answer can refer to its sources by number.
+pub fn format_context(chunks: &[ContextChunk]) -> String {
+    chunks.iter()
+        .enumerate()
+        .map(|(i, chunk)| format!("[{}] {}\n{}", i + 1, chunk.citation(), chunk.text.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TopicEmbedder;
+
+    fn passage(text: &str, source: &str) -> Passage {
+        Passage {
+            text: text.to_string(),
+            embedding: TopicEmbedder.embed_text(text).unwrap(),
+            metadata: HashMap::from([("source".to_string(), source.to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_retrieve_skips_duplicates_and_keeps_to_budget() -> Result<()> {
+        let passages = vec![
+            passage("Rust has ownership", "a.md"),
+            passage("Rust has ownership", "copy.md"),
+            passage("Rust and rust: a very long chapter on the borrow checker with one python aside", "b.md"),
+            passage("Rust with a little python", "c.md"),
+            passage("Baking bread", "d.md"),
+        ];
+        let retriever = Retriever::new(TopicEmbedder, passages)?;
+
+        let chunks = retriever.retrieve("rust", 3, 16)?;
+        let sources: Vec<&str> = chunks.iter().map(|chunk| chunk.metadata["source"].as_str()).collect();
+        assert_eq!(sources, vec!["a.md", "c.md"]);
+        assert!(chunks.iter().map(|chunk| chunk.tokens).sum::<usize>() <= 16);
+        assert!(format_context(&chunks).starts_with("[1] a.md\nRust has ownership\n\n[2] c.md\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_citation_uses_path_and_lines() {
+        let chunk = ContextChunk {
+            index: 4,
+            text: "fn main() {}".to_string(),
+            score: 1.0,
+            tokens: 3,
+            metadata: HashMap::from([
+                ("path".to_string(), "src/main.rs".to_string()),
+                ("start_line".to_string(), "10".to_string()),
+                ("end_line".to_string(), "24".to_string()),
+            ]),
+        };
+        assert_eq!(chunk.citation(), "src/main.rs:10-24");
+        assert_eq!(ContextChunk { metadata: HashMap::new(), ..chunk }.citation(), "#4");
+    }
+}
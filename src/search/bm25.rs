@@ -0,0 +1,113 @@
+use super::{sort_hits, SearchHit};
+use std::collections::HashMap;
+
+/// Default term-frequency saturation parameter
+pub const DEFAULT_K1: f32 = 1.2;
+
+/// Default document-length normalization parameter
+pub const DEFAULT_B: f32 = 0.75;
+
+/// A small in-memory inverted index scoring documents with Okapi BM25
+#[derive(Debug, Clone)]
+pub struct Bm25Index {
+    k1: f32,
+    b: f32,
+    /// Term → list of (document, term frequency)
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    doc_lengths: Vec<usize>,
+    avg_doc_length: f32,
+}
+
+impl Bm25Index {
+    /// Builds an index over the texts with the default BM25 parameters
+    pub fn new(texts: &[String]) -> Self {
+        Self::with_params(texts, DEFAULT_K1, DEFAULT_B)
+    }
+
+    /// Builds an index over the texts with custom `k1` and `b` parameters
+    pub fn with_params(texts: &[String], k1: f32, b: f32) -> Self {
+        let mut postings: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        let mut doc_lengths = Vec::with_capacity(texts.len());
+
+        for (doc, text) in texts.iter().enumerate() {
+            let tokens = tokenize(text);
+            doc_lengths.push(tokens.len());
+
+            let mut frequencies: HashMap<String, u32> = HashMap::new();
+            for token in tokens {
+                *frequencies.entry(token).or_insert(0) += 1;
+            }
+            for (term, frequency) in frequencies {
+                postings.entry(term).or_default().push((doc, frequency));
+            }
+        }
+
+        let total: usize = doc_lengths.iter().sum();
+        let avg_doc_length = if doc_lengths.is_empty() {
+            0.0
+        } else {
+            total as f32 / doc_lengths.len() as f32
+        };
+
+        Self {
+            k1,
+            b,
+            postings,
+            doc_lengths,
+            avg_doc_length,
+        }
+    }
+
+    /// Number of indexed documents
+    pub fn len(&self) -> usize {
+        self.doc_lengths.len()
+    }
+
+    /// Returns true if no documents are indexed
+    pub fn is_empty(&self) -> bool {
+        self.doc_lengths.is_empty()
+    }
+
+    /// Inverse document frequency of a term
+    pub fn idf(&self, term: &str) -> f32 {
+        let n = self.len() as f32;
+        let df = self.postings.get(term).map(|p| p.len()).unwrap_or(0) as f32;
+        ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+
+    /// Scores every document containing a query term, returning the `k` best
+    pub fn search(&self, query: &str, k: usize) -> Vec<SearchHit> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+
+        for term in tokenize(query) {
+            let Some(postings) = self.postings.get(&term) else { continue };
+            let idf = self.idf(&term);
+
+            for &(doc, frequency) in postings {
+                let frequency = frequency as f32;
+                let length_ratio = if self.avg_doc_length > 0.0 {
+                    self.doc_lengths[doc] as f32 / self.avg_doc_length
+                } else {
+                    1.0
+                };
+                let denominator = frequency + self.k1 * (1.0 - self.b + self.b * length_ratio);
+                *scores.entry(doc).or_insert(0.0) += idf * frequency * (self.k1 + 1.0) / denominator;
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores.into_iter()
+            .map(|(index, score)| SearchHit { index, score })
+            .collect();
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+}
+
+/// Splits text into lowercase alphanumeric terms
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
@@ -0,0 +1,176 @@
+use super::{sort_hits, SearchHit};
+#[cfg(feature = "torch")]
+use crate::models::mini_lm::MiniLMEmbedder;
+use crate::error::{err, Result};
+use ndarray::{Array2, Axis};
+
+/// Tokens that carry no content and are dropped from stored representations
+const SPECIAL_TOKENS: &[&str] = &["[CLS]", "[SEP]", "[PAD]"];
+
+/// A text represented by one normalized vector per token
+#[derive(Debug, Clone)]
+pub struct MultiVector {
+    pub tokens: Vec<String>,
+    pub vectors: Array2<f32>,
+}
+
+impl MultiVector {
+    /// Creates a representation from token vectors, normalizing each row and dropping special tokens
+    pub fn new(tokens: Vec<String>, vectors: Array2<f32>) -> Result<Self> {
+        if tokens.len() != vectors.nrows() {
+            return Err(err!("Got {} tokens but {} vectors", tokens.len(), vectors.nrows()));
+        }
+
+        let keep: Vec<usize> = tokens.iter()
+            .enumerate()
+            .filter(|(_, token)| !SPECIAL_TOKENS.contains(&token.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut vectors = vectors.select(Axis(0), &keep);
+        for mut row in vectors.rows_mut() {
+            let norm = row.dot(&row).sqrt();
+            if norm > 0.0 {
+                row.mapv_inplace(|x| x / norm);
+            }
+        }
+
+        let tokens = keep.into_iter().map(|i| tokens[i].clone()).collect();
+        Ok(Self { tokens, vectors })
+    }
+
+    /// Embeds a text token by token
+    #[cfg(feature = "torch")]
+    pub fn embed(embedder: &mut MiniLMEmbedder, text: &str) -> Result<Self> {
+        let (tokens, vectors) = embedder.embed_tokens(text)?;
+        Self::new(tokens, vectors)
+    }
+
+    /// Number of token vectors
+    pub fn len(&self) -> usize {
+        self.vectors.nrows()
+    }
+
+    /// Returns true if no token vectors remain
+    pub fn is_empty(&self) -> bool {
+        self.vectors.nrows() == 0
+    }
+}
+
+/// Late-interaction score: each query token's best cosine match in the document, summed
+pub fn max_sim(query: &MultiVector, document: &MultiVector) -> f32 {
+    if query.is_empty() || document.is_empty() || query.vectors.ncols() != document.vectors.ncols() {
+        return 0.0;
+    }
+
+    query.vectors.dot(&document.vectors.t())
+        .rows()
+        .into_iter()
+        .map(|similarities| similarities.fold(f32::NEG_INFINITY, |best, &s| best.max(s)))
+        .sum()
+}
+
+/// Stores multi-vector representations of texts and ranks them with MaxSim
+#[derive(Debug, Clone, Default)]
+pub struct ColbertIndex {
+    texts: Vec<String>,
+    documents: Vec<MultiVector>,
+}
+
+impl ColbertIndex {
+    /// Creates an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Embeds and indexes texts token by token
+    #[cfg(feature = "torch")]
+    pub fn from_texts(embedder: &mut MiniLMEmbedder, texts: &[String]) -> Result<Self> {
+        let mut index = Self::new();
+        for text in texts {
+            index.add(text.clone(), MultiVector::embed(embedder, text)?);
+        }
+        Ok(index)
+    }
+
+    /// Adds a text with its token vectors, returning its position in the index
+    pub fn add(&mut self, text: String, document: MultiVector) -> usize {
+        self.texts.push(text);
+        self.documents.push(document);
+        self.documents.len() - 1
+    }
+
+    /// Number of indexed texts
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns true if nothing is indexed
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Text stored at a position in the index
+    pub fn text(&self, index: usize) -> Option<&str> {
+        self.texts.get(index).map(String::as_str)
+    }
+
+    /// Scores every indexed text against the query and returns the best `k`
+    pub fn search(&self, query: &MultiVector, k: usize) -> Vec<SearchHit> {
+        let candidates: Vec<usize> = (0..self.documents.len()).collect();
+        self.rerank(query, &candidates, k)
+    }
+
+    /// Re-scores candidates from a cheaper first-stage search (e.g. single-vector top-k) with MaxSim
+    pub fn rerank(&self, query: &MultiVector, candidates: &[usize], k: usize) -> Vec<SearchHit> {
+        let mut hits: Vec<SearchHit> = candidates.iter()
+            .filter_map(|&index| self.documents.get(index).map(|document| SearchHit {
+                index,
+                score: max_sim(query, document),
+            }))
+            .collect();
+
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+
+    /// Embeds a query token by token and searches the index
+    #[cfg(feature = "torch")]
+    pub fn search_text(&self, embedder: &mut MiniLMEmbedder, query: &str, k: usize) -> Result<Vec<SearchHit>> {
+        let query = MultiVector::embed(embedder, query)?;
+        Ok(self.search(&query, k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn tokens(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_max_sim_matches_each_query_token() -> Result<()> {
+        let query = MultiVector::new(tokens(&["[CLS]", "rust", "fast", "[SEP]"]), array![
+            [1.0, 1.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 1.0],
+        ])?;
+        assert_eq!(query.tokens, tokens(&["rust", "fast"]));
+
+        let both = MultiVector::new(tokens(&["fast", "rust"]), array![[0.0, 2.0, 0.0], [3.0, 0.0, 0.0]])?;
+        let one = MultiVector::new(tokens(&["rust", "slow"]), array![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]])?;
+
+        assert!((max_sim(&query, &both) - 2.0).abs() < 1e-6);
+        assert!((max_sim(&query, &one) - 1.0).abs() < 1e-6);
+
+        let mut index = ColbertIndex::new();
+        index.add("one".to_string(), one);
+        index.add("both".to_string(), both);
+        let hits = index.search(&query, 1);
+        assert_eq!(hits[0].index, 1);
+
+        Ok(())
+    }
+}
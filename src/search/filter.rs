@@ -0,0 +1,253 @@
+use crate::error::{err, Error, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A condition on the metadata stored with an embedding, such as `metadata.lang == "en" && metadata.year >= 2022`
+///
+/// Fields are named `metadata.NAME` or just `NAME`. They are compared with quoted strings, numbers or
+/// `true`/`false` using `==`, `!=`, `<`, `<=`, `>` and `>=`, and conditions combine with `&&`, `||`, `!` and
+/// parentheses. Comparisons with a number are numeric and fail on values that aren't numbers, other values compare
+/// as text. A field the embedding doesn't have fails every comparison, `!=` included.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Compare { field: String, op: CompareOp, value: Literal },
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// How a field is compared with a literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A value written in a filter
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl Filter {
+    /// Parses a filter expression
+    pub fn parse(expression: &str) -> Result<Self> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(filter),
+            Some(token) => Err(err!("Unexpected {} in filter '{}'", token, expression)),
+        }
+    }
+
+    /// Whether an embedding's metadata satisfies the filter
+    pub fn matches(&self, metadata: &HashMap<String, String>) -> bool {
+        match self {
+            Filter::Compare { field, op, value } => metadata.get(field).is_some_and(|stored| op.holds(compare(stored, value))),
+            Filter::And(left, right) => left.matches(metadata) && right.matches(metadata),
+            Filter::Or(left, right) => left.matches(metadata) || right.matches(metadata),
+            Filter::Not(inner) => !inner.matches(metadata),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        Self::parse(expression)
+    }
+}
+
+impl CompareOp {
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match (self, ordering) {
+            (CompareOp::Ne, Some(ordering)) => ordering != Ordering::Equal,
+            (_, None) => false,
+            (CompareOp::Eq, Some(ordering)) => ordering == Ordering::Equal,
+            (CompareOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (CompareOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (CompareOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (CompareOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Orders a stored value against a literal, None when a number is compared with text
+fn compare(stored: &str, value: &Literal) -> Option<Ordering> {
+    match value {
+        Literal::Number(number) => stored.trim().parse::<f64>().ok()?.partial_cmp(number),
+        Literal::Bool(flag) => Some(stored.trim().cmp(if *flag { "true" } else { "false" })),
+        Literal::Text(text) => Some(stored.cmp(text.as_str())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Field(String),
+    Literal(Literal),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Field(name) => write!(f, "field '{}'", name),
+            Token::Literal(_) => write!(f, "value"),
+            Token::Op(_) => write!(f, "comparison"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+/// Splits an expression into tokens
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '-');
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut followed_by = |expected: char| chars.next_if(|&(_, c)| c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if followed_by('&') => Token::And,
+            '|' if followed_by('|') => Token::Or,
+            '=' if followed_by('=') => Token::Op(CompareOp::Eq),
+            '!' if followed_by('=') => Token::Op(CompareOp::Ne),
+            '!' => Token::Not,
+            '<' if followed_by('=') => Token::Op(CompareOp::Le),
+            '<' => Token::Op(CompareOp::Lt),
+            '>' if followed_by('=') => Token::Op(CompareOp::Ge),
+            '>' => Token::Op(CompareOp::Gt),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => text.extend(chars.next().map(|(_, escaped)| escaped)),
+                        Some((_, quote)) if quote == c => break,
+                        Some((_, other)) => text.push(other),
+                        None => return Err(err!("Unterminated string starting at {} in filter '{}'", start, expression)),
+                    }
+                }
+                Token::Literal(Literal::Text(text))
+            }
+            c if is_word(c) => {
+                let mut word = c.to_string();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| is_word(c)) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "true" => Token::Literal(Literal::Bool(true)),
+                    "false" => Token::Literal(Literal::Bool(false)),
+                    _ => match word.parse::<f64>() {
+                        Ok(number) => Token::Literal(Literal::Number(number)),
+                        Err(_) => Token::Field(word.strip_prefix("metadata.").unwrap_or(&word).to_string()),
+                    },
+                }
+            }
+            _ => return Err(err!("Unexpected '{}' at {} in filter '{}'", c, start, expression)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, `||` binding loosest and `!` tightest
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(expected);
+        self.position += usize::from(found);
+        found
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut filter = self.and()?;
+        while self.eat(&Token::Or) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filter = self.unary()?;
+        while self.eat(&Token::And) {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter> {
+        match self.next().cloned() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(err!("Missing ')' in filter"));
+                }
+                Ok(filter)
+            }
+            Some(Token::Field(field)) => match (self.next().cloned(), self.next().cloned()) {
+                (Some(Token::Op(op)), Some(Token::Literal(value))) => Ok(Filter::Compare { field, op, value }),
+                _ => Err(err!("Expected a comparison with a value after field '{}'", field)),
+            },
+            Some(token) => Err(err!("Expected a field, '!' or '(' but found {}", token)),
+            None => Err(err!("Filter ends where a condition was expected")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_and_evaluates_expressions() -> Result<()> {
+        let metadata = HashMap::from([
+            ("lang".to_string(), "en".to_string()),
+            ("year".to_string(), "2023".to_string()),
+            ("draft".to_string(), "false".to_string()),
+        ]);
+
+        let filter: Filter = r#"metadata.lang == "en" && metadata.year >= 2022"#.parse()?;
+        assert!(filter.matches(&metadata));
+        assert!(!Filter::parse("year < 2022.5 || (lang != 'en' && !draft == true)")?.matches(&metadata));
+        assert!(Filter::parse("!(draft == true) && year > 999")?.matches(&metadata));
+        // Missing fields fail every comparison
+        assert!(!Filter::parse("author != 'x'")?.matches(&metadata));
+
+        assert!(Filter::parse("lang ==").is_err());
+        assert!(Filter::parse("(lang == 'en'").is_err());
+        assert!(Filter::parse("lang == 'en' year").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use super::bm25::Bm25Index;
+use super::{sort_hits, top_k, SearchHit};
+use crate::embedding::Embedder;
+#[cfg(feature = "proto-store")]
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use std::collections::HashMap;
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// Settings for combining lexical and dense rankings
+#[derive(Debug, Clone)]
+pub struct HybridConfig {
+    /// Rank offset of reciprocal rank fusion, higher values flatten the contribution of top ranks
+    pub rrf_k: f32,
+
+    /// Weight of the dense (embedding) ranking
+    pub dense_weight: f32,
+
+    /// Weight of the lexical (BM25) ranking
+    pub lexical_weight: f32,
+
+    /// Number of candidates taken from each ranking before fusion
+    pub candidates: usize,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            rrf_k: 60.0,
+            dense_weight: 1.0,
+            lexical_weight: 1.0,
+            candidates: 100,
+        }
+    }
+}
+
+/// A fused search result
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridHit {
+    /// Position of the match in the index
+    pub index: usize,
+
+    /// Fused reciprocal rank score
+    pub score: f32,
+
+    /// Rank in the dense results (0-based), if the document was a dense candidate
+    pub dense_rank: Option<usize>,
+
+    /// Rank in the BM25 results (0-based), if the document was a lexical candidate
+    pub lexical_rank: Option<usize>,
+}
+
+/// Searches stored texts with both BM25 and embedding similarity
+#[derive(Debug, Clone)]
+pub struct HybridIndex {
+    texts: Vec<String>,
+    embeddings: Vec<Array1<f32>>,
+    bm25: Bm25Index,
+    config: HybridConfig,
+}
+
+impl HybridIndex {
+    /// Builds an index over texts and their embeddings
+    pub fn new(texts: Vec<String>, embeddings: Vec<Array1<f32>>) -> Result<Self> {
+        Self::with_config(texts, embeddings, HybridConfig::default())
+    }
+
+    /// Builds an index with custom fusion settings
+    pub fn with_config(texts: Vec<String>, embeddings: Vec<Array1<f32>>, config: HybridConfig) -> Result<Self> {
+        if texts.len() != embeddings.len() {
+            return Err(err!(
+                "Got {} texts but {} embeddings", texts.len(), embeddings.len()
+            ));
+        }
+
+        let bm25 = Bm25Index::new(&texts);
+
+        Ok(Self {
+            texts,
+            embeddings,
+            bm25,
+            config,
+        })
+    }
+
+    /// Builds an index from a saved embedding collection, which must include its texts
+    #[cfg(feature = "proto-store")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (embeddings, texts) = utils::load_embeddings(path)?;
+        let texts = texts.ok_or_else(|| err!("Hybrid search requires stored texts"))?;
+        Self::new(texts, embeddings)
+    }
+
+    /// The text stored at an index position
+    pub fn text(&self, index: usize) -> Option<&str> {
+        self.texts.get(index).map(String::as_str)
+    }
+
+    /// Number of indexed documents
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    /// Returns true if no documents are indexed
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Searches with a query text and its precomputed embedding
+    pub fn search(&self, query: &str, query_embedding: &Array1<f32>, k: usize) -> Vec<HybridHit> {
+        let dense = top_k(query_embedding, &self.embeddings, self.config.candidates);
+        let lexical = self.bm25.search(query, self.config.candidates);
+
+        let fused = reciprocal_rank_fusion(
+            &[(&dense, self.config.dense_weight), (&lexical, self.config.lexical_weight)],
+            self.config.rrf_k,
+        );
+
+        let dense_ranks = ranks(&dense);
+        let lexical_ranks = ranks(&lexical);
+
+        fused.into_iter()
+            .take(k)
+            .map(|hit| HybridHit {
+                index: hit.index,
+                score: hit.score,
+                dense_rank: dense_ranks.get(&hit.index).copied(),
+                lexical_rank: lexical_ranks.get(&hit.index).copied(),
+            })
+            .collect()
+    }
+
+    /// Embeds the query and searches
+    pub fn search_with<E: Embedder>(&self, embedder: &E, query: &str, k: usize) -> Result<Vec<HybridHit>> {
+        let query_embedding = embedder.embed_text(query)?;
+        Ok(self.search(query, &query_embedding, k))
+    }
+}
+
+/// Combines several weighted rankings with reciprocal rank fusion
+///
+/// Each document scores `weight / (rrf_k + rank)` per ranking it appears in, with 1-based ranks.
+pub fn reciprocal_rank_fusion(rankings: &[(&[SearchHit], f32)], rrf_k: f32) -> Vec<SearchHit> {
+    let mut scores: HashMap<usize, f32> = HashMap::new();
+
+    for (ranking, weight) in rankings {
+        for (rank, hit) in ranking.iter().enumerate() {
+            *scores.entry(hit.index).or_insert(0.0) += weight / (rrf_k + rank as f32 + 1.0);
+        }
+    }
+
+    let mut fused: Vec<SearchHit> = scores.into_iter()
+        .map(|(index, score)| SearchHit { index, score })
+        .collect();
+    sort_hits(&mut fused);
+    fused
+}
+
+/// Maps document indices to their position in a ranking
+fn ranks(hits: &[SearchHit]) -> HashMap<usize, usize> {
+    hits.iter()
+        .enumerate()
+        .map(|(rank, hit)| (hit.index, rank))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_bm25_prefers_exact_keyword() {
+        let texts = vec![
+            "the cat sat on the mat".to_string(),
+            "error code E1234 in the payment service".to_string(),
+            "dogs and cats are pets".to_string(),
+        ];
+        let index = Bm25Index::new(&texts);
+
+        let hits = index.search("E1234", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].index, 1);
+    }
+
+    #[test]
+    fn test_fusion_rewards_agreement() -> Result<()> {
+        let texts = vec![
+            "rust embedding library".to_string(),
+            "python machine learning".to_string(),
+            "rust compiler errors".to_string(),
+        ];
+        let embeddings = vec![array![1.0, 0.0], array![0.0, 1.0], array![0.6, 0.8]];
+        let index = HybridIndex::new(texts, embeddings)?;
+
+        let hits = index.search("rust embedding", &array![0.9, 0.1], 3);
+
+        assert_eq!(hits[0].index, 0);
+        assert_eq!(hits[0].dense_rank, Some(0));
+        assert_eq!(hits[0].lexical_rank, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_mismatched_lengths() {
+        let result = HybridIndex::new(vec!["a".to_string()], vec![]);
+        assert!(result.is_err());
+    }
+}
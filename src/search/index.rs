@@ -0,0 +1,249 @@
+use super::{sort_hits, Filter, SearchHit};
+#[cfg(feature = "proto-store")]
+use crate::collection::{StoredCollection, StoredEmbedding};
+#[cfg(feature = "proto-store")]
+use crate::embedding::ModelInfo;
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use std::collections::HashMap;
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// A match found in a `VectorIndex`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexHit<'a> {
+    pub id: &'a str,
+
+    /// Cosine similarity between the query and the match
+    pub score: f32,
+
+    pub metadata: &'a HashMap<String, String>,
+}
+
+/// A vector stored under its id, marked deleted until the index is compacted
+#[derive(Debug, Clone)]
+struct Entry {
+    id: String,
+    vector: Array1<f32>,
+    metadata: HashMap<String, String>,
+    deleted: bool,
+}
+
+/// Searchable vectors keyed by id, updated in place as documents change
+///
+/// `upsert` replaces a document's vector where it is stored, and `delete` only marks it as a tombstone, which
+/// searches skip. Once tombstones make up more than `compaction_threshold` of the stored vectors, the next
+/// deletion compacts them away, so a long-lived index never needs rebuilding from scratch.
+#[derive(Debug, Clone)]
+pub struct VectorIndex {
+    entries: Vec<Entry>,
+    positions: HashMap<String, usize>,
+    tombstones: usize,
+    dimension: Option<usize>,
+    compaction_threshold: f32,
+}
+
+impl Default for VectorIndex {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            positions: HashMap::new(),
+            tombstones: 0,
+            dimension: None,
+            compaction_threshold: 0.25,
+        }
+    }
+}
+
+impl VectorIndex {
+    /// An empty index, compacting once a quarter of its vectors are deleted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compacts once this share of the stored vectors are tombstones, 1.0 or more leaves it to `compact`
+    pub fn with_compaction_threshold(mut self, threshold: f32) -> Self {
+        self.compaction_threshold = threshold;
+        self
+    }
+
+    /// Inserts a vector, or replaces the vector and metadata stored under `id`
+    ///
+    /// Every vector must have the dimension of the first one inserted.
+    pub fn upsert(&mut self, id: impl Into<String>, vector: Array1<f32>, metadata: HashMap<String, String>) -> Result<()> {
+        let dimension = *self.dimension.get_or_insert(vector.len());
+        if vector.len() != dimension {
+            return Err(err!("Index holds {}-dimensional vectors, got {} dimensions", dimension, vector.len()));
+        }
+
+        let id = id.into();
+        match self.positions.get(&id) {
+            Some(&position) => {
+                let entry = &mut self.entries[position];
+                entry.vector = vector;
+                entry.metadata = metadata;
+            }
+            None => {
+                self.positions.insert(id.clone(), self.entries.len());
+                self.entries.push(Entry { id, vector, metadata, deleted: false });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the vector stored under `id`, returning false when there is none
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(position) = self.positions.remove(id) else {
+            return false;
+        };
+        self.entries[position].deleted = true;
+        self.tombstones += 1;
+
+        if self.tombstones as f32 > self.compaction_threshold * self.entries.len() as f32 {
+            self.compact();
+        }
+        true
+    }
+
+    /// Drops the deleted vectors from storage
+    pub fn compact(&mut self) {
+        if self.tombstones == 0 {
+            return;
+        }
+        tracing::debug!("Compacting {} deleted vector(s) out of {}", self.tombstones, self.entries.len());
+        self.entries.retain(|entry| !entry.deleted);
+        self.positions = self.entries.iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.id.clone(), position))
+            .collect();
+        self.tombstones = 0;
+    }
+
+    /// The vector and metadata stored under `id`
+    pub fn get(&self, id: &str) -> Option<(&Array1<f32>, &HashMap<String, String>)> {
+        self.positions.get(id).map(|&position| {
+            let entry = &self.entries[position];
+            (&entry.vector, &entry.metadata)
+        })
+    }
+
+    /// Number of vectors, not counting deleted ones
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns true if no vectors are stored
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Number of deleted vectors still taking up space until the next compaction
+    pub fn tombstones(&self) -> usize {
+        self.tombstones
+    }
+
+    /// Dimension of the stored vectors, None until the first is inserted
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    /// Ids, vectors and metadata of the vectors that aren't deleted, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Array1<f32>, &HashMap<String, String>)> {
+        self.entries.iter()
+            .filter(|entry| !entry.deleted)
+            .map(|entry| (entry.id.as_str(), &entry.vector, &entry.metadata))
+    }
+
+    /// Returns the `k` vectors most similar to the query, best first
+    pub fn search(&self, query: &Array1<f32>, k: usize) -> Vec<IndexHit<'_>> {
+        self.search_where(query, k, |_| true)
+    }
+
+    /// Returns the `k` vectors most similar to the query among those whose metadata matches `filter`, best first
+    pub fn search_filtered(&self, query: &Array1<f32>, k: usize, filter: &Filter) -> Vec<IndexHit<'_>> {
+        self.search_where(query, k, |metadata| filter.matches(metadata))
+    }
+
+    fn search_where(&self, query: &Array1<f32>, k: usize, keep: impl Fn(&HashMap<String, String>) -> bool) -> Vec<IndexHit<'_>> {
+        let mut hits: Vec<SearchHit> = self.entries.iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.deleted && entry.vector.len() == query.len() && keep(&entry.metadata))
+            .map(|(index, entry)| SearchHit { index, score: utils::cosine_similarity(query, &entry.vector) })
+            .collect();
+        sort_hits(&mut hits);
+
+        hits.into_iter()
+            .take(k)
+            .map(|hit| {
+                let entry = &self.entries[hit.index];
+                IndexHit { id: &entry.id, score: hit.score, metadata: &entry.metadata }
+            })
+            .collect()
+    }
+
+    /// Loads an index saved with `save`, or any collection whose embeddings have ids
+    ///
+    /// Embeddings without an id are keyed by their position, and a later embedding with the same id replaces
+    /// an earlier one. Namespaces are ignored, `VectorStore::load` keeps them apart.
+    #[cfg(feature = "proto-store")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let collection = StoredCollection::load(path)?;
+        let mut index = Self::new();
+        for (position, embedding) in collection.embeddings.into_iter().enumerate() {
+            let id = embedding.id.clone().unwrap_or_else(|| position.to_string());
+            let vector = embedding.dense_vector().cloned().ok_or_else(|| err!("Embedding {} is sparse", id))?;
+            index.upsert(id, vector, embedding.metadata)?;
+        }
+        Ok(index)
+    }
+
+    /// Saves the vectors that aren't deleted, with their ids and metadata, as made by the model of `info`
+    #[cfg(feature = "proto-store")]
+    pub fn save(&self, path: impl AsRef<Path>, info: &ModelInfo) -> Result<()> {
+        let mut collection = StoredCollection::for_model(info);
+        collection.embeddings = self.iter()
+            .map(|(id, vector, metadata)| StoredEmbedding {
+                id: Some(id.to_string()),
+                metadata: metadata.clone(),
+                ..StoredEmbedding::dense(vector.clone(), None)
+            })
+            .collect();
+        collection.save(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upserts_deletes_and_compacts() -> Result<()> {
+        let mut index = VectorIndex::new().with_compaction_threshold(0.5);
+        for (id, vector) in [("a", [1.0, 0.0]), ("b", [0.0, 1.0]), ("c", [0.7, 0.7])] {
+            index.upsert(id, Array1::from(vector.to_vec()), HashMap::new())?;
+        }
+        index.upsert("b", Array1::from(vec![1.0, 0.1]), HashMap::from([("version".to_string(), "2".to_string())]))?;
+        assert_eq!(index.len(), 3);
+        assert!(index.upsert("d", Array1::from(vec![1.0]), HashMap::new()).is_err());
+
+        let query = Array1::from(vec![1.0, 0.0]);
+        let ids: Vec<&str> = index.search(&query, 2).iter().map(|hit| hit.id).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(index.search(&query, 2)[1].metadata["version"], "2");
+        let filtered = index.search_filtered(&query, 2, &Filter::parse("metadata.version >= 2")?);
+        assert_eq!(filtered.iter().map(|hit| hit.id).collect::<Vec<_>>(), ["b"]);
+
+        assert!(index.delete("a"));
+        assert!(!index.delete("a"));
+        assert_eq!((index.len(), index.tombstones()), (2, 1));
+        assert_eq!(index.search(&query, 1)[0].id, "b");
+
+        // A second deletion crosses half of the three stored vectors and compacts
+        assert!(index.delete("c"));
+        assert_eq!((index.len(), index.tombstones()), (1, 0));
+        assert!(index.get("b").is_some() && index.get("c").is_none());
+        Ok(())
+    }
+}
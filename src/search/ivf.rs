@@ -0,0 +1,286 @@
+use super::pq::{PqConfig, ProductQuantizer};
+use super::{sort_hits, SearchHit};
+use crate::analysis::kmeans::{KMeans, KMeansOptions};
+#[cfg(feature = "proto-store")]
+use crate::collection::{shard, ShardedCollection, StoredVector};
+use crate::utils::{self, simd};
+use crate::error::{err, Result};
+use ndarray::Array1;
+use rand::rngs::StdRng;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use rand::SeedableRng;
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// Settings of an IVF index
+#[derive(Debug, Clone)]
+pub struct IvfConfig {
+    /// Number of inverted lists, that is k-means clusters, around the square root of the vector count works well
+    pub nlist: usize,
+
+    /// Lists searched per query, higher finds more true neighbors at the cost of speed
+    pub nprobe: usize,
+
+    /// Most vectors the clusters are trained on, a random sample is drawn from larger inputs
+    pub training_sample: usize,
+
+    /// Lloyd iterations when training the clusters
+    pub max_iterations: usize,
+
+    /// Seed for the training sample and k-means++ initialization
+    pub seed: u64,
+
+    /// Compresses the stored vectors with product quantization, None keeps them exact
+    pub pq: Option<PqConfig>,
+}
+
+impl Default for IvfConfig {
+    fn default() -> Self {
+        Self {
+            nlist: 1024,
+            nprobe: 16,
+            training_sample: 50_000,
+            max_iterations: 20,
+            seed: 42,
+            pq: None,
+        }
+    }
+}
+
+/// Vectors assigned to one cluster, stored back to back to avoid a heap allocation per vector
+#[derive(Debug, Clone, Default)]
+struct InvertedList {
+    positions: Vec<usize>,
+    /// Exact vectors, empty when the index quantizes
+    vectors: Vec<f32>,
+    /// Product quantization codes, empty when the index stores exact vectors
+    codes: Vec<u8>,
+}
+
+/// Approximate nearest neighbor index that clusters vectors with k-means and searches only the nearest clusters
+///
+/// Vectors are stored normalized, in one flat buffer per cluster, so the index takes little more than the
+/// vectors themselves. A query is compared with the `nlist` centroids and then only with the vectors of the
+/// `nprobe` closest clusters, which trades a little recall for searching a fraction of the collection. With
+/// product quantization, each vector is kept only as its code, a few dozen bytes.
+#[derive(Debug, Clone)]
+pub struct IvfIndex {
+    dimension: usize,
+    centroids: Vec<Array1<f32>>,
+    quantizer: Option<ProductQuantizer>,
+    lists: Vec<InvertedList>,
+    nprobe: usize,
+    len: usize,
+}
+
+impl IvfIndex {
+    /// Trains the clusters on vectors and adds them all, identified by their positions in `vectors`
+    pub fn build(vectors: &[Array1<f32>], config: &IvfConfig) -> Result<Self> {
+        let mut index = Self::train(vectors, config)?;
+        for vector in vectors {
+            index.add(vector)?;
+        }
+        Ok(index)
+    }
+
+    /// Trains the clusters, and the quantizer if there is one, on a sample of vectors, leaving the index empty for `add`
+    pub fn train(vectors: &[Array1<f32>], config: &IvfConfig) -> Result<Self> {
+        if vectors.is_empty() {
+            return Err(err!("Cannot train an IVF index without vectors"));
+        }
+        let dimension = vectors[0].len();
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let sample: Vec<Array1<f32>> = rand::seq::index::sample(&mut rng, vectors.len(), config.training_sample.clamp(1, vectors.len()))
+            .into_iter()
+            .map(|position| normalized(&vectors[position]))
+            .collect();
+        let options = KMeansOptions { max_iterations: config.max_iterations, seed: config.seed, ..KMeansOptions::new(config.nlist) };
+        let clusters = KMeans::fit(&sample, &options)?;
+        tracing::debug!("Trained {} IVF lists on {} vectors in {} iterations", clusters.k(), sample.len(), clusters.iterations);
+        let quantizer = config.pq.as_ref().map(|pq| ProductQuantizer::train(&sample, pq)).transpose()?;
+
+        Ok(Self {
+            dimension,
+            lists: vec![InvertedList::default(); clusters.k()],
+            centroids: clusters.centroids,
+            quantizer,
+            nprobe: config.nprobe.max(1),
+            len: 0,
+        })
+    }
+
+    /// Adds a vector to the list of its nearest cluster, returning its position
+    pub fn add(&mut self, vector: &Array1<f32>) -> Result<usize> {
+        if vector.len() != self.dimension {
+            return Err(err!("Index holds {}-dimensional vectors, got {} dimensions", self.dimension, vector.len()));
+        }
+        let vector = normalized(vector);
+        let nearest = self.nearest_lists(&vector, 1)[0];
+        let list = &mut self.lists[nearest];
+        list.positions.push(self.len);
+        match &self.quantizer {
+            Some(quantizer) => list.codes.extend(quantizer.encode(&vector)),
+            None => list.vectors.extend(vector.iter()),
+        }
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// Builds an index from a saved collection, reading a sharded one shard by shard
+    ///
+    /// The clusters of a sharded collection are trained on its first shard.
+    #[cfg(feature = "proto-store")]
+    pub fn from_file(path: impl AsRef<Path>, config: &IvfConfig) -> Result<Self> {
+        let path = path.as_ref();
+        if !shard::is_sharded(path) {
+            let (embeddings, _) = utils::load_embeddings(path)?;
+            return Self::build(&embeddings, config);
+        }
+
+        let mut index: Option<Self> = None;
+        for shard in ShardedCollection::open(path)?.shards() {
+            let vectors = shard?.embeddings.into_iter()
+                .map(|embedding| match embedding.vector {
+                    StoredVector::Dense(vector) => Ok(vector),
+                    StoredVector::Sparse(_) => Err(err!("IVF indexes hold dense vectors, {} has sparse ones", path.display())),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let index = match &mut index {
+                Some(index) => index,
+                None if vectors.is_empty() => continue,
+                None => index.insert(Self::train(&vectors, config)?),
+            };
+            for vector in &vectors {
+                index.add(vector)?;
+            }
+        }
+        index.ok_or_else(|| err!("{} holds no embeddings", path.display()))
+    }
+
+    /// Changes how many lists a query searches
+    pub fn set_nprobe(&mut self, nprobe: usize) {
+        self.nprobe = nprobe.max(1);
+    }
+
+    /// Lists a query searches
+    pub fn nprobe(&self) -> usize {
+        self.nprobe
+    }
+
+    /// Number of inverted lists
+    pub fn nlist(&self) -> usize {
+        self.lists.len()
+    }
+
+    /// Number of indexed vectors
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no vectors are indexed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Approximate bytes taken by the stored vectors or codes, centroids and positions
+    pub fn memory_bytes(&self) -> usize {
+        let vectors: usize = self.lists.iter()
+            .map(|list| list.vectors.capacity() * 4 + list.codes.capacity() + list.positions.capacity() * std::mem::size_of::<usize>())
+            .sum();
+        let codebooks = self.quantizer.as_ref().map_or(0, ProductQuantizer::memory_bytes);
+        vectors + codebooks + self.centroids.len() * self.dimension * 4
+    }
+
+    /// Returns the `k` vectors most similar to the query among the `nprobe` nearest lists, best first
+    ///
+    /// Scores of a quantizing index are the similarities to the vectors the codes stand for, so they are
+    /// approximate as well.
+    pub fn search(&self, query: &Array1<f32>, k: usize) -> Vec<SearchHit> {
+        if query.len() != self.dimension {
+            return Vec::new();
+        }
+        let query = normalized(query);
+        let values = query.as_slice().unwrap_or_default();
+        let table = self.quantizer.as_ref().map(|quantizer| quantizer.dot_table(values));
+
+        let mut hits: Vec<SearchHit> = Vec::new();
+        for list in self.nearest_lists(&query, self.nprobe) {
+            let list = &self.lists[list];
+            match (&self.quantizer, &table) {
+                (Some(quantizer), Some(table)) => hits.extend(list.positions.iter()
+                    .zip(list.codes.chunks_exact(quantizer.code_size()))
+                    .map(|(&index, code)| SearchHit { index, score: quantizer.score(table, code) })),
+                _ => hits.extend(list.positions.iter()
+                    .zip(list.vectors.chunks_exact(self.dimension))
+                    .map(|(&index, vector)| SearchHit { index, score: simd::dot(values, vector) })),
+            }
+        }
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+
+    /// Searches for many queries at once, probing for them on all cores with the `parallel` feature
+    pub fn search_batch(&self, queries: &[Array1<f32>], k: usize) -> Vec<Vec<SearchHit>> {
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        return queries.par_iter().map(|query| self.search(query, k)).collect();
+        #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+        return queries.iter().map(|query| self.search(query, k)).collect();
+    }
+
+    /// Lists whose centroids are closest to a normalized vector, nearest first
+    fn nearest_lists(&self, vector: &Array1<f32>, count: usize) -> Vec<usize> {
+        let mut distances: Vec<SearchHit> = self.centroids.iter()
+            .enumerate()
+            .map(|(index, centroid)| SearchHit { index, score: -crate::analysis::kmeans::squared_distance(vector, centroid) })
+            .collect();
+        sort_hits(&mut distances);
+        distances.into_iter().take(count).map(|hit| hit.index).collect()
+    }
+}
+
+/// A unit-length copy of a vector
+fn normalized(vector: &Array1<f32>) -> Array1<f32> {
+    let mut vector = vector.as_standard_layout().into_owned();
+    utils::normalize(&mut vector);
+    vector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search;
+
+    #[test]
+    fn test_finds_neighbors_in_probed_lists() -> Result<()> {
+        // Points on three well separated arcs of the unit circle
+        let vectors: Vec<Array1<f32>> = (0..60)
+            .map(|i| {
+                let angle = (i % 3) as f32 * 2.0 + (i / 3) as f32 * 0.01;
+                Array1::from(vec![angle.cos(), angle.sin()])
+            })
+            .collect();
+        let config = IvfConfig { nlist: 3, nprobe: 1, ..IvfConfig::default() };
+        let mut index = IvfIndex::build(&vectors, &config)?;
+        assert_eq!((index.nlist(), index.len()), (3, 60));
+        assert!(index.memory_bytes() >= 60 * 2 * 4);
+
+        let query = Array1::from(vec![2.05f32.cos(), 2.05f32.sin()]);
+        let exact: Vec<usize> = search::top_k(&query, &vectors, 5).iter().map(|hit| hit.index).collect();
+        let approximate: Vec<usize> = index.search(&query, 5).iter().map(|hit| hit.index).collect();
+        assert_eq!(approximate, exact);
+
+        index.set_nprobe(3);
+        assert_eq!(index.search(&query, 60).len(), 60);
+        assert_eq!(index.search_batch(&[query.clone(), query.clone()], 5)[1], index.search(&query, 5));
+        assert!(index.add(&Array1::from(vec![1.0])).is_err());
+
+        let config = IvfConfig { pq: Some(PqConfig { subspaces: 2, ..PqConfig::default() }), ..config };
+        let quantized = IvfIndex::build(&vectors, &config)?;
+        assert_eq!(quantized.search(&query, 1)[0].index, exact[0]);
+        assert!(quantized.memory_bytes() < index.memory_bytes() + 60 * 2 * 4);
+        Ok(())
+    }
+}
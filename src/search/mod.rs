@@ -0,0 +1,190 @@
+pub mod bm25;
+pub mod colbert;
+pub mod filter;
+pub mod hybrid;
+pub mod index;
+pub mod ivf;
+pub mod pq;
+pub mod sparse;
+pub mod store;
+pub mod tune;
+#[cfg(all(feature = "proto-store", not(target_arch = "wasm32")))]
+pub mod wal;
+
+pub use bm25::Bm25Index;
+pub use colbert::{max_sim, ColbertIndex, MultiVector};
+pub use filter::Filter;
+pub use hybrid::{HybridConfig, HybridHit, HybridIndex};
+pub use index::{IndexHit, VectorIndex};
+pub use ivf::{IvfConfig, IvfIndex};
+pub use pq::{PqConfig, ProductQuantizer};
+pub use sparse::SparseIndex;
+pub use store::{NamespaceStats, VectorStore};
+pub use tune::{tune_nprobe, TuneOptions, TuneReport};
+#[cfg(all(feature = "proto-store", not(target_arch = "wasm32")))]
+pub use wal::{DurableStore, StoreReader};
+
+use crate::utils;
+use ndarray::{Array1, Array2, Axis};
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+/// Queries scored together in one matrix product by `top_k_batch`
+const QUERY_BLOCK: usize = 256;
+
+/// A match found in a searched collection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchHit {
+    /// Position of the match in the searched collection
+    pub index: usize,
+
+    /// Cosine similarity between the query and the match
+    pub score: f32,
+}
+
+/// Returns every embedding whose similarity to the query is at least `min_score`, best first
+pub fn find_above(query: &Array1<f32>, embeddings: &[Array1<f32>], min_score: f32) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = score_all(query, embeddings)
+        .filter(|hit| hit.score >= min_score)
+        .collect();
+
+    sort_hits(&mut hits);
+    hits
+}
+
+/// Returns the `k` embeddings most similar to the query, best first
+pub fn top_k(query: &Array1<f32>, embeddings: &[Array1<f32>], k: usize) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = score_all(query, embeddings).collect();
+
+    sort_hits(&mut hits);
+    hits.truncate(k);
+    hits
+}
+
+/// Returns the `k` embeddings most similar to the query among those whose positions `keep` accepts, best first
+///
+/// Rejected embeddings are never scored, so a selective filter, such as `Filter::matches` on their metadata,
+/// also makes the search faster.
+pub fn top_k_where(query: &Array1<f32>, embeddings: &[Array1<f32>], k: usize, keep: impl Fn(usize) -> bool) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = embeddings.iter()
+        .enumerate()
+        .filter(|&(index, embedding)| embedding.len() == query.len() && keep(index))
+        .map(|(index, embedding)| SearchHit { index, score: utils::cosine_similarity(query, embedding) })
+        .collect();
+
+    sort_hits(&mut hits);
+    hits.truncate(k);
+    hits
+}
+
+/// Returns the `k` embeddings most similar to each query, best first, in query order
+///
+/// The normalized queries are multiplied with the normalized embeddings a block at a time, which is much
+/// faster than calling `top_k` per query when there are many of both, as in offline linking jobs. Blocks
+/// run on all cores with the `parallel` feature. Embeddings of another dimension than the queries are skipped.
+pub fn top_k_batch(queries: &[Array1<f32>], embeddings: &[Array1<f32>], k: usize) -> Vec<Vec<SearchHit>> {
+    let Some(dimension) = queries.first().map(|query| query.len()) else {
+        return Vec::new();
+    };
+    let positions: Vec<usize> = (0..embeddings.len()).filter(|&i| embeddings[i].len() == dimension).collect();
+    let matrix = normalized_rows(positions.iter().map(|&i| &embeddings[i]), positions.len(), dimension);
+
+    let search_block = |block: &[Array1<f32>]| -> Vec<Vec<SearchHit>> {
+        let scores = normalized_rows(block.iter(), block.len(), dimension).dot(&matrix.t());
+        scores.axis_iter(Axis(0))
+            .map(|row| {
+                let mut hits: Vec<SearchHit> = row.iter()
+                    .zip(&positions)
+                    .map(|(&score, &index)| SearchHit { index, score })
+                    .collect();
+                if hits.len() > k {
+                    hits.select_nth_unstable_by(k, |a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+                    hits.truncate(k);
+                }
+                sort_hits(&mut hits);
+                hits
+            })
+            .collect()
+    };
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let blocks: Vec<Vec<Vec<SearchHit>>> = queries.par_chunks(QUERY_BLOCK).map(search_block).collect();
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let blocks: Vec<Vec<Vec<SearchHit>>> = queries.chunks(QUERY_BLOCK).map(search_block).collect();
+    blocks.into_iter().flatten().collect()
+}
+
+/// Sorts hits by descending score
+pub fn sort_hits(hits: &mut [SearchHit]) {
+    hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+}
+
+/// Scores every embedding against the query, skipping dimension mismatches
+fn score_all<'a>(query: &'a Array1<f32>, embeddings: &'a [Array1<f32>]) -> impl Iterator<Item = SearchHit> + 'a {
+    embeddings.iter()
+        .enumerate()
+        .filter(move |(_, embedding)| embedding.len() == query.len())
+        .map(move |(index, embedding)| SearchHit {
+            index,
+            score: utils::cosine_similarity(query, embedding),
+        })
+}
+
+/// Stacks vectors into a matrix with unit-length rows, zero vectors stay zero
+fn normalized_rows<'a>(vectors: impl Iterator<Item = &'a Array1<f32>>, rows: usize, dimension: usize) -> Array2<f32> {
+    let mut matrix = Array2::zeros((rows, dimension));
+    for (mut row, vector) in matrix.axis_iter_mut(Axis(0)).zip(vectors) {
+        let norm = vector.dot(vector).sqrt();
+        if norm > 0.0 {
+            row.assign(&(vector / norm));
+        }
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_search_matches_single_queries() {
+        let embeddings: Vec<Array1<f32>> = (0..50)
+            .map(|i| Array1::from(vec![(i as f32 * 0.7).cos(), (i as f32 * 0.7).sin(), (i % 5) as f32]))
+            .chain([Array1::from(vec![1.0, 0.0])])
+            .collect();
+        let queries: Vec<Array1<f32>> = (0..300).map(|i| embeddings[i % 50].clone() * 2.0).collect();
+
+        let batch = top_k_batch(&queries, &embeddings, 3);
+        assert_eq!(batch.len(), queries.len());
+        for (query, hits) in queries.iter().zip(&batch) {
+            // Scores rather than positions, which near ties may swap
+            let expected = top_k(query, &embeddings, 3);
+            assert_eq!(hits.len(), expected.len());
+            assert!(hits.iter().zip(&expected).all(|(hit, expected)| (hit.score - expected.score).abs() < 1e-5));
+        }
+        assert!(top_k_batch(&[], &embeddings, 3).is_empty());
+    }
+
+    #[test]
+    fn test_find_above_keeps_scores_at_the_threshold_best_first() {
+        let query = Array1::from(vec![1.0, 0.0]);
+        let embeddings = vec![
+            Array1::from(vec![0.0, 1.0]),
+            Array1::from(vec![1.0, 1.0]),
+            Array1::from(vec![1.0, 0.0, 0.0]),
+            Array1::from(vec![2.0, 0.0]),
+            Array1::from(vec![1.0, 0.5]),
+        ];
+        let threshold = utils::cosine_similarity(&query, &embeddings[1]);
+
+        let hits = find_above(&query, &embeddings, threshold);
+        // The 3-dimensional embedding is skipped rather than scored
+        assert_eq!(hits.iter().map(|hit| hit.index).collect::<Vec<_>>(), vec![3, 4, 1]);
+        assert_eq!(hits[2].score, threshold);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        assert!(find_above(&query, &embeddings, 1.5).is_empty());
+        assert!(find_above(&query, &[], -1.0).is_empty());
+    }
+}
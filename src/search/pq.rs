@@ -0,0 +1,151 @@
+use crate::analysis::kmeans::{KMeans, KMeansOptions};
+use crate::error::{err, Result};
+use ndarray::{s, Array1};
+
+/// Most centroids per subspace, so that each code fits in a byte
+const MAX_CENTROIDS: usize = 256;
+
+/// Settings of product quantization
+#[derive(Debug, Clone)]
+pub struct PqConfig {
+    /// Slices each vector is cut into, one byte each, the dimension must be a multiple of it
+    pub subspaces: usize,
+
+    /// Lloyd iterations when training each subspace's centroids
+    pub max_iterations: usize,
+
+    /// Seed for k-means++ initialization
+    pub seed: u64,
+}
+
+impl Default for PqConfig {
+    fn default() -> Self {
+        Self {
+            subspaces: 48,
+            max_iterations: 20,
+            seed: 42,
+        }
+    }
+}
+
+/// Compresses vectors to one byte per subspace, the index of the nearest of 256 centroids trained for it
+///
+/// A 384-dimensional vector with 48 subspaces takes 48 bytes instead of 1536. Similarities are computed
+/// asymmetrically: the query stays exact and is compared with each subspace's centroids once, after which
+/// scoring a code is a sum of table lookups.
+#[derive(Debug, Clone)]
+pub struct ProductQuantizer {
+    dimension: usize,
+    subspace_dimension: usize,
+    /// Centroids per subspace, fewer than 256 when trained on fewer vectors
+    centroids: usize,
+    /// Centroids of each subspace, `centroids` rows of `subspace_dimension` values back to back
+    codebooks: Vec<Vec<f32>>,
+}
+
+impl ProductQuantizer {
+    /// Trains the centroids of each subspace on vectors of the same dimension
+    pub fn train(vectors: &[Array1<f32>], config: &PqConfig) -> Result<Self> {
+        let dimension = vectors.first().map(|vector| vector.len()).ok_or_else(|| err!("Cannot train a quantizer without vectors"))?;
+        if config.subspaces == 0 || dimension % config.subspaces != 0 {
+            return Err(err!("{} dimensions cannot be split into {} subspaces", dimension, config.subspaces));
+        }
+        let subspace_dimension = dimension / config.subspaces;
+
+        let options = KMeansOptions { max_iterations: config.max_iterations, seed: config.seed, ..KMeansOptions::new(MAX_CENTROIDS) };
+        let codebooks = (0..config.subspaces)
+            .map(|subspace| {
+                let range = subspace * subspace_dimension..(subspace + 1) * subspace_dimension;
+                let slices: Vec<Array1<f32>> = vectors.iter().map(|vector| vector.slice(s![range.clone()]).to_owned()).collect();
+                let clusters = KMeans::fit(&slices, &options)?;
+                Ok(clusters.centroids.iter().flat_map(|centroid| centroid.iter().copied()).collect())
+            })
+            .collect::<Result<Vec<Vec<f32>>>>()?;
+
+        let centroids = codebooks[0].len() / subspace_dimension;
+        Ok(Self { dimension, subspace_dimension, centroids, codebooks })
+    }
+
+    /// Bytes per encoded vector
+    pub fn code_size(&self) -> usize {
+        self.codebooks.len()
+    }
+
+    /// Bytes taken by the centroids
+    pub fn memory_bytes(&self) -> usize {
+        self.codebooks.iter().map(|codebook| codebook.len() * 4).sum()
+    }
+
+    /// Dimension of the vectors the quantizer was trained on
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Encodes a vector as the nearest centroid of each subspace
+    pub fn encode(&self, vector: &Array1<f32>) -> Vec<u8> {
+        self.codebooks.iter()
+            .enumerate()
+            .map(|(subspace, codebook)| {
+                let slice = &vector.as_slice().unwrap_or_default()[subspace * self.subspace_dimension..][..self.subspace_dimension];
+                let nearest = codebook.chunks_exact(self.subspace_dimension)
+                    .map(|centroid| centroid.iter().zip(slice).map(|(c, x)| (c - x) * (c - x)).sum::<f32>())
+                    .enumerate()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map_or(0, |(index, _)| index);
+                nearest as u8
+            })
+            .collect()
+    }
+
+    /// Rebuilds the approximate vector a code stands for
+    pub fn decode(&self, code: &[u8]) -> Array1<f32> {
+        code.iter()
+            .zip(&self.codebooks)
+            .flat_map(|(&centroid, codebook)| codebook[centroid as usize * self.subspace_dimension..][..self.subspace_dimension].iter().copied())
+            .collect()
+    }
+
+    /// Dot products of the query's slices with every centroid, the lookup table for `score`
+    pub fn dot_table(&self, query: &[f32]) -> Vec<f32> {
+        self.codebooks.iter()
+            .enumerate()
+            .flat_map(|(subspace, codebook)| {
+                let slice = &query[subspace * self.subspace_dimension..][..self.subspace_dimension];
+                codebook.chunks_exact(self.subspace_dimension)
+                    .map(move |centroid| centroid.iter().zip(slice).map(|(c, x)| c * x).sum::<f32>())
+            })
+            .collect()
+    }
+
+    /// Dot product of the query a table was made for with the vector a code stands for
+    pub fn score(&self, table: &[f32], code: &[u8]) -> f32 {
+        code.iter()
+            .enumerate()
+            .map(|(subspace, &centroid)| table[subspace * self.centroids + centroid as usize])
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_approximate_dot_products() -> Result<()> {
+        let vectors: Vec<Array1<f32>> = (0..40)
+            .map(|i| Array1::from_iter((0..8).map(|d| ((i * 7 + d * 13) % 17) as f32 / 17.0)))
+            .collect();
+        let quantizer = ProductQuantizer::train(&vectors, &PqConfig { subspaces: 4, ..PqConfig::default() })?;
+        assert_eq!(quantizer.code_size(), 4);
+        assert!(ProductQuantizer::train(&vectors, &PqConfig { subspaces: 3, ..PqConfig::default() }).is_err());
+
+        // Fewer distinct slices than centroids, so every vector is reproduced exactly
+        let code = quantizer.encode(&vectors[5]);
+        assert_eq!(quantizer.decode(&code), vectors[5]);
+
+        let query = Array1::from(vec![0.5, -1.0, 0.25, 1.0, 0.0, 2.0, -0.5, 1.5]);
+        let table = quantizer.dot_table(query.as_slice().unwrap_or_default());
+        assert!((quantizer.score(&table, &code) - query.dot(&vectors[5])).abs() < 1e-4);
+        Ok(())
+    }
+}
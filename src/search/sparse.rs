@@ -0,0 +1,85 @@
+use super::{sort_hits, SearchHit};
+use crate::embedding::SparseEmbedding;
+use std::collections::HashMap;
+
+/// Inverted index over sparse embeddings, scored by dot product
+#[derive(Debug, Clone, Default)]
+pub struct SparseIndex {
+    /// Documents containing each term, with the term's weight in that document
+    postings: HashMap<u32, Vec<(usize, f32)>>,
+    len: usize,
+}
+
+impl SparseIndex {
+    /// Builds an index over sparse embeddings
+    pub fn new(embeddings: &[SparseEmbedding]) -> Self {
+        let mut index = Self::default();
+        for embedding in embeddings {
+            index.add(embedding);
+        }
+        index
+    }
+
+    /// Adds an embedding, returning its position in the index
+    pub fn add(&mut self, embedding: &SparseEmbedding) -> usize {
+        let document = self.len;
+        for (&term, &weight) in embedding.indices.iter().zip(&embedding.values) {
+            self.postings.entry(term).or_default().push((document, weight));
+        }
+        self.len += 1;
+        document
+    }
+
+    /// Number of indexed embeddings
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if nothing is indexed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the `k` documents with the highest dot product with the query, best first
+    ///
+    /// Only documents sharing at least one term with the query are scored.
+    pub fn search(&self, query: &SparseEmbedding, k: usize) -> Vec<SearchHit> {
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for (term, &query_weight) in query.indices.iter().zip(&query.values) {
+            if let Some(postings) = self.postings.get(term) {
+                for &(document, weight) in postings {
+                    *scores.entry(document).or_insert(0.0) += query_weight * weight;
+                }
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores.into_iter()
+            .map(|(index, score)| SearchHit { index, score })
+            .collect();
+        sort_hits(&mut hits);
+        hits.truncate(k);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scores_shared_terms() {
+        let documents = vec![
+            SparseEmbedding::from_pairs([(1, 1.0), (5, 2.0)]),
+            SparseEmbedding::from_pairs([(5, 0.5), (9, 3.0)]),
+            SparseEmbedding::from_pairs([(7, 4.0)]),
+        ];
+        let index = SparseIndex::new(&documents);
+        let query = SparseEmbedding::from_pairs([(5, 1.0), (9, 1.0)]);
+
+        let hits = index.search(&query, 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0], SearchHit { index: 1, score: 3.5 });
+        assert_eq!(hits[1], SearchHit { index: 0, score: 2.0 });
+        assert_eq!(query.dot(&documents[1]), 3.5);
+    }
+}
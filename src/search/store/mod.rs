@@ -0,0 +1,255 @@
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use super::{Filter, IndexHit, VectorIndex};
+#[cfg(feature = "proto-store")]
+use crate::collection::{StoredCollection, StoredEmbedding};
+#[cfg(feature = "proto-store")]
+use crate::embedding::ModelInfo;
+#[cfg(feature = "proto-store")]
+use crate::utils::storage;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+
+/// Namespace of embeddings saved without one
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Size of one namespace of a `VectorStore`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NamespaceStats {
+    pub namespace: String,
+
+    /// Vectors stored, not counting deleted ones
+    pub vectors: usize,
+
+    /// Deleted vectors still taking up space until the next compaction
+    pub tombstones: usize,
+}
+
+/// Several isolated `VectorIndex`es, one per namespace such as a user or project, saved together in one file
+///
+/// Ids only need to be unique within a namespace, and searches never return vectors of another one. All
+/// namespaces hold vectors of the same model, so they share one dimension.
+#[derive(Debug, Clone)]
+pub struct VectorStore {
+    namespaces: BTreeMap<String, VectorIndex>,
+    dimension: Option<usize>,
+    compaction_threshold: f32,
+}
+
+impl Default for VectorStore {
+    fn default() -> Self {
+        Self {
+            namespaces: BTreeMap::new(),
+            dimension: None,
+            compaction_threshold: 0.25,
+        }
+    }
+}
+
+impl VectorStore {
+    /// An empty store, each namespace compacting once a quarter of its vectors are deleted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compaction threshold of the namespaces created from now on, see `VectorIndex::with_compaction_threshold`
+    pub fn with_compaction_threshold(mut self, threshold: f32) -> Self {
+        self.compaction_threshold = threshold;
+        self
+    }
+
+    /// Inserts a vector into a namespace, creating it if needed, or replaces the vector stored there under `id`
+    pub fn upsert(&mut self, namespace: &str, id: impl Into<String>, vector: Array1<f32>, metadata: HashMap<String, String>) -> Result<()> {
+        let dimension = *self.dimension.get_or_insert(vector.len());
+        if vector.len() != dimension {
+            return Err(err!("Store holds {}-dimensional vectors, got {} dimensions", dimension, vector.len()));
+        }
+        let threshold = self.compaction_threshold;
+        self.namespaces.entry(namespace.to_string())
+            .or_insert_with(|| VectorIndex::new().with_compaction_threshold(threshold))
+            .upsert(id, vector, metadata)
+    }
+
+    /// Removes the vector stored under `id` in a namespace, returning false when there is none
+    pub fn delete(&mut self, namespace: &str, id: &str) -> bool {
+        self.namespaces.get_mut(namespace).is_some_and(|index| index.delete(id))
+    }
+
+    /// Removes a namespace with all its vectors, returning false when there is none
+    pub fn delete_namespace(&mut self, namespace: &str) -> bool {
+        self.namespaces.remove(namespace).is_some()
+    }
+
+    /// The index of a namespace
+    pub fn namespace(&self, namespace: &str) -> Option<&VectorIndex> {
+        self.namespaces.get(namespace)
+    }
+
+    /// Names of the namespaces, in alphabetical order
+    pub fn namespaces(&self) -> impl Iterator<Item = &str> {
+        self.namespaces.keys().map(String::as_str)
+    }
+
+    /// Size of a namespace
+    pub fn stats(&self, namespace: &str) -> Option<NamespaceStats> {
+        self.namespaces.get(namespace).map(|index| NamespaceStats {
+            namespace: namespace.to_string(),
+            vectors: index.len(),
+            tombstones: index.tombstones(),
+        })
+    }
+
+    /// Sizes of every namespace, in alphabetical order
+    pub fn all_stats(&self) -> Vec<NamespaceStats> {
+        self.namespaces().filter_map(|namespace| self.stats(namespace)).collect()
+    }
+
+    /// Number of vectors across namespaces, not counting deleted ones
+    pub fn len(&self) -> usize {
+        self.namespaces.values().map(VectorIndex::len).sum()
+    }
+
+    /// Returns true if no namespace holds vectors
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Dimension shared by every namespace's vectors, None until the first is inserted
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    /// Returns the `k` vectors of a namespace most similar to the query, best first
+    pub fn search(&self, namespace: &str, query: &Array1<f32>, k: usize) -> Vec<IndexHit<'_>> {
+        self.namespaces.get(namespace).map_or_else(Vec::new, |index| index.search(query, k))
+    }
+
+    /// Returns the `k` vectors of a namespace most similar to the query among those whose metadata matches `filter`
+    pub fn search_filtered(&self, namespace: &str, query: &Array1<f32>, k: usize, filter: &Filter) -> Vec<IndexHit<'_>> {
+        self.namespaces.get(namespace).map_or_else(Vec::new, |index| index.search_filtered(query, k, filter))
+    }
+
+    /// Loads a store saved with `save` or `snapshot`, putting embeddings without a namespace in `DEFAULT_NAMESPACE`
+    ///
+    /// As in `VectorIndex::load`, embeddings without an id are keyed by their position in the file.
+    #[cfg(feature = "proto-store")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_collection(StoredCollection::load(path)?)
+    }
+
+    /// A store holding the dense embeddings of a collection, in their namespaces
+    #[cfg(feature = "proto-store")]
+    pub fn from_collection(collection: StoredCollection) -> Result<Self> {
+        Self::new().read(collection)
+    }
+
+    /// Saves every namespace's vectors that aren't deleted to one file, as made by the model of `info`
+    #[cfg(feature = "proto-store")]
+    pub fn save(&self, path: impl AsRef<Path>, info: &ModelInfo) -> Result<()> {
+        self.to_collection(info).save(path)?;
+        Ok(())
+    }
+
+    /// Writes a point-in-time copy of the store for backups, without stopping the service using it
+    ///
+    /// Only a shared borrow is needed, so behind a `RwLock` searches go on while the snapshot is taken and
+    /// writers wait for it, which keeps it consistent across namespaces. A local file is written under a
+    /// temporary name and renamed into place, so an interrupted snapshot never replaces a good backup.
+    #[cfg(feature = "proto-store")]
+    pub fn snapshot(&self, path: impl AsRef<Path>, info: &ModelInfo) -> Result<()> {
+        let path = path.as_ref();
+        storage::write_atomic(path, self.to_collection(info).to_bytes())?;
+        tracing::info!("Snapshot of {} vector(s) in {} namespace(s) written to {}", self.len(), self.namespaces.len(), path.display());
+        Ok(())
+    }
+
+    /// Replaces the contents of the store with a snapshot, leaving them untouched if it can't be read
+    #[cfg(feature = "proto-store")]
+    pub fn restore(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let empty = Self::new().with_compaction_threshold(self.compaction_threshold);
+        *self = empty.read(StoredCollection::load(path)?)?;
+        Ok(())
+    }
+
+    /// Adds the dense embeddings of a collection to their namespaces
+    #[cfg(feature = "proto-store")]
+    fn read(mut self, collection: StoredCollection) -> Result<Self> {
+        for (position, embedding) in collection.embeddings.into_iter().enumerate() {
+            let namespace = embedding.namespace.clone().unwrap_or_else(|| DEFAULT_NAMESPACE.to_string());
+            let id = embedding.id.clone().unwrap_or_else(|| position.to_string());
+            let vector = embedding.dense_vector().cloned().ok_or_else(|| err!("Embedding {} in namespace {} is sparse", id, namespace))?;
+            self.upsert(&namespace, id, vector, embedding.metadata)?;
+        }
+        Ok(self)
+    }
+
+    /// The vectors of every namespace that aren't deleted, as a collection made by the model of `info`
+    #[cfg(feature = "proto-store")]
+    fn to_collection(&self, info: &ModelInfo) -> StoredCollection {
+        let mut collection = StoredCollection::for_model(info);
+        collection.embeddings = self.namespaces.iter()
+            .flat_map(|(namespace, index)| index.iter().map(move |(id, vector, metadata)| StoredEmbedding {
+                id: Some(id.to_string()),
+                metadata: metadata.clone(),
+                namespace: Some(namespace.clone()),
+                ..StoredEmbedding::dense(vector.clone(), None)
+            }))
+            .collect();
+        collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaces_are_isolated() -> Result<()> {
+        let mut store = VectorStore::new();
+        store.upsert("alice", "doc", Array1::from(vec![1.0, 0.0]), HashMap::new())?;
+        store.upsert("bob", "doc", Array1::from(vec![0.0, 1.0]), HashMap::new())?;
+        store.upsert("bob", "other", Array1::from(vec![0.6, 0.8]), HashMap::new())?;
+        assert!(store.upsert("carol", "doc", Array1::from(vec![1.0]), HashMap::new()).is_err());
+        assert_eq!(store.namespaces().collect::<Vec<_>>(), ["alice", "bob"]);
+
+        let query = Array1::from(vec![1.0, 0.0]);
+        assert_eq!(store.search("alice", &query, 5).len(), 1);
+        assert_eq!(store.search("bob", &query, 5)[0].id, "other");
+        assert!(store.search("carol", &query, 5).is_empty());
+
+        assert!(store.delete("bob", "doc"));
+        assert!(!store.delete("alice", "other"));
+        assert_eq!(store.stats("bob"), Some(NamespaceStats { namespace: "bob".to_string(), vectors: 1, tombstones: 0 }));
+        assert!(store.delete_namespace("alice"));
+        assert_eq!((store.len(), store.all_stats().len()), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "proto-store")]
+    fn test_restores_a_snapshot() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_store_snapshot_{}.pb", std::process::id()));
+        let mut store = VectorStore::new();
+        store.upsert("alice", "doc", Array1::from(vec![1.0, 0.0]), HashMap::from([("lang".to_string(), "en".to_string())]))?;
+        store.upsert("bob", "doc", Array1::from(vec![0.0, 1.0]), HashMap::new())?;
+        store.snapshot(&path, &ModelInfo::new("model", "1", 2))?;
+
+        store.delete_namespace("alice");
+        store.upsert("bob", "later", Array1::from(vec![0.6, 0.8]), HashMap::new())?;
+        assert!(store.restore(path.with_extension("missing")).is_err());
+        assert_eq!(store.len(), 2);
+
+        store.restore(&path)?;
+        assert_eq!(store.namespaces().collect::<Vec<_>>(), ["alice", "bob"]);
+        assert_eq!(store.namespace("alice").and_then(|index| index.get("doc")).map(|(_, metadata)| metadata["lang"].as_str()), Some("en"));
+        assert!(store.namespace("bob").is_some_and(|index| index.get("later").is_none()));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
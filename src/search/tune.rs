@@ -0,0 +1,143 @@
+use super::ivf::IvfIndex;
+use super::top_k;
+use ndarray::Array1;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// What an `nprobe` sweep measures and aims for
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    /// Results compared with exact search per query
+    pub k: usize,
+
+    /// Values of `nprobe` to try, powers of two up to the number of lists when empty
+    pub nprobes: Vec<usize>,
+
+    /// Recall@k the recommended setting must reach
+    pub target_recall: f32,
+}
+
+impl Default for TuneOptions {
+    fn default() -> Self {
+        Self { k: 10, nprobes: Vec::new(), target_recall: 0.95 }
+    }
+}
+
+/// Recall and latency of one `nprobe` setting
+#[derive(Debug, Clone, Serialize)]
+pub struct TunePoint {
+    pub nprobe: usize,
+
+    /// Mean share of the exact top k found by the index
+    pub recall: f32,
+
+    /// Mean time per query
+    pub latency: Duration,
+}
+
+/// Results of sweeping `nprobe` against exact search
+#[derive(Debug, Clone, Serialize)]
+pub struct TuneReport {
+    pub k: usize,
+    pub target_recall: f32,
+
+    /// Mean time per query of exact search over every vector
+    pub exact_latency: Duration,
+
+    pub points: Vec<TunePoint>,
+
+    /// Smallest `nprobe` reaching the target recall, None when none does
+    pub recommended: Option<usize>,
+}
+
+impl fmt::Display for TuneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>8}  {:>10}  {:>12}  {:>8}", "nprobe", format!("recall@{}", self.k), "latency (ms)", "speedup")?;
+        for point in &self.points {
+            writeln!(
+                f, "{:>8}  {:>10.4}  {:>12.3}  {:>7.1}x",
+                point.nprobe, point.recall, point.latency.as_secs_f64() * 1000.0,
+                self.exact_latency.as_secs_f64() / point.latency.as_secs_f64().max(f64::EPSILON),
+            )?;
+        }
+        writeln!(f, "{:>8}  {:>10.4}  {:>12.3}  {:>7.1}x", "exact", 1.0, self.exact_latency.as_secs_f64() * 1000.0, 1.0)?;
+        match self.recommended {
+            Some(nprobe) => write!(f, "Recommended: nprobe = {} for recall@{} of at least {}", nprobe, self.k, self.target_recall),
+            None => write!(f, "No nprobe reaches recall@{} of {}, quantization loses too much, use more subspaces or none", self.k, self.target_recall),
+        }
+    }
+}
+
+/// Searches with each `nprobe` setting and compares the results with exact search over `vectors`
+///
+/// `vectors` must be the ones the index was built from, in the same order. The index is left with the
+/// recommended `nprobe`, or its original one when no setting reaches the target.
+pub fn tune_nprobe(index: &mut IvfIndex, vectors: &[Array1<f32>], queries: &[Array1<f32>], options: &TuneOptions) -> TuneReport {
+    let start = Instant::now();
+    let exact: Vec<HashSet<usize>> = queries.iter()
+        .map(|query| top_k(query, vectors, options.k).into_iter().map(|hit| hit.index).collect())
+        .collect();
+    let exact_latency = start.elapsed() / queries.len().max(1) as u32;
+
+    let mut nprobes = options.nprobes.clone();
+    if nprobes.is_empty() {
+        nprobes = std::iter::successors(Some(1), |&nprobe| Some(nprobe * 2))
+            .take_while(|&nprobe| nprobe < index.nlist())
+            .chain([index.nlist()])
+            .collect();
+    }
+
+    let original = index.nprobe();
+    let points: Vec<TunePoint> = nprobes.into_iter()
+        .map(|nprobe| {
+            index.set_nprobe(nprobe);
+            let start = Instant::now();
+            let found: Vec<Vec<usize>> = queries.iter()
+                .map(|query| index.search(query, options.k).into_iter().map(|hit| hit.index).collect())
+                .collect();
+            let latency = start.elapsed() / queries.len().max(1) as u32;
+
+            let recalls: Vec<f32> = exact.iter()
+                .zip(&found)
+                .filter(|(expected, _)| !expected.is_empty())
+                .map(|(expected, found)| found.iter().filter(|index| expected.contains(index)).count() as f32 / expected.len() as f32)
+                .collect();
+            let recall = if recalls.is_empty() { 0.0 } else { recalls.iter().sum::<f32>() / recalls.len() as f32 };
+            TunePoint { nprobe, recall, latency }
+        })
+        .collect();
+
+    // Latency grows with nprobe, and timings of small sweeps are too noisy to pick between settings
+    let recommended = points.iter()
+        .filter(|point| point.recall >= options.target_recall)
+        .map(|point| point.nprobe)
+        .min();
+    index.set_nprobe(recommended.unwrap_or(original));
+
+    TuneReport { k: options.k, target_recall: options.target_recall, exact_latency, points, recommended }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::IvfConfig;
+
+    #[test]
+    fn test_sweep_reaches_full_recall_at_every_list() -> crate::Result<()> {
+        let vectors: Vec<Array1<f32>> = (0..200)
+            .map(|i| Array1::from(vec![(i as f32 * 0.37).cos(), (i as f32 * 0.37).sin(), (i % 7) as f32 * 0.1]))
+            .collect();
+        let queries: Vec<Array1<f32>> = vectors.iter().step_by(20).cloned().collect();
+        let mut index = IvfIndex::build(&vectors, &IvfConfig { nlist: 8, nprobe: 1, ..IvfConfig::default() })?;
+
+        let report = tune_nprobe(&mut index, &vectors, &queries, &TuneOptions { k: 5, ..TuneOptions::default() });
+        assert_eq!(report.points.iter().map(|point| point.nprobe).collect::<Vec<_>>(), vec![1, 2, 4, 8]);
+        assert_eq!(report.points.last().map(|point| point.recall), Some(1.0));
+        assert!(report.recommended.is_some());
+        assert_eq!(Some(index.nprobe()), report.recommended);
+        assert!(report.to_string().contains("Recommended: nprobe = "));
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use super::VectorStore;
+use crate::collection::{StoredCollection, StoredEmbedding};
+use crate::embedding::ModelInfo;
+use crate::proto;
+use crate::utils::storage;
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes before each record: its length and CRC-32, both little-endian u32
+const RECORD_HEADER: usize = 8;
+
+/// A `VectorStore` saved to a local file, whose changes are logged before they are applied so none is lost in a crash
+///
+/// `upsert`, `delete` and `delete_namespace` append a checksummed record to `PATH.wal` and sync it to disk
+/// before changing the store. `open` loads the last checkpoint from `PATH` and replays the log over it. A
+/// record cut short by a crash fails its checksum and is dropped with everything after it, and checkpoints
+/// are written under a temporary name and renamed into place, so neither a torn log nor a partially written
+/// protobuf file can corrupt the store. `checkpoint` folds the log into `PATH` and empties it, which also
+/// happens on its own every `checkpoint_every` records.
+///
+/// Only one process can hold a store open for writing, others fail to open it. Any number of `StoreReader`s
+/// can follow it meanwhile, in the same process or others.
+#[derive(Debug)]
+pub struct DurableStore {
+    store: VectorStore,
+    path: PathBuf,
+    info: ModelInfo,
+    wal: File,
+    records: usize,
+    checkpoint_every: usize,
+    generation: u64,
+    /// Held exclusively while the store is open, released when the file is closed
+    _writer_lock: File,
+}
+
+impl DurableStore {
+    /// Opens the store at a local path, creating it if missing and recovering the changes logged since its last checkpoint
+    pub fn open(path: impl AsRef<Path>, info: &ModelInfo) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if storage::is_remote(&path) {
+            return Err(err!("Durable stores need a local path, got {}", path.display()));
+        }
+        let writer_lock = lock_file(&path, "writer")?;
+        match writer_lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(err!("{} is already open for writing by another process", path.display())),
+            Err(TryLockError::Error(error)) => return Err(error).with_context(|| format!("Failed to lock {}", path.display())),
+        }
+        let mut store = if path.exists() { VectorStore::load(&path)? } else { VectorStore::new() };
+
+        let wal_path = wal_path(&path);
+        let mut wal = OpenOptions::new().read(true).append(true).create(true).open(&wal_path)
+            .with_context(|| format!("Failed to open {}", wal_path.display()))?;
+        let mut bytes = Vec::new();
+        wal.read_to_end(&mut bytes)?;
+
+        let (records, valid) = replay(&mut store, &bytes)?;
+        if valid < bytes.len() {
+            tracing::warn!("Dropping {} byte(s) of incomplete records at the end of {}", bytes.len() - valid, wal_path.display());
+            wal.set_len(valid as u64)?;
+            wal.sync_data()?;
+        }
+        if records > 0 {
+            tracing::info!("Recovered {} logged change(s) from {}", records, wal_path.display());
+        }
+
+        let generation = read_generation(&path)?;
+        Ok(Self { store, path, info: info.clone(), wal, records, checkpoint_every: 10_000, generation, _writer_lock: writer_lock })
+    }
+
+    /// Checkpoints on its own once the log holds this many records, 0 leaves it to `checkpoint`
+    pub fn with_checkpoint_every(mut self, records: usize) -> Self {
+        self.checkpoint_every = records;
+        self
+    }
+
+    /// The store, for searches and stats
+    pub fn store(&self) -> &VectorStore {
+        &self.store
+    }
+
+    /// Records logged since the last checkpoint
+    pub fn logged_records(&self) -> usize {
+        self.records
+    }
+
+    /// Logs and applies the insertion or replacement of a vector, see `VectorStore::upsert`
+    pub fn upsert(&mut self, namespace: &str, id: impl Into<String>, vector: Array1<f32>, metadata: HashMap<String, String>) -> Result<()> {
+        // Validate before logging so that replay never meets a record it can't apply
+        if let Some(dimension) = self.store.dimension().filter(|&dimension| dimension != vector.len()) {
+            return Err(err!("Store holds {}-dimensional vectors, got {} dimensions", dimension, vector.len()));
+        }
+        let id = id.into();
+        let embedding = StoredEmbedding {
+            id: Some(id.clone()),
+            metadata: metadata.clone(),
+            namespace: Some(namespace.to_string()),
+            ..StoredEmbedding::dense(vector.clone(), None)
+        };
+        self.log(proto::WalOperation::Upsert, &embedding)?;
+        self.store.upsert(namespace, id, vector, metadata)?;
+        self.checkpoint_if_due()
+    }
+
+    /// Logs and applies the deletion of a vector, returning false, without logging, when there is none
+    pub fn delete(&mut self, namespace: &str, id: &str) -> Result<bool> {
+        if self.store.namespace(namespace).and_then(|index| index.get(id)).is_none() {
+            return Ok(false);
+        }
+        let embedding = StoredEmbedding { id: Some(id.to_string()), ..key(namespace) };
+        self.log(proto::WalOperation::Delete, &embedding)?;
+        self.store.delete(namespace, id);
+        self.checkpoint_if_due()?;
+        Ok(true)
+    }
+
+    /// Logs and applies the deletion of a namespace, returning false, without logging, when there is none
+    pub fn delete_namespace(&mut self, namespace: &str) -> Result<bool> {
+        if self.store.namespace(namespace).is_none() {
+            return Ok(false);
+        }
+        self.log(proto::WalOperation::DeleteNamespace, &key(namespace))?;
+        self.store.delete_namespace(namespace);
+        self.checkpoint_if_due()?;
+        Ok(true)
+    }
+
+    /// Writes the store to its path, starts a new generation and empties the log
+    ///
+    /// Readers wait while this runs, so that none sees the new checkpoint with the old log or the reverse. A
+    /// crash part way replays the log over a checkpoint that already has its changes, which leaves the same
+    /// store, since every change gives the same result when applied twice.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let swap_lock = lock_file(&self.path, "swap")?;
+        swap_lock.lock()?;
+        self.store.snapshot(&self.path, &self.info)?;
+        storage::write_atomic(generation_path(&self.path), (self.generation + 1).to_string().into_bytes())?;
+        self.generation += 1;
+        self.wal.set_len(0)?;
+        self.wal.sync_data()?;
+        self.records = 0;
+        Ok(())
+    }
+
+    fn checkpoint_if_due(&mut self) -> Result<()> {
+        if self.checkpoint_every > 0 && self.records >= self.checkpoint_every {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Appends a record and waits until it is on disk
+    fn log(&mut self, operation: proto::WalOperation, embedding: &StoredEmbedding) -> Result<()> {
+        let mut record = proto::WalRecord { embedding: Some(embedding.into()), ..Default::default() };
+        record.set_operation(operation);
+        let payload = prost::Message::encode_to_vec(&record);
+
+        let mut bytes = Vec::with_capacity(RECORD_HEADER + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        self.wal.write_all(&bytes)?;
+        self.wal.sync_data()?;
+        self.records += 1;
+        Ok(())
+    }
+}
+
+/// A read-only view of a `DurableStore` that another process, or thread, keeps writing to
+///
+/// `open` maps the last checkpoint into memory and replays the log written since. `refresh` then catches up
+/// with the writer: it replays only the records appended since the last call, and maps the checkpoint again
+/// only once the writer has started a new generation. A record still being appended is left for the next
+/// refresh. Readers never lock out the writer, except while it swaps in a checkpoint.
+#[derive(Debug)]
+pub struct StoreReader {
+    store: VectorStore,
+    path: PathBuf,
+    generation: u64,
+    /// Bytes of the log already applied
+    offset: usize,
+}
+
+impl StoreReader {
+    /// Opens the store at a local path, empty when nothing was checkpointed or logged yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = Self { store: VectorStore::new(), path: path.as_ref().to_path_buf(), generation: 0, offset: 0 };
+        reader.reload()?;
+        Ok(reader)
+    }
+
+    /// The store as of the last `open` or `refresh`
+    pub fn store(&self) -> &VectorStore {
+        &self.store
+    }
+
+    /// Checkpoints written since the store was created, as of the last `open` or `refresh`
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Applies the writer's changes since the last call, returning false when there were none
+    pub fn refresh(&mut self) -> Result<bool> {
+        let swap_lock = lock_file(&self.path, "swap")?;
+        swap_lock.lock_shared()?;
+        let wal = read_from(&wal_path(&self.path), self.offset)?;
+        if read_generation(&self.path)? != self.generation || wal.is_none() {
+            drop(swap_lock);
+            self.reload()?;
+            return Ok(true);
+        }
+
+        let bytes = wal.unwrap_or_default();
+        let (records, valid) = replay(&mut self.store, &bytes)?;
+        self.offset += valid;
+        Ok(records > 0)
+    }
+
+    /// Maps the checkpoint and replays the whole log, both from the same generation
+    fn reload(&mut self) -> Result<()> {
+        let swap_lock = lock_file(&self.path, "swap")?;
+        swap_lock.lock_shared()?;
+        let mut store = if self.path.exists() {
+            let bytes = read_checkpoint(&self.path)?;
+            VectorStore::from_collection(StoredCollection::from_bytes(&bytes)?)?
+        } else {
+            VectorStore::new()
+        };
+        let bytes = read_from(&wal_path(&self.path), 0)?.unwrap_or_default();
+        let (_, valid) = replay(&mut store, &bytes)?;
+
+        self.generation = read_generation(&self.path)?;
+        self.store = store;
+        self.offset = valid;
+        Ok(())
+    }
+}
+
+/// Maps the checkpoint at `path` into memory
+#[cfg(feature = "memory-mapped")]
+fn read_checkpoint(path: &Path) -> Result<memmap2::Mmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    // SAFETY: checkpoints are renamed into place and never written in place, so the mapped file doesn't change
+    Ok(unsafe { memmap2::Mmap::map(&file)? })
+}
+
+/// Reads the checkpoint at `path`, which the `memory-mapped` feature maps instead
+#[cfg(not(feature = "memory-mapped"))]
+fn read_checkpoint(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Location of the write-ahead log of the store at `path`
+pub fn wal_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.wal", path.display()))
+}
+
+/// Location of the number of checkpoints written to the store at `path`
+fn generation_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.generation", path.display()))
+}
+
+/// Opens, creating if needed, the file locked for one purpose around the store at `path`
+fn lock_file(path: &Path, purpose: &str) -> Result<File> {
+    let lock_path = PathBuf::from(format!("{}.{}.lock", path.display(), purpose));
+    if let Some(parent) = lock_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))
+}
+
+/// Checkpoints written to the store at `path`, 0 before the first
+fn read_generation(path: &Path) -> Result<u64> {
+    let generation_path = generation_path(path);
+    match std::fs::read_to_string(&generation_path) {
+        Ok(text) => text.trim().parse().with_context(|| format!("Invalid generation in {}", generation_path.display())),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(error) => Err(error).with_context(|| format!("Failed to read {}", generation_path.display())),
+    }
+}
+
+/// The bytes of a file from `offset` on, None when it is shorter than that, as after a checkpoint emptied it
+fn read_from(path: &Path, offset: usize) -> Result<Option<Vec<u8>>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok((offset == 0).then(Vec::new)),
+        Err(error) => return Err(error).with_context(|| format!("Failed to open {}", path.display())),
+    };
+    if file.metadata()?.len() < offset as u64 {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(offset as u64))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// An embedding carrying only the namespace of a deletion
+fn key(namespace: &str) -> StoredEmbedding {
+    StoredEmbedding { namespace: Some(namespace.to_string()), ..StoredEmbedding::dense(Array1::zeros(0), None) }
+}
+
+/// CRC-32 (IEEE) of a record's payload
+fn checksum(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg()))
+    })
+}
+
+/// Applies the complete records at the start of a log, returning how many there were and the bytes they take
+fn replay(store: &mut VectorStore, bytes: &[u8]) -> Result<(usize, usize)> {
+    let (mut records, mut offset) = (0, 0);
+    while let Some(header) = bytes.get(offset..offset + RECORD_HEADER) {
+        let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let expected = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let Some(payload) = bytes.get(offset + RECORD_HEADER..offset + RECORD_HEADER + length) else {
+            break;
+        };
+        if checksum(payload) != expected {
+            break;
+        }
+
+        let record: proto::WalRecord = prost::Message::decode(payload)?;
+        let operation = record.operation();
+        let embedding = StoredEmbedding::try_from(record.embedding.unwrap_or_default())?;
+        let namespace = embedding.namespace.as_deref().unwrap_or(super::store::DEFAULT_NAMESPACE);
+        let id = embedding.id.clone().unwrap_or_default();
+        match operation {
+            proto::WalOperation::Upsert => {
+                let vector = embedding.dense_vector().cloned().ok_or_else(|| err!("Logged embedding {} is sparse", id))?;
+                store.upsert(namespace, id, vector, embedding.metadata.clone())?;
+            }
+            proto::WalOperation::Delete => {
+                store.delete(namespace, &id);
+            }
+            proto::WalOperation::DeleteNamespace => {
+                store.delete_namespace(namespace);
+            }
+        }
+        records += 1;
+        offset += RECORD_HEADER + length;
+    }
+    Ok((records, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_is_crc32() {
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_recovers_logged_changes_and_drops_torn_records() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_durable_{}.pb", std::process::id()));
+        let info = ModelInfo::new("model", "1", 2);
+
+        let mut durable = DurableStore::open(&path, &info)?.with_checkpoint_every(0);
+        durable.upsert("alice", "a", Array1::from(vec![1.0, 0.0]), HashMap::new())?;
+        durable.checkpoint()?;
+        durable.upsert("alice", "b", Array1::from(vec![0.0, 1.0]), HashMap::new())?;
+        durable.upsert("bob", "a", Array1::from(vec![0.6, 0.8]), HashMap::new())?;
+        assert!(durable.delete("alice", "a")?);
+        assert!(!durable.delete("alice", "missing")?);
+        assert!(durable.upsert("bob", "c", Array1::from(vec![1.0]), HashMap::new()).is_err());
+        assert_eq!(durable.logged_records(), 3);
+        drop(durable);
+
+        // A crash in the middle of appending a record
+        OpenOptions::new().append(true).open(wal_path(&path))?.write_all(&[40, 0, 0, 0, 1, 2])?;
+
+        let mut durable = DurableStore::open(&path, &info)?;
+        assert_eq!(durable.logged_records(), 3);
+        assert_eq!(durable.store().namespaces().collect::<Vec<_>>(), ["alice", "bob"]);
+        assert!(durable.store().namespace("alice").is_some_and(|index| index.get("a").is_none() && index.get("b").is_some()));
+
+        durable.checkpoint()?;
+        assert_eq!(std::fs::metadata(wal_path(&path))?.len(), 0);
+        drop(durable);
+        assert_eq!(DurableStore::open(&path, &info)?.store().len(), 2);
+
+        for suffix in ["", ".wal", ".generation", ".writer.lock", ".swap.lock"] {
+            std::fs::remove_file(format!("{}{}", path.display(), suffix))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_follow_the_writer() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_shared_{}.pb", std::process::id()));
+        let info = ModelInfo::new("model", "1", 2);
+        let mut writer = DurableStore::open(&path, &info)?.with_checkpoint_every(0);
+        assert!(DurableStore::open(&path, &info).is_err());
+
+        let mut reader = StoreReader::open(&path)?;
+        assert!(reader.store().is_empty());
+        writer.upsert("alice", "a", Array1::from(vec![1.0, 0.0]), HashMap::new())?;
+        assert!(reader.refresh()?);
+        assert!(!reader.refresh()?);
+        assert_eq!(reader.store().len(), 1);
+
+        writer.checkpoint()?;
+        writer.upsert("alice", "b", Array1::from(vec![0.0, 1.0]), HashMap::new())?;
+        assert!(reader.refresh()?);
+        assert_eq!((reader.generation(), reader.store().len()), (1, 2));
+
+        drop(writer);
+        let writer = DurableStore::open(&path, &info)?;
+        assert_eq!(writer.store().len(), 2);
+        drop(writer);
+
+        for suffix in ["", ".wal", ".generation", ".writer.lock", ".swap.lock"] {
+            std::fs::remove_file(format!("{}{}", path.display(), suffix))?;
+        }
+        Ok(())
+    }
+}
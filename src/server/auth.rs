@@ -0,0 +1,171 @@
+use super::ServeError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Authentication and rate limiting settings for the HTTP server
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthSettings {
+    /// API keys accepted as `Authorization: Bearer <key>`
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+
+    /// Environment variable holding more API keys, separated by commas
+    #[serde(default = "default_api_keys_env")]
+    pub api_keys_env: String,
+
+    /// Sustained requests per second allowed for each key, unlimited if unset
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+
+    /// Requests a key may make in a burst, defaults to one second's worth
+    #[serde(default)]
+    pub burst: Option<f64>,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            api_keys_env: default_api_keys_env(),
+            requests_per_second: None,
+            burst: None,
+        }
+    }
+}
+
+fn default_api_keys_env() -> String {
+    "RUST_EMBED_API_KEYS".to_string()
+}
+
+/// A token bucket for one client
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token-bucket rate limiter keyed by client
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter refilling `rate` tokens per second up to `burst`
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for the client, or returns how long until one is available
+    pub fn acquire(&self, client: &str) -> Result<(), Duration> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    fn acquire_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+/// Checks the API key and rate limit of incoming requests
+pub struct Gatekeeper {
+    keys: Vec<String>,
+    limiter: Option<RateLimiter>,
+}
+
+impl Gatekeeper {
+    /// Collects the keys from the settings and environment and sets up rate limiting
+    pub fn from_settings(settings: &AuthSettings) -> Self {
+        let mut keys = settings.api_keys.clone();
+        if let Ok(value) = std::env::var(&settings.api_keys_env) {
+            keys.extend(value.split(',').map(str::trim).filter(|key| !key.is_empty()).map(str::to_string));
+        }
+
+        let limiter = settings.requests_per_second
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| RateLimiter::new(rate, settings.burst.unwrap_or(rate)));
+
+        Self { keys, limiter }
+    }
+
+    /// Returns true if requests must carry an API key
+    pub fn requires_key(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Admits or rejects a request given its Authorization header and peer address
+    ///
+    /// Without configured keys every request is admitted and rate limited by peer address.
+    pub fn admit(&self, authorization: Option<&str>, peer: &str) -> Result<(), ServeError> {
+        let client = if self.requires_key() {
+            let key = authorization
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(str::trim)
+                .ok_or(ServeError::Unauthorized)?;
+            if !self.keys.iter().any(|known| constant_time_eq(known.as_bytes(), key.as_bytes())) {
+                return Err(ServeError::Unauthorized);
+            }
+            key
+        } else {
+            peer
+        };
+
+        match &self.limiter {
+            Some(limiter) => limiter.acquire(client).map_err(ServeError::RateLimited),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Compares two byte strings without exiting early on the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_and_rate_limit() {
+        let gatekeeper = Gatekeeper::from_settings(&AuthSettings {
+            api_keys: vec!["secret".to_string()],
+            api_keys_env: "RUST_EMBED_TEST_UNSET_KEYS".to_string(),
+            requests_per_second: Some(1.0),
+            burst: Some(2.0),
+        });
+
+        assert!(matches!(gatekeeper.admit(None, "peer"), Err(ServeError::Unauthorized)));
+        assert!(matches!(gatekeeper.admit(Some("Bearer wrong"), "peer"), Err(ServeError::Unauthorized)));
+        assert!(gatekeeper.admit(Some("Bearer secret"), "peer").is_ok());
+        assert!(gatekeeper.admit(Some("Bearer secret"), "peer").is_ok());
+        assert!(matches!(gatekeeper.admit(Some("Bearer secret"), "peer"), Err(ServeError::RateLimited(_))));
+
+        let limiter = RateLimiter::new(2.0, 1.0);
+        let start = Instant::now();
+        assert!(limiter.acquire_at("a", start).is_ok());
+        assert!(limiter.acquire_at("a", start).is_err());
+        assert!(limiter.acquire_at("b", start).is_ok());
+        assert!(limiter.acquire_at("a", start + Duration::from_millis(500)).is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+use super::{rpc, ModelRouter};
+use crate::embedding::{Embedder, ModelInfo};
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Environment variable overriding where the daemon listens and the CLI looks for it
+pub const SOCKET_ENV: &str = "RUST_EMBED_DAEMON_SOCKET";
+
+/// Socket of the daemon: `RUST_EMBED_DAEMON_SOCKET`, else `rust_embed.sock` in the user's runtime directory or `Paths::cache`
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_ENV).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    dirs::runtime_dir()
+        .unwrap_or_else(|| crate::utils::paths::current().cache)
+        .join("rust_embed.sock")
+}
+
+/// Keeps the router's models loaded and answers JSON-RPC on a Unix domain socket until the process exits
+///
+/// Fails if another daemon already answers on the socket, a stale socket file left by a daemon that was killed
+/// is replaced.
+pub fn run_daemon(router: Arc<ModelRouter>, path: &Path) -> Result<()> {
+    if DaemonClient::connect(path).is_some() {
+        return Err(err!("A daemon is already listening on {}", path.display()));
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    rpc::serve_uds(router, path)
+}
+
+/// Embedder that sends its texts to a running daemon's default model
+///
+/// Each call opens its own connection, so clones can be used from several threads.
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    path: PathBuf,
+    info: ModelInfo,
+}
+
+impl DaemonClient {
+    /// Connects to the daemon on a socket, None when no daemon answers there
+    pub fn connect(path: impl Into<PathBuf>) -> Option<Self> {
+        let path = path.into();
+        let mut client = Self { path, info: ModelInfo::default() };
+        let mut model = client.call("describe", Value::Null).ok()?;
+        client.info = serde_json::from_value(model["info"].take()).ok()?;
+        Some(client)
+    }
+
+    /// Socket the daemon listens on
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends one JSON-RPC request and waits for its result
+    #[cfg(unix)]
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let mut stream = UnixStream::connect(&self.path)
+            .with_context(|| format!("Failed to connect to the daemon on {}", self.path.display()))?;
+        writeln!(stream, "{}", json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        let mut response: Value = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid response from the daemon on {}", self.path.display()))?;
+        if let Some(error) = response.get("error") {
+            return Err(err!("Daemon error: {}", error["message"].as_str().unwrap_or("unknown error")));
+        }
+        Ok(response["result"].take())
+    }
+
+    /// Unix domain sockets are not available on this platform
+    #[cfg(not(unix))]
+    fn call(&self, _method: &str, _params: Value) -> Result<Value> {
+        Err(err!("Cannot reach the daemon on {}, Unix domain sockets are not supported on this platform", self.path.display()))
+    }
+}
+
+impl Embedder for DaemonClient {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        let mut embeddings = self.embed_batch(&[text.to_string()])?;
+        embeddings.pop().ok_or_else(|| err!("Daemon returned no embedding"))
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let result = self.call("embed", json!({ "input": texts }))?;
+        let embeddings: Vec<Vec<f32>> = serde_json::from_value(result["embeddings"].clone())
+            .map_err(|e| err!("Invalid embeddings from the daemon: {}", e))?;
+        if embeddings.len() != texts.len() {
+            return Err(err!("Daemon returned {} embeddings for {} texts", embeddings.len(), texts.len()));
+        }
+        Ok(embeddings.into_iter().map(Array1::from).collect())
+    }
+
+    fn model_name(&self) -> &str {
+        &self.info.name
+    }
+
+    fn model_version(&self) -> &str {
+        &self.info.version
+    }
+
+    fn dimension(&self) -> usize {
+        self.info.dimension
+    }
+
+    /// The daemon's model info, with the device and weights it runs on
+    fn model_info(&self) -> ModelInfo {
+        self.info.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::registry::ModelSpec;
+    use crate::server::HostedModelConfig;
+    use crate::test_support::LengthEmbedder;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_client_embeds_through_daemon() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_daemon_{}.sock", std::process::id()));
+        assert!(DaemonClient::connect(&path).is_none());
+
+        let mut router = ModelRouter::new();
+        router.host_with(HostedModelConfig::new("length", ModelSpec::new("length")), || Ok(LengthEmbedder::default()))?;
+        let router = Arc::new(router);
+        let server_path = path.clone();
+        std::thread::spawn(move || run_daemon(router, &server_path));
+
+        let client = (0..100)
+            .find_map(|_| DaemonClient::connect(&path).or_else(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                None
+            }))
+            .context("Daemon did not start")?;
+        assert_eq!((client.model_name(), client.dimension()), ("length", 2));
+        let embeddings = client.embed_batch(&["ab".to_string(), "abcd".to_string()])?;
+        assert_eq!(embeddings[1], Array1::from_elem(2, 4.0));
+        assert!(run_daemon(Arc::new(ModelRouter::new()), &path).is_err());
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use super::{EmbedRequest, Gatekeeper, ModelRouter, ServeError, ServerSettings};
+use crate::error::{err, Result};
+use serde::Serialize;
+use std::io::Read;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Maximum accepted request body size (16 MB)
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Serves the router over HTTP until the process exits
+///
+/// Endpoints:
+/// - `POST /embed` with `{"model": "name", "input": "text" | ["text", ...]}`
+/// - `GET /models` listing the hosted models
+/// - `GET /metrics` in the Prometheus text format
+/// - `GET /health`, which needs no API key
+pub fn serve(router: Arc<ModelRouter>, settings: &ServerSettings) -> Result<()> {
+    let server = Server::http(&settings.bind)
+        .map_err(|e| err!("Failed to bind {}: {}", settings.bind, e))?;
+    let server = Arc::new(server);
+    let gatekeeper = Arc::new(Gatekeeper::from_settings(&settings.auth));
+
+    if gatekeeper.requires_key() {
+        tracing::info!("API keys are required");
+    } else if !settings.bind.starts_with("127.0.0.1") && !settings.bind.starts_with("localhost") {
+        tracing::warn!("Listening on {} without API keys, anyone who can reach it may use it", settings.bind);
+    }
+
+    tracing::info!("Listening on http://{}", settings.bind);
+
+    let handles: Vec<_> = (0..settings.http_threads.max(1))
+        .map(|i| {
+            let server = Arc::clone(&server);
+            let router = Arc::clone(&router);
+            let gatekeeper = Arc::clone(&gatekeeper);
+
+            thread::Builder::new()
+                .name(format!("http-{}", i))
+                .spawn(move || {
+                    while let Ok(request) = server.recv() {
+                        handle(&router, &gatekeeper, request);
+                    }
+                })
+        })
+        .collect::<std::io::Result<_>>()?;
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+/// Handles a single HTTP request
+#[tracing::instrument(name = "http_request", skip_all, fields(method = %request.method(), url = %request.url()))]
+fn handle(router: &ModelRouter, gatekeeper: &Gatekeeper, mut request: Request) {
+    let admitted = match (request.method(), request.url()) {
+        (Method::Get, "/health") => Ok(()),
+        _ => {
+            let authorization = request.headers()
+                .iter()
+                .find(|header| header.field.equiv("Authorization"))
+                .map(|header| header.value.as_str().to_string());
+            let peer = request.remote_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+            gatekeeper.admit(authorization.as_deref(), &peer)
+        }
+    };
+
+    let result = admitted.and_then(|()| match (request.method(), request.url()) {
+        (Method::Post, "/embed") => read_json::<EmbedRequest>(&mut request)
+            .and_then(|body| router.embed(body))
+            .and_then(|response| to_json(&response)),
+        (Method::Get, "/models") => to_json(&router.models()),
+        (Method::Get, "/metrics") => Ok(router.metrics().render()),
+        (Method::Get, "/health") => Ok(r#"{"status":"ok"}"#.to_string()),
+        (_, url) => Err(ServeError::BadRequest(format!("no route for {}", url))),
+    });
+
+    let response = match result {
+        Ok(body) if request.url() == "/metrics" => text_response(body),
+        Ok(body) => json_response(body, 200),
+        Err(e) => {
+            let status = status_code(&e);
+            if status >= 500 {
+                tracing::warn!("Request to {} failed: {}", request.url(), e);
+            }
+            let response = json_response(serde_json::json!({ "error": e.to_string() }).to_string(), status);
+            match &e {
+                ServeError::RateLimited(wait) => {
+                    let seconds = wait.as_secs_f64().ceil().max(1.0).to_string();
+                    let header = Header::from_bytes(&b"Retry-After"[..], seconds.as_bytes())
+                        .expect("numeric header is valid");
+                    response.with_header(header)
+                }
+                _ => response,
+            }
+        }
+    };
+
+    if let Err(e) = request.respond(response) {
+        tracing::warn!("Failed to send response: {}", e);
+    }
+}
+
+/// Maps a serve error to an HTTP status code
+fn status_code(error: &ServeError) -> u16 {
+    match error {
+        ServeError::UnknownModel(_) => 404,
+        ServeError::NoDefaultModel | ServeError::BadRequest(_) => 400,
+        ServeError::Unauthorized => 401,
+        ServeError::RateLimited(_) => 429,
+        ServeError::Overloaded(_) => 503,
+        ServeError::Internal(_) => 500,
+    }
+}
+
+/// Reads and parses a JSON request body
+fn read_json<T: serde::de::DeserializeOwned>(request: &mut Request) -> Result<T, ServeError> {
+    let mut body = String::new();
+    request.as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_string(&mut body)
+        .map_err(|e| ServeError::BadRequest(format!("failed to read body: {}", e)))?;
+
+    serde_json::from_str(&body)
+        .map_err(|e| ServeError::BadRequest(format!("invalid JSON: {}", e)))
+}
+
+/// Serializes a response body
+fn to_json<T: Serialize>(value: &T) -> Result<String, ServeError> {
+    serde_json::to_string(value)
+        .map_err(|e| ServeError::Internal(format!("failed to serialize response: {}", e)))
+}
+
+/// Builds a JSON response with the given status code
+fn json_response(body: String, status: u16) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header)
+}
+
+/// Builds a Prometheus text response
+fn text_response(body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+        .expect("static header is valid");
+    Response::from_string(body).with_header(header)
+}
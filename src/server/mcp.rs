@@ -0,0 +1,267 @@
+use super::rpc::{self, INVALID_PARAMS, METHOD_NOT_FOUND};
+use super::{EmbedInput, EmbedRequest, ModelRouter, ServeError};
+use crate::search;
+#[cfg(feature = "proto-store")]
+use crate::utils;
+use crate::error::{err, Result};
+use ndarray::Array1;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+#[cfg(feature = "proto-store")]
+use std::path::Path;
+use std::sync::Arc;
+
+/// MCP protocol revision implemented by this server
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Number of search results returned when the client does not ask for a count
+const DEFAULT_TOP_K: usize = 5;
+
+/// A stored embedding collection searched by the `search` tool
+pub struct SearchCorpus {
+    texts: Vec<String>,
+    embeddings: Vec<Array1<f32>>,
+}
+
+impl SearchCorpus {
+    /// Loads a collection saved with its texts, as written by `--output` or a proto pipeline sink
+    #[cfg(feature = "proto-store")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (embeddings, texts) = utils::load_embeddings(path)?;
+        let texts = texts.ok_or_else(|| err!("{} does not contain texts", path.display()))?;
+        Ok(Self { texts, embeddings })
+    }
+
+    /// Number of documents in the collection
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    /// Returns true if the collection holds no documents
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+}
+
+/// Arguments of the `tools/call` method
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Arguments of the `embed` tool
+#[derive(Deserialize)]
+struct EmbedArguments {
+    texts: Vec<String>,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// Arguments of the `search` tool
+#[derive(Deserialize)]
+struct SearchArguments {
+    query: String,
+    #[serde(default)]
+    top_k: Option<usize>,
+}
+
+/// Exposes the hosted models as Model Context Protocol tools
+///
+/// Tools:
+/// - `embed` returns the embeddings of `texts`, optionally with a named `model`
+/// - `search` returns the stored texts closest to `query`, when a corpus is loaded
+pub struct McpServer {
+    router: Arc<ModelRouter>,
+    corpus: Option<SearchCorpus>,
+}
+
+impl McpServer {
+    /// Creates a server offering only the `embed` tool
+    pub fn new(router: Arc<ModelRouter>) -> Self {
+        Self { router, corpus: None }
+    }
+
+    /// Adds a corpus for the `search` tool, embedded with the default model
+    pub fn with_corpus(mut self, corpus: SearchCorpus) -> Result<Self> {
+        let model = self.router.route(None)?;
+        if let Some(embedding) = corpus.embeddings.first() {
+            if embedding.len() != model.dimension() {
+                return Err(err!(
+                    "Corpus embeddings have {} dimensions but model '{}' produces {}",
+                    embedding.len(), model.name(), model.dimension()
+                ));
+            }
+        }
+        self.corpus = Some(corpus);
+        Ok(self)
+    }
+
+    /// Answers newline-delimited JSON-RPC messages until the input closes
+    pub fn serve<R: BufRead, W: Write>(&self, input: R, output: W) -> Result<()> {
+        rpc::serve_lines(input, output, |line| self.handle(line))
+    }
+
+    /// Answers a single message, notifications get no response
+    pub fn handle(&self, line: &str) -> Option<Value> {
+        let message = match rpc::parse_message(line) {
+            Ok(message) => message,
+            Err(response) => return Some(response),
+        };
+
+        let id = message.id?;
+        let _span = tracing::debug_span!("mcp_request", method = %message.method).entered();
+
+        let result = match message.method.as_str() {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "rust_embed", "version": crate::VERSION },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.tools() })),
+            "tools/call" => serde_json::from_value::<ToolCall>(message.params)
+                .map_err(|e| (INVALID_PARAMS, format!("Invalid tool call: {}", e)))
+                .and_then(|call| self.call(&call.name, call.arguments)),
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+
+        Some(match result {
+            Ok(result) => rpc::result_response(id, result),
+            Err((code, message)) => rpc::error_response(id, code, &message),
+        })
+    }
+
+    /// Descriptions and input schemas of the offered tools
+    fn tools(&self) -> Vec<Value> {
+        let mut tools = vec![json!({
+            "name": "embed",
+            "description": "Embed texts into dense vectors with a local model",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "texts": { "type": "array", "items": { "type": "string" }, "description": "Texts to embed" },
+                    "model": { "type": "string", "description": "Hosted model name, the default model if omitted" },
+                },
+                "required": ["texts"],
+            },
+        })];
+
+        if let Some(corpus) = &self.corpus {
+            tools.push(json!({
+                "name": "search",
+                "description": format!("Find the most relevant of {} indexed documents for a query", corpus.len()),
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "What to search for" },
+                        "top_k": { "type": "integer", "minimum": 1, "description": "Number of results, 5 if omitted" },
+                    },
+                    "required": ["query"],
+                },
+            }));
+        }
+
+        tools
+    }
+
+    /// Runs a tool, failures are reported to the client as tool errors
+    fn call(&self, name: &str, arguments: Value) -> Result<Value, (i64, String)> {
+        let output = match name {
+            "embed" => {
+                let arguments: EmbedArguments = serde_json::from_value(arguments)
+                    .map_err(|e| (INVALID_PARAMS, format!("Invalid arguments for embed: {}", e)))?;
+                self.embed(arguments)
+            }
+            "search" if self.corpus.is_some() => {
+                let arguments: SearchArguments = serde_json::from_value(arguments)
+                    .map_err(|e| (INVALID_PARAMS, format!("Invalid arguments for search: {}", e)))?;
+                self.search(arguments)
+            }
+            name => return Err((INVALID_PARAMS, format!("Unknown tool '{}'", name))),
+        };
+
+        Ok(match output {
+            Ok(output) => json!({ "content": [{ "type": "text", "text": output.to_string() }], "isError": false }),
+            Err(e) => json!({ "content": [{ "type": "text", "text": e.to_string() }], "isError": true }),
+        })
+    }
+
+    /// The `embed` tool
+    fn embed(&self, arguments: EmbedArguments) -> Result<Value, ServeError> {
+        let response = self.router.embed(EmbedRequest {
+            model: arguments.model,
+            input: EmbedInput::Batch(arguments.texts),
+        })?;
+        serde_json::to_value(response).map_err(|e| ServeError::Internal(e.to_string()))
+    }
+
+    /// The `search` tool
+    fn search(&self, arguments: SearchArguments) -> Result<Value, ServeError> {
+        let corpus = self.corpus.as_ref().ok_or_else(|| ServeError::BadRequest("no corpus loaded".to_string()))?;
+        let query = self.router.route(None)?
+            .embed(vec![arguments.query])?
+            .pop()
+            .ok_or_else(|| ServeError::Internal("model returned no embedding".to_string()))?;
+
+        let hits: Vec<Value> = search::top_k(&query, &corpus.embeddings, arguments.top_k.unwrap_or(DEFAULT_TOP_K))
+            .into_iter()
+            .map(|hit| json!({ "index": hit.index, "score": hit.score, "text": corpus.texts[hit.index] }))
+            .collect();
+        Ok(Value::Array(hits))
+    }
+}
+
+/// Serves the router as an MCP server over stdin and stdout until stdin closes
+pub fn serve_stdio(server: &McpServer) -> Result<()> {
+    tracing::info!("Serving MCP over stdio");
+    server.serve(std::io::stdin().lock(), std::io::stdout().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::Embedder;
+    use crate::models::registry::ModelSpec;
+    use crate::server::HostedModelConfig;
+    use crate::test_support::TopicEmbedder;
+
+    #[test]
+    fn test_lists_and_calls_tools() -> Result<()> {
+        let mut router = ModelRouter::new();
+        router.host_with(HostedModelConfig::new("topic", ModelSpec::new("topic")), || Ok(TopicEmbedder))?;
+        let texts = vec!["baking bread".to_string(), "rust borrow checker".to_string()];
+        let corpus = SearchCorpus { embeddings: TopicEmbedder.embed_batch(&texts)?, texts };
+        let server = McpServer::new(Arc::new(router)).with_corpus(corpus)?;
+
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"search","arguments":{"query":"rust lifetimes","top_k":1}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"embed","arguments":{"texts":["x"],"model":"missing"}}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"resources/list"}"#,
+        ].join("\n");
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output)?;
+
+        let responses: Vec<Value> = String::from_utf8(output)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0]["result"]["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(responses[1]["result"]["tools"].as_array().map(Vec::len), Some(2));
+
+        let hits: Value = serde_json::from_str(responses[2]["result"]["content"][0]["text"].as_str().unwrap_or_default())?;
+        assert_eq!(hits[0]["text"], "rust borrow checker");
+
+        assert_eq!(responses[3]["result"]["isError"], true);
+        assert_eq!(responses[4]["error"]["code"], METHOD_NOT_FOUND);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,694 @@
+pub mod auth;
+pub mod daemon;
+pub mod http;
+pub mod mcp;
+pub mod rpc;
+
+pub use auth::{AuthSettings, Gatekeeper, RateLimiter};
+pub use daemon::DaemonClient;
+pub use mcp::{McpServer, SearchCorpus};
+
+use crate::embedding::{Embedder, ModelInfo};
+use crate::metrics::{Metrics, ModelMetrics};
+use crate::models::registry::ModelSpec;
+use crate::error::{err, Context, Result};
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Errors surfaced to clients of the embedding server
+#[derive(Debug, thiserror::Error)]
+pub enum ServeError {
+    #[error("unknown model '{0}'")]
+    UnknownModel(String),
+
+    #[error("no model specified and no default model configured")]
+    NoDefaultModel,
+
+    #[error("model '{0}' is at capacity, retry later")]
+    Overloaded(String),
+
+    #[error("missing or invalid API key")]
+    Unauthorized,
+
+    #[error("rate limit exceeded, retry in {0:?}")]
+    RateLimited(Duration),
+
+    #[error("invalid request: {0}")]
+    BadRequest(String),
+
+    #[error("embedding failed: {0}")]
+    Internal(String),
+}
+
+impl From<ServeError> for crate::Error {
+    fn from(error: ServeError) -> Self {
+        crate::Error::other(error)
+    }
+}
+
+/// Top-level server configuration, usually loaded from a TOML file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    /// General server settings
+    #[serde(default)]
+    pub server: ServerSettings,
+
+    /// Models hosted by this server
+    #[serde(default)]
+    pub models: Vec<HostedModelConfig>,
+}
+
+impl ServerConfig {
+    /// Loads the server configuration from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read server config {}", path.display()))?;
+        let config: ServerConfig = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse server config {}", path.display()))?;
+
+        if config.models.is_empty() {
+            return Err(err!("Server config {} does not declare any [[models]]", path.display()));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Network and routing settings for the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerSettings {
+    /// Address to listen on
+    #[serde(default = "default_bind")]
+    pub bind: String,
+
+    /// Model used when a request does not name one, the first model if unset
+    #[serde(default)]
+    pub default_model: Option<String>,
+
+    /// Number of threads accepting HTTP requests
+    #[serde(default = "default_http_threads")]
+    pub http_threads: usize,
+
+    /// API keys and rate limits, requests are open and unlimited by default
+    #[serde(default)]
+    pub auth: AuthSettings,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+            default_model: None,
+            http_threads: default_http_threads(),
+            auth: AuthSettings::default(),
+        }
+    }
+}
+
+/// Configuration of a single hosted model
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostedModelConfig {
+    /// Name used to route requests to this model
+    pub name: String,
+
+    /// Alternative names routed to this model
+    #[serde(default)]
+    pub aliases: Vec<String>,
+
+    /// Which model to load and on which device
+    #[serde(flatten)]
+    pub spec: ModelSpec,
+
+    /// Maximum number of texts sent through the model in one call
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+
+    /// Number of requests processed concurrently, each worker holds its own model instance
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+
+    /// Number of requests allowed to wait for a free worker before rejecting
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+
+    /// How long a worker waits for more requests to fill a batch, 0 only merges already queued requests
+    #[serde(default = "default_max_batch_delay_ms")]
+    pub max_batch_delay_ms: u64,
+}
+
+impl HostedModelConfig {
+    /// Creates a hosted model config with default limits
+    pub fn new(name: &str, spec: ModelSpec) -> Self {
+        Self {
+            name: name.to_string(),
+            aliases: Vec::new(),
+            spec,
+            max_batch_size: default_max_batch_size(),
+            max_concurrency: default_max_concurrency(),
+            max_queue: default_max_queue(),
+            max_batch_delay_ms: default_max_batch_delay_ms(),
+        }
+    }
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+fn default_http_threads() -> usize {
+    4
+}
+
+fn default_max_batch_size() -> usize {
+    32
+}
+
+fn default_max_concurrency() -> usize {
+    1
+}
+
+fn default_max_queue() -> usize {
+    64
+}
+
+fn default_max_batch_delay_ms() -> u64 {
+    2
+}
+
+/// Texts to embed, either a single string or a list
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum EmbedInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+impl EmbedInput {
+    /// Converts the input into a list of texts
+    pub fn into_texts(self) -> Vec<String> {
+        match self {
+            EmbedInput::Single(text) => vec![text],
+            EmbedInput::Batch(texts) => texts,
+        }
+    }
+}
+
+/// A request to embed one or more texts
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbedRequest {
+    /// Name or alias of the model to use, the default model if omitted
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// Texts to embed
+    pub input: EmbedInput,
+}
+
+/// The embeddings produced for a request
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbedResponse {
+    /// Name of the hosted model that served the request
+    pub model: String,
+
+    /// Dimension of each embedding
+    pub dimension: usize,
+
+    /// One embedding per input text, in input order
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// Public description of a hosted model
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelDescription {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub model_name: String,
+    pub model_version: String,
+    pub dimension: usize,
+    pub info: ModelInfo,
+    pub max_batch_size: usize,
+    pub max_concurrency: usize,
+    pub max_batch_delay_ms: u64,
+    pub in_flight: usize,
+}
+
+/// A unit of work handed to a model worker
+struct Job {
+    texts: Vec<String>,
+    reply: mpsc::Sender<Result<Vec<Array1<f32>>>>,
+    // Span of the request, so the batch it lands in can be linked back to it
+    span: tracing::Span,
+}
+
+/// A model hosted on its own pool of worker threads
+pub struct HostedModel {
+    config: HostedModelConfig,
+    info: ModelInfo,
+    sender: SyncSender<Job>,
+    in_flight: AtomicUsize,
+    metrics: Arc<ModelMetrics>,
+}
+
+impl HostedModel {
+    /// Spawns the workers for a model, each building its own embedder with the factory
+    pub fn spawn<E, F>(config: HostedModelConfig, factory: F) -> Result<Self>
+    where
+        E: Embedder + 'static,
+        F: Fn() -> Result<E> + Send + Sync + 'static,
+    {
+        let workers = config.max_concurrency.max(1);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(config.max_queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let factory = Arc::new(factory);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let metrics = Arc::new(ModelMetrics::new(config.spec.device.as_deref().unwrap_or("default")));
+
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let factory = Arc::clone(&factory);
+            let ready_tx = ready_tx.clone();
+            let metrics = Arc::clone(&metrics);
+            let max_batch_size = config.max_batch_size.max(1);
+            let max_delay = Duration::from_millis(config.max_batch_delay_ms);
+
+            thread::Builder::new()
+                .name(format!("{}-worker-{}", config.name, i))
+                .spawn(move || {
+                    // Build the model on the worker thread, since model instances are thread-local
+                    let embedder = match factory() {
+                        Ok(embedder) => embedder,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+
+                    let _ = ready_tx.send(Ok(embedder.model_info()));
+                    drop(ready_tx);
+
+                    run_worker(&embedder, &receiver, &metrics, max_batch_size, max_delay);
+                })?;
+        }
+        drop(ready_tx);
+
+        // Wait for every worker to load its model before accepting requests
+        let mut info = None;
+        for _ in 0..workers {
+            let result = ready_rx.recv()
+                .map_err(|_| err!("Worker for model '{}' exited during startup", config.name))?;
+            info = Some(result.with_context(|| format!("Failed to load model '{}'", config.name))?);
+        }
+        let info: ModelInfo = info
+            .ok_or_else(|| err!("Model '{}' has no workers", config.name))?;
+
+        tracing::info!(
+            "Hosting model '{}' ({} v{}, {} dimensions) with {} worker(s)",
+            config.name, info.name, info.version, info.dimension, workers
+        );
+
+        Ok(Self {
+            config,
+            info,
+            sender,
+            in_flight: AtomicUsize::new(0),
+            metrics,
+        })
+    }
+
+    /// The routing name of this model
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Dimension of the embeddings produced by this model
+    pub fn dimension(&self) -> usize {
+        self.info.dimension
+    }
+
+    /// Counters and histograms collected while serving this model
+    pub fn metrics(&self) -> Arc<ModelMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Embeds texts on one of the model workers, rejecting the call when the queue is full
+    #[tracing::instrument(name = "embed_request", skip_all, fields(model = %self.config.name, texts = texts.len()))]
+    pub fn embed(&self, texts: Vec<String>) -> Result<Vec<Array1<f32>>, ServeError> {
+        let (reply, response) = mpsc::channel();
+        let start = Instant::now();
+
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let result = match self.sender.try_send(Job { texts, reply, span: tracing::Span::current() }) {
+            Ok(()) => response.recv()
+                .map_err(|_| ServeError::Internal(format!("worker for '{}' stopped", self.name())))
+                .and_then(|result| result.map_err(|e| ServeError::Internal(e.report()))),
+            Err(TrySendError::Full(_)) => Err(ServeError::Overloaded(self.name().to_string())),
+            Err(TrySendError::Disconnected(_)) => {
+                Err(ServeError::Internal(format!("worker for '{}' stopped", self.name())))
+            }
+        };
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.metrics.record_request(result.is_ok(), start.elapsed());
+
+        result
+    }
+
+    /// Describes the hosted model for listing endpoints
+    pub fn describe(&self) -> ModelDescription {
+        ModelDescription {
+            name: self.config.name.clone(),
+            aliases: self.config.aliases.clone(),
+            model_name: self.info.name.clone(),
+            model_version: self.info.version.clone(),
+            dimension: self.info.dimension,
+            info: self.info.clone(),
+            max_batch_size: self.config.max_batch_size,
+            max_concurrency: self.config.max_concurrency,
+            max_batch_delay_ms: self.config.max_batch_delay_ms,
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Processes jobs until the model is dropped, coalescing concurrent requests into shared batches
+fn run_worker<E: Embedder>(
+    embedder: &E,
+    receiver: &Mutex<Receiver<Job>>,
+    metrics: &ModelMetrics,
+    max_batch_size: usize,
+    max_delay: Duration,
+) {
+    loop {
+        // Hold the queue while collecting, so each batch is filled by a single worker
+        let jobs = {
+            let Ok(receiver) = receiver.lock() else { return };
+            let Ok(first) = receiver.recv() else { return };
+            collect_jobs(&receiver, first, max_batch_size, max_delay)
+        };
+
+        let span = tracing::debug_span!("batch", requests = jobs.len());
+        for job in &jobs {
+            span.follows_from(&job.span);
+        }
+        let _entered = span.enter();
+
+        if jobs.len() == 1 {
+            for job in jobs {
+                let _ = job.reply.send(embed_chunked(embedder, &job.texts, metrics, max_batch_size));
+            }
+            continue;
+        }
+
+        let texts: Vec<String> = jobs.iter().flat_map(|job| job.texts.iter().cloned()).collect();
+        match embed_chunked(embedder, &texts, metrics, max_batch_size) {
+            Ok(embeddings) => {
+                let mut embeddings = embeddings.into_iter();
+                for job in jobs {
+                    let _ = job.reply.send(Ok(embeddings.by_ref().take(job.texts.len()).collect()));
+                }
+            }
+            Err(_) => {
+                // Retry each request alone so one bad input doesn't fail the requests batched with it
+                for job in jobs {
+                    let _ = job.reply.send(embed_chunked(embedder, &job.texts, metrics, max_batch_size));
+                }
+            }
+        }
+    }
+}
+
+/// Gathers queued jobs behind the first until the batch is full or the delay has passed
+fn collect_jobs(receiver: &Receiver<Job>, first: Job, max_batch_size: usize, max_delay: Duration) -> Vec<Job> {
+    let deadline = Instant::now() + max_delay;
+    let mut texts = first.texts.len();
+    let mut jobs = vec![first];
+
+    while texts < max_batch_size {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let job = if remaining.is_zero() {
+            receiver.try_recv().ok()
+        } else {
+            receiver.recv_timeout(remaining).ok()
+        };
+
+        let Some(job) = job else { break };
+        texts += job.texts.len();
+        jobs.push(job);
+    }
+
+    jobs
+}
+
+/// Embeds texts in chunks of at most `max_batch_size`, recording each forward pass
+fn embed_chunked<E: Embedder>(
+    embedder: &E,
+    texts: &[String],
+    metrics: &ModelMetrics,
+    max_batch_size: usize,
+) -> Result<Vec<Array1<f32>>> {
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for chunk in texts.chunks(max_batch_size) {
+        let start = Instant::now();
+        embeddings.extend(embedder.embed_batch(chunk)?);
+        metrics.record_batch(chunk.len(), start.elapsed());
+    }
+    Ok(embeddings)
+}
+
+/// Routes embedding requests to one of several hosted models
+#[derive(Default)]
+pub struct ModelRouter {
+    models: HashMap<String, Arc<HostedModel>>,
+    aliases: HashMap<String, String>,
+    default_model: Option<String>,
+    metrics: Metrics,
+}
+
+impl ModelRouter {
+    /// Creates an empty router
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a router hosting every model declared in the config
+    pub fn from_config(config: &ServerConfig) -> Result<Self> {
+        let mut router = Self::new();
+
+        for model in &config.models {
+            router.host(model.clone())?;
+        }
+
+        if let Some(default_model) = &config.server.default_model {
+            router.set_default(default_model)?;
+        }
+
+        Ok(router)
+    }
+
+    /// Hosts a model from the registry
+    pub fn host(&mut self, config: HostedModelConfig) -> Result<()> {
+        let spec = config.spec.clone();
+        self.host_with(config, move || spec.build())
+    }
+
+    /// Hosts a model built by a custom factory, the first hosted model becomes the default
+    pub fn host_with<E, F>(&mut self, config: HostedModelConfig, factory: F) -> Result<()>
+    where
+        E: Embedder + 'static,
+        F: Fn() -> Result<E> + Send + Sync + 'static,
+    {
+        let name = config.name.clone();
+        let aliases = config.aliases.clone();
+
+        if self.models.contains_key(&name) || self.aliases.contains_key(&name) {
+            return Err(err!("Model name '{}' is already in use", name));
+        }
+        if let Some(alias) = aliases.iter().find(|a| self.models.contains_key(*a) || self.aliases.contains_key(*a)) {
+            return Err(err!("Alias '{}' of model '{}' is already in use", alias, name));
+        }
+
+        let model = HostedModel::spawn(config, factory)?;
+        self.metrics.register(&name, model.metrics());
+        for alias in aliases {
+            self.aliases.insert(alias, name.clone());
+        }
+        if self.default_model.is_none() {
+            self.default_model = Some(name.clone());
+        }
+        self.models.insert(name, Arc::new(model));
+
+        Ok(())
+    }
+
+    /// Sets the model used for requests that do not name one
+    pub fn set_default(&mut self, name: &str) -> Result<()> {
+        let model = self.route(Some(name))
+            .map_err(|_| err!("Default model '{}' is not hosted", name))?;
+        self.default_model = Some(model.name().to_string());
+        Ok(())
+    }
+
+    /// Resolves a model name or alias, falling back to the default model
+    pub fn route(&self, model: Option<&str>) -> Result<Arc<HostedModel>, ServeError> {
+        let name = match model {
+            Some(name) => self.aliases.get(name).map(String::as_str).unwrap_or(name),
+            None => self.default_model.as_deref().ok_or(ServeError::NoDefaultModel)?,
+        };
+
+        self.models.get(name)
+            .cloned()
+            .ok_or_else(|| ServeError::UnknownModel(name.to_string()))
+    }
+
+    /// Serves an embedding request
+    pub fn embed(&self, request: EmbedRequest) -> Result<EmbedResponse, ServeError> {
+        let model = self.route(request.model.as_deref())?;
+        let texts = request.input.into_texts();
+
+        if texts.is_empty() {
+            return Err(ServeError::BadRequest("input must contain at least one text".to_string()));
+        }
+
+        let embeddings = model.embed(texts)?;
+
+        Ok(EmbedResponse {
+            model: model.name().to_string(),
+            dimension: model.dimension(),
+            embeddings: embeddings.into_iter().map(|e| e.to_vec()).collect(),
+        })
+    }
+
+    /// Metrics of all hosted models
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Describes all hosted models, sorted by name
+    pub fn models(&self) -> Vec<ModelDescription> {
+        let mut models: Vec<ModelDescription> = self.models.values()
+            .map(|model| model.describe())
+            .collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        models
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CountingEmbedder, LengthEmbedder};
+
+    fn hosted(name: &str, dimension: usize) -> (HostedModelConfig, impl Fn() -> Result<LengthEmbedder> + Send + Sync) {
+        let config = HostedModelConfig::new(name, ModelSpec::new("length"));
+        (config, move || Ok(LengthEmbedder { dimension, ..LengthEmbedder::default() }))
+    }
+
+    fn request(model: Option<&str>, texts: &[&str]) -> EmbedRequest {
+        EmbedRequest {
+            model: model.map(str::to_string),
+            input: EmbedInput::Batch(texts.iter().map(|t| t.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_routes_by_name_alias_and_default() -> Result<()> {
+        let mut router = ModelRouter::new();
+
+        let (fast, factory) = hosted("fast", 2);
+        router.host_with(fast, factory)?;
+
+        let (mut quality, factory) = hosted("quality", 4);
+        quality.aliases.push("hq".to_string());
+        router.host_with(quality, factory)?;
+        router.set_default("fast")?;
+
+        assert_eq!(router.embed(request(Some("quality"), &["abc"]))?.dimension, 4);
+        assert_eq!(router.embed(request(Some("hq"), &["abc"]))?.model, "quality");
+        assert_eq!(router.embed(request(None, &["abc"]))?.model, "fast");
+        assert!(matches!(router.embed(request(Some("missing"), &["abc"])), Err(ServeError::UnknownModel(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserves_order_across_batches() -> Result<()> {
+        let mut router = ModelRouter::new();
+
+        let (mut config, factory) = hosted("fast", 1);
+        config.max_batch_size = 2;
+        router.host_with(config, factory)?;
+
+        let response = router.embed(request(None, &["a", "bb", "ccc", "dddd", "eeeee"]))?;
+        let lengths: Vec<f32> = response.embeddings.iter().map(|e| e[0]).collect();
+        assert_eq!(lengths, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_coalesces_concurrent_requests() -> Result<()> {
+        let batches = Arc::new(AtomicUsize::new(0));
+        let mut config = HostedModelConfig::new("fast", ModelSpec::new("counting"));
+        config.max_batch_delay_ms = 200;
+        config.max_batch_size = 4;
+
+        let embedder = CountingEmbedder { batches: Arc::clone(&batches), ..CountingEmbedder::default() };
+        let model = Arc::new(HostedModel::spawn(config, move || Ok(embedder.clone()))?);
+
+        let handles: Vec<_> = (1..=4)
+            .map(|length| {
+                let model = Arc::clone(&model);
+                thread::spawn(move || model.embed(vec!["x".repeat(length)]))
+            })
+            .collect();
+
+        for (length, handle) in (1..=4).zip(handles) {
+            let embeddings = handle.join().expect("request thread panicked")?;
+            assert_eq!(embeddings[0][0], length as f32);
+        }
+        assert_eq!(batches.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parses_server_config() -> Result<()> {
+        let config: ServerConfig = toml::from_str(r#"
+            [server]
+            bind = "0.0.0.0:9000"
+
+            [[models]]
+            name = "fast"
+            model = "minilm"
+            device = "cpu"
+            max_concurrency = 2
+        "#)?;
+
+        assert_eq!(config.server.bind, "0.0.0.0:9000");
+        assert_eq!(config.models[0].spec.device.as_deref(), Some("cpu"));
+        assert_eq!(config.models[0].max_concurrency, 2);
+        assert_eq!(config.models[0].max_batch_size, 32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_duplicate_names() -> Result<()> {
+        let mut router = ModelRouter::new();
+
+        let (config, factory) = hosted("fast", 2);
+        router.host_with(config, factory)?;
+
+        let (config, factory) = hosted("fast", 2);
+        assert!(router.host_with(config, factory).is_err());
+
+        Ok(())
+    }
+}
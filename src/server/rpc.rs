@@ -0,0 +1,226 @@
+use super::{EmbedRequest, ModelRouter, ServeError};
+use crate::error::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// JSON-RPC error codes
+pub(crate) const PARSE_ERROR: i64 = -32700;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const UNKNOWN_MODEL: i64 = -32001;
+const OVERLOADED: i64 = -32002;
+
+/// A JSON-RPC message from the client, requests carry an id and notifications don't
+#[derive(Deserialize)]
+pub(crate) struct RpcMessage {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Builds a JSON-RPC success response
+pub(crate) fn result_response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// Builds a JSON-RPC error response
+pub(crate) fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Parses one line into a message, or the error response to send back
+pub(crate) fn parse_message(line: &str) -> Result<RpcMessage, Value> {
+    serde_json::from_str(line)
+        .map_err(|e| error_response(Value::Null, PARSE_ERROR, &format!("Invalid JSON-RPC message: {}", e)))
+}
+
+/// Answers newline-delimited JSON-RPC messages until the input closes
+pub(crate) fn serve_lines<R, W>(input: R, mut output: W, handle: impl Fn(&str) -> Option<Value>) -> Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(response) = handle(&line) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers a single JSON-RPC message for the router, notifications get no response
+///
+/// Methods:
+/// - `embed` with the same params as the HTTP `POST /embed` body
+/// - `models` listing the hosted models
+/// - `describe` describing the model named by an optional `model` param, the default model without one
+/// - `health`
+pub fn handle(router: &ModelRouter, line: &str) -> Option<Value> {
+    let message = match parse_message(line) {
+        Ok(message) => message,
+        Err(response) => return Some(response),
+    };
+    let id = message.id?;
+    let _span = tracing::debug_span!("rpc_request", method = %message.method).entered();
+
+    let result = match message.method.as_str() {
+        "embed" => serde_json::from_value::<EmbedRequest>(message.params)
+            .map_err(|e| ServeError::BadRequest(format!("invalid params: {}", e)))
+            .and_then(|request| router.embed(request))
+            .and_then(|response| serde_json::to_value(response).map_err(|e| ServeError::Internal(e.to_string()))),
+        "models" => serde_json::to_value(router.models()).map_err(|e| ServeError::Internal(e.to_string())),
+        "describe" => router.route(message.params.get("model").and_then(Value::as_str))
+            .and_then(|model| serde_json::to_value(model.describe()).map_err(|e| ServeError::Internal(e.to_string()))),
+        "health" => Ok(json!({ "status": "ok" })),
+        method => return Some(error_response(id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", method))),
+    };
+
+    Some(match result {
+        Ok(result) => result_response(id, result),
+        Err(e) => {
+            if matches!(e, ServeError::Internal(_)) {
+                tracing::warn!("RPC request failed: {}", e);
+            }
+            error_response(id, error_code(&e), &e.to_string())
+        }
+    })
+}
+
+/// Maps a serve error to a JSON-RPC error code
+fn error_code(error: &ServeError) -> i64 {
+    match error {
+        ServeError::UnknownModel(_) => UNKNOWN_MODEL,
+        ServeError::NoDefaultModel | ServeError::BadRequest(_) => INVALID_PARAMS,
+        ServeError::Overloaded(_) | ServeError::RateLimited(_) => OVERLOADED,
+        ServeError::Unauthorized | ServeError::Internal(_) => INTERNAL_ERROR,
+    }
+}
+
+/// Serves JSON-RPC over stdin and stdout until stdin closes
+pub fn serve_stdio(router: Arc<ModelRouter>) -> Result<()> {
+    tracing::info!("Serving JSON-RPC over stdio");
+    serve_lines(std::io::stdin().lock(), std::io::stdout().lock(), |line| handle(&router, line))
+}
+
+/// Serves JSON-RPC on a Unix domain socket until the process exits, one thread per connection
+///
+/// The socket is only accessible to the current user, a stale socket file is replaced.
+#[cfg(unix)]
+pub fn serve_uds(router: Arc<ModelRouter>, path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    tracing::info!("Listening on unix:{}", path.display());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+
+        std::thread::Builder::new()
+            .name("uds-connection".to_string())
+            .spawn(move || {
+                let result = stream.try_clone()
+                    .map_err(crate::Error::from)
+                    .and_then(|reader| serve_lines(BufReader::new(reader), stream, |line| handle(&router, line)));
+                if let Err(e) = result {
+                    tracing::debug!("Connection closed: {}", e);
+                }
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Unix domain sockets are not available on this platform
+#[cfg(not(unix))]
+pub fn serve_uds(_router: Arc<ModelRouter>, path: &Path) -> Result<()> {
+    Err(crate::error::err!("Cannot listen on {}, Unix domain sockets are not supported on this platform", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::Embedder;
+    use crate::models::registry::ModelSpec;
+    use crate::server::HostedModelConfig;
+    use ndarray::Array1;
+
+    #[derive(Clone)]
+    struct OnesEmbedder;
+
+    impl Embedder for OnesEmbedder {
+        fn embed_text(&self, _text: &str) -> crate::Result<Array1<f32>> {
+            Ok(Array1::ones(3))
+        }
+
+        fn model_name(&self) -> &str {
+            "ones"
+        }
+
+        fn model_version(&self) -> &str {
+            "1"
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serves_embeddings_over_unix_socket() -> Result<()> {
+        use std::os::unix::net::UnixStream;
+
+        let mut router = ModelRouter::new();
+        router.host_with(HostedModelConfig::new("ones", ModelSpec::new("ones")), || Ok(OnesEmbedder))?;
+        let router = Arc::new(router);
+
+        let path = std::env::temp_dir().join(format!("rust_embed_rpc_{}.sock", std::process::id()));
+        let server_path = path.clone();
+        std::thread::spawn(move || serve_uds(router, &server_path));
+
+        let mut stream = (0..100)
+            .find_map(|_| UnixStream::connect(&path).ok().or_else(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                None
+            }))
+            .context("Server did not start")?;
+        writeln!(stream, r#"{{"jsonrpc":"2.0","id":1,"method":"embed","params":{{"input":["a","b"]}}}}"#)?;
+        writeln!(stream, r#"{{"jsonrpc":"2.0","id":2,"method":"embed","params":{{"model":"missing","input":"a"}}}}"#)?;
+
+        let mut lines = BufReader::new(stream).lines();
+        let first: Value = serde_json::from_str(&lines.next().context("No response")??)?;
+        assert_eq!(first["result"]["embeddings"].as_array().map(Vec::len), Some(2));
+        let second: Value = serde_json::from_str(&lines.next().context("No response")??)?;
+        assert_eq!(second["error"]["code"], UNKNOWN_MODEL);
+
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::embedding::{Embedder, ModelInfo};
+use ndarray::Array1;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Embeds a text as its length, repeated `dimension` times, or as a unit vector when `normalized`
+#[derive(Clone)]
+pub(crate) struct LengthEmbedder {
+    pub(crate) dimension: usize,
+    pub(crate) normalized: bool,
+}
+
+impl Default for LengthEmbedder {
+    fn default() -> Self {
+        Self { dimension: 2, normalized: false }
+    }
+}
+
+impl Embedder for LengthEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        let mut embedding = Array1::from_elem(self.dimension, text.len() as f32);
+        if self.normalized {
+            crate::utils::normalize(&mut embedding);
+        }
+        Ok(embedding)
+    }
+
+    fn model_name(&self) -> &str {
+        "length"
+    }
+
+    fn model_version(&self) -> &str {
+        "1"
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo { normalized: self.normalized, ..ModelInfo::new(self.model_name(), self.model_version(), self.dimension) }
+    }
+}
+
+/// Embeds like the default `LengthEmbedder`, counting the batches and texts it is given
+#[derive(Clone, Default)]
+pub(crate) struct CountingEmbedder {
+    pub(crate) batches: Arc<AtomicUsize>,
+    pub(crate) texts: Arc<AtomicUsize>,
+}
+
+impl Embedder for CountingEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        self.texts.fetch_add(1, Ordering::SeqCst);
+        LengthEmbedder::default().embed_text(text)
+    }
+
+    fn embed_batch(&self, texts: &[String]) -> crate::Result<Vec<Array1<f32>>> {
+        self.batches.fetch_add(1, Ordering::SeqCst);
+        texts.iter().map(|text| self.embed_text(text)).collect()
+    }
+
+    fn model_name(&self) -> &str {
+        "counting"
+    }
+
+    fn model_version(&self) -> &str {
+        "1"
+    }
+
+    fn dimension(&self) -> usize {
+        2
+    }
+}
+
+/// Embeds a text by how often it mentions "rust", "python" and "bread", as a unit vector
+#[derive(Clone)]
+pub(crate) struct TopicEmbedder;
+
+impl Embedder for TopicEmbedder {
+    fn embed_text(&self, text: &str) -> crate::Result<Array1<f32>> {
+        let text = text.to_lowercase();
+        let counts = ["rust", "python", "bread"].map(|word| text.matches(word).count() as f32 + 0.01);
+        let norm = counts.iter().map(|count| count * count).sum::<f32>().sqrt();
+        Ok(Array1::from_iter(counts.map(|count| count / norm)))
+    }
+
+    fn model_name(&self) -> &str {
+        "topic"
+    }
+
+    fn model_version(&self) -> &str {
+        "1"
+    }
+
+    fn dimension(&self) -> usize {
+        3
+    }
+}
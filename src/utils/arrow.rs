@@ -0,0 +1,34 @@
+use crate::error::{err, Error, Result};
+use arrow_array::{FixedSizeListArray, Float32Array};
+use arrow_schema::{DataType, Field};
+use ndarray::Array2;
+use std::sync::Arc;
+
+/// Converts a matrix of embeddings into an Arrow list array with one fixed-size list per row
+///
+/// A matrix in standard layout hands its buffer to Arrow without copying the values.
+pub fn to_fixed_size_list(matrix: Array2<f32>) -> Result<FixedSizeListArray> {
+    let dimension = i32::try_from(matrix.ncols())
+        .map_err(|_| err!("Embedding dimension {} is too large for Arrow", matrix.ncols()))?;
+    let matrix = if matrix.is_standard_layout() { matrix } else { matrix.as_standard_layout().into_owned() };
+
+    let values = Float32Array::from(matrix.into_raw_vec());
+    let field = Arc::new(Field::new("item", DataType::Float32, false));
+    FixedSizeListArray::try_new(field, dimension, Arc::new(values), None).map_err(Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+
+    #[test]
+    fn test_rows_become_lists() {
+        let list = to_fixed_size_list(ndarray::arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]).reversed_axes()).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.value_length(), 2);
+        let row = list.value(1);
+        let row = row.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(row.values().to_vec(), vec![2.0, 5.0]);
+    }
+}
@@ -0,0 +1,264 @@
+use super::cache_home;
+use crate::error::{err, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest, the first entry of every bundle
+pub const BUNDLE_MANIFEST: &str = "bundle.json";
+
+/// Layout version written to new bundles
+const FORMAT_VERSION: u32 = 1;
+
+/// Files of a model directory that are never bundled, the cache's own bookkeeping
+const SKIPPED_FILES: &[&str] = &[super::model_cache::MANIFEST_FILE, BUNDLE_MANIFEST];
+
+/// A bundled file with the hash it is checked against on extraction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// The model a bundle was made from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleModel {
+    /// Registry name of the model kind, such as "minilm" or "bge-small"
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    pub model_name: String,
+    pub model_version: String,
+    pub dimension: usize,
+    /// Whether the files are the int8 export of an ONNX model
+    #[serde(default)]
+    pub quantized: bool,
+}
+
+/// What a bundle holds: the model it was made from and the hash of each of its files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    #[serde(flatten)]
+    pub model: BundleModel,
+    /// Version of rust_embed that wrote the bundle
+    pub crate_version: String,
+    /// Paths relative to the model directory, with `/` separators
+    pub files: BTreeMap<String, BundleFile>,
+}
+
+/// Packs a model directory into one zstd-compressed tar archive, `bundle.json` first
+///
+/// Downloads that never completed and the cache's manifest are left out.
+pub fn create_bundle(model_dir: &Path, output: &Path, model: BundleModel) -> Result<BundleManifest> {
+    let mut files = BTreeMap::new();
+    for path in walk(model_dir)? {
+        let name = relative_name(model_dir, &path);
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut File::open(&path)?, &mut hasher)?;
+        files.insert(name, BundleFile { sha256: format!("{:x}", hasher.finalize()), size });
+    }
+    if files.is_empty() {
+        return Err(err!("{} holds no model files to bundle", model_dir.display()));
+    }
+
+    let manifest = BundleManifest {
+        format_version: FORMAT_VERSION,
+        model,
+        crate_version: crate::VERSION.to_string(),
+        files,
+    };
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+
+    // Written next to the output first so an interrupted run never leaves a truncated bundle behind
+    let partial = output.with_file_name(format!("{}.partial", output.file_name().unwrap_or_default().to_string_lossy()));
+    let encoder = zstd::Encoder::new(File::create(&partial)?, 0)?;
+    let mut archive = tar::Builder::new(encoder);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, BUNDLE_MANIFEST, manifest_bytes.as_slice())?;
+    for name in manifest.files.keys() {
+        archive.append_path_with_name(model_dir.join(name), name)?;
+    }
+    archive.into_inner()?.finish()?;
+    fs::rename(&partial, output)?;
+
+    tracing::info!("Bundled {} files of {} into {}", manifest.files.len(), model_dir.display(), output.display());
+    Ok(manifest)
+}
+
+/// Extracts a bundle into the cache, once per bundle content, and returns the directory with its manifest
+///
+/// Each file is checked against the hash in `bundle.json` before the directory is put in place.
+pub fn extract_bundle(bundle: &Path) -> Result<(PathBuf, BundleManifest)> {
+    extract_bundle_into(bundle, &cache_home())
+}
+
+/// Bytes of the bundle compiled into the binary by the `embedded-model` feature
+#[cfg(feature = "embedded-model")]
+static EMBEDDED_BUNDLE: &[u8] = include_bytes!(env!("RUST_EMBED_EMBEDDED_BUNDLE"));
+
+/// The bundle compiled into the binary, None unless built with the `embedded-model` feature
+#[cfg(feature = "embedded-model")]
+pub fn embedded_bundle() -> Option<&'static [u8]> {
+    Some(EMBEDDED_BUNDLE)
+}
+
+/// The bundle compiled into the binary, None unless built with the `embedded-model` feature
+#[cfg(not(feature = "embedded-model"))]
+pub fn embedded_bundle() -> Option<&'static [u8]> {
+    None
+}
+
+/// Extracts the bundle compiled into the binary into the cache, as `extract_bundle` does for a file
+pub fn extract_embedded() -> Result<Option<(PathBuf, BundleManifest)>> {
+    embedded_bundle()
+        .map(|bytes| extract_from(|| Ok(bytes), "the embedded model bundle", &cache_home()))
+        .transpose()
+}
+
+/// Extracts a bundle file into `bundle-HASH` under one cache directory
+fn extract_bundle_into(bundle: &Path, cache_dir: &Path) -> Result<(PathBuf, BundleManifest)> {
+    let open = || File::open(bundle).with_context(|| format!("Failed to open bundle {}", bundle.display()));
+    extract_from(open, &bundle.display().to_string(), cache_dir)
+}
+
+/// Extracts the bundle that `open` reads, opening it once for the manifest and once for the files
+fn extract_from<R: Read>(open: impl Fn() -> Result<R>, label: &str, cache_dir: &Path) -> Result<(PathBuf, BundleManifest)> {
+    let manifest_bytes = read_manifest_bytes(open()?, label)?;
+    let manifest: BundleManifest = serde_json::from_slice(&manifest_bytes)
+        .with_context(|| format!("Invalid {} in {}", BUNDLE_MANIFEST, label))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(err!(
+            "{} uses bundle format {}, this version of rust_embed reads up to {}",
+            label, manifest.format_version, FORMAT_VERSION
+        ));
+    }
+
+    let key = format!("{:x}", Sha256::digest(&manifest_bytes));
+    let target = cache_dir.join(format!("bundle-{}", &key[..16]));
+    let _lock = super::lock::CacheLock::acquire(&target)?;
+    if target.join(BUNDLE_MANIFEST).exists() {
+        return Ok((target, manifest));
+    }
+
+    let staging = target.with_file_name(format!("bundle-{}.staging", &key[..16]));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(open()?)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        // `unpack_in` refuses paths that would land outside the staging directory
+        if !entry.unpack_in(&staging)? {
+            return Err(err!("{} contains an entry outside the bundle: {}", label, entry.path()?.display()));
+        }
+    }
+
+    for (name, file) in &manifest.files {
+        let path = staging.join(name);
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(
+            &mut File::open(&path).with_context(|| format!("{} is missing {}", label, name))?,
+            &mut hasher,
+        )?;
+        if size != file.size || format!("{:x}", hasher.finalize()) != file.sha256 {
+            fs::remove_dir_all(&staging)?;
+            return Err(err!("{} in {} does not match its recorded SHA-256", name, label));
+        }
+    }
+
+    fs::rename(&staging, &target)?;
+    tracing::info!("Extracted {} into {}", label, target.display());
+    Ok((target, manifest))
+}
+
+/// Reads the manifest, which is the first entry, without decompressing the rest of the bundle
+fn read_manifest_bytes(reader: impl Read, label: &str) -> Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(reader)?);
+    let mut entry = archive.entries()?
+        .next()
+        .ok_or_else(|| err!("{} is an empty archive", label))??;
+    if *entry.path()? != *Path::new(BUNDLE_MANIFEST) {
+        return Err(err!("{} is not a model bundle, it does not start with {}", label, BUNDLE_MANIFEST));
+    }
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Files under a directory to bundle, in a stable order
+fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read model directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if SKIPPED_FILES.contains(&name.as_str()) || name.ends_with(".partial") {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(walk(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn default_kind() -> String {
+    "minilm".to_string()
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundles_and_extracts_a_model_directory() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("rust_embed_bundle_{}", std::process::id()));
+        let model = root.join("model");
+        fs::create_dir_all(model.join("1_Pooling"))?;
+        fs::write(model.join("config.json"), "{\"hidden_size\": 384}")?;
+        fs::write(model.join("1_Pooling").join("config.json"), "{}")?;
+        fs::write(model.join("rust_model.ot.partial"), "interrupted")?;
+
+        let bundle = root.join("minilm.tar.zst");
+        let info = BundleModel {
+            kind: "minilm".to_string(),
+            model_name: "MiniLM-L6-v2".to_string(),
+            model_version: "2.0".to_string(),
+            dimension: 384,
+            quantized: false,
+        };
+        let manifest = create_bundle(&model, &bundle, info)?;
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(), vec!["1_Pooling/config.json", "config.json"]);
+
+        let (extracted, read) = extract_bundle_into(&bundle, &root)?;
+        assert_eq!(read, manifest);
+        assert_eq!(fs::read_to_string(extracted.join("config.json"))?, "{\"hidden_size\": 384}");
+        assert!(extracted.join("1_Pooling").join("config.json").exists());
+        assert_eq!(extract_bundle_into(&bundle, &root)?.0, extracted);
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use crate::error::{err, Result};
+
+/// Environment variable limiting the rayon pool used for parallel batches
+pub const MAX_THREADS_ENV: &str = "RUST_EMBED_MAX_THREADS";
+/// Environment variable limiting the threads libtorch uses within one operation
+pub const TORCH_THREADS_ENV: &str = "RUST_EMBED_TORCH_THREADS";
+
+/// Thread limits for rayon and libtorch, which otherwise both take every core and oversubscribe each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConcurrencyConfig {
+    /// Threads of the global rayon pool, every core if None
+    pub max_threads: Option<usize>,
+    /// Intra-op threads of libtorch, every core if None
+    pub torch_intraop_threads: Option<usize>,
+}
+
+impl ConcurrencyConfig {
+    /// Reads `RUST_EMBED_MAX_THREADS` and `RUST_EMBED_TORCH_THREADS`, unset variables keep the defaults
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            max_threads: read_env(MAX_THREADS_ENV)?,
+            torch_intraop_threads: read_env(TORCH_THREADS_ENV)?,
+        })
+    }
+
+    /// Fills the limits left unset from the environment
+    pub fn or_env(self) -> Result<Self> {
+        let env = Self::from_env()?;
+        Ok(Self {
+            max_threads: self.max_threads.or(env.max_threads),
+            torch_intraop_threads: self.torch_intraop_threads.or(env.torch_intraop_threads),
+        })
+    }
+
+    /// Applies the limits to the process
+    ///
+    /// The rayon pool can only be sized before its first use, later calls leave it as it is.
+    pub fn apply(&self) -> Result<()> {
+        if let Some(threads) = self.max_threads {
+            if threads == 0 {
+                return Err(err!("max_threads must be at least 1"));
+            }
+            #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+            {
+                match rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+                    Ok(()) => tracing::info!("Using {} threads for parallel batches", threads),
+                    Err(_) => tracing::debug!("Thread pool already running with {} threads", rayon::current_num_threads()),
+                }
+            }
+        }
+
+        if let Some(threads) = self.torch_intraop_threads {
+            if threads == 0 {
+                return Err(err!("torch_intraop_threads must be at least 1"));
+            }
+            #[cfg(feature = "torch")]
+            if super::reproducibility::active().is_some() {
+                tracing::info!("Keeping libtorch on one thread in reproducible mode instead of {}", threads);
+            } else {
+                tch::set_num_threads(threads.min(i32::MAX as usize) as i32);
+                tracing::info!("Using {} libtorch threads per operation", threads);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a positive thread count from an environment variable
+fn read_env(name: &str) -> Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => parse_threads(name, &value).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Parses a positive thread count
+fn parse_threads(name: &str, value: &str) -> Result<usize> {
+    value.trim().parse::<usize>().ok()
+        .filter(|&threads| threads > 0)
+        .ok_or_else(|| err!("{} must be a positive number of threads, got '{}'", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_threads() {
+        assert_eq!(parse_threads(MAX_THREADS_ENV, " 4 ").unwrap(), 4);
+        assert!(parse_threads(MAX_THREADS_ENV, "0").is_err());
+        assert!(parse_threads(TORCH_THREADS_ENV, "all").is_err());
+        assert!(ConcurrencyConfig { max_threads: Some(0), ..Default::default() }.apply().is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use super::cache_home;
+use super::libtorch;
+use super::paths;
+#[cfg(feature = "torch")]
+use super::libtorch::{LibtorchConfig, TCH_LIBTORCH_VERSION};
+use super::model_cache::{self, GcOptions};
+#[cfg(feature = "torch")]
+use super::offline;
+#[cfg(feature = "torch")]
+use crate::models::{mini_lm, HubSource};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works, but slower or less reliably than it could
+    Warn,
+    /// Embedding will fail until this is fixed
+    Fail,
+}
+
+/// One finding of `diagnose`, with what to do about it when it isn't ok
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, status: Status::Ok, detail: detail.into(), fix: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Warn, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Fail, detail: detail.into(), fix: Some(fix.into()) }
+    }
+}
+
+/// Every check `diagnose` ran, in order
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Number of checks that failed
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| check.status == Status::Fail).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let label = match check.status {
+                Status::Ok => "ok",
+                Status::Warn => "warn",
+                Status::Fail => "FAIL",
+            };
+            writeln!(f, "[{:<4}] {}: {}", label, check.name, check.detail)?;
+            if let Some(fix) = &check.fix {
+                writeln!(f, "       fix: {}", fix)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the environment rust_embed runs in: libtorch and its libraries, MPS, the model cache and the permissions
+/// of every directory in `paths::current()`
+///
+/// Nothing is downloaded or changed, so it is safe to run on a broken or offline machine.
+pub fn diagnose() -> Report {
+    let mut checks = Vec::new();
+    #[cfg(feature = "torch")]
+    checks.extend(check_libtorch(&LibtorchConfig::from_env()));
+    #[cfg(not(feature = "torch"))]
+    checks.push(Check::ok("libtorch", "not needed, built without the torch feature"));
+    checks.push(check_mps());
+    checks.push(check_model_cache());
+    #[cfg(feature = "torch")]
+    checks.push(check_default_model());
+
+    let paths = paths::current();
+    checks.push(Check::ok("paths", format!(
+        "models {}, libtorch {}, cache {}, indexes {}",
+        paths.models.display(), paths.libtorch.display(), paths.cache.display(), paths.indexes.display(),
+    )));
+    let mut dirs: Vec<&Path> = Vec::new();
+    for dir in [&paths.models, &paths.libtorch, &paths.cache, &paths.indexes] {
+        if !dirs.contains(&dir.as_path()) {
+            dirs.push(dir);
+        }
+    }
+    checks.extend(dirs.into_iter().map(check_writable));
+    Report { checks }
+}
+
+/// Whether libtorch is installed, matches the release tch was built against and has its libraries resolvable
+#[cfg(feature = "torch")]
+fn check_libtorch(config: &LibtorchConfig) -> Vec<Check> {
+    let Some(path) = libtorch::find_libtorch(config) else {
+        let fix = if offline::is_enabled() {
+            "Offline mode forbids downloading it, set LIBTORCH to an existing libtorch installation".to_string()
+        } else {
+            format!("It is downloaded on first use, or set LIBTORCH to a libtorch {} installation", config.version)
+        };
+        return vec![Check::fail("libtorch", "no installation found in LIBTORCH or the default locations", fix)];
+    };
+
+    let mut checks = vec![match libtorch::installed_version(&path) {
+        Some(version) if libtorch::same_minor_release(&version, TCH_LIBTORCH_VERSION) => {
+            Check::ok("libtorch", format!("{} at {}", version, path.display()))
+        }
+        Some(version) => Check::fail(
+            "libtorch",
+            format!("{} at {}, but tch expects {}", version, path.display(), TCH_LIBTORCH_VERSION),
+            format!("Install libtorch {} and point LIBTORCH at it", TCH_LIBTORCH_VERSION),
+        ),
+        None => Check::warn(
+            "libtorch",
+            format!("found at {}, but its build-version file is missing", path.display()),
+            format!("Make sure it is libtorch {}, other releases fail to load", TCH_LIBTORCH_VERSION),
+        ),
+    }];
+
+    let library = path.join("lib").join(libtorch::libtorch_cpu_library());
+    checks.push(match libtorch::unresolved_dependencies(&library) {
+        Ok(missing) if missing.is_empty() => Check::ok("libraries", format!("dependencies of {} resolve", library.display())),
+        Ok(missing) if cfg!(target_os = "macos") => Check::fail(
+            "libraries",
+            format!("{} can't find {}", library.display(), missing.join(", ")),
+            format!("Add the rpath with `install_name_tool -add_rpath {} <dylib>`, or run `rust_embed --package DIR` to bundle fixed copies", library.parent().unwrap_or(&path).display()),
+        ),
+        Ok(missing) => Check::fail(
+            "libraries",
+            format!("{} can't find {}", library.display(), missing.join(", ")),
+            "Install the missing system libraries, such as libgomp1, or use a libtorch build that ships them",
+        ),
+        Err(e) => Check::warn("libraries", format!("not checked: {}", e), "Make sure the libtorch libraries are on the loader's search path"),
+    });
+    checks
+}
+
+/// Whether an Apple Silicon Mac can run the model on the GPU
+fn check_mps() -> Check {
+    let mps = libtorch::mps_status();
+    if mps.available {
+        Check::ok("MPS", format!("available (detected by {})", mps.detection))
+    } else if mps.detection == libtorch::MpsDetection::NotAppleSilicon {
+        Check::ok("MPS", "not an Apple Silicon Mac, models run on the CPU")
+    } else {
+        Check::warn(
+            "MPS",
+            format!("unavailable according to {}, models run on the CPU", mps.detection),
+            "MPS needs macOS 12.3 or later and an arm64 build of rust_embed and libtorch, not one running under Rosetta",
+        )
+    }
+}
+
+/// Whether the cached downloads are complete and match their recorded hashes
+fn check_model_cache() -> Check {
+    match model_cache::gc(&GcOptions { dry_run: true, ..GcOptions::default() }) {
+        Ok(report) if report.removed.is_empty() => Check::ok("model cache", format!("{} is intact", cache_home().display())),
+        Ok(report) => {
+            let problems: Vec<String> = report.removed.iter()
+                .map(|(path, reason)| format!("{} ({})", path.display(), reason))
+                .collect();
+            Check::fail("model cache", problems.join(", "), "Run `rust_embed model gc` to remove them, they are downloaded again on next use")
+        }
+        Err(e) => Check::fail("model cache", format!("could not be read: {}", e), format!("Check the permissions of {}", cache_home().display())),
+    }
+}
+
+/// Whether the default model is on disk, which matters most when downloads are disabled
+#[cfg(feature = "torch")]
+fn check_default_model() -> Check {
+    let model_dir = HubSource::default().cache_dir();
+    let missing: Vec<&str> = mini_lm::HUB_MODEL_FILES.iter()
+        .copied()
+        .filter(|file| !model_dir.join(file).exists())
+        .collect();
+    if missing.is_empty() || super::bundle::embedded_bundle().is_some() {
+        Check::ok("default model", "available without a download")
+    } else if offline::is_enabled() {
+        Check::fail(
+            "default model",
+            format!("{} is missing {}", model_dir.display(), missing.join(", ")),
+            "Run once without offline mode to download it, or copy the files there",
+        )
+    } else {
+        Check::warn(
+            "default model",
+            format!("not downloaded yet, {} is missing {}", model_dir.display(), missing.join(", ")),
+            "It is downloaded on first use, run `rust_embed model info` to fetch it now",
+        )
+    }
+}
+
+/// Whether files can be created in a cache directory, creating it if needed
+fn check_writable(dir: &Path) -> Check {
+    let probe: PathBuf = dir.join(format!(".doctor-{}", std::process::id()));
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => Check::ok("permissions", format!("{} is writable", dir.display())),
+        Err(e) => Check::fail(
+            "permissions",
+            format!("can't write to {}: {}", dir.display(), e),
+            "Fix its ownership or point RUST_EMBED_HOME at a writable directory",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_checks_with_fixes() {
+        let dir = std::env::temp_dir().join(format!("rust_embed_doctor_{}", std::process::id()));
+        let writable = check_writable(&dir);
+        assert_eq!(writable.status, Status::Ok);
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let report = Report {
+            checks: vec![writable, Check::fail("libtorch", "not found", "Set LIBTORCH")],
+        };
+        assert_eq!(report.failures(), 1);
+        let printed = report.to_string();
+        assert!(printed.contains("[ok  ] permissions: "));
+        assert!(printed.contains("[FAIL] libtorch: not found\n       fix: Set LIBTORCH\n"));
+    }
+}
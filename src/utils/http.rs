@@ -0,0 +1,161 @@
+use crate::error::{err, Context, Result};
+use reqwest::blocking::ClientBuilder;
+use reqwest::{Certificate, NoProxy, Proxy};
+use std::path::Path;
+
+/// Environment variable naming a PEM file with extra root certificates, e.g. a corporate CA
+pub const CA_BUNDLE_ENV: &str = "RUST_EMBED_CA_BUNDLE";
+
+/// Builds a proxy for one URL scheme from the proxy URL
+type ProxyForScheme = fn(&str) -> reqwest::Result<Proxy>;
+
+/// Proxy settings read from `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY`
+#[derive(Debug, Default, PartialEq)]
+struct ProxyEnv {
+    https: Option<String>,
+    http: Option<String>,
+    all: Option<String>,
+    no_proxy: Option<String>,
+}
+
+impl ProxyEnv {
+    /// Reads each variable in upper or lower case through `var`, skipping empty values
+    fn read(var: impl Fn(&str) -> Option<String>) -> Self {
+        let any = |names: [&str; 2]| names.into_iter()
+            .filter_map(&var)
+            .find(|value| !value.trim().is_empty());
+        Self {
+            https: any(["HTTPS_PROXY", "https_proxy"]),
+            http: any(["HTTP_PROXY", "http_proxy"]),
+            all: any(["ALL_PROXY", "all_proxy"]),
+            no_proxy: any(["NO_PROXY", "no_proxy"]),
+        }
+    }
+
+    /// A proxy for each configured scheme, bypassed for the `NO_PROXY` hosts
+    fn proxies(&self) -> Result<Vec<Proxy>> {
+        let no_proxy = self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let schemes: [(&Option<String>, ProxyForScheme); 3] = [
+            (&self.https, |url| Proxy::https(url)),
+            (&self.http, |url| Proxy::http(url)),
+            (&self.all, |url| Proxy::all(url)),
+        ];
+        schemes.into_iter()
+            .filter_map(|(url, proxy)| url.as_deref().map(|url| (url, proxy)))
+            .map(|(url, proxy)| {
+                let proxy = proxy(url).map_err(|e| err!("Invalid proxy URL {}: {}", url, e))?;
+                tracing::debug!("Using proxy {}", url);
+                Ok(proxy.no_proxy(no_proxy.clone()))
+            })
+            .collect()
+    }
+}
+
+/// HTTP client builder for downloads, honoring the proxy variables and extra root certificates
+///
+/// `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` are read in upper or lower case.
+pub fn client_builder() -> Result<ClientBuilder> {
+    let mut builder = reqwest::blocking::Client::builder();
+
+    for proxy in ProxyEnv::read(|name| std::env::var(name).ok()).proxies()? {
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = std::env::var(CA_BUNDLE_ENV).ok().filter(|path| !path.trim().is_empty()) {
+        for certificate in load_certificates(Path::new(&path))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder)
+}
+
+/// HTTP client for downloads, see `client_builder`
+pub fn client() -> Result<reqwest::blocking::Client> {
+    client_builder()?.build().context("Failed to create HTTP client")
+}
+
+/// Returns true if the URL points at this machine, which offline mode still allows
+pub fn is_loopback(url: &str) -> bool {
+    let Some(host) = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)) else {
+        return false;
+    };
+    host == "localhost" || host.trim_matches(|c| c == '[' || c == ']')
+        .parse::<std::net::IpAddr>()
+        .map(|ip| ip.is_loopback())
+        .unwrap_or(false)
+}
+
+/// Reads every certificate in a PEM bundle
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+    let certificates = Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("Failed to parse CA bundle {}", path.display()))?;
+    if certificates.is_empty() {
+        return Err(err!("CA bundle {} contains no certificates", path.display()));
+    }
+    tracing::debug!("Trusting {} extra root certificate(s) from {}", certificates.len(), path.display());
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBkDCCATegAwIBAgIURAUS2XgWTWfK7B6LLcDA+oNlYYowCgYIKoZIzj0EAwIw
+HTEbMBkGA1UEAwwScnVzdF9lbWJlZCB0ZXN0IENBMCAXDTI2MTAxNjE1MDI0MloY
+DzIxMjYwOTIyMTUwMjQyWjAdMRswGQYDVQQDDBJydXN0X2VtYmVkIHRlc3QgQ0Ew
+WTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAQmEj1/6pi4Kgp6ot8/x232fgfxTnf2
+MyOelBya4p7iS0+giuzxgLmQKNrSRgWRCxuoS8rH6pETasRkXIkzv9+Zo1MwUTAd
+BgNVHQ4EFgQUyYz/xAAxg/mffD+P27fsCIC4GvAwHwYDVR0jBBgwFoAUyYz/xAAx
+g/mffD+P27fsCIC4GvAwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNHADBE
+AiBf9F5XYB0mtT0XzkXkax2NabuIE0KKrrb81Tfmr2izTQIgeSHvBxt0mWn6UdNp
+4cIbd8c2Rb5p9CDee3lI9UO+pXQ=
+-----END CERTIFICATE-----
+";
+
+    fn read(vars: &[(&str, &str)]) -> ProxyEnv {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        ProxyEnv::read(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_reads_proxy_variables_in_either_case() {
+        let env = read(&[
+            ("HTTPS_PROXY", "http://proxy.corp:3128"),
+            ("https_proxy", "http://ignored:1"),
+            ("HTTP_PROXY", " "),
+            ("http_proxy", "http://plain.corp:8080"),
+            ("no_proxy", "localhost,.internal"),
+        ]);
+        assert_eq!(env, ProxyEnv {
+            https: Some("http://proxy.corp:3128".to_string()),
+            http: Some("http://plain.corp:8080".to_string()),
+            all: None,
+            no_proxy: Some("localhost,.internal".to_string()),
+        });
+        assert_eq!(env.proxies().unwrap().len(), 2);
+        assert!(read(&[]).proxies().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_loads_the_extra_root_certificates() {
+        let dir = std::env::temp_dir().join(format!("rust_embed_http_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (bundle, garbage) = (dir.join("ca.pem"), dir.join("garbage.pem"));
+        std::fs::write(&bundle, CERTIFICATE.repeat(2)).unwrap();
+        std::fs::write(&garbage, "not a certificate").unwrap();
+
+        let certificates = load_certificates(&bundle).map(|certificates| certificates.len());
+        let garbage_error = load_certificates(&garbage).unwrap_err().report();
+        let missing_error = load_certificates(&dir.join("missing.pem")).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(certificates.unwrap(), 2);
+        assert!(garbage_error.contains(&format!("CA bundle {}", garbage.display())), "{}", garbage_error);
+        assert_eq!(missing_error, format!("Failed to read CA bundle {}", dir.join("missing.pem").display()));
+    }
+}
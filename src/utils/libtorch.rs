@@ -1,16 +1,166 @@
-use anyhow::{anyhow, Context, Result};
+use crate::error::{err, Context, Result};
 use std::path::{Path, PathBuf};
-use std::fs::File;
+#[cfg(feature = "download")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "download")]
 use std::io::{Write, Read};
-use std::sync::Once;
+use std::sync::{Mutex, OnceLock, PoisonError};
 use std::time::Duration;
 use std::process::Command;
+#[cfg(feature = "download")]
+use sha2::{Digest, Sha256};
 
+// Download host for libtorch, replaced by `LibtorchConfig::mirror`
+pub const PYTORCH_DOWNLOAD_BASE: &str = "https://download.pytorch.org";
+// libtorch release the tch crate (0.13) is built against, bump together with tch
+pub const TCH_LIBTORCH_VERSION: &str = "2.0.0";
+// Default release to download, RUST_EMBED_LIBTORCH_VERSION at build time pins another patch release
+pub const LIBTORCH_VERSION: &str = match option_env!("RUST_EMBED_LIBTORCH_VERSION") {
+    Some(version) => version,
+    None => TCH_LIBTORCH_VERSION,
+};
+const _: () = assert!(
+    same_minor_release(LIBTORCH_VERSION, TCH_LIBTORCH_VERSION),
+    "RUST_EMBED_LIBTORCH_VERSION must be a patch release of the libtorch version tch expects"
+);
+
+// Archive paths below the download host, `{version}` is replaced by the release
 // For Apple Silicon (M-series), we use the ARM64 version of libtorch
-pub const LIBTORCH_URL_ARM64: &str = "https://download.pytorch.org/libtorch/cpu/libtorch-macos-2.0.0.zip";
+pub const LIBTORCH_PATH_ARM64: &str = "libtorch/cpu/libtorch-macos-{version}.zip";
+// Intel Macs
+pub const LIBTORCH_PATH_X86_64_MACOS: &str = "libtorch/cpu/libtorch-macos-{version}.zip";
+// x86_64 Linux, CPU-only build with the C++11 ABI
+pub const LIBTORCH_PATH_X86_64_LINUX: &str = "libtorch/cpu/libtorch-cxx11-abi-shared-with-deps-{version}%2Bcpu.zip";
+// ARM64 Linux (Graviton, Raspberry Pi) has no standalone libtorch, so libtorch is taken from the PyTorch wheel
+pub const LIBTORCH_PATH_AARCH64_LINUX: &str = "whl/cpu/torch-{version}-cp310-cp310-manylinux2014_aarch64.whl";
+// x86_64 Windows, CPU-only build
+pub const LIBTORCH_PATH_X86_64_WINDOWS: &str = "libtorch/cpu/libtorch-win-shared-with-deps-{version}%2Bcpu.zip";
 pub const LIBTORCH_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600); // 10 minutes
+// Attempts at fetching the archive, each one resuming where the previous stopped
+pub const LIBTORCH_DOWNLOAD_ATTEMPTS: usize = 3;
+
+/// libtorch installation found by `ensure_libtorch`, shared by every thread of the process
+static LIBTORCH_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Set in the environment of a process started by `reexec_with_runtime_env`, so it never restarts twice
+const REEXEC_MARKER: &str = "RUST_EMBED_REEXECUTED";
+
+/// Which libtorch release to download and where from
+#[derive(Debug, Clone)]
+pub struct LibtorchConfig {
+    /// Release to download, must share major and minor version with `TCH_LIBTORCH_VERSION`
+    pub version: String,
+    /// Full archive URL, used instead of the platform's default archive
+    pub url_override: Option<String>,
+    /// Base URL of a download.pytorch.org mirror
+    pub mirror: Option<String>,
+    /// Expected SHA-256 of the archive as hex, the download is rejected if it differs
+    pub sha256: Option<String>,
+}
+
+impl Default for LibtorchConfig {
+    fn default() -> Self {
+        Self {
+            version: LIBTORCH_VERSION.to_string(),
+            url_override: None,
+            mirror: None,
+            sha256: None,
+        }
+    }
+}
 
-static LIBTORCH_INIT: Once = Once::new();
+impl LibtorchConfig {
+    /// Default configuration, with `RUST_EMBED_LIBTORCH_URL`, `_MIRROR` and `_SHA256` applied
+    pub fn from_env() -> Self {
+        let non_empty = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        Self {
+            url_override: non_empty("RUST_EMBED_LIBTORCH_URL"),
+            mirror: non_empty("RUST_EMBED_LIBTORCH_MIRROR"),
+            sha256: non_empty("RUST_EMBED_LIBTORCH_SHA256"),
+            ..Self::default()
+        }
+    }
+
+    /// Download URL of the configured release for this platform
+    pub fn url(&self) -> Result<String> {
+        self.check_version()?;
+        if let Some(url) = &self.url_override {
+            return Ok(url.clone());
+        }
+
+        let path = if is_apple_silicon()? {
+            LIBTORCH_PATH_ARM64
+        } else {
+            archive_path(std::env::consts::OS, std::env::consts::ARCH)?
+        };
+        Ok(self.archive_url(path))
+    }
+
+    /// URL of an archive path on the mirror or the PyTorch download server
+    fn archive_url(&self, path: &str) -> String {
+        let base = self.mirror.as_deref().unwrap_or(PYTORCH_DOWNLOAD_BASE).trim_end_matches('/');
+        format!("{}/{}", base, path.replace("{version}", &self.version))
+    }
+
+    /// Rejects releases tch cannot load, unless LIBTORCH_BYPASS_VERSION_CHECK is set as for tch's own build
+    fn check_version(&self) -> Result<()> {
+        if same_minor_release(&self.version, TCH_LIBTORCH_VERSION) || std::env::var_os("LIBTORCH_BYPASS_VERSION_CHECK").is_some() {
+            return Ok(());
+        }
+        Err(err!(
+            "libtorch {} is not compatible with tch, which expects libtorch {}",
+            self.version, TCH_LIBTORCH_VERSION
+        ))
+    }
+}
+
+/// Archive path of the prebuilt libtorch for an OS and architecture other than Apple Silicon
+fn archive_path(os: &str, arch: &str) -> Result<&'static str> {
+    match (os, arch) {
+        ("macos", "x86_64") => Ok(LIBTORCH_PATH_X86_64_MACOS),
+        ("linux", "x86_64") => Ok(LIBTORCH_PATH_X86_64_LINUX),
+        ("linux", "aarch64") => Ok(LIBTORCH_PATH_AARCH64_LINUX),
+        ("windows", "x86_64") => Ok(LIBTORCH_PATH_X86_64_WINDOWS),
+        (os, arch) => Err(err!(
+            "No prebuilt libtorch for {} on {}, install it and point LIBTORCH at it",
+            os, arch
+        )),
+    }
+}
+
+/// Returns true if both versions have the same major and minor version
+pub(crate) const fn same_minor_release(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let length = minor_release_length(a);
+    if length != minor_release_length(b) {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < length {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Length of the `major.minor` prefix of a version
+const fn minor_release_length(version: &[u8]) -> usize {
+    let mut i = 0;
+    let mut dots = 0;
+    while i < version.len() {
+        if version[i] == b'.' {
+            dots += 1;
+            if dots == 2 {
+                break;
+            }
+        }
+        i += 1;
+    }
+    i
+}
 
 /// Detects if running on Apple Silicon (M-series)
 pub fn is_apple_silicon() -> Result<bool> {
@@ -38,191 +188,387 @@ pub fn is_apple_silicon() -> Result<bool> {
     Ok(false)
 }
 
-/// Check if Metal Performance Shaders (MPS) is available
+/// How MPS availability was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpsDetection {
+    /// Not macOS on Apple Silicon, so nothing was checked
+    NotAppleSilicon,
+    /// libtorch was asked whether it was built with MPS and can reach the GPU
+    Libtorch,
+    /// Metal was asked for the system's default GPU, in builds without libtorch
+    Metal,
+}
+
+impl std::fmt::Display for MpsDetection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MpsDetection::NotAppleSilicon => write!(f, "not Apple Silicon"),
+            MpsDetection::Libtorch => write!(f, "libtorch"),
+            MpsDetection::Metal => write!(f, "Metal device query"),
+        }
+    }
+}
+
+/// Whether MPS is available and how that was found out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MpsStatus {
+    pub available: bool,
+    pub detection: MpsDetection,
+}
+
+/// Detects MPS once per process and returns the cached result
+pub fn mps_status() -> MpsStatus {
+    static STATUS: OnceLock<MpsStatus> = OnceLock::new();
+    *STATUS.get_or_init(|| {
+        if !cfg!(target_os = "macos") || !is_apple_silicon().unwrap_or(false) {
+            return MpsStatus { available: false, detection: MpsDetection::NotAppleSilicon };
+        }
+        #[cfg(feature = "torch")]
+        let status = MpsStatus { available: tch::utils::has_mps(), detection: MpsDetection::Libtorch };
+        #[cfg(not(feature = "torch"))]
+        let status = MpsStatus { available: has_metal_device(), detection: MpsDetection::Metal };
+        tracing::debug!("MPS {} according to {}", if status.available { "available" } else { "unavailable" }, status.detection);
+        status
+    })
+}
+
+/// Check if Metal Performance Shaders (MPS) is available, detected once per process
 pub fn has_mps() -> Result<bool> {
-    if !cfg!(target_os = "macos") {
-        return Ok(false);
+    Ok(mps_status().available)
+}
+
+/// Whether Metal has a default GPU, asked directly of the framework
+#[cfg(all(target_os = "macos", not(feature = "torch")))]
+fn has_metal_device() -> bool {
+    use std::ffi::c_void;
+
+    #[link(name = "Metal", kind = "framework")]
+    extern "C" {
+        fn MTLCreateSystemDefaultDevice() -> *mut c_void;
     }
-    
-    if !is_apple_silicon()? {
-        return Ok(false);
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(object: *const c_void);
     }
-    
-    // Check if we can access Metal APIs
-    // This is a basic check - in a real application, you'd use the Metal framework directly
-    let output = Command::new("system_profiler")
-        .arg("SPDisplaysDataType")
-        .output()
-        .context("Failed to execute system_profiler command")?;
-    
-    let result = String::from_utf8_lossy(&output.stdout);
-    
-    // If Metal is mentioned, it's likely available
-    Ok(result.contains("Metal"))
-}
 
-/// Ensures libtorch is available for Apple Silicon, downloading it if necessary
-pub fn ensure_libtorch() -> Result<PathBuf> {
-    // Set up only once at runtime
-    let mut libtorch_path = PathBuf::new();
-    
-    LIBTORCH_INIT.call_once(|| {
-        if let Ok(path) = find_or_download_libtorch() {
-            libtorch_path = path;
+    // SAFETY: MTLCreateSystemDefaultDevice takes no arguments and returns a retained device or null,
+    // which is released right away since only its existence matters
+    unsafe {
+        let device = MTLCreateSystemDefaultDevice();
+        if device.is_null() {
+            return false;
         }
-    });
-    
-    if libtorch_path.as_os_str().is_empty() {
-        find_or_download_libtorch()
+        CFRelease(device);
+        true
+    }
+}
+
+/// Metal only exists on macOS
+#[cfg(all(not(target_os = "macos"), not(feature = "torch")))]
+fn has_metal_device() -> bool {
+    false
+}
+
+/// Download URL of the libtorch build for this platform
+pub fn libtorch_url() -> Result<String> {
+    LibtorchConfig::from_env().url()
+}
+
+/// File name of the core libtorch library, used to recognize an installation
+pub(crate) fn libtorch_cpu_library() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "libtorch_cpu.dylib"
+    } else if cfg!(windows) {
+        "torch_cpu.dll"
     } else {
-        Ok(libtorch_path)
+        "libtorch_cpu.so"
     }
 }
 
-/// Finds an existing libtorch installation or downloads a new one
-fn find_or_download_libtorch() -> Result<PathBuf> {
-    // First check if we're on Apple Silicon
-    if !is_apple_silicon()? {
-        return Err(anyhow!("This version is optimized for Apple Silicon (M-series) processors only"));
+/// Ensures libtorch is available, downloading it if necessary
+///
+/// Safe to call from several threads: the first one finds or downloads libtorch while the others wait for it.
+/// A failure isn't remembered, so a later call tries again.
+pub fn ensure_libtorch() -> Result<PathBuf> {
+    let mut libtorch_path = LIBTORCH_PATH.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(path) = &*libtorch_path {
+        return Ok(path.clone());
     }
-    
-    // First check if LIBTORCH env var is set
-    if let Ok(libtorch_path) = std::env::var("LIBTORCH") {
-        let path = Path::new(&libtorch_path);
-        if path.exists() && path.join("lib").join("libtorch_cpu.dylib").exists() {
-            log::info!("Using libtorch from LIBTORCH env var: {}", libtorch_path);
-            return Ok(path.to_path_buf());
-        }
+    let path = find_or_download_libtorch(&LibtorchConfig::from_env())?;
+    *libtorch_path = Some(path.clone());
+    Ok(path)
+}
+
+/// Default places libtorch is looked for, prioritizing user locations to avoid permission issues
+fn default_libtorch_paths(config: &LibtorchConfig) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    paths.extend(dirs::home_dir().map(|home_dir| home_dir.join("libtorch")));
+    paths.push(libtorch_cache_dir().join(&config.version).join("libtorch"));
+    paths.push(PathBuf::from("/usr/local/libtorch"));
+    paths.push(PathBuf::from("/opt/homebrew/libtorch"));
+    paths
+}
+
+/// Finds an existing libtorch installation, from LIBTORCH or a default location, without downloading one
+pub fn find_libtorch(config: &LibtorchConfig) -> Option<PathBuf> {
+    std::env::var_os("LIBTORCH")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(default_libtorch_paths(config))
+        .find(|path| path.join("lib").join(libtorch_cpu_library()).exists())
+}
+
+/// Release of a libtorch installation as recorded in its `build-version` file, such as "2.0.0+cpu"
+pub fn installed_version(libtorch_path: &Path) -> Option<String> {
+    std::fs::read_to_string(libtorch_path.join("build-version"))
+        .ok()
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// Finds an existing libtorch installation or downloads a new one
+fn find_or_download_libtorch(config: &LibtorchConfig) -> Result<PathBuf> {
+    if let Some(path) = find_libtorch(config) {
+        tracing::info!("Using libtorch from: {}", path.display());
+        return Ok(path);
     }
     
-    // Check default locations (prioritizing user locations to avoid permission issues)
-    let home_dir = dirs::home_dir().context("Failed to determine home directory")?;
-    let libtorch_paths = vec![
-        home_dir.join("libtorch"),
-        dirs::cache_dir().unwrap_or_else(|| PathBuf::from("/tmp")).join("rust_embed").join("libtorch"),
-        PathBuf::from("/usr/local/libtorch"),
-        PathBuf::from("/opt/homebrew/libtorch"),
-    ];
-    
-    for path in libtorch_paths {
-        if path.exists() && path.join("lib").join("libtorch_cpu.dylib").exists() {
-            // Set LIBTORCH env var for future processes
-            std::env::set_var("LIBTORCH", path.to_string_lossy().to_string());
-            log::info!("Using libtorch from: {}", path.display());
-            return Ok(path);
-        }
-    }
+    super::offline::ensure_download_allowed(
+        "libtorch",
+        &default_libtorch_paths(config),
+        "Set LIBTORCH to an existing libtorch installation",
+    )?;
     
     // If we can't find libtorch, attempt to download it
-    download_libtorch()
+    #[cfg(feature = "download")]
+    return download_libtorch(config);
+    
+    #[cfg(not(feature = "download"))]
+    Err(err!("libtorch {} was not found and downloads are disabled, set LIBTORCH or enable the download feature", config.version))
 }
 
-/// Downloads libtorch for Apple Silicon
-fn download_libtorch() -> Result<PathBuf> {
-    log::info!("Downloading libtorch for Apple Silicon (M-series)...");
-    
-    // Ensure we're on Apple Silicon
-    if !is_apple_silicon()? {
-        return Err(anyhow!("Cannot download libtorch - this version requires Apple Silicon (M-series)"));
-    }
+/// Directory downloaded libtorch releases are kept in, one subdirectory per version
+pub(crate) fn libtorch_cache_dir() -> PathBuf {
+    super::paths::current().libtorch
+}
+
+/// Downloads the configured libtorch build for this platform, unless it is already cached
+#[cfg(feature = "download")]
+pub fn download_libtorch(config: &LibtorchConfig) -> Result<PathBuf> {
+    let url = config.url()?;
+    tracing::info!("Downloading libtorch {} for {} ({})...", config.version, std::env::consts::OS, std::env::consts::ARCH);
     
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("rust_embed");
+    let cache_dir = libtorch_cache_dir().join(&config.version);
     std::fs::create_dir_all(&cache_dir).context("Failed to create cache directory")?;
     
+    // Parallel jobs wait for the first one's download instead of writing the same archive at once
+    let _lock = super::lock::CacheLock::acquire(&cache_dir)?;
+    
+    let part_path = cache_dir.join("libtorch.zip.part");
     let zip_path = cache_dir.join("libtorch.zip");
+    let staging_path = cache_dir.join("libtorch.staging");
     let extract_path = cache_dir.join("libtorch");
     
     // Only download if we don't already have it
     if !extract_path.exists() {
-        log::info!("Downloading libtorch from {}", LIBTORCH_URL_ARM64);
+        tracing::info!("Downloading libtorch from {}", url);
         
         // Create a client with a timeout
-        let client = reqwest::blocking::Client::builder()
+        let client = super::http::client_builder()?
             .timeout(LIBTORCH_DOWNLOAD_TIMEOUT)
             .build()?;
         
-        // Download the file with progress reporting
-        let mut response = client.get(LIBTORCH_URL_ARM64).send()?;
-        let total_size = response.content_length().unwrap_or(0);
-        
-        let mut file = File::create(&zip_path)?;
-        let mut downloaded: u64 = 0;
-        
-        let mut last_percent = 0;
-        let mut buffer = [0; 8192];
-        
-        log::info!("Downloading libtorch ({:.1} MB)...", total_size as f64 / 1_048_576.0);
-        
-        while let Ok(n) = response.read(&mut buffer) {
-            if n == 0 { break; }
-            
-            file.write_all(&buffer[..n])?;
-            downloaded += n as u64;
-            
-            if total_size > 0 {
-                let percent = (downloaded * 100 / total_size) as u8;
-                if percent > last_percent && percent % 10 == 0 {
-                    log::info!("Download progress: {}% ({:.1}/{:.1} MB)", 
-                        percent,
-                        downloaded as f64 / 1_048_576.0,
-                        total_size as f64 / 1_048_576.0);
-                    last_percent = percent;
-                }
+        // Interrupted downloads continue from the bytes already on disk
+        let mut attempt = 1;
+        while let Err(e) = fetch_archive(&client, &url, &part_path) {
+            if attempt == LIBTORCH_DOWNLOAD_ATTEMPTS {
+                return Err(e).context(format!("Failed to download libtorch after {} attempts", attempt));
             }
+            tracing::warn!("Download of libtorch interrupted ({}), resuming", e);
+            attempt += 1;
         }
         
-        // Extract the zip
-        log::info!("Extracting libtorch to {}", extract_path.display());
+        verify_sha256(&part_path, config.sha256.as_deref())?;
+        std::fs::rename(&part_path, &zip_path)?;
+        
+        // Extract next to the final location and rename, so an interrupted extraction never looks installed
+        tracing::info!("Extracting libtorch to {}", extract_path.display());
+        if staging_path.exists() {
+            std::fs::remove_dir_all(&staging_path)?;
+        }
         let file = File::open(&zip_path)?;
         let mut archive = zip::ZipArchive::new(file)?;
-        archive.extract(&cache_dir)?;
+        if url.ends_with(".whl") {
+            extract_wheel(&mut archive, &staging_path.join("libtorch"))?;
+        } else {
+            archive.extract(&staging_path)?;
+        }
+        std::fs::rename(staging_path.join("libtorch"), &extract_path)
+            .context("The libtorch archive has no top-level libtorch directory")?;
+        std::fs::remove_dir_all(&staging_path)?;
         
         // Remove the zip file
         std::fs::remove_file(zip_path)?;
     }
     
-    // Set the LIBTORCH env var
-    std::env::set_var("LIBTORCH", extract_path.to_string_lossy().to_string());
-    
-    // Set up environment variables specific to Apple Silicon
-    setup_apple_silicon_env(&extract_path)?;
-    
-    log::info!("Libtorch successfully installed to {}", extract_path.display());
+    tracing::info!("Libtorch successfully installed to {}", extract_path.display());
     Ok(extract_path)
 }
 
-/// Set up environment variables for Apple Silicon
-fn setup_apple_silicon_env(libtorch_path: &Path) -> Result<()> {
-    let lib_path = libtorch_path.join("lib");
+/// Downloads the archive into `part_path`, continuing a partial file with an HTTP range request
+#[cfg(feature = "download")]
+fn fetch_archive(client: &reqwest::blocking::Client, url: &str, part_path: &Path) -> Result<()> {
+    let resume_from = std::fs::metadata(part_path).map(|metadata| metadata.len()).unwrap_or(0);
+    
+    let mut request = client.get(url);
+    if resume_from > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let response = request.send()?;
     
-    // Add lib to DYLD_LIBRARY_PATH
-    if let Ok(current_path) = std::env::var("DYLD_LIBRARY_PATH") {
-        let new_path = format!("{}:{}", lib_path.display(), current_path);
-        std::env::set_var("DYLD_LIBRARY_PATH", new_path);
-    } else {
-        std::env::set_var("DYLD_LIBRARY_PATH", lib_path.to_string_lossy().to_string());
+    // The partial file already holds the whole archive
+    if resume_from > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(());
     }
+    let mut response = response.error_for_status()?;
     
-    // Set DYLD_FALLBACK_LIBRARY_PATH (more reliable than DYLD_LIBRARY_PATH in some cases)
-    if let Ok(current_path) = std::env::var("DYLD_FALLBACK_LIBRARY_PATH") {
-        let new_path = format!("{}:{}", lib_path.display(), current_path);
-        std::env::set_var("DYLD_FALLBACK_LIBRARY_PATH", new_path);
+    // Servers without range support send the whole archive again
+    let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let (mut file, mut downloaded) = if resumed {
+        tracing::info!("Resuming libtorch download at {:.1} MB", resume_from as f64 / 1_048_576.0);
+        (OpenOptions::new().append(true).open(part_path)?, resume_from)
     } else {
-        std::env::set_var("DYLD_FALLBACK_LIBRARY_PATH", lib_path.to_string_lossy().to_string());
-    }
+        (File::create(part_path)?, 0)
+    };
+    let total_size = response.content_length().map(|length| downloaded + length).unwrap_or(0);
     
-    // Set additional variables if needed for MPS backend
-    if has_mps()? {
-        std::env::set_var("PYTORCH_ENABLE_MPS_FALLBACK", "1");
+    let mut last_percent = 0;
+    let mut buffer = [0; 8192];
+    
+    tracing::info!("Downloading libtorch ({:.1} MB)...", total_size as f64 / 1_048_576.0);
+    
+    loop {
+        let n = response.read(&mut buffer)?;
+        if n == 0 { break; }
+        
+        file.write_all(&buffer[..n])?;
+        downloaded += n as u64;
+        
+        if total_size > 0 {
+            let percent = (downloaded * 100 / total_size) as u8;
+            if percent > last_percent && percent % 10 == 0 {
+                tracing::info!("Download progress: {}% ({:.1}/{:.1} MB)", 
+                    percent,
+                    downloaded as f64 / 1_048_576.0,
+                    total_size as f64 / 1_048_576.0);
+                last_percent = percent;
+            }
+        }
     }
+    file.sync_all()?;
     
-    log::info!("Set dynamic library paths to include {}", lib_path.display());
+    if total_size > 0 && downloaded < total_size {
+        return Err(err!("Connection closed after {} of {} bytes", downloaded, total_size));
+    }
+    Ok(())
+}
+
+/// Checks the archive against the expected SHA-256, deleting it on a mismatch so the next run starts over
+#[cfg(feature = "download")]
+fn verify_sha256(path: &Path, expected: Option<&str>) -> Result<()> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    let digest = format!("{:x}", hasher.finalize());
+    
+    let Some(expected) = expected else {
+        tracing::info!("Downloaded libtorch with SHA-256 {}, set RUST_EMBED_LIBTORCH_SHA256 to pin it", digest);
+        return Ok(());
+    };
+    
+    if !digest.eq_ignore_ascii_case(expected.trim()) {
+        std::fs::remove_file(path)?;
+        return Err(err!("libtorch archive has SHA-256 {} but {} was expected", digest, expected.trim()));
+    }
+    tracing::info!("Verified libtorch SHA-256 {}", digest);
+    Ok(())
+}
+
+/// Unpacks the libtorch part of a PyTorch wheel (`torch/lib`, `torch/include`, `torch/share`) into the libtorch layout
+#[cfg(feature = "download")]
+fn extract_wheel(archive: &mut zip::ZipArchive<File>, extract_path: &Path) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(name) = entry.enclosed_name().map(Path::to_path_buf) else { continue };
+        let Ok(relative) = name.strip_prefix("torch") else { continue };
+        if !["lib", "include", "share"].iter().any(|dir| relative.starts_with(dir)) || entry.is_dir() {
+            continue;
+        }
+
+        let target = extract_path.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(&target)?)?;
+    }
+
+    if !extract_path.join("lib").join(libtorch_cpu_library()).exists() {
+        return Err(err!("The PyTorch wheel does not contain {}", libtorch_cpu_library()));
+    }
     Ok(())
 }
 
-/// Creates a symbolic link to libtorch libraries in a custom location
+/// Returns true for shared libraries, including versioned Linux names like `libgomp.so.1`
+pub(crate) fn is_shared_library(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    name.ends_with(".dylib") || name.ends_with(".so") || name.contains(".so.") || name.ends_with(".dll")
+}
+
+/// Variables libtorch reads at runtime that this process should have but doesn't, with their values
+///
+/// On a Mac with MPS, `PYTORCH_ENABLE_MPS_FALLBACK=1` makes operations without an MPS kernel run on the CPU
+/// instead of failing. Variables the user set, to any value, are left alone.
+pub fn missing_runtime_env() -> Vec<(&'static str, &'static str)> {
+    let mut missing = Vec::new();
+    if mps_status().available && std::env::var_os("PYTORCH_ENABLE_MPS_FALLBACK").is_none() {
+        missing.push(("PYTORCH_ENABLE_MPS_FALLBACK", "1"));
+    }
+    missing
+}
+
+/// Restarts the executable with `missing_runtime_env` added to its environment, if anything is missing
+///
+/// Call it first thing in `main`, before any other thread starts. The environment is never changed in place:
+/// `std::env::set_var` races with threads reading the environment, and libraries that already read a variable
+/// never see the change. Only returns on success when nothing was missing, or on platforms without `exec`,
+/// where a warning names the variables to set.
+pub fn reexec_with_runtime_env() -> Result<()> {
+    let missing = missing_runtime_env();
+    if missing.is_empty() || std::env::var_os(REEXEC_MARKER).is_some() {
+        return Ok(());
+    }
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        
+        tracing::debug!("Restarting with {:?} set for libtorch", missing);
+        let error = Command::new(std::env::current_exe()?)
+            .args(std::env::args_os().skip(1))
+            .envs(missing)
+            .env(REEXEC_MARKER, "1")
+            .exec();
+        Err(error).context("Failed to restart with the libtorch environment")
+    }
+    
+    #[cfg(not(unix))]
+    {
+        let names: Vec<String> = missing.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        tracing::warn!("Set {} before starting rust_embed", names.join(" "));
+        Ok(())
+    }
+}
+
+/// Creates a symbolic link to libtorch libraries in a custom location, Windows gets copies of the DLLs
 pub fn create_libtorch_symlinks(target_dir: &Path) -> Result<()> {
     let libtorch_path = ensure_libtorch()?;
     
@@ -233,22 +579,29 @@ pub fn create_libtorch_symlinks(target_dir: &Path) -> Result<()> {
     let target_lib_path = target_dir.join("lib");
     std::fs::create_dir_all(&target_lib_path).context("Failed to create lib directory")?;
     
-    // Symlink the dylib files
+    // Symlink the shared libraries
     let entries = std::fs::read_dir(lib_path)?;
     
     for entry in entries {
         let entry = entry?;
         let path = entry.path();
         
-        if let Some(ext) = path.extension() {
-            if ext == "dylib" {
-                let target = target_lib_path.join(path.file_name().unwrap());
-                log::info!("Creating symlink: {} -> {}", path.display(), target.display());
-                
-                if target.exists() {
-                    std::fs::remove_file(&target)?;
-                }
-                
+        if is_shared_library(&path) {
+            let target = target_lib_path.join(path.file_name().unwrap());
+            if target.exists() {
+                std::fs::remove_file(&target)?;
+            }
+            
+            // Symlinks need extra privileges on Windows, and the loader resolves DLLs by directory anyway
+            #[cfg(windows)]
+            {
+                tracing::info!("Copying {} -> {}", path.display(), target.display());
+                std::fs::copy(&path, &target)?;
+            }
+            
+            #[cfg(unix)]
+            {
+                tracing::info!("Creating symlink: {} -> {}", path.display(), target.display());
                 std::os::unix::fs::symlink(&path, &target)?;
             }
         }
@@ -282,7 +635,7 @@ pub fn fix_rpath_issues() -> Result<()> {
             
             if let Some(ext) = path.extension() {
                 if ext == "dylib" {
-                    log::info!("Fixing RPATH for {}", path.display());
+                    tracing::info!("Fixing RPATH for {}", path.display());
                     
                     // Add @rpath to the library
                     Command::new("install_name_tool")
@@ -308,35 +661,149 @@ pub fn fix_rpath_issues() -> Result<()> {
             }
         }
     } else {
-        log::warn!("install_name_tool not found, skipping RPATH fixes");
+        tracing::warn!("install_name_tool not found, skipping RPATH fixes");
+    }
+    
+    Ok(())
+}
+
+/// Libraries a shared library links against that the dynamic loader can't find
+///
+/// Linux asks `ldd`. macOS lists the `@rpath/` dependencies with `otool -L` and looks for each in the library's
+/// LC_RPATH entries, the ones `fix_rpath_issues` adds. Other platforms aren't checked and return an error.
+pub fn unresolved_dependencies(library: &Path) -> Result<Vec<String>> {
+    let run = |program: &str, args: &[&str]| -> Result<String> {
+        let output = Command::new(program)
+            .args(args)
+            .arg(library)
+            .output()
+            .with_context(|| format!("Failed to run {}", program))?;
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+    
+    if cfg!(target_os = "linux") {
+        return Ok(run("ldd", &[])?
+            .lines()
+            .filter(|line| line.contains("not found"))
+            .filter_map(|line| line.split_whitespace().next().map(str::to_string))
+            .collect());
+    }
+    if !cfg!(target_os = "macos") {
+        return Err(err!("Checking library dependencies is not supported on {}", std::env::consts::OS));
+    }
+    
+    // `otool -l` prints each LC_RPATH command as "cmd LC_RPATH", "cmdsize N", "path DIR (offset N)"
+    let loader_dir = library.parent().unwrap_or(Path::new("."));
+    let load_commands = run("otool", &["-l"])?;
+    let mut rpaths = Vec::new();
+    let mut lines = load_commands.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line == "cmd LC_RPATH" {
+            if let Some(path) = lines.nth(1).and_then(|line| line.strip_prefix("path ")) {
+                let path = path.split(" (offset").next().unwrap_or(path);
+                rpaths.push(PathBuf::from(path.replace("@loader_path", &loader_dir.to_string_lossy())));
+            }
+        }
     }
     
+    Ok(run("otool", &["-L"])?
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.trim().split(" (").next())
+        .filter_map(|dependency| dependency.strip_prefix("@rpath/"))
+        .filter(|name| !rpaths.iter().any(|rpath| rpath.join(name).exists()))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Set up libtorch for CPU inference on Intel Macs, Linux and Windows
+pub fn setup_for_cpu() -> Result<()> {
+    ensure_libtorch()?;
+    
+    if cfg!(target_os = "macos") {
+        fix_rpath_issues()?;
+    }
+    
+    tracing::info!("CPU environment configured successfully");
     Ok(())
 }
 
-/// Set up all environment variables and paths for Apple Silicon
+/// Set up libtorch and its library paths for Apple Silicon
+///
+/// The process environment is left untouched, see `reexec_with_runtime_env` for the variables libtorch reads.
 pub fn setup_for_apple_silicon() -> Result<()> {
     // Verify we're on Apple Silicon
     if !is_apple_silicon()? {
-        return Err(anyhow!("This function should only be called on Apple Silicon (M-series) Macs"));
+        return Err(err!("This function should only be called on Apple Silicon (M-series) Macs"));
     }
     
     // Ensure libtorch is available
-    let libtorch_path = ensure_libtorch()?;
-    
-    // Set up dynamic library paths
-    setup_apple_silicon_env(&libtorch_path)?;
+    ensure_libtorch()?;
     
     // Fix RPATH issues
     fix_rpath_issues()?;
     
     // Print MPS availability for diagnostics
     if has_mps()? {
-        log::info!("Metal Performance Shaders (MPS) is available - will use hardware acceleration");
+        tracing::info!("Metal Performance Shaders (MPS) is available - will use hardware acceleration");
     } else {
-        log::info!("Metal Performance Shaders (MPS) not detected - will use CPU only");
+        tracing::info!("Metal Performance Shaders (MPS) not detected - will use CPU only");
     }
     
-    log::info!("Apple Silicon environment configured successfully");
+    tracing::info!("Apple Silicon environment configured successfully");
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_libtorch_config_versions_and_mirror() -> Result<()> {
+        assert!(same_minor_release("2.0.1", "2.0.0"));
+        assert!(!same_minor_release("2.1.0", "2.0.0"));
+        assert!(!same_minor_release("2.00.0", "2.0.0"));
+
+        let config = LibtorchConfig {
+            version: "2.0.1".to_string(),
+            url_override: None,
+            mirror: Some("https://mirror.example.com/pytorch/".to_string()),
+            sha256: None,
+        };
+        let url = config.archive_url(archive_path("linux", "x86_64")?);
+        assert_eq!(url, "https://mirror.example.com/pytorch/libtorch/cpu/libtorch-cxx11-abi-shared-with-deps-2.0.1%2Bcpu.zip");
+        assert!(archive_path("freebsd", "x86_64").is_err());
+
+        let url_override = "https://example.com/libtorch.zip".to_string();
+        let config = LibtorchConfig { url_override: Some(url_override.clone()), ..config };
+        assert_eq!(config.url()?, url_override);
+
+        let config = LibtorchConfig { version: "1.13.1".to_string(), ..LibtorchConfig::default() };
+        assert!(std::env::var_os("LIBTORCH_BYPASS_VERSION_CHECK").is_some() || config.url().is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "download")]
+    fn test_verify_sha256_removes_mismatched_archive() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_sha256_{}.part", std::process::id()));
+        std::fs::write(&path, "abc")?;
+
+        verify_sha256(&path, Some("BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"))?;
+        assert!(path.exists());
+
+        assert!(verify_sha256(&path, Some(&"0".repeat(64))).is_err());
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn test_mps_status_is_unavailable_off_macos() -> Result<()> {
+        let status = mps_status();
+        assert!(!status.available);
+        assert_eq!(status.detection, MpsDetection::NotAppleSilicon);
+        assert!(!has_mps()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::error::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Suffix of the lock file kept next to a locked cache entry
+const LOCK_SUFFIX: &str = ".lock";
+
+/// Advisory lock on a cache entry, held across processes on the machine until dropped
+///
+/// The lock is taken on `ENTRY.lock` next to the entry, since the entry may not exist yet or be replaced while
+/// locked. It only keeps other rust_embed processes out, nothing stops other programs from writing to the entry.
+#[derive(Debug)]
+pub struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    /// Waits until no other process holds the lock on `entry`, logging when it has to wait
+    pub fn acquire(entry: &Path) -> Result<Self> {
+        let (file, path) = open(entry)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                tracing::info!("Waiting for another process using {}", entry.display());
+                file.lock().with_context(|| format!("Failed to lock {}", path.display()))?;
+            }
+            Err(TryLockError::Error(error)) => return Err(error).with_context(|| format!("Failed to lock {}", path.display())),
+        }
+        Ok(Self { file })
+    }
+
+    /// Takes the lock on `entry` unless another process holds it
+    pub fn try_acquire(entry: &Path) -> Result<Option<Self>> {
+        let (file, path) = open(entry)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(error)) => Err(error).with_context(|| format!("Failed to lock {}", path.display())),
+        }
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // The file stays, deleting it would let two processes lock different files of the same name
+        let _ = self.file.unlock();
+    }
+}
+
+/// Opens, creating it if needed, the lock file of an entry
+fn open(entry: &Path) -> Result<(File, PathBuf)> {
+    let name = entry.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let path = entry.with_file_name(format!("{}{}", name, LOCK_SUFFIX));
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+    Ok((file, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excludes_a_second_holder_until_released() -> Result<()> {
+        let entry = std::env::temp_dir().join(format!("rust_embed_lock_{}", std::process::id())).join("model");
+        let held = CacheLock::acquire(&entry)?;
+        assert!(CacheLock::try_acquire(&entry)?.is_none());
+        drop(held);
+        assert!(CacheLock::try_acquire(&entry)?.is_some());
+        fs::remove_dir_all(entry.parent().unwrap())?;
+        Ok(())
+    }
+}
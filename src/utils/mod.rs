@@ -1,80 +1,118 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod libtorch;
+#[cfg(all(feature = "bundle", not(target_arch = "wasm32")))]
+pub mod bundle;
+pub mod concurrency;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod doctor;
+#[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+pub mod http;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lock;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod model_cache;
+pub mod offline;
+pub mod paths;
+#[cfg(all(feature = "package", not(target_arch = "wasm32")))]
+pub mod package;
+pub mod reproducibility;
+pub mod simd;
+pub mod storage;
+#[cfg(all(feature = "tables", not(target_arch = "wasm32")))]
+pub mod table;
 
-use anyhow::Result;
+use crate::error::{err, Result};
+use concurrency::ConcurrencyConfig;
 use std::path::Path;
-use std::os::unix::fs::PermissionsExt;
 
-/// Initialize all necessary utilities for rust-embed on Apple Silicon
+/// Initialize all necessary utilities for rust-embed, with thread limits from the environment
 pub fn initialize() -> Result<()> {
+    initialize_with(&ConcurrencyConfig::default())
+}
+
+/// Whether the platform setup of `initialize_with` has completed in this process
+#[cfg(not(target_arch = "wasm32"))]
+static PLATFORM_READY: std::sync::Mutex<bool> = std::sync::Mutex::new(false);
+
+/// Initialize all necessary utilities for rust-embed, with MPS on Apple Silicon and the CPU elsewhere
+///
+/// Limits left unset in `concurrency` are read from `RUST_EMBED_MAX_THREADS` and `RUST_EMBED_TORCH_THREADS`.
+/// Safe to call from several threads, the platform setup runs once and never changes the process environment.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn initialize_with(concurrency: &ConcurrencyConfig) -> Result<()> {
+    concurrency.or_env()?.apply()?;
+    
+    // Threads initializing together wait for the first one instead of fixing up libtorch at the same time
+    let mut ready = PLATFORM_READY.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    if *ready {
+        return Ok(());
+    }
+    
     // Check if we're running on Apple Silicon
     if libtorch::is_apple_silicon()? {
         // Set up the Apple Silicon environment
         libtorch::setup_for_apple_silicon()?;
         
         // Report MPS availability
-        if libtorch::has_mps()? {
-            log::info!("Metal Performance Shaders acceleration is available and enabled");
+        let mps = libtorch::mps_status();
+        if mps.available {
+            tracing::info!("Metal Performance Shaders acceleration is available and enabled (detected by {})", mps.detection);
         } else {
-            log::info!("Metal Performance Shaders not available, using CPU only");
+            tracing::info!("Metal Performance Shaders not available according to {}, using CPU only", mps.detection);
         }
+    } else if simd::has_avx2() {
+        tracing::info!("Running on {} with AVX2, using the CPU backend", std::env::consts::ARCH);
     } else {
-        log::warn!("This version is optimized for Apple Silicon (M-series) processors");
-        log::warn!("Some functionality may not work correctly on Intel Macs");
+        tracing::info!("Running on {}, using the CPU backend", std::env::consts::ARCH);
     }
     
+    *ready = true;
     Ok(())
 }
 
-/// Create a wrapper binary directory with all necessary libraries for Apple Silicon
+/// Nothing needs initializing in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn initialize_with(_concurrency: &ConcurrencyConfig) -> Result<()> {
+    Ok(())
+}
+
+/// Create a wrapper binary directory with all necessary libraries, for the platform this runs on
+#[cfg(all(feature = "package", not(target_arch = "wasm32")))]
 pub fn create_binary_wrapper<P: AsRef<Path>>(target_dir: P) -> Result<()> {
-    let target_dir = target_dir.as_ref();
-    
-    // Create libtorch symlinks for packaging
-    libtorch::create_libtorch_symlinks(target_dir)?;
-    
-    // Create shell script wrapper to set up environment variables
-    let wrapper_path = target_dir.join("run_rust_embed.sh");
-    let wrapper_content = format!(
-        "#!/bin/bash\n\
-         # Wrapper script for rust_embed on Apple Silicon\n\
-         SCRIPT_DIR=\"$( cd \"$( dirname \"${{BASH_SOURCE[0]}}\" )\" && pwd )\"\n\
-         export DYLD_LIBRARY_PATH=\"$SCRIPT_DIR/lib:$DYLD_LIBRARY_PATH\"\n\
-         export DYLD_FALLBACK_LIBRARY_PATH=\"$SCRIPT_DIR/lib:$DYLD_FALLBACK_LIBRARY_PATH\"\n\
-         export LIBTORCH=\"$SCRIPT_DIR\"\n\
-         \n\
-         # Enable Metal Performance Shaders if available\n\
-         if [ -f \"$SCRIPT_DIR/metal/mps_available\" ]; then\n\
-         export PYTORCH_ENABLE_MPS_FALLBACK=1\n\
-         fi\n\
-         \n\
-         # Run the actual binary\n\
-         \"$SCRIPT_DIR/rust_embed\" \"$@\"\n"
-    );
-    
-    std::fs::write(&wrapper_path, wrapper_content)?;
-    std::fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755))?;
-    
-    log::info!("Binary wrapper created in {}", target_dir.display());
+    let config = package::PackageConfig::new(package::PackageTarget::host()?);
+    package::package(&config, target_dir.as_ref())?;
     Ok(())
 }
 
 /// Returns true if running on Apple Silicon (M-series processors)
+#[cfg(not(target_arch = "wasm32"))]
 pub fn is_apple_silicon() -> bool {
     libtorch::is_apple_silicon().unwrap_or(false)
 }
 
 /// Returns true if Metal Performance Shaders (MPS) acceleration is available
+#[cfg(not(target_arch = "wasm32"))]
 pub fn has_mps() -> bool {
     libtorch::has_mps().unwrap_or(false)
 }
 
-/// Cache home directory for model storage
+/// Always false in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn is_apple_silicon() -> bool {
+    false
+}
+
+/// Always false in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn has_mps() -> bool {
+    false
+}
+
+/// Cache home directory for model storage, `paths::Paths::models` of the current paths
 pub fn cache_home() -> std::path::PathBuf {
-    if let Some(cache_dir) = dirs::cache_dir() {
-        cache_dir.join("rust_embed")
-    } else {
-        std::env::temp_dir().join("rust_embed")
-    }
+    paths::current().models
 }
 
 /// Normalizes a vector to unit length
@@ -85,6 +123,30 @@ pub fn normalize(vec: &mut ndarray::Array1<f32>) {
     }
 }
 
+/// Computes the cosine similarity between two vectors
+pub fn cosine_similarity(a: &ndarray::Array1<f32>, b: &ndarray::Array1<f32>) -> f32 {
+    if let (Some(a), Some(b)) = (a.as_slice(), b.as_slice()) {
+        if a.len() == b.len() {
+            return simd::cosine_similarity(a, b);
+        }
+    }
+    
+    let dot_product = a.dot(b);
+    let norm_a = a.dot(a).sqrt();
+    let norm_b = b.dot(b).sqrt();
+    
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    
+    dot_product / (norm_a * norm_b)
+}
+
+/// Estimates the WordPiece tokens of a text, about one per four characters of each word plus [CLS] and [SEP]
+pub fn estimate_tokens(text: &str) -> usize {
+    text.split_whitespace().map(|word| word.chars().count().div_ceil(4)).sum::<usize>() + 2
+}
+
 /// Preprocesses text for embedding
 pub fn preprocess_text(text: &str) -> String {
     // Simple preprocessing: trim, lowercase, collapse whitespace
@@ -94,6 +156,7 @@ pub fn preprocess_text(text: &str) -> String {
 }
 
 /// Save an embedding model to disk
+#[cfg(feature = "proto-store")]
 pub fn save_embeddings(
     embeddings: &[ndarray::Array1<f32>],
     texts: Option<&[String]>,
@@ -102,11 +165,219 @@ pub fn save_embeddings(
     dimension: i32,
     path: impl AsRef<Path>,
 ) -> Result<()> {
+    let info = crate::embedding::ModelInfo::new(model_name, model_version, usize::try_from(dimension)?);
+    save_embeddings_with(embeddings, &info, SaveOptions { texts, ..SaveOptions::default() }, path)
+}
+
+/// What `save_embeddings_with` stores along with each vector
+#[cfg(feature = "proto-store")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveOptions<'a> {
+    /// Texts of the embeddings, or the image paths for `Modality::Image`
+    pub texts: Option<&'a [String]>,
+    /// The caller's id of each embedding, such as a CSV id column
+    pub ids: Option<&'a [String]>,
+    /// Fields of each input record, such as JSONL meta fields
+    pub metadata: &'a [std::collections::HashMap<String, String>],
+    /// What kind of input the embeddings were computed from
+    pub modality: crate::collection::Modality,
+    /// Record the device, pooling, normalization, weights hash and preprocessing of the model info, which
+    /// `rust_embed info` shows later
+    pub provenance: bool,
+}
+
+/// Save embeddings made by the model described by `info`, with the texts, ids, metadata and provenance of `options`
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display(), embeddings = embeddings.len()))]
+pub fn save_embeddings_with(
+    embeddings: &[ndarray::Array1<f32>],
+    info: &crate::embedding::ModelInfo,
+    options: SaveOptions<'_>,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let modality = match options.modality {
+        crate::collection::Modality::Text => crate::proto::Modality::Text,
+        crate::collection::Modality::Image => crate::proto::Modality::Image,
+    };
+    let mut collection = dense_collection(
+        embeddings, options.texts, modality, &info.name, &info.version, i32::try_from(info.dimension)?,
+    );
+    if options.provenance {
+        collection.provenance = Some((&crate::collection::Provenance::from_model_info(info)).into());
+    }
+    for (embedding, id) in collection.embeddings.iter_mut().zip(options.ids.unwrap_or_default()) {
+        embedding.id = id.clone();
+    }
+    for (embedding, fields) in collection.embeddings.iter_mut().zip(options.metadata) {
+        embedding.metadata = fields.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+    }
+    storage::write(path, prost::Message::encode_to_vec(&collection))?;
+    Ok(())
+}
+
+/// Save texts with their embeddings and the provenance of the model that made them
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display(), embeddings = items.len()))]
+pub fn save_embedded_texts(
+    items: &[crate::embedding::EmbeddedText],
+    info: &crate::embedding::ModelInfo,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let mut collection = crate::collection::StoredCollection::for_model(info);
+    collection.embeddings = items.iter().map(crate::collection::StoredEmbedding::from).collect();
+    collection.save(path)
+}
+
+/// Load texts with their embeddings, as saved by `save_embedded_texts` or any collection with texts
+#[cfg(feature = "proto-store")]
+pub fn load_embedded_texts(path: impl AsRef<Path>) -> Result<Vec<crate::embedding::EmbeddedText>> {
+    let collection = crate::collection::StoredCollection::load(path)?;
+    Ok(collection.embeddings.into_iter().map(crate::embedding::EmbeddedText::from).collect())
+}
+
+/// Combine collections made by the same model into one, in the order given
+///
+/// Every input must have the first one's model name, version and dimension, the same weights hash where both
+/// record one, and the same kind of vectors, otherwise this fails with
+/// `Error::IncompatibleEmbeddings` naming the first input that differs. Sharded collections are read shard by
+/// shard. The merged collection keeps the first input's provenance, and ids found in more than one input are
+/// logged as a warning but kept.
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(inputs = paths.len()))]
+pub fn merge_collections<P: AsRef<Path>>(paths: &[P]) -> Result<crate::collection::StoredCollection> {
+    use crate::collection::{shard, ShardedCollection, StoredCollection};
+
+    let mut merged: Option<StoredCollection> = None;
+    for path in paths {
+        let path = path.as_ref();
+        let parts = if shard::is_sharded(path) {
+            ShardedCollection::open(path)?.shards().collect::<Result<Vec<_>>>()?
+        } else {
+            vec![StoredCollection::load(path)?]
+        };
+        for part in parts {
+            let Some(merged) = merged.as_mut() else {
+                merged = Some(part);
+                continue;
+            };
+            let mismatches = collection_mismatches(merged, &part);
+            if !mismatches.is_empty() {
+                return Err(crate::Error::IncompatibleEmbeddings {
+                    path: path.display().to_string(),
+                    model: merged.model_name.clone(),
+                    mismatches: mismatches.join(", "),
+                });
+            }
+            merged.embeddings.extend(part.embeddings);
+        }
+    }
+    let merged = merged.ok_or_else(|| err!("No collections to merge"))?;
+
+    let mut seen = std::collections::HashSet::new();
+    let duplicates = merged.embeddings.iter()
+        .filter_map(|embedding| embedding.id.as_deref())
+        .filter(|id| !seen.insert(*id))
+        .count();
+    if duplicates > 0 {
+        tracing::warn!("{} id(s) appear in more than one of the merged collections", duplicates);
+    }
+    Ok(merged)
+}
+
+/// Split a collection into `chunks` parts of nearly equal size, each with the model and provenance of the whole
+///
+/// Parts are contiguous and in order, the first ones get one embedding more when the count doesn't divide
+/// evenly. There are fewer parts than asked when the collection has fewer embeddings.
+#[cfg(feature = "proto-store")]
+pub fn split_collection(
+    collection: crate::collection::StoredCollection,
+    chunks: usize,
+) -> Result<Vec<crate::collection::StoredCollection>> {
+    if chunks == 0 {
+        return Err(err!("Cannot split a collection into 0 parts"));
+    }
+    let total = collection.embeddings.len();
+    let parts = chunks.min(total.max(1));
+    let template = crate::collection::StoredCollection { embeddings: Vec::new(), ..collection.clone() };
+
+    let mut embeddings = collection.embeddings.into_iter();
+    Ok((0..parts)
+        .map(|part| {
+            let size = total / parts + usize::from(part < total % parts);
+            crate::collection::StoredCollection { embeddings: embeddings.by_ref().take(size).collect(), ..template.clone() }
+        })
+        .collect())
+}
+
+/// Describes how a collection differs from the one it is merged into, empty when they can be combined
+#[cfg(feature = "proto-store")]
+fn collection_mismatches(
+    merged: &crate::collection::StoredCollection,
+    other: &crate::collection::StoredCollection,
+) -> Vec<String> {
+    use crate::collection::StoredVector;
+
+    let mut mismatches = Vec::new();
+    if other.model_name != merged.model_name {
+        mismatches.push(format!("made by '{}'", other.model_name));
+    }
+    if other.model_version != merged.model_version {
+        mismatches.push(format!("version '{}' instead of '{}'", other.model_version, merged.model_version));
+    }
+    if other.dimension != merged.dimension {
+        mismatches.push(format!("{} dimensions instead of {}", other.dimension, merged.dimension));
+    }
+    let weights = |collection: &crate::collection::StoredCollection| collection.provenance.as_ref()
+        .and_then(|provenance| provenance.weights_sha256.clone());
+    if let (Some(expected), Some(found)) = (weights(merged), weights(other)) {
+        if expected != found {
+            mismatches.push(format!("weights {} instead of {}", found, expected));
+        }
+    }
+
+    let sparse = |collection: &crate::collection::StoredCollection| collection.embeddings.first()
+        .map(|embedding| matches!(embedding.vector, StoredVector::Sparse(_)));
+    if let (Some(expected), Some(found)) = (sparse(merged), sparse(other)) {
+        if expected != found {
+            mismatches.push(format!("{} vectors", if found { "sparse" } else { "dense" }));
+        }
+    }
+    mismatches
+}
+
+/// Load the metadata stored with each embedding, empty for embeddings saved without any
+#[cfg(feature = "proto-store")]
+pub fn load_embedding_metadata(path: impl AsRef<Path>) -> Result<Vec<std::collections::HashMap<String, String>>> {
+    let bytes = storage::read(path)?;
+    let collection: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
+    Ok(collection.embeddings.into_iter().map(|e| e.metadata.into_iter().collect()).collect())
+}
+
+/// Load the ids stored with each embedding, None when the collection has none
+#[cfg(feature = "proto-store")]
+pub fn load_embedding_ids(path: impl AsRef<Path>) -> Result<Option<Vec<String>>> {
+    let bytes = storage::read(path)?;
+    let collection: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
+    let has_ids = collection.embeddings.iter().any(|e| !e.id.is_empty());
+    Ok(has_ids.then(|| collection.embeddings.into_iter().map(|e| e.id).collect()))
+}
+
+/// Builds the protobuf message for dense embeddings
+#[cfg(feature = "proto-store")]
+fn dense_collection(
+    embeddings: &[ndarray::Array1<f32>],
+    texts: Option<&[String]>,
+    modality: crate::proto::Modality,
+    model_name: &str,
+    model_version: &str,
+    dimension: i32,
+) -> crate::proto::EmbeddingCollection {
     // Create a protobuf message for the embeddings
     let mut pb_embeddings = crate::proto::EmbeddingCollection::default();
     pb_embeddings.model_name = model_name.to_string();
     pb_embeddings.model_version = model_version.to_string();
     pb_embeddings.dimension = dimension;
+    pb_embeddings.provenance = Some((&crate::collection::Provenance::current()).into());
     
     // Add the embeddings and texts to the message
     for (i, embedding) in embeddings.iter().enumerate() {
@@ -119,28 +390,84 @@ pub fn save_embeddings(
             }
         }
         
-        pb_embedding.timestamp = chrono::Utc::now().timestamp();
+        pb_embedding.timestamp = reproducibility::timestamp();
+        pb_embedding.set_modality(modality);
         pb_embeddings.embeddings.push(pb_embedding);
     }
     
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.as_ref().parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    
-    // Serialize the embeddings to protobuf
-    let bytes = prost::Message::encode_to_vec(&pb_embeddings);
+    pb_embeddings
+}
+
+/// Save sparse embeddings to disk, `vocab_size` is stored as the collection dimension
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display(), embeddings = embeddings.len()))]
+pub fn save_sparse_embeddings(
+    embeddings: &[crate::embedding::SparseEmbedding],
+    texts: Option<&[String]>,
+    model_name: &str,
+    model_version: &str,
+    vocab_size: i32,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let collection = crate::proto::EmbeddingCollection {
+        model_name: model_name.to_string(),
+        model_version: model_version.to_string(),
+        dimension: vocab_size,
+        provenance: Some((&crate::collection::Provenance::current()).into()),
+        embeddings: embeddings.iter()
+            .enumerate()
+            .map(|(i, embedding)| crate::proto::Embedding {
+                text: texts.and_then(|texts| texts.get(i)).cloned().unwrap_or_default(),
+                timestamp: reproducibility::timestamp(),
+                sparse: Some(crate::proto::SparseVector {
+                    indices: embedding.indices.clone(),
+                    values: embedding.values.clone(),
+                }),
+                ..Default::default()
+            })
+            .collect(),
+    };
     
-    // Write the serialized embeddings to disk
-    std::fs::write(path, bytes)?;
+    storage::write(path, prost::Message::encode_to_vec(&collection))?;
     
     Ok(())
 }
 
+/// Load sparse embeddings from disk
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display()))]
+pub fn load_sparse_embeddings(path: impl AsRef<Path>) -> Result<(Vec<crate::embedding::SparseEmbedding>, Option<Vec<String>>)> {
+    let bytes = storage::read(path)?;
+    let collection: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
+    
+    let has_texts = collection.embeddings.iter().any(|e| !e.text.is_empty());
+    let mut embeddings = Vec::with_capacity(collection.embeddings.len());
+    let mut texts = Vec::with_capacity(collection.embeddings.len());
+    
+    for embedding in collection.embeddings {
+        let sparse = embedding.sparse
+            .ok_or_else(|| err!("Collection contains dense embeddings, expected sparse ones"))?;
+        if sparse.indices.len() != sparse.values.len() {
+            return Err(err!("Sparse embedding has {} indices but {} values", sparse.indices.len(), sparse.values.len()));
+        }
+        embeddings.push(crate::embedding::SparseEmbedding {
+            indices: sparse.indices,
+            values: sparse.values,
+        });
+        if has_texts {
+            texts.push(embedding.text);
+        }
+    }
+    
+    Ok((embeddings, has_texts.then_some(texts)))
+}
+
 /// Load embeddings from disk
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display()))]
 pub fn load_embeddings(path: impl AsRef<Path>) -> Result<(Vec<ndarray::Array1<f32>>, Option<Vec<String>>)> {
-    // Read the file
-    let bytes = std::fs::read(path)?;
+    // Read the file from disk or object storage
+    let bytes = storage::read(path)?;
     
     // Deserialize the embeddings from protobuf
     let proto_embeddings: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
@@ -149,8 +476,109 @@ pub fn load_embeddings(path: impl AsRef<Path>) -> Result<(Vec<ndarray::Array1<f3
     convert_proto_embeddings(proto_embeddings)
 }
 
+/// What `load_embeddings_checked_with` does when stored embeddings don't match the embedder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MismatchPolicy {
+    /// Fail with `Error::IncompatibleEmbeddings`
+    #[default]
+    Error,
+    /// Log a warning and return the embeddings anyway
+    Warn,
+}
+
+/// Load embeddings, failing if they were not made by the model of `embedder`
+///
+/// Similarities between vectors from different models are meaningless, so the stored model name, version and
+/// dimension are compared with the embedder's, and for models that normalize, the vectors must be unit length.
+/// Files without a model name or version skip those checks.
+#[cfg(feature = "proto-store")]
+pub fn load_embeddings_checked<E: crate::Embedder>(
+    path: impl AsRef<Path>,
+    embedder: &E,
+) -> Result<(Vec<ndarray::Array1<f32>>, Option<Vec<String>>)> {
+    load_embeddings_checked_with(path, embedder, MismatchPolicy::Error)
+}
+
+/// Load embeddings checked against `embedder`, with a choice of failing or warning on a mismatch
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display()))]
+pub fn load_embeddings_checked_with<E: crate::Embedder>(
+    path: impl AsRef<Path>,
+    embedder: &E,
+    policy: MismatchPolicy,
+) -> Result<(Vec<ndarray::Array1<f32>>, Option<Vec<String>>)> {
+    convert_proto_embeddings(read_checked(path.as_ref(), embedder, policy)?)
+}
+
+/// Load a whole collection, with its ids and metadata, checked against `embedder` like `load_embeddings_checked`
+#[cfg(feature = "proto-store")]
+#[tracing::instrument(skip_all, fields(path = %path.as_ref().display()))]
+pub fn load_collection_checked<E: crate::Embedder>(
+    path: impl AsRef<Path>,
+    embedder: &E,
+) -> Result<crate::collection::StoredCollection> {
+    read_checked(path.as_ref(), embedder, MismatchPolicy::Error)?.try_into()
+}
+
+/// Decodes an embedding file and applies `policy` to how it differs from what `embedder` produces
+#[cfg(feature = "proto-store")]
+fn read_checked<E: crate::Embedder>(
+    path: &Path,
+    embedder: &E,
+    policy: MismatchPolicy,
+) -> Result<crate::proto::EmbeddingCollection> {
+    let bytes = storage::read(path)?;
+    let collection: crate::proto::EmbeddingCollection = prost::Message::decode(bytes.as_slice())?;
+
+    let info = embedder.model_info();
+    let mismatches = embedding_mismatches(&collection, &info);
+    if !mismatches.is_empty() {
+        let mismatches = mismatches.join(", ");
+        match policy {
+            MismatchPolicy::Error => {
+                return Err(crate::Error::IncompatibleEmbeddings { path: path.display().to_string(), model: info.name, mismatches });
+            }
+            MismatchPolicy::Warn => {
+                tracing::warn!("Embeddings in {} do not match model '{}': {}", path.display(), info.name, mismatches);
+            }
+        }
+    }
+    Ok(collection)
+}
+
+/// Describes how a stored collection differs from what the model produces, empty when it matches
+#[cfg(feature = "proto-store")]
+fn embedding_mismatches(collection: &crate::proto::EmbeddingCollection, info: &crate::embedding::ModelInfo) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if !collection.model_name.is_empty() && collection.model_name != info.name {
+        mismatches.push(format!("made by '{}'", collection.model_name));
+    }
+    if !collection.model_version.is_empty() && collection.model_version != info.version {
+        mismatches.push(format!("version '{}' instead of '{}'", collection.model_version, info.version));
+    }
+
+    let dimension = collection.embeddings.iter()
+        .map(|embedding| embedding.values.len())
+        .find(|&len| len != info.dimension)
+        .or_else(|| (collection.dimension > 0 && collection.dimension as usize != info.dimension).then_some(collection.dimension as usize));
+    if let Some(dimension) = dimension {
+        mismatches.push(format!("{} dimensions instead of {}", dimension, info.dimension));
+    }
+
+    if info.normalized {
+        let unnormalized = collection.embeddings.iter()
+            .filter(|embedding| !embedding.values.is_empty())
+            .any(|embedding| (embedding.values.iter().map(|v| v * v).sum::<f32>().sqrt() - 1.0).abs() > 1e-3);
+        if unnormalized {
+            mismatches.push("vectors are not unit length".to_string());
+        }
+    }
+    mismatches
+}
+
 /// Convert a proto Embeddings to a tuple of vectors and texts
-pub fn convert_proto_embeddings(proto_embeddings: crate::proto::EmbeddingCollection) 
+#[cfg(feature = "proto-store")]
+pub(crate) fn convert_proto_embeddings(proto_embeddings: crate::proto::EmbeddingCollection) 
     -> Result<(Vec<ndarray::Array1<f32>>, Option<Vec<String>>)> {
     
     let mut embeddings = Vec::with_capacity(proto_embeddings.embeddings.len());
@@ -167,4 +595,113 @@ pub fn convert_proto_embeddings(proto_embeddings: crate::proto::EmbeddingCollect
     let texts = if has_texts { Some(texts) } else { None };
     
     Ok((embeddings, texts))
-} 
\ No newline at end of file
+} 
+
+#[cfg(all(test, feature = "proto-store"))]
+mod tests {
+    use super::*;
+    use crate::test_support::LengthEmbedder;
+    use ndarray::Array1;
+
+    /// A model whose vectors are unit length, so checked loads also check the stored vectors
+    const UNIT: LengthEmbedder = LengthEmbedder { dimension: 2, normalized: true };
+
+    #[test]
+    fn test_checked_load_rejects_other_models() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_checked_{}.pb", std::process::id()));
+        let unit = [Array1::from(vec![0.6, 0.8])];
+        save_embeddings(&unit, None, "length", "1", 2, &path)?;
+        assert_eq!(load_embeddings_checked(&path, &UNIT)?.0.len(), 1);
+
+        save_embeddings(&[Array1::from(vec![3.0, 4.0])], None, "length", "0", 2, &path)?;
+        match load_embeddings_checked(&path, &UNIT) {
+            Err(crate::Error::IncompatibleEmbeddings { mismatches, .. }) => {
+                assert_eq!(mismatches, "version '0' instead of '1', vectors are not unit length");
+            }
+            other => panic!("expected a mismatch, got {:?}", other.map(|(embeddings, _)| embeddings)),
+        }
+        assert!(load_embeddings_checked_with(&path, &UNIT, MismatchPolicy::Warn).is_ok());
+        assert!(matches!(load_collection_checked(&path, &UNIT), Err(crate::Error::IncompatibleEmbeddings { .. })));
+
+        save_embeddings(&unit, Some(&["unit".to_string()]), "length", "1", 2, &path)?;
+        let collection = load_collection_checked(&path, &UNIT)?;
+        assert_eq!(collection.embeddings[0].text.as_deref(), Some("unit"));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_provenance_round_trips() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_provenance_{}.pb", std::process::id()));
+        let info = crate::embedding::ModelInfo {
+            device: Some("cuda:1".to_string()),
+            pooling: Some("mean".to_string()),
+            weights_sha256: Some("ab".repeat(32)),
+            preprocessing: [("lowercase".to_string(), "true".to_string())].into(),
+            ..crate::Embedder::model_info(&UNIT)
+        };
+        let ids = ["a".to_string()];
+        let options = SaveOptions { ids: Some(&ids), provenance: true, ..SaveOptions::default() };
+        save_embeddings_with(&[Array1::from(vec![0.6, 0.8])], &info, options, &path)?;
+
+        let collection = crate::collection::StoredCollection::load(&path)?;
+        assert_eq!((collection.model_name.as_str(), collection.dimension, collection.embeddings.len()), ("length", 2, 1));
+        let provenance = collection.provenance.expect("provenance is recorded");
+        assert_eq!(provenance.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!((provenance.device.as_deref(), provenance.normalized), (Some("cuda:1"), true));
+        assert_eq!(provenance.preprocessing["lowercase"], "true");
+        assert_eq!(load_embedding_ids(&path)?, Some(vec!["a".to_string()]));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_modality_round_trips() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_modality_{}.pb", std::process::id()));
+        let paths = vec!["photos/cat.jpg".to_string()];
+        let options = SaveOptions { texts: Some(&paths), modality: crate::collection::Modality::Image, ..SaveOptions::default() };
+        save_embeddings_with(&[Array1::from(vec![0.6, 0.8])], &crate::embedding::ModelInfo::new("clip", "1.0", 2), options, &path)?;
+
+        let collection = crate::collection::StoredCollection::load(&path)?;
+        let embedding = &collection.embeddings[0];
+        assert_eq!(embedding.modality, crate::collection::Modality::Image);
+        assert_eq!(embedding.text.as_deref(), Some("photos/cat.jpg"));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_merges_compatible_collections_and_splits_them() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let paths: Vec<_> = ["a", "b", "c"].iter()
+            .map(|name| dir.join(format!("rust_embed_merge_{}_{}.pb", name, std::process::id())))
+            .collect();
+        let info = crate::Embedder::model_info(&UNIT);
+        let options = SaveOptions { provenance: true, ..SaveOptions::default() };
+        save_embeddings_with(&vec![Array1::from(vec![1.0, 0.0]); 3], &info, options, &paths[0])?;
+        save_embeddings_with(&vec![Array1::from(vec![0.0, 1.0]); 2], &info, options, &paths[1])?;
+        save_embeddings(&[Array1::from(vec![1.0, 0.0, 0.0])], None, "other", "1", 3, &paths[2])?;
+
+        let merged = merge_collections(&paths[..2])?;
+        assert_eq!(merged.embeddings.len(), 5);
+        assert_eq!(merged.embeddings[3].dense_vector(), Some(&Array1::from(vec![0.0, 1.0])));
+        match merge_collections(&paths) {
+            Err(crate::Error::IncompatibleEmbeddings { mismatches, .. }) => {
+                assert_eq!(mismatches, "made by 'other', 3 dimensions instead of 2");
+            }
+            other => panic!("expected a mismatch, got {:?}", other.map(|merged| merged.embeddings.len())),
+        }
+
+        let parts = split_collection(merged, 2)?;
+        assert_eq!(parts.iter().map(|part| part.embeddings.len()).collect::<Vec<_>>(), vec![3, 2]);
+        assert_eq!(parts[1].provenance, parts[0].provenance);
+
+        for path in &paths {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use super::cache_home;
+use super::lock::CacheLock;
+#[cfg(feature = "download")]
+use crate::error::{err, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the file recording the hashes of a cached model's downloads
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Suffixes of downloads and extractions that never completed
+const INCOMPLETE_SUFFIXES: [&str; 3] = [".partial", ".part", ".staging"];
+
+/// A downloaded file as recorded in the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    sha256: String,
+    size: u64,
+}
+
+/// Hashes of the files downloaded into a model directory, and when the model was last loaded
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    files: BTreeMap<String, ManifestEntry>,
+    #[serde(default)]
+    last_used: u64,
+}
+
+impl Manifest {
+    /// Reads the manifest of a model directory, None if nothing was downloaded into it
+    fn load(model_dir: &Path) -> Result<Option<Self>> {
+        let path = model_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    /// Writes the manifest through a temporary file
+    fn save(&self, model_dir: &Path) -> Result<()> {
+        let partial = model_dir.join(format!("{}.partial", MANIFEST_FILE));
+        fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&partial, model_dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+/// Downloads `url` into `model_dir/file` and records its hash
+///
+/// HuggingFace serves large files with their SHA-256 as ETag, the download is rejected if it differs.
+/// `token` is sent as a bearer token, as gated and private repositories require. Processes downloading into the
+/// same directory take turns, and a file another process finished meanwhile isn't downloaded again.
+#[cfg(feature = "download")]
+pub fn download(model_dir: &Path, file: &str, url: &str, token: Option<&str>) -> Result<PathBuf> {
+    let _lock = CacheLock::acquire(model_dir)?;
+    let path = model_dir.join(file);
+    if path.exists() {
+        return Ok(path);
+    }
+    
+    tracing::info!("Downloading {}", url);
+    let request = super::http::client()?.get(url);
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    let response = request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::download_failed(url, e))?;
+    let expected = response.headers()
+        .get("x-linked-etag")
+        .or_else(|| response.headers().get(reqwest::header::ETAG))
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.trim_start_matches("W/").trim_matches('"').to_ascii_lowercase())
+        .filter(|etag| etag.len() == 64 && etag.bytes().all(|b| b.is_ascii_hexdigit()));
+    let bytes = response.bytes().map_err(|e| Error::download_failed(url, e))?;
+
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+    if let Some(expected) = expected {
+        if sha256 != expected {
+            return Err(err!("Download of {} has SHA-256 {} but the server announced {}", url, sha256, expected));
+        }
+    }
+
+    // Write to a temporary file first so an interrupted download is never mistaken for a complete one
+    let partial = model_dir.join(format!("{}.partial", file));
+    fs::write(&partial, &bytes)?;
+    fs::rename(&partial, &path)?;
+
+    let mut manifest = Manifest::load(model_dir)?.unwrap_or_default();
+    manifest.files.insert(file.to_string(), ManifestEntry { sha256, size: bytes.len() as u64 });
+    manifest.save(model_dir)?;
+    Ok(path)
+}
+
+/// Checks the files of a model directory against their recorded hashes and marks the model as used
+///
+/// Directories without a manifest, such as user-provided exports, are not checked.
+pub fn verify(model_dir: &Path) -> Result<()> {
+    let Some(manifest) = Manifest::load(model_dir)? else {
+        return Ok(());
+    };
+
+    if let Some(problem) = find_problem(model_dir, &manifest)? {
+        return Err(err!(
+            "Cached model in {} is corrupt: {}. Delete it or run `rust_embed model gc` to download it again",
+            model_dir.display(), problem
+        ));
+    }
+
+    // Hashing runs unlocked, only the update is serialized, on a fresh copy in case a download added files
+    let _lock = CacheLock::acquire(model_dir)?;
+    let mut manifest = Manifest::load(model_dir)?.unwrap_or(manifest);
+    manifest.last_used = unix_now();
+    manifest.save(model_dir)
+}
+
+/// SHA-256 of a model file, None if it cannot be read
+///
+/// Downloads take the hash recorded in the manifest. Other files, such as user-provided exports, are hashed
+/// once per process and size and modification time, since weights run to hundreds of MB.
+pub fn file_sha256(model_dir: &Path, file: &str) -> Option<String> {
+    static HASHES: Mutex<BTreeMap<(PathBuf, u64, SystemTime), String>> = Mutex::new(BTreeMap::new());
+
+    if let Ok(Some(manifest)) = Manifest::load(model_dir) {
+        if let Some(entry) = manifest.files.get(file) {
+            return Some(entry.sha256.clone());
+        }
+    }
+
+    let path = model_dir.join(file);
+    let metadata = fs::metadata(&path).ok()?;
+    let key = (path, metadata.len(), metadata.modified().ok()?);
+    if let Some(sha256) = HASHES.lock().ok()?.get(&key) {
+        return Some(sha256.clone());
+    }
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(&key.0).ok()?, &mut hasher).ok()?;
+    let sha256 = format!("{:x}", hasher.finalize());
+    HASHES.lock().ok()?.insert(key, sha256.clone());
+    Some(sha256)
+}
+
+/// Describes the first recorded file that is missing or changed
+fn find_problem(model_dir: &Path, manifest: &Manifest) -> Result<Option<String>> {
+    for (file, entry) in &manifest.files {
+        let path = model_dir.join(file);
+        let Ok(metadata) = fs::metadata(&path) else {
+            return Ok(Some(format!("{} is missing", file)));
+        };
+        if metadata.len() != entry.size {
+            return Ok(Some(format!("{} has {} bytes instead of {}", file, metadata.len(), entry.size)));
+        }
+
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(&path)?, &mut hasher)?;
+        if format!("{:x}", hasher.finalize()) != entry.sha256 {
+            return Ok(Some(format!("{} does not match its recorded SHA-256", file)));
+        }
+    }
+    Ok(None)
+}
+
+/// What `gc` removes
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Also remove models not loaded for this long
+    pub max_unused: Option<Duration>,
+    /// Report what would be removed without deleting anything
+    pub dry_run: bool,
+}
+
+/// Why an entry was removed from the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GcReason {
+    /// A download or extraction that never completed
+    Incomplete,
+    /// A model whose files don't match their recorded hashes
+    Corrupt(String),
+    /// A model not loaded within `GcOptions::max_unused`
+    Unused,
+}
+
+impl std::fmt::Display for GcReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GcReason::Incomplete => write!(f, "incomplete"),
+            GcReason::Corrupt(problem) => write!(f, "corrupt, {}", problem),
+            GcReason::Unused => write!(f, "unused"),
+        }
+    }
+}
+
+/// Entries removed by `gc`, or that would be with `dry_run`
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub removed: Vec<(PathBuf, GcReason)>,
+    pub freed_bytes: u64,
+}
+
+/// Prunes incomplete downloads, corrupt models and optionally unused models from `cache_home()`
+///
+/// Only models downloaded by rust_embed, which have a manifest, are considered for removal as corrupt or unused.
+pub fn gc(options: &GcOptions) -> Result<GcReport> {
+    gc_dir(&cache_home(), options)
+}
+
+/// Prunes one cache directory
+fn gc_dir(cache_dir: &Path, options: &GcOptions) -> Result<GcReport> {
+    let mut report = GcReport::default();
+    if !cache_dir.exists() {
+        return Ok(report);
+    }
+
+    let now = unix_now();
+    for entry in fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        
+        // A directory another process is downloading into or extracting holds `.partial` files on purpose
+        let Some(_lock) = CacheLock::try_acquire(&path)? else {
+            tracing::info!("Skipping {}, another process is using it", path.display());
+            continue;
+        };
+
+        // Leftovers inside the directory, such as `rust_model.ot.partial` or `libtorch.staging`
+        for inner in fs::read_dir(&path)? {
+            let inner = inner?.path();
+            if is_incomplete(&inner) {
+                remove(&inner, GcReason::Incomplete, options, &mut report)?;
+            }
+        }
+
+        let Some(manifest) = Manifest::load(&path).ok().flatten() else {
+            continue;
+        };
+        if let Some(problem) = find_problem(&path, &manifest)? {
+            remove(&path, GcReason::Corrupt(problem), options, &mut report)?;
+        } else if let Some(max_unused) = options.max_unused {
+            if now.saturating_sub(manifest.last_used) > max_unused.as_secs() {
+                remove(&path, GcReason::Unused, options, &mut report)?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns true for the temporary names used while downloading or extracting
+fn is_incomplete(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    INCOMPLETE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Deletes a file or directory unless this is a dry run, and records it
+fn remove(path: &Path, reason: GcReason, options: &GcOptions, report: &mut GcReport) -> Result<()> {
+    let size = disk_usage(path);
+    if !options.dry_run {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    tracing::info!("{} {} ({})", if options.dry_run { "Would remove" } else { "Removed" }, path.display(), reason);
+    report.freed_bytes += size;
+    report.removed.push((path.to_path_buf(), reason));
+    Ok(())
+}
+
+/// Total size of a file or directory tree, best effort
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+/// Seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gc_removes_corrupt_and_incomplete_entries() -> Result<()> {
+        let cache_dir = std::env::temp_dir().join(format!("rust_embed_gc_{}", std::process::id()));
+        let healthy = cache_dir.join("healthy");
+        let corrupt = cache_dir.join("corrupt");
+        let unmanaged = cache_dir.join("unmanaged");
+        for dir in [&healthy, &corrupt, &unmanaged] {
+            fs::create_dir_all(dir)?;
+        }
+
+        // SHA-256 of "abc"
+        let entry = ManifestEntry {
+            sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+            size: 3,
+        };
+        let manifest = Manifest { files: BTreeMap::from([("weights.bin".to_string(), entry)]), last_used: unix_now() };
+        fs::write(healthy.join("weights.bin"), "abc")?;
+        manifest.save(&healthy)?;
+        fs::write(corrupt.join("weights.bin"), "abd")?;
+        manifest.save(&corrupt)?;
+        fs::write(healthy.join("config.json.partial"), "{")?;
+        fs::write(unmanaged.join("weights.bin"), "anything")?;
+
+        verify(&healthy)?;
+        assert!(verify(&corrupt).is_err());
+
+        let report = gc_dir(&cache_dir, &GcOptions::default())?;
+        assert_eq!(report.removed.len(), 2);
+        assert!(healthy.join("weights.bin").exists() && !healthy.join("config.json.partial").exists());
+        assert!(!corrupt.exists() && unmanaged.exists());
+
+        fs::remove_dir_all(&cache_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::error::{err, Error, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable that turns offline mode on for air-gapped deployments
+pub const OFFLINE_ENV: &str = "RUST_EMBED_OFFLINE";
+
+/// Set by `enable`, for the `--offline` flag
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbids all network access for the rest of the process
+pub fn enable() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+/// Returns true if network access is forbidden, by `enable` or `RUST_EMBED_OFFLINE=1`
+pub fn is_enabled() -> bool {
+    OFFLINE.load(Ordering::Relaxed) || std::env::var(OFFLINE_ENV)
+        .map(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+/// Fails in offline mode, where `what` would have been downloaded, listing where it was looked for
+pub fn ensure_download_allowed(what: &str, expected: &[PathBuf], hint: &str) -> Result<()> {
+    if !is_enabled() {
+        return Ok(());
+    }
+    Err(missing_locally(what, expected, hint))
+}
+
+/// Error for a download offline mode refused
+fn missing_locally(what: &str, expected: &[PathBuf], hint: &str) -> Error {
+    let locations: Vec<String> = expected.iter().map(|path| format!("  {}", path.display())).collect();
+    err!(
+        "Offline mode forbids downloading {}, which was not found at:\n{}\n{}",
+        what, locations.join("\n"), hint
+    )
+}
+
+/// Fails in offline mode, where `target` would have been reached over the network
+pub fn ensure_network_allowed(target: &str) -> Result<()> {
+    if is_enabled() {
+        return Err(err!("Offline mode forbids network access to {}", target));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_error_lists_expected_paths() {
+        let error = missing_locally("weights", &[PathBuf::from("/models/a"), PathBuf::from("/models/b")], "Set model_path")
+            .to_string();
+        assert_eq!(error, "Offline mode forbids downloading weights, which was not found at:\n  /models/a\n  /models/b\nSet model_path");
+    }
+}
@@ -0,0 +1,331 @@
+use super::libtorch;
+use crate::error::{err, Context, Error, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Platforms a release package can be built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageTarget {
+    MacosArm64,
+    MacosX86_64,
+    LinuxX86_64,
+    LinuxAarch64,
+    WindowsX86_64,
+}
+
+impl PackageTarget {
+    /// All targets, in the order they are listed to users
+    pub const ALL: [PackageTarget; 5] = [
+        PackageTarget::MacosArm64,
+        PackageTarget::MacosX86_64,
+        PackageTarget::LinuxX86_64,
+        PackageTarget::LinuxAarch64,
+        PackageTarget::WindowsX86_64,
+    ];
+
+    /// The platform this binary runs on
+    pub fn host() -> Result<Self> {
+        match (std::env::consts::OS, std::env::consts::ARCH) {
+            ("macos", "aarch64") => Ok(PackageTarget::MacosArm64),
+            ("macos", "x86_64") => Ok(PackageTarget::MacosX86_64),
+            ("linux", "x86_64") => Ok(PackageTarget::LinuxX86_64),
+            ("linux", "aarch64") => Ok(PackageTarget::LinuxAarch64),
+            ("windows", "x86_64") => Ok(PackageTarget::WindowsX86_64),
+            _ => Err(Error::unsupported_platform("Packaging")),
+        }
+    }
+
+    /// Name used in package file names, such as `macos-arm64`
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackageTarget::MacosArm64 => "macos-arm64",
+            PackageTarget::MacosX86_64 => "macos-x86_64",
+            PackageTarget::LinuxX86_64 => "linux-x86_64",
+            PackageTarget::LinuxAarch64 => "linux-aarch64",
+            PackageTarget::WindowsX86_64 => "windows-x86_64",
+        }
+    }
+
+    /// File name of the rust_embed executable on this platform
+    pub fn executable(&self) -> &'static str {
+        if *self == PackageTarget::WindowsX86_64 {
+            "rust_embed.exe"
+        } else {
+            "rust_embed"
+        }
+    }
+}
+
+impl FromStr for PackageTarget {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        PackageTarget::ALL.into_iter()
+            .find(|target| target.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = PackageTarget::ALL.iter().map(PackageTarget::name).collect();
+                err!("Unknown package target '{}', expected one of {}", name, names.join(", "))
+            })
+    }
+}
+
+impl std::fmt::Display for PackageTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What goes into a release package
+#[derive(Debug, Clone)]
+pub struct PackageConfig {
+    /// Platform the package runs on
+    pub target: PackageTarget,
+    /// rust_embed binary built for the target, the running executable if None
+    pub binary: Option<PathBuf>,
+    /// libtorch installation for the target, found or downloaded for the host if None
+    pub libtorch: Option<PathBuf>,
+    /// Model directories bundled under `models/`, where the launcher points the model cache
+    pub models: Vec<PathBuf>,
+    /// Also write the package as a `.tar.gz` next to its directory
+    pub tarball: bool,
+}
+
+impl PackageConfig {
+    /// Packages the running binary and the host's libtorch, without models or tarball
+    pub fn new(target: PackageTarget) -> Self {
+        Self {
+            target,
+            binary: None,
+            libtorch: None,
+            models: Vec::new(),
+            tarball: false,
+        }
+    }
+}
+
+/// Where a package was written
+#[derive(Debug, Clone)]
+pub struct PackageReport {
+    pub directory: PathBuf,
+    pub tarball: Option<PathBuf>,
+}
+
+/// Bundles the binary, libtorch's shared libraries, models and launchers into `output_dir`
+///
+/// Layout:
+/// - `rust_embed` (or `rust_embed.exe`) and `run_rust_embed.sh` (or `.cmd` and `.ps1`)
+/// - `lib/` with the libtorch libraries
+/// - `models/` with the bundled models, if any
+pub fn package(config: &PackageConfig, output_dir: &Path) -> Result<PackageReport> {
+    let host = PackageTarget::host().ok();
+    if host != Some(config.target) && (config.binary.is_none() || config.libtorch.is_none()) {
+        return Err(err!(
+            "Packaging for {} on another platform needs the binary and libtorch built for {}",
+            config.target, config.target
+        ));
+    }
+
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create package directory {}", output_dir.display()))?;
+
+    let binary = match &config.binary {
+        Some(binary) => binary.clone(),
+        None => std::env::current_exe()?,
+    };
+    fs::copy(&binary, output_dir.join(config.target.executable()))
+        .with_context(|| format!("Failed to copy {}", binary.display()))?;
+
+    let libtorch_path = match &config.libtorch {
+        Some(path) => path.clone(),
+        None => libtorch::ensure_libtorch()?,
+    };
+    copy_libraries(&libtorch_path.join("lib"), &output_dir.join("lib"))?;
+
+    for model in &config.models {
+        let name = model.file_name()
+            .ok_or_else(|| err!("Model path {} has no directory name", model.display()))?;
+        copy_tree(model, &output_dir.join("models").join(name))?;
+    }
+
+    // The launcher enables the MPS fallback when this flag exists
+    if config.target == PackageTarget::MacosArm64 {
+        fs::create_dir_all(output_dir.join("metal"))?;
+        fs::write(output_dir.join("metal").join("mps_available"), "1")?;
+    }
+
+    write_launchers(config.target, output_dir)?;
+    tracing::info!("Package for {} created in {}", config.target, output_dir.display());
+
+    let tarball = if config.tarball {
+        Some(write_tarball(output_dir)?)
+    } else {
+        None
+    };
+
+    Ok(PackageReport { directory: output_dir.to_path_buf(), tarball })
+}
+
+/// Copies the shared libraries of a libtorch `lib` directory, following symlinks
+fn copy_libraries(lib_path: &Path, target_lib_path: &Path) -> Result<()> {
+    fs::create_dir_all(target_lib_path)?;
+    let entries = fs::read_dir(lib_path)
+        .with_context(|| format!("Failed to read libtorch libraries in {}", lib_path.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        if libtorch::is_shared_library(&path) {
+            let target = target_lib_path.join(path.file_name().unwrap());
+            tracing::debug!("Copying {} -> {}", path.display(), target.display());
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Recursively copies a directory
+fn copy_tree(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source).with_context(|| format!("Failed to read {}", source.display()))? {
+        let path = entry?.path();
+        let destination = target.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_tree(&path, &destination)?;
+        } else {
+            fs::copy(&path, &destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the launcher scripts of the target platform
+pub(crate) fn write_launchers(target: PackageTarget, output_dir: &Path) -> Result<()> {
+    match target {
+        PackageTarget::WindowsX86_64 => write_windows_launchers(output_dir),
+        _ => write_shell_launcher(target, output_dir),
+    }
+}
+
+/// Writes a bash launcher that points the dynamic loader at the bundled libraries
+fn write_shell_launcher(target: PackageTarget, output_dir: &Path) -> Result<()> {
+    // macOS finds libraries through DYLD_*, Linux (x86_64 and aarch64) through LD_LIBRARY_PATH
+    let library_path_exports = match target {
+        PackageTarget::MacosArm64 | PackageTarget::MacosX86_64 => {
+            "export DYLD_LIBRARY_PATH=\"$SCRIPT_DIR/lib:$DYLD_LIBRARY_PATH\"\n\
+             export DYLD_FALLBACK_LIBRARY_PATH=\"$SCRIPT_DIR/lib:$DYLD_FALLBACK_LIBRARY_PATH\"\n"
+        }
+        _ => "export LD_LIBRARY_PATH=\"$SCRIPT_DIR/lib:$LD_LIBRARY_PATH\"\n",
+    };
+
+    let launcher_path = output_dir.join("run_rust_embed.sh");
+    let launcher = format!(
+        "#!/bin/bash\n\
+         # Wrapper script for rust_embed\n\
+         SCRIPT_DIR=\"$( cd \"$( dirname \"${{BASH_SOURCE[0]}}\" )\" && pwd )\"\n\
+         {}\
+         export LIBTORCH=\"$SCRIPT_DIR\"\n\
+         \n\
+         # Load bundled models instead of downloading them\n\
+         if [ -d \"$SCRIPT_DIR/models\" ]; then\n\
+         export RUST_EMBED_CACHE_DIR=\"$SCRIPT_DIR/models\"\n\
+         fi\n\
+         \n\
+         # Enable Metal Performance Shaders if available\n\
+         if [ -f \"$SCRIPT_DIR/metal/mps_available\" ]; then\n\
+         export PYTORCH_ENABLE_MPS_FALLBACK=1\n\
+         fi\n\
+         \n\
+         # Run the actual binary\n\
+         \"$SCRIPT_DIR/rust_embed\" \"$@\"\n",
+        library_path_exports
+    );
+
+    fs::write(&launcher_path, launcher)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&launcher_path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Writes cmd and PowerShell launchers that put the bundled DLLs on PATH
+fn write_windows_launchers(output_dir: &Path) -> Result<()> {
+    let cmd_launcher = "@echo off\r\n\
+         rem Wrapper script for rust_embed\r\n\
+         set \"SCRIPT_DIR=%~dp0\"\r\n\
+         set \"PATH=%SCRIPT_DIR%lib;%PATH%\"\r\n\
+         set \"LIBTORCH=%SCRIPT_DIR%\"\r\n\
+         if exist \"%SCRIPT_DIR%models\" set \"RUST_EMBED_CACHE_DIR=%SCRIPT_DIR%models\"\r\n\
+         \"%SCRIPT_DIR%rust_embed.exe\" %*\r\n";
+    fs::write(output_dir.join("run_rust_embed.cmd"), cmd_launcher)?;
+
+    let ps_launcher = "# Wrapper script for rust_embed\r\n\
+         $env:PATH = \"$PSScriptRoot\\lib;$env:PATH\"\r\n\
+         $env:LIBTORCH = $PSScriptRoot\r\n\
+         if (Test-Path \"$PSScriptRoot\\models\") { $env:RUST_EMBED_CACHE_DIR = \"$PSScriptRoot\\models\" }\r\n\
+         & \"$PSScriptRoot\\rust_embed.exe\" @args\r\n\
+         exit $LASTEXITCODE\r\n";
+    fs::write(output_dir.join("run_rust_embed.ps1"), ps_launcher)?;
+
+    Ok(())
+}
+
+/// Archives the package directory as `<directory>.tar.gz`, under its own name
+fn write_tarball(output_dir: &Path) -> Result<PathBuf> {
+    let name = output_dir.file_name()
+        .ok_or_else(|| err!("Package directory {} has no name", output_dir.display()))?;
+    let tarball_path = output_dir.with_file_name(format!("{}.tar.gz", name.to_string_lossy()));
+
+    let encoder = flate2::write::GzEncoder::new(File::create(&tarball_path)?, flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    archive.follow_symlinks(true);
+    archive.append_dir_all(name, output_dir)?;
+    archive.into_inner()?.finish()?;
+
+    tracing::info!("Package archived to {}", tarball_path.display());
+    Ok(tarball_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packages_cross_target_into_tarball() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("rust_embed_package_{}", std::process::id()));
+        let libtorch_lib = root.join("libtorch").join("lib");
+        let model = root.join("all-MiniLM-L6-v2");
+        fs::create_dir_all(&libtorch_lib)?;
+        fs::create_dir_all(model.join("1_Pooling"))?;
+        fs::write(root.join("rust_embed"), "binary")?;
+        fs::write(libtorch_lib.join("libtorch_cpu.so"), "library")?;
+        fs::write(libtorch_lib.join("libtorch_cpu.a"), "static")?;
+        fs::write(model.join("1_Pooling").join("config.json"), "{}")?;
+
+        let target = if PackageTarget::host().ok() == Some(PackageTarget::LinuxAarch64) {
+            PackageTarget::LinuxX86_64
+        } else {
+            PackageTarget::LinuxAarch64
+        };
+        let output_dir = root.join(format!("rust_embed-{}", target));
+        let config = PackageConfig {
+            binary: Some(root.join("rust_embed")),
+            libtorch: Some(root.join("libtorch")),
+            models: vec![model],
+            tarball: true,
+            ..PackageConfig::new(target)
+        };
+        assert!(package(&PackageConfig::new(target), &output_dir).is_err());
+
+        let report = package(&config, &output_dir)?;
+        assert!(output_dir.join("lib").join("libtorch_cpu.so").exists());
+        assert!(!output_dir.join("lib").join("libtorch_cpu.a").exists());
+        assert!(output_dir.join("models/all-MiniLM-L6-v2/1_Pooling/config.json").exists());
+        assert!(fs::read_to_string(output_dir.join("run_rust_embed.sh"))?.contains("LD_LIBRARY_PATH"));
+        assert!(report.tarball.is_some_and(|tarball| tarball.exists()));
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
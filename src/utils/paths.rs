@@ -0,0 +1,114 @@
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+
+/// Environment variable putting every directory of rust_embed under one root
+pub const HOME_ENV: &str = "RUST_EMBED_HOME";
+/// Environment variable overriding the model directory alone, kept from before `RUST_EMBED_HOME`
+pub const CACHE_DIR_ENV: &str = "RUST_EMBED_CACHE_DIR";
+
+/// Set by `set_current`, replacing what the environment says
+static CURRENT: RwLock<Option<Paths>> = RwLock::new(None);
+
+/// Where rust_embed keeps what it downloads and builds
+///
+/// `current` resolves them, in order of precedence, from `set_current`, then `RUST_EMBED_HOME`, which puts each
+/// under its own subdirectory, then the XDG base directories. Without overrides, models and libtorch share
+/// `~/.cache/rust_embed` as in earlier versions, and indexes go to `~/.local/share/rust_embed/indexes`.
+/// `RUST_EMBED_CACHE_DIR` still moves the models alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// Downloaded, bundled and extracted models, one directory per model
+    pub models: PathBuf,
+    /// Downloaded libtorch releases, one directory per version
+    pub libtorch: PathBuf,
+    /// Other cached state, such as the daemon socket when there is no runtime directory
+    pub cache: PathBuf,
+    /// Indexes created without an explicit path, such as those of `rust_embed watch`
+    pub indexes: PathBuf,
+}
+
+impl Paths {
+    /// Every directory under one root: `models/`, `libtorch/`, `cache/` and `indexes/`
+    pub fn under(home: impl AsRef<Path>) -> Self {
+        let home = home.as_ref();
+        Self {
+            models: home.join("models"),
+            libtorch: home.join("libtorch"),
+            cache: home.join("cache"),
+            indexes: home.join("indexes"),
+        }
+    }
+
+    /// Directories from `RUST_EMBED_HOME`, `RUST_EMBED_CACHE_DIR` and `XDG_CACHE_HOME` and `XDG_DATA_HOME`
+    pub fn from_env() -> Self {
+        let mut paths = match non_empty_env(HOME_ENV) {
+            Some(home) => Self::under(home),
+            None => {
+                let cache = base_dir("XDG_CACHE_HOME", dirs::cache_dir);
+                Self {
+                    models: cache.clone(),
+                    libtorch: cache.clone(),
+                    indexes: base_dir("XDG_DATA_HOME", dirs::data_dir).join("indexes"),
+                    cache,
+                }
+            }
+        };
+        if let Some(models) = non_empty_env(CACHE_DIR_ENV) {
+            paths.models = models;
+        }
+        paths
+    }
+
+    /// Default index file for a directory, named after it and a hash of its absolute path
+    pub fn index_for(&self, dir: &Path) -> PathBuf {
+        let absolute = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let hash = format!("{:x}", Sha256::digest(absolute.to_string_lossy().as_bytes()));
+        let name = absolute.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "root".to_string());
+        self.indexes.join(format!("{}-{}.jsonl", name, &hash[..12]))
+    }
+}
+
+/// The directories in use by this process
+pub fn current() -> Paths {
+    CURRENT.read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_else(Paths::from_env)
+}
+
+/// Uses `paths` for the rest of the process instead of the environment, for programs configuring them in code
+pub fn set_current(paths: Paths) {
+    *CURRENT.write().unwrap_or_else(PoisonError::into_inner) = Some(paths);
+}
+
+fn non_empty_env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+/// `rust_embed` under an XDG variable if set, else under the platform's directory, else the temp directory
+fn base_dir(xdg: &str, platform: fn() -> Option<PathBuf>) -> PathBuf {
+    non_empty_env(xdg)
+        .filter(|dir| dir.is_absolute())
+        .or_else(platform)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rust_embed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lays_out_directories_under_a_home() {
+        let paths = Paths::under("/srv/rust_embed");
+        assert_eq!(paths.models, PathBuf::from("/srv/rust_embed/models"));
+        assert_eq!(paths.libtorch, PathBuf::from("/srv/rust_embed/libtorch"));
+        assert_eq!(paths.indexes, PathBuf::from("/srv/rust_embed/indexes"));
+
+        let index = paths.index_for(Path::new("/srv/docs"));
+        assert_eq!(index, paths.index_for(Path::new("/srv/docs")));
+        assert_ne!(index, paths.index_for(Path::new("/home/docs")));
+        assert!(index.file_name().unwrap().to_string_lossy().starts_with("docs-"));
+    }
+}
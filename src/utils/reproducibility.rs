@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::sync::{PoisonError, RwLock};
+
+/// Environment variable turning on reproducible runs in the CLI, its value is the seed or 1 for the default
+pub const REPRODUCIBLE_ENV: &str = "RUST_EMBED_REPRODUCIBLE";
+/// Seed used when none is given
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Set by `ReproducibilityConfig::apply`
+static ACTIVE: RwLock<Option<ReproducibilityConfig>> = RwLock::new(None);
+
+/// Settings that make two runs over the same corpus write byte-identical embedding files, for auditing
+///
+/// Applying them seeds libtorch, turns off cuDNN and its autotuner, runs libtorch on one thread so sums are
+/// always reduced in the same order, and keeps `Device::Auto` on the CPU. Timestamps stored with embeddings
+/// become `timestamp` and collections record the environment they were made in, so a second run can be
+/// compared against it. Embedding is slower while they are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReproducibilityConfig {
+    /// Seed of libtorch's CPU and CUDA generators
+    pub seed: u64,
+    /// Stored as the creation time of every embedding, in seconds since the Unix epoch
+    pub timestamp: i64,
+}
+
+impl Default for ReproducibilityConfig {
+    /// The default seed, with the timestamp from `SOURCE_DATE_EPOCH` or 0 when unset
+    fn default() -> Self {
+        let timestamp = std::env::var("SOURCE_DATE_EPOCH").ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0);
+        Self { seed: DEFAULT_SEED, timestamp }
+    }
+}
+
+impl ReproducibilityConfig {
+    /// A config with `seed` and the default timestamp
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed, ..Self::default() }
+    }
+
+    /// Reads `RUST_EMBED_REPRODUCIBLE`, None when it is unset, empty or 0
+    pub fn from_env() -> Option<Self> {
+        match std::env::var(REPRODUCIBLE_ENV).ok()?.trim() {
+            "" | "0" | "false" => None,
+            "1" | "true" => Some(Self::default()),
+            seed => match seed.parse() {
+                Ok(seed) => Some(Self::with_seed(seed)),
+                Err(_) => {
+                    tracing::warn!("{} should be 1 or a seed, got '{}', using seed {}", REPRODUCIBLE_ENV, seed, DEFAULT_SEED);
+                    Some(Self::default())
+                }
+            },
+        }
+    }
+
+    /// Applies the settings to the process, before the model is loaded
+    ///
+    /// Later calls replace the seed and timestamp of earlier ones.
+    pub fn apply(&self) {
+        #[cfg(feature = "torch")]
+        {
+            tch::manual_seed(self.seed as i64);
+            tch::Cuda::manual_seed_all(self.seed);
+            tch::Cuda::cudnn_set_benchmark(false);
+            tch::Cuda::set_user_enabled_cudnn(false);
+            tch::set_num_threads(1);
+            // cuBLAS reads it when it starts, so it can only be set before launching the process
+            if tch::Cuda::is_available() && std::env::var_os("CUBLAS_WORKSPACE_CONFIG").is_none() {
+                tracing::warn!("Set CUBLAS_WORKSPACE_CONFIG=:4096:8 before starting for deterministic results on CUDA");
+            }
+        }
+        tracing::info!("Reproducible mode with seed {} and timestamp {}", self.seed, self.timestamp);
+        *ACTIVE.write().unwrap_or_else(PoisonError::into_inner) = Some(*self);
+    }
+
+    /// What the output depends on besides the corpus and the model, recorded with each collection
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let mut environment = BTreeMap::new();
+        environment.insert("seed".to_string(), self.seed.to_string());
+        environment.insert("timestamp".to_string(), self.timestamp.to_string());
+        environment.insert("os".to_string(), std::env::consts::OS.to_string());
+        environment.insert("arch".to_string(), std::env::consts::ARCH.to_string());
+        #[cfg(feature = "torch")]
+        {
+            let libtorch = super::libtorch::find_libtorch(&super::libtorch::LibtorchConfig::from_env())
+                .and_then(|path| super::libtorch::installed_version(&path))
+                .unwrap_or_else(|| super::libtorch::TCH_LIBTORCH_VERSION.to_string());
+            environment.insert("libtorch".to_string(), libtorch);
+            environment.insert("torch_threads".to_string(), tch::get_num_threads().to_string());
+            environment.insert("cudnn".to_string(), tch::Cuda::user_enabled_cudnn().to_string());
+        }
+        environment
+    }
+}
+
+/// The settings applied to this process, None unless reproducible mode is on
+pub fn active() -> Option<ReproducibilityConfig> {
+    *ACTIVE.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Creation time to store with a new embedding: the fixed timestamp in reproducible mode, else now
+#[cfg(feature = "proto-store")]
+pub fn timestamp() -> i64 {
+    match active() {
+        Some(config) => config.timestamp,
+        None => chrono::Utc::now().timestamp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_the_environment() {
+        let config = ReproducibilityConfig { seed: 7, timestamp: 1_700_000_000 };
+        let environment = config.environment();
+        assert_eq!(environment["seed"], "7");
+        assert_eq!(environment["timestamp"], "1700000000");
+        assert_eq!(environment["arch"], std::env::consts::ARCH);
+        assert_eq!(config.environment(), environment);
+    }
+}
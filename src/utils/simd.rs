@@ -0,0 +1,115 @@
+/// Dot product of two equally long slices, using AVX2 and FMA when the CPU has them
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Vectors must have the same length");
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            // SAFETY: the CPU supports AVX2 and FMA, checked above
+            return unsafe { dot_avx2(a, b) };
+        }
+    }
+
+    dot_fallback(a, b)
+}
+
+/// Cosine similarity of two equally long slices, 0 if either is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norm_a = dot(a, a).sqrt();
+    let norm_b = dot(b, b).sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot(a, b) / (norm_a * norm_b)
+}
+
+/// Returns true if the vectorized x86_64 kernels are used on this CPU
+pub fn has_avx2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Portable dot product, the independent accumulators let the compiler vectorize it (NEON on Apple Silicon)
+fn dot_fallback(a: &[f32], b: &[f32]) -> f32 {
+    let mut sums = [0.0f32; 8];
+    let chunks_a = a.chunks_exact(8);
+    let chunks_b = b.chunks_exact(8);
+    let tail: f32 = chunks_a.remainder().iter()
+        .zip(chunks_b.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+
+    for (chunk_a, chunk_b) in chunks_a.zip(chunks_b) {
+        for ((sum, x), y) in sums.iter_mut().zip(chunk_a).zip(chunk_b) {
+            *sum += x * y;
+        }
+    }
+
+    sums.iter().sum::<f32>() + tail
+}
+
+/// Dot product over 8-lane AVX2 registers with fused multiply-add
+///
+/// # Safety
+/// The CPU must support AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn dot_avx2(a: &[f32], b: &[f32]) -> f32 {
+    use std::arch::x86_64::*;
+
+    let len = a.len();
+    let mut sum0 = _mm256_setzero_ps();
+    let mut sum1 = _mm256_setzero_ps();
+    let mut i = 0;
+
+    // Two accumulators hide the FMA latency
+    while i + 16 <= len {
+        sum0 = _mm256_fmadd_ps(_mm256_loadu_ps(a.as_ptr().add(i)), _mm256_loadu_ps(b.as_ptr().add(i)), sum0);
+        sum1 = _mm256_fmadd_ps(_mm256_loadu_ps(a.as_ptr().add(i + 8)), _mm256_loadu_ps(b.as_ptr().add(i + 8)), sum1);
+        i += 16;
+    }
+    if i + 8 <= len {
+        sum0 = _mm256_fmadd_ps(_mm256_loadu_ps(a.as_ptr().add(i)), _mm256_loadu_ps(b.as_ptr().add(i)), sum0);
+        i += 8;
+    }
+
+    let sum = _mm256_add_ps(sum0, sum1);
+    let low = _mm256_castps256_ps128(sum);
+    let high = _mm256_extractf128_ps(sum, 1);
+    let quad = _mm_add_ps(low, high);
+    let pair = _mm_add_ps(quad, _mm_movehl_ps(quad, quad));
+    let single = _mm_add_ss(pair, _mm_shuffle_ps(pair, pair, 1));
+
+    let mut total = _mm_cvtss_f32(single);
+    while i < len {
+        total += a[i] * b[i];
+        i += 1;
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_matches_naive_sum() {
+        for len in [0, 1, 7, 8, 15, 16, 17, 384] {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.37).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.11).cos()).collect();
+            let naive: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+
+            assert!((dot(&a, &b) - naive).abs() < 1e-4, "length {}", len);
+            assert!((dot_fallback(&a, &b) - naive).abs() < 1e-4, "length {}", len);
+        }
+    }
+}
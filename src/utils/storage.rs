@@ -0,0 +1,139 @@
+use crate::error::{err, Context, Result};
+use std::path::Path;
+
+/// URI schemes served from object storage instead of the local filesystem
+const REMOTE_SCHEMES: [&str; 3] = ["s3://", "s3a://", "gs://"];
+
+/// Returns true if the location is an object storage URI such as `s3://bucket/key`
+pub fn is_remote(location: impl AsRef<Path>) -> bool {
+    let location = location.as_ref().to_string_lossy();
+    REMOTE_SCHEMES.iter().any(|scheme| location.starts_with(scheme))
+}
+
+/// Reads a local file or an object storage URI
+pub fn read(location: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let location = location.as_ref();
+    if is_remote(location) {
+        super::offline::ensure_network_allowed(&location.to_string_lossy())?;
+        return remote::read(&location.to_string_lossy());
+    }
+
+    std::fs::read(location).with_context(|| format!("Failed to read {}", location.display()))
+}
+
+/// Reads a local file or an object storage URI as UTF-8 text
+pub fn read_to_string(location: impl AsRef<Path>) -> Result<String> {
+    let location = location.as_ref();
+    String::from_utf8(read(location)?)
+        .map_err(|_| err!("{} is not valid UTF-8", location.display()))
+}
+
+/// Writes a local file, creating its parent directories, or uploads to an object storage URI
+pub fn write(location: impl AsRef<Path>, bytes: Vec<u8>) -> Result<()> {
+    let location = location.as_ref();
+    if is_remote(location) {
+        super::offline::ensure_network_allowed(&location.to_string_lossy())?;
+        return remote::write(&location.to_string_lossy(), bytes);
+    }
+
+    if let Some(parent) = location.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(location, bytes).with_context(|| format!("Failed to write {}", location.display()))
+}
+
+/// Like `write`, but a local file is written under a temporary name and renamed into place, so it is never seen half written
+///
+/// Object storage uploads replace the object at once already.
+pub fn write_atomic(location: impl AsRef<Path>, bytes: Vec<u8>) -> Result<()> {
+    let location = location.as_ref();
+    if is_remote(location) {
+        return write(location, bytes);
+    }
+
+    let partial = std::path::PathBuf::from(format!("{}.partial", location.display()));
+    write(&partial, bytes)?;
+    std::fs::rename(&partial, location).with_context(|| format!("Failed to move {} into place", partial.display()))
+}
+
+#[cfg(feature = "object-store")]
+mod remote {
+    use crate::error::{err, Context, Result};
+    use object_store::aws::AmazonS3Builder;
+    use object_store::gcp::GoogleCloudStorageBuilder;
+    use object_store::path::Path;
+    use object_store::ObjectStore;
+    use std::future::Future;
+
+    /// Connects to the bucket of a URI, with credentials from the environment, and returns the object key
+    fn open(uri: &str) -> Result<(Box<dyn ObjectStore>, Path)> {
+        let (scheme, rest) = uri.split_once("://").ok_or_else(|| err!("Invalid object storage URI {}", uri))?;
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() || key.is_empty() {
+            return Err(err!("Object storage URI {} needs both a bucket and a key", uri));
+        }
+
+        let bucket_url = format!("{}://{}", scheme, bucket);
+        let store: Box<dyn ObjectStore> = match scheme {
+            "s3" | "s3a" => Box::new(AmazonS3Builder::from_env().with_url(bucket_url).build()?),
+            "gs" => Box::new(GoogleCloudStorageBuilder::from_env().with_url(bucket_url).build()?),
+            _ => return Err(err!("Unsupported object storage scheme '{}'", scheme)),
+        };
+
+        Ok((store, Path::from(key)))
+    }
+
+    /// Runs an object storage call to completion on a private runtime
+    fn block_on<T>(future: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(future)
+    }
+
+    #[tracing::instrument(skip_all, fields(uri = %uri))]
+    pub fn read(uri: &str) -> Result<Vec<u8>> {
+        let (store, key) = open(uri)?;
+        block_on(async {
+            let bytes = store.get(&key).await?.bytes().await?;
+            Ok(bytes.to_vec())
+        })
+        .with_context(|| format!("Failed to download {}", uri))
+    }
+
+    #[tracing::instrument(skip_all, fields(uri = %uri, bytes = bytes.len()))]
+    pub fn write(uri: &str, bytes: Vec<u8>) -> Result<()> {
+        let (store, key) = open(uri)?;
+        block_on(async {
+            store.put(&key, bytes.into()).await?;
+            Ok(())
+        })
+        .with_context(|| format!("Failed to upload {}", uri))
+    }
+}
+
+#[cfg(not(feature = "object-store"))]
+mod remote {
+    use crate::error::{err, Result};
+
+    pub fn read(uri: &str) -> Result<Vec<u8>> {
+        Err(err!("Reading {} needs the 'object-store' feature", uri))
+    }
+
+    pub fn write(uri: &str, _bytes: Vec<u8>) -> Result<()> {
+        Err(err!("Writing {} needs the 'object-store' feature", uri))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_remote_locations() {
+        assert!(is_remote("s3://bucket/embeddings.pb"));
+        assert!(is_remote(Path::new("gs://bucket/texts.txt")));
+        assert!(!is_remote("data/s3://not-a-uri"));
+        assert!(!is_remote("/tmp/embeddings.pb"));
+    }
+}
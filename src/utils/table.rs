@@ -0,0 +1,148 @@
+use super::storage;
+use crate::error::{err, Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Texts read from a column of a CSV or TSV table
+#[derive(Debug, Clone, Default)]
+pub struct ColumnTexts {
+    pub texts: Vec<String>,
+    /// Value of the id column for each text, when one was asked for
+    pub ids: Option<Vec<String>>,
+    /// Rows left out because their text was empty
+    pub skipped: usize,
+}
+
+/// Texts read from a field of JSON Lines records
+#[derive(Debug, Clone, Default)]
+pub struct FieldTexts {
+    pub texts: Vec<String>,
+    /// Requested meta fields of each record, fields a record lacks are left out
+    pub metadata: Vec<HashMap<String, String>>,
+    /// Records left out because their text was missing or empty
+    pub skipped: usize,
+}
+
+/// Reads the texts of a CSV, or TSV for `.tsv` and `.tab` files, with the ids from another column
+pub fn read_text_column(path: impl AsRef<Path>, text_column: &str, id_column: Option<&str>) -> Result<ColumnTexts> {
+    let path = path.as_ref();
+    let tsv = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("tsv") || ext.eq_ignore_ascii_case("tab"))
+        .unwrap_or(false);
+    let bytes = storage::read(path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(if tsv { b'\t' } else { b',' })
+        .flexible(true)
+        .from_reader(bytes.as_slice());
+
+    let headers = reader.headers()
+        .with_context(|| format!("Failed to read the header row of {}", path.display()))?
+        .clone();
+    let position = |column: &str| {
+        headers.iter().position(|header| header.trim() == column).ok_or_else(|| err!(
+            "Column '{}' not found in {}, its columns are {}",
+            column, path.display(), headers.iter().collect::<Vec<_>>().join(", ")
+        ))
+    };
+    let text_index = position(text_column)?;
+    let id_index = id_column.map(position).transpose()?;
+
+    let mut rows = ColumnTexts { ids: id_index.map(|_| Vec::new()), ..Default::default() };
+    for (line, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Failed to read row {} of {}", line + 2, path.display()))?;
+        let text = record.get(text_index).unwrap_or_default().trim();
+        if text.is_empty() {
+            rows.skipped += 1;
+            continue;
+        }
+        rows.texts.push(text.to_string());
+        if let (Some(ids), Some(index)) = (&mut rows.ids, id_index) {
+            ids.push(record.get(index).unwrap_or_default().trim().to_string());
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads the texts of a JSON Lines file with the given meta fields of each record
+///
+/// Fields are top-level keys or dotted paths into nested objects, such as `source.url`. String values are kept
+/// as they are, other values as their JSON text.
+pub fn read_jsonl_field(path: impl AsRef<Path>, text_field: &str, meta_fields: &[String]) -> Result<FieldTexts> {
+    let path = path.as_ref();
+    let content = storage::read_to_string(path)?;
+
+    let mut records = FieldTexts::default();
+    for (line, record) in content.lines().enumerate() {
+        if record.trim().is_empty() {
+            continue;
+        }
+        let record: Value = serde_json::from_str(record)
+            .with_context(|| format!("Invalid JSON on line {} of {}", line + 1, path.display()))?;
+
+        let text = field(&record, text_field).and_then(Value::as_str).map(str::trim).unwrap_or_default();
+        if text.is_empty() {
+            records.skipped += 1;
+            continue;
+        }
+        records.texts.push(text.to_string());
+        records.metadata.push(meta_fields.iter()
+            .filter_map(|name| {
+                let value = match field(&record, name)? {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                Some((name.clone(), value))
+            })
+            .collect());
+    }
+    Ok(records)
+}
+
+/// Looks up a top-level key or a dotted path into nested objects
+fn field<'a>(record: &'a Value, name: &str) -> Option<&'a Value> {
+    record.get(name).or_else(|| name.split('.').try_fold(record, |value, key| value.get(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_text_and_id_columns() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_table_{}.tsv", std::process::id()));
+        std::fs::write(&path, "sku\tdescription\nA1\tRed mug\nA2\t\nA3\t\"Blue, large\"\n")?;
+
+        let rows = read_text_column(&path, "description", Some("sku"))?;
+        assert_eq!(rows.texts, vec!["Red mug", "Blue, large"]);
+        assert_eq!(rows.ids, Some(vec!["A1".to_string(), "A3".to_string()]));
+        assert_eq!(rows.skipped, 1);
+        assert!(read_text_column(&path, "title", None).is_err());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_jsonl_text_and_meta_fields() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rust_embed_table_{}.jsonl", std::process::id()));
+        std::fs::write(&path, concat!(
+            "{\"body\": \"First\", \"title\": \"One\", \"source\": {\"url\": \"https://a.example\"}, \"rank\": 3}\n",
+            "{\"title\": \"No body\"}\n",
+            "\n",
+            "{\"body\": \"Second\"}\n",
+        ))?;
+
+        let fields = ["title".to_string(), "source.url".to_string(), "rank".to_string()];
+        let records = read_jsonl_field(&path, "body", &fields)?;
+        assert_eq!(records.texts, vec!["First", "Second"]);
+        assert_eq!(records.skipped, 1);
+        assert_eq!(records.metadata[0]["source.url"], "https://a.example");
+        assert_eq!(records.metadata[0]["rank"], "3");
+        assert!(records.metadata[1].is_empty());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
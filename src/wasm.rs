@@ -0,0 +1,64 @@
+use crate::embedding::Embedder;
+use crate::models::static_embed::StaticEmbedder;
+use crate::{search, utils};
+use ndarray::Array1;
+use wasm_bindgen::prelude::*;
+
+/// A static embedding model running in the browser
+#[wasm_bindgen]
+pub struct WasmEmbedder {
+    inner: StaticEmbedder,
+}
+
+#[wasm_bindgen]
+impl WasmEmbedder {
+    /// Loads a model2vec model from the bytes of its `model.safetensors` and `tokenizer.json`
+    #[wasm_bindgen(constructor)]
+    pub fn new(safetensors: &[u8], tokenizer_json: &[u8], normalize: bool) -> Result<WasmEmbedder, JsError> {
+        let inner = StaticEmbedder::from_model2vec_bytes("model2vec", safetensors, tokenizer_json, normalize)
+            .map_err(|e| JsError::new(&e.report()))?;
+        Ok(Self { inner })
+    }
+
+    /// Embeds a single text
+    #[wasm_bindgen(js_name = embedText)]
+    pub fn embed_text(&self, text: &str) -> Result<Vec<f32>, JsError> {
+        self.inner.embed_text(text)
+            .map(|embedding| embedding.to_vec())
+            .map_err(|e| JsError::new(&e.report()))
+    }
+
+    /// Dimension of the produced embeddings
+    pub fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+}
+
+/// Cosine similarity between two embeddings
+#[wasm_bindgen(js_name = cosineSimilarity)]
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    utils::cosine_similarity(&Array1::from(a.to_vec()), &Array1::from(b.to_vec()))
+}
+
+/// Indices of the `k` embeddings most similar to the query, best first
+///
+/// `embeddings` holds the candidate embeddings back to back, `dimension` floats each.
+#[wasm_bindgen(js_name = topK)]
+pub fn top_k(query: &[f32], embeddings: &[f32], dimension: usize, k: usize) -> Result<Vec<u32>, JsError> {
+    if dimension == 0 || !embeddings.len().is_multiple_of(dimension) {
+        return Err(JsError::new(&format!(
+            "Got {} floats, which is not a whole number of {}-dimensional embeddings",
+            embeddings.len(), dimension
+        )));
+    }
+
+    let query = Array1::from(query.to_vec());
+    let embeddings: Vec<Array1<f32>> = embeddings.chunks_exact(dimension)
+        .map(|embedding| Array1::from(embedding.to_vec()))
+        .collect();
+
+    Ok(search::top_k(&query, &embeddings, k)
+        .into_iter()
+        .map(|hit| hit.index as u32)
+        .collect())
+}